@@ -0,0 +1,1569 @@
+//! Pieces of the `codex app-server` JSON-RPC protocol shared by the desktop app and the web
+//! server, so both runners parse and persist the stream the same way.
+
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    future::Future,
+    ops::ControlFlow,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncWrite, AsyncWriteExt, Lines},
+    sync::{mpsc, oneshot},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The run's cancel sender fired while waiting for output.
+    Cancelled,
+    /// The app-server closed stdout before answering.
+    Closed,
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The JSON-RPC `error` object of a response, serialized.
+    Rpc(String),
+    MissingResult,
+}
+
+impl Error {
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Error::Cancelled)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Cancelled => f.write_str("cancelled"),
+            Error::Closed => f.write_str("codex app-server stdout closed"),
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Json(e) => write!(f, "{e}"),
+            Error::Rpc(e) => f.write_str(e),
+            Error::MissingResult => f.write_str("missing result"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<Error> for String {
+    fn from(e: Error) -> Self {
+        e.to_string()
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Receives the notifications that arrive while a request is waiting for its response. The
+/// desktop app emits them through Tauri, the server broadcasts them over SSE; both also persist
+/// them to `events.jsonl`.
+pub trait NotificationSink {
+    fn notification(&mut self, raw: &str, json: &Value) -> impl Future<Output = ()> + Send;
+}
+
+pub async fn write_jsonrpc_request<W: AsyncWrite + Unpin>(
+    stdin: &mut W,
+    id: i64,
+    method: &str,
+    params: Value,
+) -> Result<()> {
+    let msg = serde_json::json!({ "id": id, "method": method, "params": params });
+    stdin.write_all(msg.to_string().as_bytes()).await?;
+    stdin.write_all(b"\n").await?;
+    Ok(())
+}
+
+/// Answers a request the app-server sent us (it has both `id` and `method`), echoing its `id`.
+pub async fn write_jsonrpc_response<W: AsyncWrite + Unpin>(
+    stdin: &mut W,
+    id: &Value,
+    result: Value,
+) -> Result<()> {
+    let msg = serde_json::json!({ "id": id, "result": result });
+    stdin.write_all(msg.to_string().as_bytes()).await?;
+    stdin.write_all(b"\n").await?;
+    Ok(())
+}
+
+/// How much of a JSON value split across stdout lines is buffered before giving up on it.
+const MAX_SPLIT_JSON_BYTES: usize = 16 * 1024 * 1024;
+
+/// Tracks where a JSON text ends, one chunk at a time, so a value split across lines is parsed
+/// once it is complete rather than after every line.
+#[derive(Default)]
+struct JsonScan {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonScan {
+    fn feed(&mut self, chunk: &str) {
+        for b in chunk.bytes() {
+            if self.in_string {
+                match b {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.depth > 0 || self.in_string
+    }
+}
+
+/// Reads the next stdout line as JSON, returning `Ok(None)` at end of stream.
+///
+/// A line that ends mid-value (e.g. a huge patch item broken up by a newline) is joined with the
+/// following lines until its brackets close, up to `MAX_SPLIT_JSON_BYTES`. A break inside a string
+/// is kept as an escaped `\n`; one between tokens is dropped. Not cancel-safe: dropping the future
+/// while it joins lines loses them.
+pub async fn read_next_json_line<R: AsyncBufRead + Unpin>(
+    lines: &mut Lines<R>,
+    cancel_rx: &mut oneshot::Receiver<()>,
+) -> Result<Option<(String, Value)>> {
+    let Some(mut line) = next_line(lines, cancel_rx).await? else {
+        return Ok(None);
+    };
+    match serde_json::from_str::<Value>(&line) {
+        Ok(json) => return Ok(Some((line, json))),
+        Err(e) if !e.is_eof() => return Err(e.into()),
+        Err(_) => {}
+    }
+    tracing::warn!(
+        bytes = line.len(),
+        "app-server line ended mid-JSON; joining the next lines"
+    );
+    let mut scan = JsonScan::default();
+    scan.feed(&line);
+    while scan.is_open() && line.len() <= MAX_SPLIT_JSON_BYTES {
+        let Some(more) = next_line(lines, cancel_rx).await? else {
+            break;
+        };
+        if scan.in_string {
+            line.push_str("\\n");
+        }
+        scan.feed(&more);
+        line.push_str(&more);
+    }
+    let json = serde_json::from_str::<Value>(&line)?;
+    Ok(Some((line, json)))
+}
+
+async fn next_line<R: AsyncBufRead + Unpin>(
+    lines: &mut Lines<R>,
+    cancel_rx: &mut oneshot::Receiver<()>,
+) -> Result<Option<String>> {
+    tokio::select! {
+        _ = &mut *cancel_rx => Err(Error::Cancelled),
+        next = lines.next_line() => Ok(next?),
+    }
+}
+
+pub fn jsonrpc_id_matches(value: &Value, expected: i64) -> bool {
+    let Some(id) = value.get("id") else {
+        return false;
+    };
+    match id {
+        Value::Number(n) => n.as_i64() == Some(expected),
+        Value::String(s) => s == expected.to_string().as_str(),
+        _ => false,
+    }
+}
+
+pub fn jsonrpc_result(response: &Value) -> Result<Value> {
+    if let Some(err) = response.get("error") {
+        return Err(Error::Rpc(err.to_string()));
+    }
+    response.get("result").cloned().ok_or(Error::MissingResult)
+}
+
+/// Waits for the response to request `expected_id`, handing every notification seen meanwhile to
+/// `sink`. Responses to other requests are skipped.
+pub async fn wait_for_app_server_response<R, S>(
+    lines: &mut Lines<R>,
+    cancel_rx: &mut oneshot::Receiver<()>,
+    sink: &mut S,
+    expected_id: i64,
+) -> Result<Value>
+where
+    R: AsyncBufRead + Unpin,
+    S: NotificationSink,
+{
+    loop {
+        let Some((raw, json)) = read_next_json_line(lines, cancel_rx).await? else {
+            return Err(Error::Closed);
+        };
+        // Responses never carry `method`, so a notification or server request is skipped even
+        // when its `id` happens to equal ours.
+        if let Some(method) = json.get("method") {
+            if method.is_string() {
+                sink.notification(&raw, &json).await;
+            }
+            continue;
+        }
+        if !jsonrpc_id_matches(&json, expected_id) {
+            continue;
+        }
+        return jsonrpc_result(&json);
+    }
+}
+
+/// High-frequency notifications that are surfaced through dedicated events (metrics, rate
+/// limits, reasoning) instead of being written to `events.jsonl`.
+pub fn is_unpersisted_notification(method: &str) -> bool {
+    method == "thread/tokenUsage/updated"
+        || method == "account/rateLimits/updated"
+        || method == "item/reasoning/summaryPartAdded"
+}
+
+/// Accumulates the current agent message from `item/agentMessage/delta` notifications, replacing
+/// it with the final text on `item/completed`.
+pub fn capture_agent_message_text(
+    msg: &Value,
+    agent_item_id: &mut Option<String>,
+    agent_text: &mut String,
+) {
+    let Some(method) = msg.get("method").and_then(|v| v.as_str()) else {
+        return;
+    };
+    if method == "item/agentMessage/delta" {
+        let Some(params) = msg.get("params") else {
+            return;
+        };
+        let Some(item_id) = params.get("itemId").and_then(|v| v.as_str()) else {
+            return;
+        };
+        let delta = params
+            .get("delta")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        if agent_item_id.as_deref() != Some(item_id) {
+            agent_text.clear();
+            *agent_item_id = Some(item_id.to_string());
+        }
+        agent_text.push_str(delta);
+        return;
+    }
+
+    if is_agent_message_completed(msg) {
+        let item = &msg["params"]["item"];
+        let Some(text) = item.get("text").and_then(|v| v.as_str()) else {
+            return;
+        };
+        agent_text.clear();
+        agent_text.push_str(text);
+        *agent_item_id = item
+            .get("id")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+    }
+}
+
+/// How a turn ended, from its `turn/completed` notification.
+#[derive(Debug, PartialEq)]
+pub struct TurnOutcome {
+    /// `completed`, `interrupted`, `failed`, ...
+    pub status: String,
+    /// The turn's error message, or its error kind (e.g. `contextWindowExceeded`) when there's no
+    /// message.
+    pub reason: Option<String>,
+}
+
+impl TurnOutcome {
+    pub fn success(&self) -> bool {
+        self.status == "completed"
+    }
+
+    /// The run's exit code: none when the turn completed or was interrupted, 1 otherwise.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self.status.as_str() {
+            "completed" | "interrupted" => None,
+            _ => Some(1),
+        }
+    }
+}
+
+/// Reads the outcome out of a `turn/completed` notification; `None` for anything else.
+pub fn turn_outcome(msg: &Value) -> Option<TurnOutcome> {
+    if msg.get("method").and_then(|v| v.as_str()) != Some("turn/completed") {
+        return None;
+    }
+    let turn = msg.get("params").and_then(|v| v.get("turn"));
+    let status = turn
+        .and_then(|t| t.get("status"))
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let reason = turn.and_then(|t| t.get("error")).and_then(|error| {
+        let message = error
+            .get("message")
+            .and_then(|v| v.as_str())
+            .filter(|m| !m.trim().is_empty());
+        let kind = match error.get("codexErrorInfo") {
+            Some(Value::String(kind)) => Some(kind.as_str()),
+            // Kinds with details are serialized as `{ "<kind>": { ... } }`.
+            Some(Value::Object(kind)) => kind.keys().next().map(String::as_str),
+            _ => None,
+        };
+        message.or(kind).or(error.as_str()).map(str::to_string)
+    });
+    Some(TurnOutcome { status, reason })
+}
+
+/// A turn for [`run_turn`] to drive on an app-server that has already answered `initialize`.
+pub struct TurnRequest {
+    /// Thread to resume; a fresh one is started when unset or when the resume fails.
+    pub thread_id: Option<String>,
+    /// Object sent as `thread/resume` or `thread/start` params (cwd, config, sandbox, approval
+    /// policy); `threadId` is added on resume.
+    pub thread_params: Value,
+    /// The `turn/start` input items.
+    pub input: Value,
+    /// Gives up on the turn after this long without a line from the app-server.
+    pub idle_timeout: Option<Duration>,
+    /// Id of the first request the turn sends; lower ids belong to the handshake.
+    pub first_request_id: i64,
+}
+
+/// The runner's side of a turn: [`run_turn`] does the JSON-RPC bookkeeping and hands everything
+/// else to the sink. Notifications that arrive while a request is pending still go through
+/// [`NotificationSink::notification`].
+pub trait TurnSink: NotificationSink {
+    /// Called once the thread is known, before `turn/start`.
+    fn thread_ready(&mut self, thread_id: &str) -> impl Future<Output = ()> + Send;
+
+    /// Handles a notification read after `turn/start`. `Break` stops the turn, which is then
+    /// interrupted.
+    fn turn_notification(
+        &mut self,
+        raw: &str,
+        json: &Value,
+    ) -> impl Future<Output = ControlFlow<()>> + Send;
+
+    /// Handles an approval request. Returning a decision answers it right away; `None` leaves it
+    /// for a `(request_id, decision)` pair on the approvals channel.
+    fn approval_request(
+        &mut self,
+        raw: &str,
+        json: &Value,
+        request_id: &str,
+    ) -> impl Future<Output = Option<String>> + Send;
+}
+
+/// How the read loop of a started turn ended.
+#[derive(Debug, PartialEq)]
+pub enum TurnEnd {
+    /// `turn/completed` arrived.
+    Completed(TurnOutcome),
+    /// Stdout closed, or sent something unreadable, before the turn completed.
+    Closed,
+    Cancelled,
+    /// Nothing arrived within the idle timeout.
+    TimedOut,
+    /// The sink stopped the turn.
+    Stopped,
+}
+
+#[derive(Debug)]
+pub struct TurnReport {
+    pub thread_id: String,
+    /// The model the thread runs on, when the app-server said.
+    pub model: Option<String>,
+    pub end: TurnEnd,
+}
+
+/// Why a turn never got to `turn/start`'s response.
+#[derive(Debug)]
+pub enum TurnStartError {
+    Cancelled,
+    ThreadStart(Error),
+    MissingThreadId,
+    TurnStart(Error),
+}
+
+impl TurnStartError {
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, TurnStartError::Cancelled)
+    }
+}
+
+impl std::fmt::Display for TurnStartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TurnStartError::Cancelled => f.write_str("Cancelled."),
+            TurnStartError::ThreadStart(e) => write!(f, "Thread start failed: {e}"),
+            TurnStartError::MissingThreadId => {
+                f.write_str("Thread start did not return a thread id")
+            }
+            TurnStartError::TurnStart(e) => write!(f, "Turn start failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TurnStartError {}
+
+/// Resumes or starts the thread, starts the turn and reads it to the end: approval requests are
+/// answered, `turn/completed` ends it, and a cancel, idle timeout or stop from the sink sends
+/// `turn/interrupt`. Errors are only returned when the turn never started.
+pub async fn run_turn<W, R, S>(
+    stdin: &mut W,
+    lines: &mut Lines<R>,
+    cancel_rx: &mut oneshot::Receiver<()>,
+    mut approvals: Option<&mut mpsc::UnboundedReceiver<(String, String)>>,
+    sink: &mut S,
+    request: TurnRequest,
+) -> std::result::Result<TurnReport, TurnStartError>
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncBufRead + Unpin,
+    S: TurnSink,
+{
+    fn thread_info(result: &Value) -> (Option<String>, Option<String>) {
+        let id = result
+            .get("thread")
+            .and_then(|v| v.get("id"))
+            .and_then(|v| v.as_str());
+        let model = result.get("model").and_then(|v| v.as_str());
+        (id.map(str::to_string), model.map(str::to_string))
+    }
+
+    let mut next_id = request.first_request_id;
+    let mut thread_id = None;
+    let mut model = None;
+
+    if let Some(existing) = request.thread_id.as_deref() {
+        let step = Instant::now();
+        let resume_id = next_id;
+        next_id += 1;
+        let mut params = request.thread_params.clone();
+        params["threadId"] = Value::String(existing.to_string());
+        let _ = write_jsonrpc_request(stdin, resume_id, "thread/resume", params).await;
+        match wait_for_app_server_response(lines, cancel_rx, sink, resume_id).await {
+            Ok(result) => {
+                let (id, m) = thread_info(&result);
+                thread_id = Some(id.unwrap_or_else(|| existing.to_string()));
+                model = m;
+                tracing::info!(
+                    elapsed_ms = step.elapsed().as_millis() as u64,
+                    "thread resumed"
+                );
+            }
+            Err(e) if e.is_cancelled() => return Err(TurnStartError::Cancelled),
+            Err(e) => tracing::warn!(
+                thread_id = existing,
+                "thread/resume failed, starting a fresh thread: {e}"
+            ),
+        }
+    }
+
+    if thread_id.is_none() {
+        let step = Instant::now();
+        let start_id = next_id;
+        next_id += 1;
+        let _ = write_jsonrpc_request(stdin, start_id, "thread/start", request.thread_params).await;
+        match wait_for_app_server_response(lines, cancel_rx, sink, start_id).await {
+            Ok(result) => {
+                (thread_id, model) = thread_info(&result);
+                tracing::info!(
+                    elapsed_ms = step.elapsed().as_millis() as u64,
+                    "thread started"
+                );
+            }
+            Err(e) if e.is_cancelled() => return Err(TurnStartError::Cancelled),
+            Err(e) => return Err(TurnStartError::ThreadStart(e)),
+        }
+    }
+
+    let Some(thread_id) = thread_id else {
+        return Err(TurnStartError::MissingThreadId);
+    };
+    sink.thread_ready(&thread_id).await;
+
+    let step = Instant::now();
+    let turn_start_id = next_id;
+    next_id += 1;
+    let params = serde_json::json!({ "threadId": thread_id, "input": request.input });
+    let _ = write_jsonrpc_request(stdin, turn_start_id, "turn/start", params).await;
+    let turn_id = match wait_for_app_server_response(lines, cancel_rx, sink, turn_start_id).await {
+        Ok(result) => result
+            .get("turn")
+            .and_then(|v| v.get("id"))
+            .and_then(|v| match v {
+                Value::String(s) => Some(s.to_string()),
+                Value::Number(n) => n.as_i64().map(|i| i.to_string()),
+                _ => None,
+            }),
+        Err(e) if e.is_cancelled() => return Err(TurnStartError::Cancelled),
+        Err(e) => return Err(TurnStartError::TurnStart(e)),
+    };
+    tracing::info!(
+        turn_id = turn_id.as_deref(),
+        elapsed_ms = step.elapsed().as_millis() as u64,
+        "turn started"
+    );
+
+    // Approval requests waiting on the channel: request id -> (JSON-RPC id, method).
+    let mut pending_approvals: HashMap<String, (Value, String)> = HashMap::new();
+    // Reset by every line read and by answering the last pending approval.
+    let mut idle_since = tokio::time::Instant::now();
+    let end = loop {
+        // The read lives on across approval answers: dropping it while it joins a split value
+        // would lose the lines read so far.
+        let read = read_next_json_line(lines, cancel_rx);
+        tokio::pin!(read);
+        // `None` means the idle timeout elapsed.
+        let next = loop {
+            let answer = async {
+                match approvals.as_deref_mut() {
+                    Some(rx) => rx.recv().await,
+                    None => None,
+                }
+            };
+            // Paused while a user decides on an approval, however long that takes.
+            let awaiting_user = !pending_approvals.is_empty();
+            let idle = async {
+                match request.idle_timeout {
+                    Some(idle) if !awaiting_user => {
+                        tokio::time::sleep_until(idle_since + idle).await
+                    }
+                    _ => std::future::pending().await,
+                }
+            };
+            let (request_id, decision) = tokio::select! {
+                Some(answer) = answer => answer,
+                () = idle => break None,
+                next = &mut read => break Some(next),
+            };
+            if let Some((id, method)) = pending_approvals.remove(&request_id) {
+                let _ =
+                    write_jsonrpc_response(stdin, &id, approval_result(&method, &decision)).await;
+                if pending_approvals.is_empty() {
+                    idle_since = tokio::time::Instant::now();
+                }
+            }
+        };
+        let Some(next) = next else {
+            break TurnEnd::TimedOut;
+        };
+        idle_since = tokio::time::Instant::now();
+        let (raw, json) = match next {
+            Ok(Some(v)) => v,
+            Ok(None) => break TurnEnd::Closed,
+            Err(e) if e.is_cancelled() => break TurnEnd::Cancelled,
+            Err(_) => break TurnEnd::Closed,
+        };
+
+        let Some(method) = json.get("method").and_then(|v| v.as_str()) else {
+            continue;
+        };
+
+        // Unanswered, an approval request stalls the turn until it times out.
+        if is_approval_request(&json) {
+            let request_id = match &json["id"] {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            match sink.approval_request(&raw, &json, &request_id).await {
+                Some(decision) => {
+                    let _ = write_jsonrpc_response(
+                        stdin,
+                        &json["id"],
+                        approval_result(method, &decision),
+                    )
+                    .await;
+                }
+                None => {
+                    pending_approvals.insert(request_id, (json["id"].clone(), method.to_string()));
+                }
+            }
+            continue;
+        }
+
+        if sink.turn_notification(&raw, &json).await.is_break() {
+            break TurnEnd::Stopped;
+        }
+        if let Some(outcome) = turn_outcome(&json) {
+            break TurnEnd::Completed(outcome);
+        }
+    };
+
+    if matches!(
+        end,
+        TurnEnd::Cancelled | TurnEnd::TimedOut | TurnEnd::Stopped
+    ) {
+        if let Some(turn_id) = turn_id.as_deref() {
+            let params = serde_json::json!({ "threadId": thread_id, "turnId": turn_id });
+            let _ = write_jsonrpc_request(stdin, next_id, "turn/interrupt", params).await;
+        }
+    }
+    Ok(TurnReport {
+        thread_id,
+        model,
+        end,
+    })
+}
+
+pub fn is_agent_message_completed(msg: &Value) -> bool {
+    msg.get("method").and_then(|v| v.as_str()) == Some("item/completed")
+        && msg
+            .get("params")
+            .and_then(|v| v.get("item"))
+            .and_then(|v| v.get("type"))
+            .and_then(|v| v.as_str())
+            == Some("agentMessage")
+}
+
+/// Removes tool citation markup (`\u{E200}...\u{E201}` spans plus leading blanks), matching
+/// what the UI strips before rendering. An unterminated span drops the rest of the text.
+pub fn strip_tool_citations(text: &str) -> String {
+    // Some OpenAI tool annotations are encoded using private-use Unicode characters
+    // like `\u{E200}` ... `\u{E201}` (e.g. citations). In a plain-text GUI these can
+    // show up as "garbled" glyph boxes, so we remove them for the conclusion file.
+    const START: &str = "\u{E200}";
+    const END: &str = "\u{E201}";
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(START) {
+        out.push_str(&rest[..start]);
+        while out.ends_with(' ') || out.ends_with('\t') {
+            out.pop();
+        }
+        let after_start = &rest[start + START.len()..];
+        if let Some(end_rel) = after_start.find(END) {
+            rest = &after_start[end_rel + END.len()..];
+        } else {
+            return out;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// What a `conclusion_template` can refer to.
+pub struct ConclusionFields<'a> {
+    pub prompt: &'a str,
+    pub answer: &'a str,
+    pub model: Option<&'a str>,
+    pub tokens: Option<u64>,
+    pub ts_ms: u64,
+}
+
+/// Renders `conclusion.md`: the answer with citations stripped, or `template` with `{prompt}`,
+/// `{answer}`, `{model}`, `{tokens}` and `{date}` filled in. Missing values become empty strings;
+/// other braces are kept, and substituted text is never expanded again.
+pub fn render_conclusion(template: Option<&str>, fields: &ConclusionFields<'_>) -> String {
+    let answer = strip_tool_citations(fields.answer);
+    let Some(template) = template.filter(|t| !t.trim().is_empty()) else {
+        return answer;
+    };
+
+    let mut out = String::with_capacity(template.len() + answer.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open..];
+        let Some(close) = after.find('}') else {
+            rest = after;
+            break;
+        };
+        match &after[1..close] {
+            "prompt" => out.push_str(fields.prompt),
+            "answer" => out.push_str(&answer),
+            "model" => out.push_str(fields.model.unwrap_or_default()),
+            "tokens" => out.push_str(&fields.tokens.map(|t| t.to_string()).unwrap_or_default()),
+            "date" => out.push_str(&utc_date_time(fields.ts_ms)),
+            _ => {
+                out.push('{');
+                rest = &after[1..];
+                continue;
+            }
+        }
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = mp + if mp < 10 { 3 } else { -9 };
+    (yoe + era * 400 + if m <= 2 { 1 } else { 0 }, m, d)
+}
+
+/// Formats a unix-ms timestamp as a UTC `YYYY-MM-DD` date.
+pub fn utc_day(ts_ms: u64) -> String {
+    let (y, m, d) = civil_from_days((ts_ms / 86_400_000) as i64);
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+/// Formats a unix-ms timestamp as `YYYY-MM-DD HH:MM UTC`.
+pub fn utc_date_time(ts_ms: u64) -> String {
+    let minutes = (ts_ms / 60_000) % (24 * 60);
+    format!(
+        "{} {:02}:{:02} UTC",
+        utc_day(ts_ms),
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Values codex accepts for the `model_reasoning_effort` config.
+pub const REASONING_EFFORTS: &[&str] = &["minimal", "low", "medium", "high"];
+
+/// Trims `raw` and checks it against [`REASONING_EFFORTS`]; blank means "codex's default".
+pub fn parse_reasoning_effort(raw: Option<String>) -> std::result::Result<Option<String>, String> {
+    let Some(effort) = raw
+        .map(|s| s.trim().to_ascii_lowercase())
+        .filter(|s| !s.is_empty())
+    else {
+        return Ok(None);
+    };
+    if !REASONING_EFFORTS.contains(&effort.as_str()) {
+        return Err(format!(
+            "invalid reasoning effort {effort:?} (expected one of: {})",
+            REASONING_EFFORTS.join(", ")
+        ));
+    }
+    Ok(Some(effort))
+}
+
+/// Requests the app-server sends before running a command or applying a patch when its approval
+/// policy isn't `never`. The turn stalls until each one is answered.
+pub const APPROVAL_METHODS: &[&str] = &[
+    "execCommandApproval",
+    "applyPatchApproval",
+    "item/commandExecution/requestApproval",
+    "item/fileChange/requestApproval",
+];
+
+/// Decisions a client may give an approval request.
+pub const APPROVAL_DECISIONS: &[&str] = &["approved", "denied"];
+
+pub fn is_approval_request(msg: &Value) -> bool {
+    msg.get("id").is_some()
+        && msg
+            .get("method")
+            .and_then(|v| v.as_str())
+            .is_some_and(|m| APPROVAL_METHODS.contains(&m))
+}
+
+pub fn parse_approval_decision(raw: &str) -> std::result::Result<String, String> {
+    let decision = raw.trim().to_ascii_lowercase();
+    if !APPROVAL_DECISIONS.contains(&decision.as_str()) {
+        return Err(format!(
+            "invalid approval decision {decision:?} (expected one of: {})",
+            APPROVAL_DECISIONS.join(", ")
+        ));
+    }
+    Ok(decision)
+}
+
+/// The response `result` for approval request `method`; the v2 `item/*/requestApproval` methods
+/// spell the decisions `accept`/`decline`.
+pub fn approval_result(method: &str, decision: &str) -> Value {
+    let approved = decision == "approved";
+    let decision = if method.starts_with("item/") {
+        if approved {
+            "accept"
+        } else {
+            "decline"
+        }
+    } else if approved {
+        "approved"
+    } else {
+        "denied"
+    };
+    serde_json::json!({ "decision": decision })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TokenUsageSnapshot {
+    pub window: u64,
+    pub total_tokens: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub reasoning_output_tokens: u64,
+    pub cached_input_tokens: u64,
+    pub pct_left: u8,
+}
+
+pub fn json_u64(v: &Value) -> Option<u64> {
+    match v {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse::<u64>().ok(),
+        _ => None,
+    }
+}
+
+pub fn extract_token_usage_snapshot(msg: &Value) -> Option<TokenUsageSnapshot> {
+    if msg.get("method").and_then(|v| v.as_str()) != Some("thread/tokenUsage/updated") {
+        return None;
+    }
+    let params = msg.get("params")?;
+    let usage = params.get("tokenUsage")?;
+    let window = params
+        .get("modelContextWindow")
+        .and_then(json_u64)
+        .or_else(|| usage.get("modelContextWindow").and_then(json_u64))
+        .unwrap_or(0);
+    if window == 0 {
+        return None;
+    }
+
+    // For context-remaining, we want the token usage of the *current request* (prompt+generated),
+    // not the cumulative, lifetime usage of the thread.
+    let last = usage.get("last").or_else(|| usage.get("total"))?;
+    let input_tokens = last.get("inputTokens").and_then(json_u64).unwrap_or(0);
+    let cached_input_tokens = last
+        .get("cachedInputTokens")
+        .and_then(json_u64)
+        .unwrap_or(0);
+    let output_tokens = last.get("outputTokens").and_then(json_u64).unwrap_or(0);
+    let reasoning_output_tokens = last
+        .get("reasoningOutputTokens")
+        .and_then(json_u64)
+        .unwrap_or(0);
+    let total_tokens = last
+        .get("totalTokens")
+        .and_then(json_u64)
+        .or_else(|| {
+            let sum = input_tokens + output_tokens + reasoning_output_tokens;
+            (sum > 0).then_some(sum)
+        })
+        .unwrap_or(0);
+
+    let remaining = window.saturating_sub(total_tokens);
+    // Round to nearest integer to avoid jitter.
+    let pct_left = ((remaining.saturating_mul(100) + (window / 2)) / window).min(100) as u8;
+
+    Some(TokenUsageSnapshot {
+        window,
+        total_tokens,
+        input_tokens,
+        output_tokens,
+        reasoning_output_tokens,
+        cached_input_tokens,
+        pct_left,
+    })
+}
+
+/// What `$CODEX_HOME/auth.json` says about the login, without exposing any secret.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodexAuth {
+    pub logged_in: bool,
+    /// `api_key` or `chatgpt`.
+    pub method: Option<&'static str>,
+    /// Expiry of the ChatGPT access token, read from its JWT `exp` claim.
+    pub expires_at_ms: Option<u64>,
+}
+
+/// Interprets the contents of `auth.json` (`None` when the file is missing). An expired ChatGPT
+/// access token still counts as logged in when a refresh token is there, since codex refreshes it.
+pub fn codex_auth_from_json(auth: Option<&Value>, now_ms: u64) -> CodexAuth {
+    let logged_out = CodexAuth {
+        logged_in: false,
+        method: None,
+        expires_at_ms: None,
+    };
+    let Some(auth) = auth else {
+        return logged_out;
+    };
+    let non_empty = |v: Option<&Value>| {
+        v.and_then(|v| v.as_str())
+            .is_some_and(|s| !s.trim().is_empty())
+    };
+    let tokens = auth.get("tokens");
+    let access_token = tokens
+        .and_then(|t| t.get("access_token"))
+        .and_then(|v| v.as_str());
+    if let Some(access_token) = access_token.filter(|t| !t.is_empty()) {
+        let expires_at_ms = jwt_exp_ms(access_token);
+        let refreshable = non_empty(tokens.and_then(|t| t.get("refresh_token")));
+        return CodexAuth {
+            logged_in: refreshable || expires_at_ms.is_none_or(|exp| exp > now_ms),
+            method: Some("chatgpt"),
+            expires_at_ms,
+        };
+    }
+    if non_empty(auth.get("OPENAI_API_KEY")) {
+        return CodexAuth {
+            logged_in: true,
+            method: Some("api_key"),
+            expires_at_ms: None,
+        };
+    }
+    logged_out
+}
+
+/// The `exp` claim (seconds) of a JWT, in ms. The signature isn't checked; this only informs UI.
+fn jwt_exp_ms(token: &str) -> Option<u64> {
+    let payload = token.split('.').nth(1)?;
+    let claims: Value = serde_json::from_slice(&base64url_decode(payload)?).ok()?;
+    json_u64(claims.get("exp")?)?.checked_mul(1000)
+}
+
+fn base64url_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for c in input.bytes().take_while(|c| *c != b'=') {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' | b'+' => 62,
+            b'_' | b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | u32::from(v);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::AsyncBufReadExt;
+
+    #[test]
+    fn jsonrpc_id_matches_numbers_and_numeric_strings() {
+        assert!(jsonrpc_id_matches(&json!({ "id": 3 }), 3));
+        assert!(jsonrpc_id_matches(&json!({ "id": "3" }), 3));
+        assert!(!jsonrpc_id_matches(&json!({ "id": 4 }), 3));
+        assert!(!jsonrpc_id_matches(&json!({ "method": "turn/started" }), 3));
+    }
+
+    #[tokio::test]
+    async fn wait_for_response_skips_notifications_reusing_the_expected_id() {
+        struct Collect(Vec<String>);
+        impl NotificationSink for Collect {
+            async fn notification(&mut self, _raw: &str, json: &Value) {
+                self.0
+                    .push(json["method"].as_str().unwrap_or_default().to_string());
+            }
+        }
+        let input = concat!(
+            r#"{"id":2,"method":"turn/started","params":{}}"#,
+            "\n",
+            r#"{"id":2,"method":null}"#,
+            "\n",
+            r#"{"id":1,"result":{"stale":true}}"#,
+            "\n",
+            r#"{"id":2,"result":{"ok":true}}"#,
+            "\n",
+        );
+        let mut lines = tokio::io::BufReader::new(input.as_bytes()).lines();
+        let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+        let mut sink = Collect(Vec::new());
+        let result = wait_for_app_server_response(&mut lines, &mut cancel_rx, &mut sink, 2)
+            .await
+            .unwrap();
+        assert_eq!(result, json!({ "ok": true }));
+        assert_eq!(sink.0, vec!["turn/started"]);
+    }
+
+    #[test]
+    fn codex_auth_reads_method_and_token_expiry() {
+        assert!(!codex_auth_from_json(None, 0).logged_in);
+        let api_key = codex_auth_from_json(
+            Some(&json!({ "OPENAI_API_KEY": "sk-test", "tokens": null })),
+            0,
+        );
+        assert_eq!((api_key.logged_in, api_key.method), (true, Some("api_key")));
+        assert!(!codex_auth_from_json(Some(&json!({ "OPENAI_API_KEY": "" })), 0).logged_in);
+
+        // {"alg":"none"} . {"exp":1700000000}
+        let jwt = "eyJhbGciOiJub25lIn0.eyJleHAiOjE3MDAwMDAwMDB9.sig";
+        let auth = json!({ "tokens": { "access_token": jwt, "refresh_token": "" } });
+        let fresh = codex_auth_from_json(Some(&auth), 1_600_000_000_000);
+        assert_eq!(fresh.expires_at_ms, Some(1_700_000_000_000));
+        assert_eq!((fresh.logged_in, fresh.method), (true, Some("chatgpt")));
+        assert!(!codex_auth_from_json(Some(&auth), 1_800_000_000_000).logged_in);
+
+        let refreshable = json!({ "tokens": { "access_token": jwt, "refresh_token": "r" } });
+        assert!(codex_auth_from_json(Some(&refreshable), 1_800_000_000_000).logged_in);
+    }
+
+    #[test]
+    fn jsonrpc_result_surfaces_errors() {
+        assert_eq!(
+            jsonrpc_result(&json!({ "id": 1, "result": { "ok": true } })).unwrap(),
+            json!({ "ok": true })
+        );
+        let err = jsonrpc_result(&json!({ "id": 1, "error": { "message": "nope" } })).unwrap_err();
+        assert_eq!(err.to_string(), r#"{"message":"nope"}"#);
+        assert!(matches!(
+            jsonrpc_result(&json!({ "id": 1 })),
+            Err(Error::MissingResult)
+        ));
+    }
+
+    #[test]
+    fn capture_agent_message_text_accumulates_deltas_until_completed() {
+        let (mut item_id, mut text) = (None, String::new());
+        let delta = |id: &str, d: &str| json!({ "method": "item/agentMessage/delta", "params": { "itemId": id, "delta": d } });
+        capture_agent_message_text(&delta("a", "Hel"), &mut item_id, &mut text);
+        capture_agent_message_text(&delta("a", "lo"), &mut item_id, &mut text);
+        assert_eq!(text, "Hello");
+
+        // A new item restarts the text.
+        capture_agent_message_text(&delta("b", "Bye"), &mut item_id, &mut text);
+        assert_eq!((item_id.as_deref(), text.as_str()), (Some("b"), "Bye"));
+
+        let completed = json!({
+            "method": "item/completed",
+            "params": { "item": { "type": "agentMessage", "id": "b", "text": "Goodbye." } }
+        });
+        capture_agent_message_text(&completed, &mut item_id, &mut text);
+        assert_eq!(text, "Goodbye.");
+        assert!(is_agent_message_completed(&completed));
+    }
+
+    #[test]
+    fn parse_reasoning_effort_normalizes_and_rejects_typos() {
+        assert_eq!(
+            parse_reasoning_effort(Some(" High ".to_string()))
+                .unwrap()
+                .as_deref(),
+            Some("high")
+        );
+        assert_eq!(
+            parse_reasoning_effort(Some("  ".to_string())).unwrap(),
+            None
+        );
+        assert_eq!(parse_reasoning_effort(None).unwrap(), None);
+        let err = parse_reasoning_effort(Some("hgih".to_string())).unwrap_err();
+        assert!(
+            err.contains("\"hgih\"") && err.contains("low, medium"),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn approval_requests_are_answered_with_their_id() {
+        let request =
+            json!({ "id": 7, "method": "execCommandApproval", "params": { "command": ["ls"] } });
+        assert!(is_approval_request(&request));
+        assert!(!is_approval_request(
+            &json!({ "method": "execCommandApproval" })
+        ));
+        assert!(!is_approval_request(
+            &json!({ "id": 1, "method": "turn/start" })
+        ));
+
+        assert_eq!(parse_approval_decision(" Approved ").unwrap(), "approved");
+        assert!(parse_approval_decision("maybe").is_err());
+        assert_eq!(
+            approval_result("applyPatchApproval", "denied"),
+            json!({ "decision": "denied" })
+        );
+        assert_eq!(
+            approval_result("item/commandExecution/requestApproval", "approved"),
+            json!({ "decision": "accept" })
+        );
+
+        let mut out = Vec::new();
+        write_jsonrpc_response(
+            &mut out,
+            &request["id"],
+            approval_result("execCommandApproval", "approved"),
+        )
+        .await
+        .unwrap();
+        let written: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            written,
+            json!({ "id": 7, "result": { "decision": "approved" } })
+        );
+    }
+
+    #[test]
+    fn strip_tool_citations_removes_spans_and_unterminated_tails() {
+        assert_eq!(
+            strip_tool_citations("see \u{E200}cite\u{E201} here"),
+            "see here"
+        );
+        assert_eq!(strip_tool_citations("open \u{E200}never closed"), "open");
+        assert_eq!(strip_tool_citations("plain"), "plain");
+    }
+
+    #[test]
+    fn extract_token_usage_snapshot_prefers_last_request_usage() {
+        let msg = json!({
+            "method": "thread/tokenUsage/updated",
+            "params": {
+                "modelContextWindow": 1000,
+                "tokenUsage": {
+                    "last": { "inputTokens": 200, "outputTokens": "50" },
+                    "total": { "totalTokens": 900 }
+                }
+            }
+        });
+        let snapshot = extract_token_usage_snapshot(&msg).unwrap();
+        assert_eq!((snapshot.total_tokens, snapshot.pct_left), (250, 75));
+        assert!(is_unpersisted_notification("thread/tokenUsage/updated"));
+        assert!(!is_unpersisted_notification("item/completed"));
+    }
+
+    #[test]
+    fn render_conclusion_fills_placeholders_once() {
+        let fields = ConclusionFields {
+            prompt: "fix {answer}",
+            answer: "Done \u{E200}cite\u{E201}.",
+            model: None,
+            tokens: Some(1234),
+            ts_ms: 1_760_000_000_000,
+        };
+        assert_eq!(render_conclusion(None, &fields), "Done.");
+        assert_eq!(render_conclusion(Some("  "), &fields), "Done.");
+        assert_eq!(
+            render_conclusion(
+                Some("# {prompt}\n{date} [{model}] {tokens} {other}\n\n{answer}"),
+                &fields
+            ),
+            "# fix {answer}\n2025-10-09 08:53 UTC [] 1234 {other}\n\nDone."
+        );
+        assert_eq!(
+            render_conclusion(Some("{answer} {unclosed"), &fields),
+            "Done. {unclosed"
+        );
+    }
+
+    struct Collect(Vec<String>);
+
+    impl NotificationSink for Collect {
+        async fn notification(&mut self, _raw: &str, json: &Value) {
+            self.0
+                .push(json["method"].as_str().unwrap_or_default().to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn wait_for_app_server_response_forwards_notifications() {
+        let stdout = concat!(
+            r#"{"method":"turn/started","params":{}}"#,
+            "\n",
+            r#"{"id":1,"result":{"stale":true}}"#,
+            "\n",
+            r#"{"id":2,"result":{"thread":{"id":"t1"}}}"#,
+            "\n",
+        );
+        let mut lines = tokio::io::BufReader::new(stdout.as_bytes()).lines();
+        let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+        let mut sink = Collect(Vec::new());
+
+        let result = wait_for_app_server_response(&mut lines, &mut cancel_rx, &mut sink, 2)
+            .await
+            .unwrap();
+        assert_eq!(result["thread"]["id"], "t1");
+        assert_eq!(sink.0, vec!["turn/started".to_string()]);
+
+        let closed = wait_for_app_server_response(&mut lines, &mut cancel_rx, &mut sink, 3).await;
+        assert!(matches!(closed, Err(Error::Closed)));
+    }
+
+    #[test]
+    fn turn_outcome_keeps_status_and_error_reason() {
+        let completed =
+            json!({ "method": "turn/completed", "params": { "turn": { "status": "completed" } } });
+        let outcome = turn_outcome(&completed).unwrap();
+        assert!(outcome.success());
+        assert_eq!((outcome.exit_code(), outcome.reason), (None, None));
+
+        let failed = json!({ "method": "turn/completed", "params": { "turn": {
+            "status": "failed",
+            "error": { "message": "", "codexErrorInfo": "contextWindowExceeded" },
+        } } });
+        assert_eq!(
+            turn_outcome(&failed),
+            Some(TurnOutcome {
+                status: "failed".to_string(),
+                reason: Some("contextWindowExceeded".to_string())
+            })
+        );
+        assert_eq!(turn_outcome(&failed).unwrap().exit_code(), Some(1));
+
+        let interrupted = json!({ "method": "turn/completed", "params": { "turn": {
+            "status": "interrupted",
+            "error": { "message": "stream disconnected", "codexErrorInfo": { "responseStreamDisconnected": {} } },
+        } } });
+        let outcome = turn_outcome(&interrupted).unwrap();
+        assert_eq!(outcome.exit_code(), None);
+        assert_eq!(outcome.reason.as_deref(), Some("stream disconnected"));
+        assert!(turn_outcome(&json!({ "method": "turn/started" })).is_none());
+    }
+
+    #[tokio::test]
+    async fn read_next_json_line_joins_a_record_split_across_lines() {
+        let (reader, mut writer) = tokio::io::duplex(1024);
+        writer
+            .write_all(
+                b"{\"method\":\"item/completed\",\"params\":{\"diff\":\"+a\n+b\"}}\n{\"id\":\n2}\n",
+            )
+            .await
+            .unwrap();
+        drop(writer);
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+
+        let (raw, json) = read_next_json_line(&mut lines, &mut cancel_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            raw,
+            r#"{"method":"item/completed","params":{"diff":"+a\n+b"}}"#
+        );
+        assert_eq!(json["params"]["diff"], "+a\n+b");
+        let (_, json) = read_next_json_line(&mut lines, &mut cancel_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(json["id"], 2);
+        assert!(read_next_json_line(&mut lines, &mut cancel_rx)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn json_scan_tracks_strings_and_nesting() {
+        let mut scan = JsonScan::default();
+        scan.feed(r#"{"a":["}\"{","#);
+        assert!(scan.is_open() && !scan.in_string && scan.depth == 2);
+        scan.feed(r#""x"#);
+        assert!(scan.in_string);
+        scan.feed(r#"y"]}"#);
+        assert!(!scan.is_open());
+    }
+
+    #[tokio::test]
+    async fn read_next_json_line_reports_cancellation() {
+        let (reader, _writer) = tokio::io::duplex(64);
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let (cancel_tx, mut cancel_rx) = oneshot::channel();
+        cancel_tx.send(()).unwrap();
+        let err = read_next_json_line(&mut lines, &mut cancel_rx)
+            .await
+            .unwrap_err();
+        assert!(err.is_cancelled());
+        assert_eq!(err.to_string(), "cancelled");
+    }
+
+    /// Records what a turn hands its sink, answering approvals with `decision` or leaving them
+    /// pending when it's unset.
+    #[derive(Default)]
+    struct Turn {
+        notifications: Vec<String>,
+        thread: Option<String>,
+        approvals: Vec<String>,
+        decision: Option<&'static str>,
+    }
+
+    impl NotificationSink for Turn {
+        async fn notification(&mut self, _raw: &str, json: &Value) {
+            self.notifications
+                .push(json["method"].as_str().unwrap_or_default().to_string());
+        }
+    }
+
+    impl TurnSink for Turn {
+        async fn thread_ready(&mut self, thread_id: &str) {
+            self.thread = Some(thread_id.to_string());
+        }
+
+        async fn turn_notification(&mut self, raw: &str, json: &Value) -> ControlFlow<()> {
+            self.notification(raw, json).await;
+            ControlFlow::Continue(())
+        }
+
+        async fn approval_request(
+            &mut self,
+            _raw: &str,
+            _json: &Value,
+            request_id: &str,
+        ) -> Option<String> {
+            self.approvals.push(request_id.to_string());
+            self.decision.map(str::to_string)
+        }
+    }
+
+    #[tokio::test]
+    async fn run_turn_falls_back_to_a_new_thread_and_answers_approvals() {
+        let stdout = concat!(
+            r#"{"id":2,"error":{"message":"no rollout"}}"#,
+            "\n",
+            r#"{"method":"thread/started","params":{}}"#,
+            "\n",
+            r#"{"id":3,"result":{"thread":{"id":"t2"},"model":"gpt-5"}}"#,
+            "\n",
+            r#"{"id":4,"result":{"turn":{"id":9}}}"#,
+            "\n",
+            r#"{"id":"a1","method":"execCommandApproval","params":{}}"#,
+            "\n",
+            r#"{"method":"turn/completed","params":{"turn":{"status":"completed"}}}"#,
+            "\n",
+        );
+        let mut lines = tokio::io::BufReader::new(stdout.as_bytes()).lines();
+        let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+        let mut stdin = Vec::new();
+        let mut sink = Turn {
+            decision: Some("approved"),
+            ..Turn::default()
+        };
+        let request = TurnRequest {
+            thread_id: Some("t1".to_string()),
+            thread_params: json!({ "cwd": "/tmp" }),
+            input: json!([{ "type": "text", "text": "hi" }]),
+            idle_timeout: None,
+            first_request_id: 2,
+        };
+
+        let report = run_turn(
+            &mut stdin,
+            &mut lines,
+            &mut cancel_rx,
+            None,
+            &mut sink,
+            request,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (report.thread_id.as_str(), report.model.as_deref()),
+            ("t2", Some("gpt-5"))
+        );
+        assert!(matches!(report.end, TurnEnd::Completed(ref o) if o.success()));
+        assert_eq!(sink.thread.as_deref(), Some("t2"));
+        assert_eq!(sink.approvals, vec!["a1"]);
+        assert_eq!(sink.notifications, vec!["thread/started", "turn/completed"]);
+
+        let sent: Vec<Value> = stdin
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_slice(l).unwrap())
+            .collect();
+        assert_eq!(sent[0]["method"], "thread/resume");
+        assert_eq!(
+            sent[0]["params"],
+            json!({ "cwd": "/tmp", "threadId": "t1" })
+        );
+        assert_eq!(
+            (sent[1]["id"].as_i64(), sent[1]["method"].as_str()),
+            (Some(3), Some("thread/start"))
+        );
+        assert_eq!(sent[2]["params"]["threadId"], "t2");
+        assert_eq!(
+            sent[3],
+            json!({ "id": "a1", "result": { "decision": "approved" } })
+        );
+        assert_eq!(sent.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn run_turn_interrupts_a_turn_that_goes_idle() {
+        let (reader, mut writer) = tokio::io::duplex(1024);
+        let stdout = concat!(
+            r#"{"id":1,"result":{"thread":{"id":"t1"}}}"#,
+            "\n",
+            r#"{"id":2,"result":{"turn":{"id":"u1"}}}"#,
+            "\n",
+        );
+        writer.write_all(stdout.as_bytes()).await.unwrap();
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+        let (answer_tx, mut answers) = mpsc::unbounded_channel();
+        // An answer for a request that isn't pending is dropped.
+        answer_tx
+            .send(("unknown".to_string(), "approved".to_string()))
+            .unwrap();
+        let mut stdin = Vec::new();
+        let request = TurnRequest {
+            thread_id: None,
+            thread_params: json!({}),
+            input: json!([]),
+            idle_timeout: Some(Duration::from_millis(20)),
+            first_request_id: 1,
+        };
+
+        let report = run_turn(
+            &mut stdin,
+            &mut lines,
+            &mut cancel_rx,
+            Some(&mut answers),
+            &mut Turn::default(),
+            request,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.end, TurnEnd::TimedOut);
+        let sent: Vec<Value> = stdin
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_slice(l).unwrap())
+            .collect();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(
+            sent[2],
+            json!({ "id": 3, "method": "turn/interrupt", "params": { "threadId": "t1", "turnId": "u1" } })
+        );
+    }
+
+    #[tokio::test]
+    async fn run_turn_keeps_a_split_record_when_an_approval_is_answered() {
+        let (reader, mut writer) = tokio::io::duplex(4096);
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+        let (answer_tx, mut answers) = mpsc::unbounded_channel();
+        let mut stdin = Vec::new();
+        let mut sink = Turn::default();
+        let request = TurnRequest {
+            thread_id: None,
+            thread_params: json!({}),
+            input: json!([]),
+            idle_timeout: None,
+            first_request_id: 1,
+        };
+        let drive = async {
+            let head = concat!(
+                r#"{"id":1,"result":{"thread":{"id":"t1"}}}"#,
+                "\n",
+                r#"{"id":2,"result":{"turn":{"id":"u1"}}}"#,
+                "\n",
+                r#"{"id":"a1","method":"execCommandApproval","params":{}}"#,
+                "\n",
+                r#"{"method":"item/completed","params":{"diff":"+a"#,
+                "\n",
+            );
+            writer.write_all(head.as_bytes()).await.unwrap();
+            // The answer arrives while the turn is joining the split record.
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            answer_tx
+                .send(("a1".to_string(), "approved".to_string()))
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let tail = concat!(
+                r#"+b"}}"#,
+                "\n",
+                r#"{"method":"turn/completed","params":{"turn":{"status":"completed"}}}"#,
+                "\n",
+            );
+            writer.write_all(tail.as_bytes()).await.unwrap();
+        };
+
+        let (report, ()) = tokio::join!(
+            run_turn(
+                &mut stdin,
+                &mut lines,
+                &mut cancel_rx,
+                Some(&mut answers),
+                &mut sink,
+                request
+            ),
+            drive
+        );
+        assert!(matches!(report.unwrap().end, TurnEnd::Completed(ref o) if o.success()));
+        assert_eq!(sink.notifications, vec!["item/completed", "turn/completed"]);
+        let sent: Vec<Value> = stdin
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_slice(l).unwrap())
+            .collect();
+        assert_eq!(
+            sent[2],
+            json!({ "id": "a1", "result": { "decision": "approved" } })
+        );
+    }
+
+    #[tokio::test]
+    async fn run_turn_pauses_the_idle_timeout_while_an_approval_is_pending() {
+        let (reader, mut writer) = tokio::io::duplex(4096);
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+        let (answer_tx, mut answers) = mpsc::unbounded_channel();
+        let mut stdin = Vec::new();
+        let mut sink = Turn::default();
+        let request = TurnRequest {
+            thread_id: None,
+            thread_params: json!({}),
+            input: json!([]),
+            idle_timeout: Some(Duration::from_millis(30)),
+            first_request_id: 1,
+        };
+        let drive = async {
+            let head = concat!(
+                r#"{"id":1,"result":{"thread":{"id":"t1"}}}"#,
+                "\n",
+                r#"{"id":2,"result":{"turn":{"id":"u1"}}}"#,
+                "\n",
+                r#"{"id":"a1","method":"execCommandApproval","params":{}}"#,
+                "\n",
+            );
+            writer.write_all(head.as_bytes()).await.unwrap();
+            // The user takes several idle timeouts to decide.
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            answer_tx
+                .send(("a1".to_string(), "denied".to_string()))
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let completed =
+                r#"{"method":"turn/completed","params":{"turn":{"status":"completed"}}}"#;
+            writer
+                .write_all(format!("{completed}\n").as_bytes())
+                .await
+                .unwrap();
+        };
+
+        let (report, ()) = tokio::join!(
+            run_turn(
+                &mut stdin,
+                &mut lines,
+                &mut cancel_rx,
+                Some(&mut answers),
+                &mut sink,
+                request
+            ),
+            drive
+        );
+        assert!(matches!(report.unwrap().end, TurnEnd::Completed(_)));
+    }
+}
@@ -1,20 +1,34 @@
 use anyhow::Context;
 use async_stream::stream;
 use axum::{
-    extract::{Path as AxumPath, Query, State},
-    http::{HeaderMap, StatusCode},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Path as AxumPath, Query, Request, State,
+    },
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
     response::{
         sse::{Event, KeepAlive, Sse},
         IntoResponse, Response,
     },
-    routing::{delete, get, post},
+    routing::{get, post},
     Json, Router,
 };
 use clap::Parser;
+use codex_protocol::{
+    capture_agent_message_text, codex_auth_from_json, extract_token_usage_snapshot,
+    is_agent_message_completed, is_unpersisted_notification, json_u64, jsonrpc_id_matches,
+    jsonrpc_result, parse_approval_decision, parse_reasoning_effort, read_next_json_line,
+    render_conclusion, strip_tool_citations, utc_day, write_jsonrpc_request, ConclusionFields,
+    NotificationSink, TokenUsageSnapshot, TurnEnd, TurnRequest, TurnSink, TurnStartError,
+};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, VecDeque},
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque},
     net::SocketAddr,
+    ops::ControlFlow,
     path::{Path, PathBuf},
     process::Stdio,
     sync::Arc,
@@ -22,19 +36,47 @@ use std::{
 };
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
-    process::{ChildStdin, ChildStdout, Command},
+    process::{ChildStdout, Command},
     sync::{broadcast, oneshot, Mutex},
     time::{timeout, Duration},
 };
-use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
 use tower_http::{
-    cors::CorsLayer,
+    compression::{
+        predicate::{DefaultPredicate, NotForContentType, Predicate},
+        CompressionLayer,
+    },
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer},
     services::{ServeDir, ServeFile},
     trace::TraceLayer,
 };
-use tracing::info;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+/// Parses a `--cors-origin` value, dropping any trailing slash so it matches the `Origin` header.
+fn parse_cors_origin(raw: &str) -> Result<String, String> {
+    let origin = raw.trim().trim_end_matches('/');
+    if !(origin.starts_with("http://") || origin.starts_with("https://")) {
+        return Err(format!(
+            "expected an origin like http://host:port, got {raw:?}"
+        ));
+    }
+    HeaderValue::from_str(origin).map_err(|e| e.to_string())?;
+    Ok(origin.to_string())
+}
+
+/// Parses a `--codex-env KEY=VALUE` pair.
+fn parse_codex_env(raw: &str) -> Result<(String, String), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{raw}`"))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("environment variable name must not be empty".to_string());
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "codex-warp-server")]
 struct Args {
@@ -57,9 +99,98 @@ struct Args {
     /// Path to the built web UI directory (Vite `dist/`). If present, the server will host it.
     #[arg(long)]
     web_dist: Option<String>,
+
+    /// Periodically write the in-progress agent message to conclusion.md during a turn, every N
+    /// seconds (and whenever an agent message completes). Disabled by default.
+    #[arg(long)]
+    conclusion_flush_secs: Option<u64>,
+
+    /// Maximum number of warp sessions to keep; the least recently used finished sessions are
+    /// deleted to make room for new ones. Unlimited by default.
+    #[arg(long)]
+    max_sessions: Option<usize>,
+
+    /// Interrupt a turn when the app-server sends nothing for N seconds (idle timeout, reset on
+    /// every line). Disabled by default.
+    #[arg(long = "turn-timeout")]
+    turn_timeout_secs: Option<u64>,
+
+    /// Seconds to wait for codex app-server to exit after a turn before killing it. The
+    /// app-server flushes its rollout file on shutdown, so a short wait can truncate it.
+    #[arg(long = "child-exit-timeout", default_value_t = 5)]
+    child_exit_timeout_secs: u64,
+
+    /// Broadcast a `codex_heartbeat` event every N seconds while a turn runs, so clients can tell
+    /// a slow turn from a hung one. 0 disables it.
+    #[arg(long = "heartbeat", default_value_t = 15)]
+    heartbeat_secs: u64,
+
+    /// How many times to respawn codex app-server when its `initialize` fails (e.g. while it
+    /// refreshes credentials). 0 disables retries.
+    #[arg(long, default_value_t = 2)]
+    init_retries: u32,
+
+    /// How to answer the app-server's command/patch approval requests (`approved` or `denied`);
+    /// they only arrive when codex's approval policy isn't `never`.
+    #[arg(long, default_value = "denied", value_parser = parse_approval_decision)]
+    approval_decision: String,
+
+    /// Per-session SSE broadcast buffer, in events. Subscribers that fall further behind than
+    /// this are told how many events they missed.
+    #[arg(long = "stream-buffer", default_value_t = 4096)]
+    stream_buffer: usize,
+
+    /// Extra environment for the codex child process, as KEY=VALUE (repeatable). Overrides
+    /// variables inherited from this server's environment.
+    #[arg(long = "codex-env", value_parser = parse_codex_env)]
+    codex_env: Vec<(String, String)>,
+
+    /// Template for conclusion.md with `{prompt}`, `{answer}`, `{model}`, `{tokens}` and `{date}`
+    /// placeholders. Without it the file holds just the final answer.
+    #[arg(long)]
+    conclusion_template: Option<String>,
+
+    /// Require `Authorization: Bearer <TOKEN>` on all /api routes (/healthz stays open)
+    #[arg(long, env = "CODEX_WARP_TOKEN")]
+    auth_token: Option<String>,
+
+    /// POST `{session_id, success, exit_code, conclusion}` here whenever a turn finishes.
+    /// Requests can override it with their own `webhook` on a `--webhook-host`.
+    #[arg(long, value_parser = parse_webhook_arg)]
+    webhook_url: Option<String>,
+
+    /// Host a request's own `webhook` may point at (repeatable). Without any, per-request
+    /// webhooks are refused so API callers can't make the server POST to arbitrary addresses.
+    #[arg(long = "webhook-host")]
+    webhook_hosts: Vec<String>,
+
+    /// Sign webhook bodies with HMAC-SHA256 under this secret, sent as
+    /// `X-Codex-Warp-Signature: sha256=<hex>`.
+    #[arg(long, env = "CODEX_WARP_WEBHOOK_SECRET")]
+    webhook_secret: Option<String>,
+
+    /// Browser origin allowed to call the API with credentials, e.g. `http://localhost:5173`
+    /// (repeatable). Without any, every origin is allowed.
+    #[arg(long = "cors-origin", value_parser = parse_cors_origin)]
+    cors_origins: Vec<String>,
+
+    /// Instructions sent ahead of every turn's prompt (e.g. coding style, directories not to
+    /// touch), for sessions that haven't pinned their own.
+    #[arg(long)]
+    instructions: Option<String>,
+
+    /// Drop usage.jsonl records older than N days, checked whenever a turn records its usage.
+    /// Kept forever by default.
+    #[arg(long = "usage-retention-days")]
+    usage_retention_days: Option<u64>,
+
+    /// Reject turn prompts larger than this many bytes with 413 instead of starting a turn that
+    /// would overflow the model's context. 0 means unlimited.
+    #[arg(long, default_value_t = 1024 * 1024)]
+    max_prompt_bytes: usize,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 enum SessionStatus {
     Running,
@@ -86,6 +217,65 @@ struct SessionMeta {
     events_path: String,
     stderr_path: String,
     conclusion_path: String,
+    /// Where a native session came from (rollout `source`, falling back to `originator`).
+    /// `None` for sessions created by this server.
+    #[serde(default)]
+    origin: Option<String>,
+    #[serde(default)]
+    git_branch: Option<String>,
+    #[serde(default)]
+    ide_workspace: Option<String>,
+    /// Model requested for this session's runs; `None` uses codex's configured default.
+    #[serde(default)]
+    model: Option<String>,
+    /// `model_reasoning_effort` for this session's threads; `None` uses codex's default.
+    #[serde(default)]
+    reasoning_effort: Option<String>,
+    /// `CODEX_HOME` for this session's runs; `None` inherits the server's environment.
+    #[serde(default)]
+    codex_home: Option<String>,
+    /// Turns run in this session by this server, including cancelled ones that used tokens.
+    #[serde(default)]
+    turn_count: u32,
+    /// Sum of the tokens each of those turns used, as recorded in usage.jsonl.
+    #[serde(default)]
+    cumulative_tokens: u64,
+    /// Whether the latest turn ran with `read_only`.
+    #[serde(default)]
+    last_turn_read_only: bool,
+    /// Pinned guidance sent ahead of every turn's prompt; overrides `--instructions`.
+    #[serde(default)]
+    instructions: Option<String>,
+    /// Freeform note (e.g. "waiting on review"). Native-only sessions keep theirs in
+    /// `data_dir/notes/<id>.txt`.
+    #[serde(default)]
+    note: Option<String>,
+    /// Archived sessions live under `data_dir/archived` and are hidden from listings by default.
+    #[serde(default)]
+    archived: bool,
+    /// Last API rate-limit snapshot seen during this session's runs.
+    #[serde(default)]
+    rate_limit: Option<RateLimitStatus>,
+    /// Latest codex rollout file for `codex_session_id`, when the native index has one. Filled in
+    /// on read; for native sessions this is the same file as `events_path`.
+    #[serde(default)]
+    rollout_path: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct RateLimitStatus {
+    used_pct: f64,
+    reset_at_ms: Option<u64>,
+    /// Length of the rate-limit window in minutes.
+    window: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+struct RateLimitUpdate {
+    session_id: String,
+    ts_ms: u64,
+    #[serde(flatten)]
+    status: RateLimitStatus,
 }
 
 #[derive(Clone, Serialize)]
@@ -103,6 +293,13 @@ struct RunFinished {
     ts_ms: u64,
     exit_code: Option<i32>,
     success: bool,
+    /// Codex thread id to pass when continuing this session, so clients needn't re-fetch meta.
+    codex_session_id: Option<String>,
+    /// The turn's raw status (`completed`, `interrupted`, `failed`, ...), or `cancelled`, `timed_out`
+    /// or `error` when the turn didn't complete on its own.
+    status: Option<String>,
+    /// Why the turn failed: codex's error message or kind, or the server's own error.
+    reason: Option<String>,
 }
 
 #[derive(Clone, Serialize)]
@@ -114,6 +311,63 @@ struct ContextMetrics {
     context_window: u64,
 }
 
+/// What the app-server reported in its `initialize` result, sent once per run as
+/// `codex_capabilities` so the UI can hide features the installed codex lacks.
+#[derive(Clone, Serialize)]
+struct CodexCapabilities {
+    session_id: String,
+    ts_ms: u64,
+    capabilities: serde_json::Value,
+}
+
+/// Liveness signal sent as `codex_heartbeat` while a turn is running; never persisted.
+#[derive(Clone, Serialize)]
+struct Heartbeat {
+    session_id: String,
+    ts_ms: u64,
+    elapsed_ms: u64,
+}
+
+/// An approval request from the app-server and the answer the server gave it, sent as
+/// `codex_approval_request`.
+#[derive(Clone, Serialize)]
+struct ApprovalRequest {
+    session_id: String,
+    ts_ms: u64,
+    request_id: serde_json::Value,
+    method: String,
+    params: serde_json::Value,
+    decision: String,
+}
+
+#[derive(Clone, Serialize)]
+struct ReasoningUpdate {
+    session_id: String,
+    ts_ms: u64,
+    item_id: Option<String>,
+    summary: String,
+}
+
+/// A finished command execution or file change, sent as `codex_tool` for tool-call timelines.
+#[derive(Serialize)]
+struct ToolUpdate {
+    session_id: String,
+    #[serde(flatten)]
+    event: NormalizedEvent,
+}
+
+#[derive(Clone, Serialize)]
+struct ContextCompacted {
+    session_id: String,
+    ts_ms: u64,
+    before_tokens: Option<u64>,
+    after_tokens: Option<u64>,
+    context_window: Option<u64>,
+    /// True when no explicit protocol signal was seen and compaction was inferred from a drop in
+    /// context usage between token-usage snapshots.
+    inferred: bool,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 struct UsageRecord {
     ts_ms: u64,
@@ -125,6 +379,8 @@ struct UsageRecord {
     reasoning_output_tokens: u64,
     cached_input_tokens: u64,
     context_window: u64,
+    #[serde(default)]
+    model: Option<String>,
 }
 
 #[derive(Clone, Serialize)]
@@ -134,15 +390,66 @@ struct SkillSummary {
     path: String,
 }
 
+/// A skill's parsed SKILL.md plus whatever keeps it from being usable, for skill editors.
+#[derive(Serialize)]
+struct SkillPreview {
+    name: String,
+    description: String,
+    body: String,
+    valid: bool,
+    errors: Vec<String>,
+}
+
 struct RunHandle {
     cancel: Option<oneshot::Sender<()>>,
     pid: Option<u32>,
+    started_at_ms: u64,
+    /// Notified when this turn finishes (see `spawn_run_webhook`).
+    webhook: Option<String>,
 }
 
 #[derive(Clone)]
 struct SseMessage {
     event: &'static str,
+    ts_ms: u64,
     data: String,
+    /// Whether this event is also in the session's backlog, and so gets an SSE id.
+    persisted: bool,
+}
+
+/// Hands out SSE event ids derived from event timestamps (`ts_ms * 1000`, bumped to stay strictly
+/// increasing), so the same persisted event gets the same id whether it arrives live or is
+/// replayed from the backlog after a reconnect. Live-only events take no id, or they would shift
+/// the numbering of the persisted ones after them.
+#[derive(Default)]
+struct EventSeq {
+    last: u64,
+}
+
+impl EventSeq {
+    fn next(&mut self, ts_ms: u64) -> u64 {
+        let base = ts_ms.saturating_mul(1000);
+        self.last = if base > self.last {
+            base
+        } else {
+            self.last + 1
+        };
+        self.last
+    }
+}
+
+/// Numbers the backlog with [`EventSeq`] and drops entries the client already saw, i.e. those at
+/// or below its `Last-Event-ID`.
+fn resume_backlog(
+    backlog: Vec<UiEvent>,
+    seq: &mut EventSeq,
+    last_event_id: Option<u64>,
+) -> Vec<(u64, UiEvent)> {
+    backlog
+        .into_iter()
+        .map(|evt| (seq.next(evt.ts_ms), evt))
+        .filter(|(id, _)| last_event_id.is_none_or(|last| *id > last))
+        .collect()
 }
 
 #[derive(Clone)]
@@ -151,24 +458,126 @@ struct AppState {
     codex_path: Option<PathBuf>,
     codex_home: Option<PathBuf>,
     runs: Arc<Mutex<HashMap<String, RunHandle>>>,
+    /// Per-session locks serializing turn starts, so the "already running" check and the `runs`
+    /// insert happen atomically.
+    session_locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    /// `Idempotency-Key` → (session id, first seen at ms) for `start_session` retries.
+    idempotency_keys: Arc<Mutex<HashMap<String, (String, u64)>>>,
     streams: Arc<Mutex<HashMap<String, broadcast::Sender<SseMessage>>>>,
     native_cache: Arc<Mutex<NativeCache>>,
+    stop_on_disconnect: Arc<Mutex<HashSet<String>>>,
+    /// Native sessions whose rollout is being tailed for subscribers (see `ensure_rollout_watcher`).
+    rollout_watchers: Arc<Mutex<HashSet<String>>>,
+    rollout_poll_interval: Duration,
+    conclusion_flush_interval: Option<Duration>,
+    max_sessions: Option<usize>,
+    turn_timeout: Option<Duration>,
+    stream_buffer: usize,
+    child_exit_timeout: Duration,
+    heartbeat_interval: Option<Duration>,
+    /// Extra attempts at spawn+`initialize` after a non-cancel `initialize` failure.
+    init_retries: u32,
+    /// Automatic answer to approval requests, since nobody is there to ask.
+    approval_decision: String,
+    codex_env: Vec<(String, String)>,
+    conclusion_template: Option<String>,
+    auth_token: Option<String>,
+    /// Origins allowed by CORS; empty allows any origin.
+    cors_origins: Vec<String>,
+    /// Default webhook for finished turns.
+    webhook_url: Option<String>,
+    /// Hosts a per-request webhook may target; empty refuses them.
+    webhook_hosts: Vec<String>,
+    webhook_secret: Option<String>,
+    /// Shared by all webhook deliveries.
+    http_client: reqwest::Client,
+    /// `initialize` result from the most recently started app-server, for `/api/status`.
+    last_capabilities: Arc<Mutex<Option<serde_json::Value>>>,
+    usage_retention: Option<Duration>,
+    /// `--instructions`, used when a session has none of its own.
+    default_instructions: Option<String>,
+    /// Serializes appends to usage.jsonl with the rewrites that prune or clear it.
+    usage_lock: Arc<Mutex<()>>,
+    max_prompt_bytes: Option<usize>,
 }
 
 #[derive(Clone)]
 struct NativeCache {
     built_at_ms: u64,
     rollouts_by_session: HashMap<String, Vec<PathBuf>>,
+    /// mtime of each session's latest rollout, captured during the scan so "most recent" queries
+    /// can rank sessions without deriving all of them.
+    latest_mtime_by_session: HashMap<String, u64>,
     derived_by_session: HashMap<String, NativeDerived>,
+    /// Monotonic counter stamped onto derived entries on every hit/insert for LRU eviction.
+    derived_clock: u64,
+    /// Directory listings from the last rollout scan, reused while a directory's mtime is unchanged.
+    scanned_dirs: HashMap<PathBuf, ScannedDir>,
+}
+
+/// One directory of a rollout tree as of the last scan.
+#[derive(Clone)]
+struct ScannedDir {
+    mtime: std::time::SystemTime,
+    /// When the listing was read; a directory modified within `SCAN_MTIME_SLACK` of it is read
+    /// again, since coarse mtimes can't tell whether the change came before or after.
+    read_at: std::time::SystemTime,
+    subdirs: Vec<PathBuf>,
+    rollouts: Vec<(String, PathBuf)>,
+}
+
+/// Upper bound on cached per-session rollout derivations.
+const NATIVE_DERIVED_CACHE_CAP: usize = 1000;
+
+/// How many native sessions `collect_sessions` derives concurrently.
+const NATIVE_DERIVE_CONCURRENCY: usize = 16;
+
+impl NativeCache {
+    /// Returns the cached derivation when it still matches the rollout path and mtime.
+    fn get_derived(
+        &mut self,
+        session_id: &str,
+        latest_path: &Path,
+        latest_mtime_ms: u64,
+    ) -> Option<NativeDerived> {
+        self.derived_clock += 1;
+        let clock = self.derived_clock;
+        let cached = self.derived_by_session.get_mut(session_id)?;
+        if cached.latest_path != latest_path || cached.latest_mtime_ms != latest_mtime_ms {
+            return None;
+        }
+        cached.last_access = clock;
+        Some(cached.clone())
+    }
+
+    fn insert_derived(&mut self, session_id: String, mut derived: NativeDerived, cap: usize) {
+        self.derived_clock += 1;
+        derived.last_access = self.derived_clock;
+        self.derived_by_session.insert(session_id, derived);
+        while self.derived_by_session.len() > cap {
+            let Some(oldest) = self
+                .derived_by_session
+                .iter()
+                .min_by_key(|(_, d)| d.last_access)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            self.derived_by_session.remove(&oldest);
+        }
+    }
 }
 
 #[derive(Clone)]
 struct NativeDerived {
     latest_path: PathBuf,
     latest_mtime_ms: u64,
+    last_access: u64,
     cwd: Option<String>,
     originator: Option<String>,
     source: Option<String>,
+    git_branch: Option<String>,
+    ide_workspace: Option<String>,
     last_prompt: Option<String>,
 }
 
@@ -188,6 +597,48 @@ fn default_data_dir() -> Option<PathBuf> {
     Some(PathBuf::from(t).join(".codex-warp"))
 }
 
+/// An exclusive lock on `data_dir/.lock`, held while the server runs so a second server on the
+/// same data dir can't race this one on meta.json and usage.jsonl. The OS releases it when the
+/// file is closed, so a crashed server never leaves it stuck.
+struct DataDirLock {
+    _file: std::fs::File,
+}
+
+fn lock_data_dir(data_dir: &Path) -> anyhow::Result<DataDirLock> {
+    use std::io::Write;
+    let path = data_dir.join(".lock");
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("open {}", path.display()))?;
+    // Windows has no `flock`; there the lock file only records the pid.
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::WouldBlock {
+                return Err(err).with_context(|| format!("lock {}", path.display()));
+            }
+            let holder = std::fs::read_to_string(&path).unwrap_or_default();
+            let holder = match holder.trim() {
+                "" => String::new(),
+                pid => format!(" (pid {pid})"),
+            };
+            anyhow::bail!(
+                "{} is already in use by another codex-warp-server{holder}; stop it or pass a different --data-dir",
+                data_dir.display()
+            );
+        }
+    }
+    file.set_len(0)?;
+    writeln!(file, "{}", std::process::id())?;
+    Ok(DataDirLock { _file: file })
+}
+
 fn default_codex_home() -> Option<PathBuf> {
     if let Ok(raw) = std::env::var("CODEX_HOME") {
         let t = raw.trim();
@@ -257,23 +708,62 @@ fn unquote_yaml_scalar(raw: &str) -> String {
     t.to_string()
 }
 
-fn parse_skill_front_matter(text: &str) -> (Option<String>, Option<String>) {
-    let mut lines = text.lines();
+/// Splits a SKILL.md into its YAML front matter (between the leading `---` fences) and the
+/// instruction body after it. An unterminated front matter swallows the whole file.
+fn split_skill_front_matter(text: &str) -> (Option<&str>, &str) {
+    let mut lines = text.split_inclusive('\n');
     let Some(first) = lines.next() else {
-        return (None, None);
+        return (None, text);
     };
     if first.trim() != "---" {
-        return (None, None);
+        return (None, text);
+    }
+
+    let start = first.len();
+    let mut offset = start;
+    for line in lines {
+        if line.trim() == "---" {
+            return (Some(&text[start..offset]), &text[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (Some(&text[start..]), "")
+}
+
+/// Checks a SKILL.md for the front matter codex needs: `---` fences and a `name` field.
+fn skill_preview(name: &str, text: &str) -> SkillPreview {
+    let mut errors = Vec::new();
+    match split_skill_front_matter(text) {
+        (None, _) => errors.push("missing front matter".to_string()),
+        (Some(_), body) => {
+            if body.is_empty() && !text.lines().skip(1).any(|l| l.trim() == "---") {
+                errors.push("missing closing --- after front matter".to_string());
+            }
+        }
+    }
+    let (parsed_name, description) = parse_skill_front_matter(text);
+    if errors.is_empty() && parsed_name.is_none() {
+        errors.push("missing name field".to_string());
+    }
+    SkillPreview {
+        name: parsed_name.unwrap_or_else(|| name.to_string()),
+        description: description.unwrap_or_default(),
+        body: split_skill_front_matter(text).1.to_string(),
+        valid: errors.is_empty(),
+        errors,
     }
+}
+
+fn parse_skill_front_matter(text: &str) -> (Option<String>, Option<String>) {
+    let (Some(front), _) = split_skill_front_matter(text) else {
+        return (None, None);
+    };
 
     let mut name: Option<String> = None;
     let mut description: Option<String> = None;
 
-    for line in lines {
+    for line in front.lines() {
         let t = line.trim();
-        if t == "---" {
-            break;
-        }
         if t.is_empty() || t.starts_with('#') {
             continue;
         }
@@ -304,6 +794,27 @@ fn sessions_root(state: &AppState) -> PathBuf {
     state.data_dir.join("sessions")
 }
 
+fn archived_root(state: &AppState) -> PathBuf {
+    state.data_dir.join("archived")
+}
+
+fn archived_session_dir(state: &AppState, session_id: &str) -> PathBuf {
+    archived_root(state).join(session_id)
+}
+
+async fn archived_session_ids(state: &AppState) -> HashSet<String> {
+    let mut out = HashSet::new();
+    let Ok(mut rd) = tokio::fs::read_dir(archived_root(state)).await else {
+        return out;
+    };
+    while let Ok(Some(entry)) = rd.next_entry().await {
+        if entry.file_type().await.is_ok_and(|t| t.is_dir()) {
+            out.insert(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    out
+}
+
 fn session_dir(state: &AppState, session_id: &str) -> PathBuf {
     sessions_root(state).join(session_id)
 }
@@ -348,34 +859,212 @@ async fn ensure_stream(state: &AppState, session_id: &str) -> broadcast::Sender<
     if let Some(tx) = locked.get(session_id) {
         return tx.clone();
     }
-    let (tx, _rx) = broadcast::channel::<SseMessage>(4096);
+    let (tx, _rx) = broadcast::channel::<SseMessage>(state.stream_buffer);
     locked.insert(session_id.to_string(), tx.clone());
     tx
 }
 
-async fn broadcast_event(state: &AppState, session_id: &str, event: &'static str, data: String) {
+async fn broadcast_event(
+    state: &AppState,
+    session_id: &str,
+    event: &'static str,
+    ts_ms: u64,
+    data: String,
+) {
     let tx = ensure_stream(state, session_id).await;
-    let _ = tx.send(SseMessage { event, data });
+    let _ = tx.send(SseMessage {
+        event,
+        ts_ms,
+        data,
+        persisted: false,
+    });
 }
 
+/// Broadcasts an event that is live-only: it never shows up in the backlog.
 async fn broadcast_ui_event(state: &AppState, payload: UiEvent) {
     if let Ok(data) = serde_json::to_string(&payload) {
-        broadcast_event(state, &payload.session_id, "codex_event", data).await;
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_event",
+            payload.ts_ms,
+            data,
+        )
+        .await;
+    }
+}
+
+/// Broadcasts an event already written to events.jsonl or a rollout, which a reconnecting client
+/// gets back from the backlog with the same id.
+async fn broadcast_persisted_event(state: &AppState, payload: UiEvent) {
+    if let Ok(data) = serde_json::to_string(&payload) {
+        let tx = ensure_stream(state, &payload.session_id).await;
+        let _ = tx.send(SseMessage {
+            event: "codex_event",
+            ts_ms: payload.ts_ms,
+            data,
+            persisted: true,
+        });
     }
 }
 
 async fn broadcast_run_finished(state: &AppState, payload: RunFinished) {
     if let Ok(data) = serde_json::to_string(&payload) {
-        broadcast_event(state, &payload.session_id, "codex_run_finished", data).await;
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_run_finished",
+            payload.ts_ms,
+            data,
+        )
+        .await;
     }
 }
 
 async fn broadcast_metrics(state: &AppState, payload: ContextMetrics) {
     if let Ok(data) = serde_json::to_string(&payload) {
-        broadcast_event(state, &payload.session_id, "codex_metrics", data).await;
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_metrics",
+            payload.ts_ms,
+            data,
+        )
+        .await;
+    }
+}
+
+async fn broadcast_capabilities(state: &AppState, payload: CodexCapabilities) {
+    if let Ok(data) = serde_json::to_string(&payload) {
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_capabilities",
+            payload.ts_ms,
+            data,
+        )
+        .await;
+    }
+}
+
+async fn broadcast_heartbeat(state: &AppState, payload: Heartbeat) {
+    if let Ok(data) = serde_json::to_string(&payload) {
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_heartbeat",
+            payload.ts_ms,
+            data,
+        )
+        .await;
+    }
+}
+
+/// Broadcasts a heartbeat every `interval` until the returned handle is aborted.
+fn spawn_heartbeat(
+    state: &AppState,
+    session_id: &str,
+    started: std::time::Instant,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    let state = state.clone();
+    let session_id = session_id.to_string();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        loop {
+            ticker.tick().await;
+            broadcast_heartbeat(
+                &state,
+                Heartbeat {
+                    session_id: session_id.clone(),
+                    ts_ms: now_ms(),
+                    elapsed_ms: started.elapsed().as_millis() as u64,
+                },
+            )
+            .await;
+        }
+    })
+}
+
+async fn broadcast_approval_request(state: &AppState, payload: ApprovalRequest) {
+    if let Ok(data) = serde_json::to_string(&payload) {
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_approval_request",
+            payload.ts_ms,
+            data,
+        )
+        .await;
+    }
+}
+
+async fn broadcast_reasoning(state: &AppState, payload: ReasoningUpdate) {
+    if let Ok(data) = serde_json::to_string(&payload) {
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_reasoning",
+            payload.ts_ms,
+            data,
+        )
+        .await;
+    }
+}
+
+async fn broadcast_tool(state: &AppState, payload: ToolUpdate) {
+    if let Ok(data) = serde_json::to_string(&payload) {
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_tool",
+            payload.event.ts_ms,
+            data,
+        )
+        .await;
+    }
+}
+
+async fn broadcast_rate_limit(state: &AppState, payload: RateLimitUpdate) {
+    if let Ok(data) = serde_json::to_string(&payload) {
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_rate_limit",
+            payload.ts_ms,
+            data,
+        )
+        .await;
+    }
+}
+
+async fn broadcast_context_compacted(state: &AppState, payload: ContextCompacted) {
+    if let Ok(data) = serde_json::to_string(&payload) {
+        broadcast_event(
+            state,
+            &payload.session_id,
+            "codex_context_compacted",
+            payload.ts_ms,
+            data,
+        )
+        .await;
     }
 }
 
+/// File names probed for the codex binary in each search directory, in preference order.
+#[cfg(windows)]
+const CODEX_BINARY_NAMES: &[&str] = &["codex.exe", "codex.cmd"];
+#[cfg(not(windows))]
+const CODEX_BINARY_NAMES: &[&str] = &["codex"];
+
+fn find_codex_in_dir(dir: &Path) -> Option<PathBuf> {
+    CODEX_BINARY_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|cand| is_executable(cand))
+}
+
+/// Windows has no executable bit, so any existing file counts there.
 fn is_executable(path: &Path) -> bool {
     if !path.is_file() {
         return false;
@@ -438,8 +1127,7 @@ fn resolve_codex_executable(state: &AppState) -> anyhow::Result<PathBuf> {
 
     if let Some(path_env) = std::env::var_os("PATH") {
         for dir in std::env::split_paths(&path_env) {
-            let cand = dir.join("codex");
-            if is_executable(&cand) {
+            if let Some(cand) = find_codex_in_dir(&dir) {
                 return Ok(cand);
             }
         }
@@ -448,33 +1136,275 @@ fn resolve_codex_executable(state: &AppState) -> anyhow::Result<PathBuf> {
     anyhow::bail!("codex executable not found on PATH (set --codex-path)")
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[derive(Serialize)]
+struct Semver {
+    major: u64,
+    minor: u64,
+    patch: u64,
+}
 
-    fn test_state(data_dir: PathBuf) -> AppState {
-        AppState {
-            data_dir,
-            codex_path: None,
-            codex_home: None,
-            runs: Arc::new(Mutex::new(HashMap::new())),
-            streams: Arc::new(Mutex::new(HashMap::new())),
-            native_cache: Arc::new(Mutex::new(NativeCache {
-                built_at_ms: 0,
-                rollouts_by_session: HashMap::new(),
-                derived_by_session: HashMap::new(),
-            })),
-        }
-    }
+#[derive(Serialize)]
+struct CodexVersion {
+    path: String,
+    /// Raw `codex --version` output, e.g. `codex-cli 0.46.0`.
+    version: String,
+    semver: Option<Semver>,
+}
 
-    #[tokio::test]
-    async fn extract_session_meta_triplet_falls_back_when_base_instructions_precedes_originator() {
-        let mut path = std::env::temp_dir();
-        path.push(format!("codex-warp-rollout-{}.jsonl", Uuid::new_v4()));
+const CODEX_VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pulls the first `X.Y.Z` (optionally `v`-prefixed, pre-release suffix ignored) out of a
+/// version string.
+fn parse_semver(text: &str) -> Option<Semver> {
+    text.split_whitespace().find_map(|token| {
+        let token = token.trim_start_matches('v');
+        let core = token.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+        let (major, minor, patch) = (parts.next()??, parts.next()??, parts.next()??);
+        Some(Semver {
+            major,
+            minor,
+            patch,
+        })
+    })
+}
 
-        let line = serde_json::json!({
-            "timestamp": "2026-01-01T00:00:00.000Z",
-            "type": "session_meta",
+async fn codex_version(State(state): State<AppState>) -> Result<Json<CodexVersion>, Response> {
+    let codex = resolve_codex_executable(&state)
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()).into_response())?;
+    let output = Command::new(&codex)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = match timeout(CODEX_VERSION_TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to run codex --version: {e}"),
+            )
+                .into_response())
+        }
+        Err(_) => {
+            return Err((
+                StatusCode::GATEWAY_TIMEOUT,
+                "codex --version timed out after 5s",
+            )
+                .into_response())
+        }
+    };
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Json(CodexVersion {
+        path: codex.to_string_lossy().to_string(),
+        semver: parse_semver(&version),
+        version,
+    }))
+}
+
+#[derive(Serialize)]
+struct CodexPreflight {
+    ok: bool,
+    /// `userAgent` reported by the app-server, e.g. `codex_cli_rs/0.46.0 (...)`.
+    version: Option<String>,
+    /// The raw `initialize` result.
+    capabilities: serde_json::Value,
+    error: Option<String>,
+}
+
+impl CodexPreflight {
+    fn failed(error: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            version: None,
+            capabilities: serde_json::Value::Null,
+            error: Some(error.into()),
+        }
+    }
+}
+
+const CODEX_PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Spawns `codex app-server`, performs only the `initialize` handshake and returns its result.
+async fn initialize_app_server(codex: &Path) -> anyhow::Result<serde_json::Value> {
+    let mut child = Command::new(codex)
+        .arg("app-server")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("failed to start codex app-server")?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .context("failed to capture app-server stdin")?;
+    let stdout = child
+        .stdout
+        .take()
+        .context("failed to capture app-server stdout")?;
+    let mut lines = BufReader::new(stdout).lines();
+    // Nothing cancels a preflight; the sender just has to outlive the read loop.
+    let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+
+    write_jsonrpc_request(
+        &mut stdin,
+        1,
+        "initialize",
+        serde_json::json!({
+            "clientInfo": {
+                "name": "codex-warp-server",
+                "version": env!("CARGO_PKG_VERSION"),
+            }
+        }),
+    )
+    .await?;
+    let result = loop {
+        let Some((_, json)) = read_next_json_line(&mut lines, &mut cancel_rx).await? else {
+            anyhow::bail!("codex app-server stdout closed");
+        };
+        if json.get("method").is_none() && jsonrpc_id_matches(&json, 1) {
+            break jsonrpc_result(&json);
+        }
+    };
+    let _ = child.kill().await;
+    Ok(result?)
+}
+
+async fn codex_preflight(State(state): State<AppState>) -> Json<CodexPreflight> {
+    let codex = match resolve_codex_executable(&state) {
+        Ok(c) => c,
+        Err(e) => return Json(CodexPreflight::failed(e.to_string())),
+    };
+    let preflight = match timeout(CODEX_PREFLIGHT_TIMEOUT, initialize_app_server(&codex)).await {
+        Ok(Ok(result)) => CodexPreflight {
+            ok: true,
+            version: result
+                .get("userAgent")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+            capabilities: result,
+            error: None,
+        },
+        Ok(Err(e)) => CodexPreflight::failed(format!("initialize failed: {e}")),
+        Err(_) => CodexPreflight::failed("codex app-server did not answer initialize within 10s"),
+    };
+    Json(preflight)
+}
+
+/// Login state from `$CODEX_HOME/auth.json`, so the UI can ask for `codex login` up front.
+#[derive(Serialize)]
+struct CodexAuthStatus {
+    logged_in: bool,
+    method: Option<String>,
+    expires_at_ms: Option<u64>,
+}
+
+async fn codex_auth_status(State(state): State<AppState>) -> Json<CodexAuthStatus> {
+    let auth = match state.codex_home.as_ref() {
+        Some(home) => tokio::fs::read(home.join("auth.json"))
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok()),
+        None => None,
+    };
+    let auth = codex_auth_from_json(auth.as_ref(), now_ms());
+    Json(CodexAuthStatus {
+        logged_in: auth.logged_in,
+        method: auth.method.map(str::to_string),
+        expires_at_ms: auth.expires_at_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A finished warp session stored under `dir`; tests override the fields they care about.
+    fn test_meta(id: &str, dir: &Path) -> SessionMeta {
+        SessionMeta {
+            id: id.to_string(),
+            title: id.to_string(),
+            created_at_ms: 1,
+            last_used_at_ms: 1,
+            cwd: None,
+            status: SessionStatus::Done,
+            codex_session_id: None,
+            context_window: None,
+            context_used_tokens: None,
+            context_left_pct: None,
+            events_path: dir.join("events.jsonl").to_string_lossy().to_string(),
+            stderr_path: dir.join("stderr.log").to_string_lossy().to_string(),
+            conclusion_path: dir.join("conclusion.md").to_string_lossy().to_string(),
+            origin: None,
+            git_branch: None,
+            ide_workspace: None,
+            model: None,
+            reasoning_effort: None,
+            codex_home: None,
+            turn_count: 0,
+            cumulative_tokens: 0,
+            last_turn_read_only: false,
+            instructions: None,
+            note: None,
+            archived: false,
+            rate_limit: None,
+            rollout_path: None,
+        }
+    }
+
+    fn test_state(data_dir: PathBuf) -> AppState {
+        AppState {
+            data_dir,
+            codex_path: None,
+            codex_home: None,
+            runs: Arc::new(Mutex::new(HashMap::new())),
+            session_locks: Arc::new(Mutex::new(HashMap::new())),
+            idempotency_keys: Arc::new(Mutex::new(HashMap::new())),
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            native_cache: Arc::new(Mutex::new(NativeCache {
+                built_at_ms: 0,
+                rollouts_by_session: HashMap::new(),
+                latest_mtime_by_session: HashMap::new(),
+                derived_by_session: HashMap::new(),
+                derived_clock: 0,
+                scanned_dirs: HashMap::new(),
+            })),
+            stop_on_disconnect: Arc::new(Mutex::new(HashSet::new())),
+            rollout_watchers: Arc::new(Mutex::new(HashSet::new())),
+            rollout_poll_interval: Duration::from_millis(20),
+            conclusion_flush_interval: None,
+            max_sessions: None,
+            turn_timeout: None,
+            stream_buffer: 4096,
+            child_exit_timeout: Duration::from_secs(5),
+            heartbeat_interval: None,
+            init_retries: 0,
+            approval_decision: "denied".to_string(),
+            codex_env: Vec::new(),
+            conclusion_template: None,
+            auth_token: None,
+            cors_origins: Vec::new(),
+            webhook_url: None,
+            webhook_hosts: Vec::new(),
+            webhook_secret: None,
+            http_client: webhook_client(),
+            last_capabilities: Arc::new(Mutex::new(None)),
+            usage_retention: None,
+            default_instructions: None,
+            usage_lock: Arc::new(Mutex::new(())),
+            max_prompt_bytes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn extract_session_meta_triplet_falls_back_when_base_instructions_precedes_originator() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("codex-warp-rollout-{}.jsonl", Uuid::new_v4()));
+
+        let line = serde_json::json!({
+            "timestamp": "2026-01-01T00:00:00.000Z",
+            "type": "session_meta",
             "payload": {
                 "cwd": "/tmp",
                 "base_instructions": { "text": "x" },
@@ -494,1628 +1424,9162 @@ mod tests {
         let _ = tokio::fs::remove_file(&path).await;
     }
 
+    async fn send(app: Router, uri: &str, auth: Option<&str>) -> StatusCode {
+        use tower::ServiceExt;
+        let mut req = axum::http::Request::builder().uri(uri);
+        if let Some(token) = auth {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        app.oneshot(req.body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
     #[tokio::test]
-    async fn list_sessions_sorts_by_last_used_then_created() {
+    async fn cors_only_allows_configured_origins() {
+        use tower::ServiceExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let mut state = test_state(data_dir.clone());
+        state.cors_origins = vec![parse_cors_origin("http://localhost:5173/").unwrap()];
+        assert!(parse_cors_origin("localhost:5173").is_err());
+        let app = api_router(state);
+
+        let allow_origin = |origin: &'static str| {
+            let app = app.clone();
+            async move {
+                let req = axum::http::Request::builder()
+                    .uri("/healthz")
+                    .header(header::ORIGIN, origin)
+                    .body(axum::body::Body::empty())
+                    .unwrap();
+                let res = app.oneshot(req).await.unwrap();
+                res.headers()
+                    .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                    .cloned()
+            }
+        };
+        assert_eq!(
+            allow_origin("http://localhost:5173").await.unwrap(),
+            "http://localhost:5173"
+        );
+        assert!(allow_origin("http://evil.example").await.is_none());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn multi_session_stream_interleaves_known_sessions() {
+        use tower::ServiceExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let (a, b) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+        for id in [&a, &b] {
+            tokio::fs::create_dir_all(session_dir(&state, id))
+                .await
+                .unwrap();
+        }
+        let app = api_router(state.clone());
+        assert_eq!(
+            send(app.clone(), "/api/stream?ids=nope", None).await,
+            StatusCode::BAD_REQUEST
+        );
+
+        let req = axum::http::Request::builder()
+            .uri(format!("/api/stream?ids={a},nope,{b}"))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        for (id, raw) in [(&a, "from a"), (&b, "from b")] {
+            broadcast_ui_event(
+                &state,
+                UiEvent {
+                    session_id: id.clone(),
+                    ts_ms: 1,
+                    stream: "stdout".to_string(),
+                    raw: raw.to_string(),
+                    json: None,
+                },
+            )
+            .await;
+        }
+        let mut body = res.into_body().into_data_stream();
+        let mut text = String::new();
+        while !(text.contains("from a") && text.contains("from b")) {
+            let chunk = timeout(Duration::from_secs(2), body.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            text.push_str(&String::from_utf8_lossy(&chunk));
+        }
+        assert!(text.contains(&a) && text.contains(&b));
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn auth_token_guards_api_routes() {
         let mut data_dir = std::env::temp_dir();
         data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
         tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.auth_token = Some("s3cret".to_string());
+        let app = api_router(state);
+
+        assert_eq!(send(app.clone(), "/healthz", None).await, StatusCode::OK);
+        assert_eq!(
+            send(app.clone(), "/api/sessions/count", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(app.clone(), "/api/sessions/count", Some("wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(app.clone(), "/api/sessions/count", Some("s3cret")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(app.clone(), "/api/status", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(app.clone(), "/api/status", Some("s3cret")).await,
+            StatusCode::OK
+        );
+
+        // EventSource can't set headers, so the stream route takes the token as a query param.
+        assert_eq!(
+            send(
+                app.clone(),
+                "/api/sessions/missing/stream?token=wrong",
+                None
+            )
+            .await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(
+                app.clone(),
+                "/api/sessions/missing/stream?token=s3cret",
+                None
+            )
+            .await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(app, "/api/sessions/count?token=s3cret", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
 
+    #[tokio::test]
+    async fn session_model_round_trips_through_meta() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
         let state = test_state(data_dir.clone());
+        let id = format!("s-{}", Uuid::new_v4());
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
 
-        let id_a = format!("s-{}", Uuid::new_v4());
-        let id_b = format!("s-{}", Uuid::new_v4());
+        assert_eq!(normalize_model(Some("  ".to_string())), None);
+        let meta = SessionMeta {
+            title: "M".to_string(),
+            model: normalize_model(Some(" gpt-5-codex ".to_string())),
+            ..test_meta(&id, &session_dir(&state, &id))
+        };
+        write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+        let loaded = read_meta(&meta_path(&state, &id)).await.unwrap();
+        assert_eq!(loaded.model.as_deref(), Some("gpt-5-codex"));
+        assert_eq!(
+            thread_config(loaded.model.as_deref(), None)["model"],
+            "gpt-5-codex"
+        );
 
-        tokio::fs::create_dir_all(session_dir(&state, &id_a))
-            .await
-            .unwrap();
-        tokio::fs::create_dir_all(session_dir(&state, &id_b))
+        // Metas written before the field existed still load.
+        let mut legacy = serde_json::to_value(&meta).unwrap();
+        legacy.as_object_mut().unwrap().remove("model");
+        tokio::fs::write(meta_path(&state, &id), legacy.to_string())
             .await
             .unwrap();
+        assert_eq!(
+            read_meta(&meta_path(&state, &id)).await.unwrap().model,
+            None
+        );
 
-        let a_dir = session_dir(&state, &id_a);
-        let b_dir = session_dir(&state, &id_b);
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
 
-        let meta_a = SessionMeta {
-            id: id_a.clone(),
-            title: "A".to_string(),
-            created_at_ms: 1_000,
-            last_used_at_ms: 2_000,
-            cwd: None,
-            status: SessionStatus::Done,
-            codex_session_id: None,
-            context_window: None,
-            context_used_tokens: None,
-            context_left_pct: None,
-            events_path: a_dir.join("events.jsonl").to_string_lossy().to_string(),
-            stderr_path: a_dir.join("stderr.log").to_string_lossy().to_string(),
-            conclusion_path: a_dir.join("conclusion.md").to_string_lossy().to_string(),
+    #[test]
+    fn turn_input_includes_image_attachments() {
+        let mut cwd = std::env::temp_dir();
+        cwd.push(format!("codex-warp-cwd-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&cwd).unwrap();
+        std::fs::write(cwd.join("shot.png"), b"png").unwrap();
+        let cwd_str = cwd.to_string_lossy().to_string();
+
+        let attachments = resolve_attachments(
+            Some(&cwd_str),
+            &[Attachment {
+                kind: AttachmentKind::Image,
+                path: "shot.png".to_string(),
+            }],
+        )
+        .unwrap();
+        let input = TurnInput {
+            prompt: "what is this?".to_string(),
+            instructions: None,
+            attachments,
+            read_only: false,
         };
-        write_meta(&meta_path(&state, &id_a), &meta_a).await.unwrap();
+        let image = std::fs::canonicalize(cwd.join("shot.png")).unwrap();
+        assert_eq!(
+            input.to_jsonrpc_input(),
+            serde_json::json!([
+                { "type": "text", "text": "what is this?" },
+                { "type": "localImage", "path": image.to_string_lossy() },
+            ])
+        );
 
-        // last_used_at_ms=0 should be treated as created_at_ms for ordering.
-        let meta_b = SessionMeta {
-            id: id_b.clone(),
-            title: "B".to_string(),
-            created_at_ms: 3_000,
-            last_used_at_ms: 0,
-            cwd: None,
-            status: SessionStatus::Done,
-            codex_session_id: None,
-            context_window: None,
-            context_used_tokens: None,
-            context_left_pct: None,
-            events_path: b_dir.join("events.jsonl").to_string_lossy().to_string(),
-            stderr_path: b_dir.join("stderr.log").to_string_lossy().to_string(),
-            conclusion_path: b_dir.join("conclusion.md").to_string_lossy().to_string(),
+        let escape = Attachment {
+            kind: AttachmentKind::File,
+            path: "../shot.png".to_string(),
         };
-        write_meta(&meta_path(&state, &id_b), &meta_b).await.unwrap();
+        assert!(resolve_attachments(Some(&cwd_str), &[escape]).is_err());
+        let missing = Attachment {
+            kind: AttachmentKind::Image,
+            path: "nope.png".to_string(),
+        };
+        assert!(resolve_attachments(Some(&cwd_str), &[missing]).is_err());
 
-        let Json(sessions) = list_sessions(State(state.clone())).await.unwrap();
-        assert_eq!(sessions.len(), 2);
-        assert_eq!(sessions[0].id, id_b);
-        assert_eq!(sessions[1].id, id_a);
+        let _ = std::fs::remove_dir_all(&cwd);
+    }
 
-        let b = sessions.iter().find(|s| s.id == id_b).unwrap();
-        assert_eq!(b.last_used_at_ms, 3_000);
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn initialize_app_server_returns_handshake_result() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut script = std::env::temp_dir();
+        script.push(format!("codex-warp-fake-codex-{}", Uuid::new_v4()));
+        let body = r#"#!/bin/sh
+read line
+echo '{"method":"account/rateLimits/updated","params":{}}'
+echo '{"id":1,"result":{"userAgent":"codex_cli_rs/0.46.0"}}'
+sleep 5
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let result = initialize_app_server(&script).await.unwrap();
+        assert_eq!(result["userAgent"], "codex_cli_rs/0.46.0");
+
+        let _ = tokio::fs::remove_file(&script).await;
+    }
 
-        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    #[cfg(windows)]
+    #[test]
+    fn find_codex_in_dir_probes_windows_binary_names() {
+        let dir = std::env::temp_dir().join(format!("codex-warp-win-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(find_codex_in_dir(&dir), None);
+
+        std::fs::write(dir.join("codex.cmd"), "@echo off\r\n").unwrap();
+        assert_eq!(find_codex_in_dir(&dir), Some(dir.join("codex.cmd")));
+        std::fs::write(dir.join("codex.exe"), "").unwrap();
+        assert_eq!(find_codex_in_dir(&dir), Some(dir.join("codex.exe")));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn extract_rate_limit_status_picks_the_tightest_window() {
+        let msg = serde_json::json!({
+            "method": "account/rateLimits/updated",
+            "params": { "rateLimits": {
+                "primary": { "usedPercent": 42.5, "windowDurationMins": 300, "resetsAt": 1_769_850_683u64 },
+                "secondary": { "usedPercent": 97.0, "windowDurationMins": 10080, "resetsAt": 1_770_000_000u64 }
+            } }
+        });
+        let status = extract_rate_limit_status(&msg).unwrap();
+        assert_eq!(status.used_pct, 97.0);
+        assert_eq!(status.window, Some(10080));
+        assert_eq!(status.reset_at_ms, Some(1_770_000_000_000));
+        assert!(
+            extract_rate_limit_status(&serde_json::json!({ "method": "turn/started" })).is_none()
+        );
+
+        let soon = RateLimitStatus {
+            used_pct: 100.0,
+            reset_at_ms: Some(now_ms() + 5 * 60_000),
+            window: Some(300),
+        };
+        assert_eq!(
+            with_rate_limit_hint(
+                "Turn start failed: 429 Too Many Requests".to_string(),
+                Some(&soon)
+            ),
+            "Turn start failed: 429 Too Many Requests (rate limit resets in ~5 min)"
+        );
+        assert_eq!(
+            with_rate_limit_hint("Turn start failed: boom".to_string(), Some(&soon)),
+            "Turn start failed: boom"
+        );
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn load_session_backlog_replays_warp_stdout_events_in_order() {
+    async fn start_session_sync_returns_the_conclusion() {
+        use std::os::unix::fs::PermissionsExt;
         let mut data_dir = std::env::temp_dir();
         data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
         tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        let body = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{\"id\":$id,\"result\":{\"userAgent\":\"codex/9.9\"}}" ;;
+    *'"thread/start"'*|*'"thread/resume"'*) echo "{\"id\":$id,\"result\":{\"thread\":{\"id\":\"t1\"}}}" ;;
+    *'"turn/start"'*)
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u1\"}}}"
+      echo '{"method":"item/completed","params":{"item":{"type":"agentMessage","id":"m1","text":"All done."}}}'
+      echo '{"method":"thread/tokenUsage/updated","params":{"modelContextWindow":1000,"tokenUsage":{"last":{"inputTokens":100,"outputTokens":20}}}}'
+      echo '{"method":"turn/completed","params":{"turn":{"status":"completed"}}}'
+      ;;
+  esac
+done
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+
+        let req: SyncStartRequest = serde_json::from_value(
+            serde_json::json!({ "prompt": "do it", "cwd": null, "timeout_secs": 10 }),
+        )
+        .unwrap();
+        let Json(result) = start_session_sync(State(state.clone()), Json(req))
+            .await
+            .ok()
+            .unwrap();
+        assert!(result.success);
+        assert_eq!(result.exit_code, None);
+        assert_eq!(result.conclusion, "All done.");
+        let meta = read_meta(&meta_path(&state, &result.session_id))
+            .await
+            .unwrap();
+        assert!(matches!(meta.status, SessionStatus::Done));
+        assert_eq!(meta.codex_session_id.as_deref(), Some("t1"));
+        assert_eq!((meta.turn_count, meta.cumulative_tokens), (1, 120));
+        let capabilities: serde_json::Value = serde_json::from_slice(
+            &std::fs::read(session_dir(&state, &result.session_id).join("capabilities.json"))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(capabilities["userAgent"], "codex/9.9");
+        let Json(status) = server_status(State(state.clone())).await;
+        assert_eq!(status.capabilities.unwrap()["userAgent"], "codex/9.9");
+
+        let req: SyncContinueRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": "again", "timeout_secs": 10 }))
+                .unwrap();
+        let Json(again) = continue_session_sync(
+            State(state.clone()),
+            AxumPath(result.session_id.clone()),
+            Json(req),
+        )
+        .await
+        .ok()
+        .unwrap();
+        assert!(again.success);
+        let meta = read_meta(&meta_path(&state, &result.session_id))
+            .await
+            .unwrap();
+        assert_eq!((meta.turn_count, meta.cumulative_tokens), (2, 240));
 
-        let state = test_state(data_dir.clone());
-        let session_id = format!("s-{}", Uuid::new_v4());
-        let dir = session_dir(&state, &session_id);
-        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
 
-        let events_path = dir.join("events.jsonl");
-        let line1 =
-            serde_json::json!({ "type": "app.prompt", "prompt": "Hello", "_ts_ms": 100u64 })
-                .to_string();
-        let line2 = serde_json::json!({
-            "type": "event_msg",
-            "payload": { "type": "assistant_message", "message": "World" },
-            "_ts_ms": 200u64
-        })
-        .to_string();
-        tokio::fs::write(&events_path, format!("{line1}\n{line2}\n"))
-            .await
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failed_initialize_is_retried_with_a_fresh_app_server() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        // The first spawn exits without answering `initialize`; later spawns behave.
+        let body = format!(
+            r#"#!/bin/sh
+marker='{}'
+if [ ! -e "$marker" ]; then touch "$marker"; exit 1; fi
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{{\"id\":$id,\"result\":{{}}}}" ;;
+    *'"thread/start"'*) echo "{{\"id\":$id,\"result\":{{\"thread\":{{\"id\":\"t1\"}}}}}}" ;;
+    *'"turn/start"'*)
+      echo "{{\"id\":$id,\"result\":{{\"turn\":{{\"id\":\"u1\"}}}}}}"
+      echo '{{"method":"turn/completed","params":{{"turn":{{"status":"completed"}}}}}}'
+      ;;
+  esac
+done
+"#,
+            data_dir.join("failed-once").display()
+        );
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+
+        let run = |state: AppState| async move {
+            let req: SyncStartRequest = serde_json::from_value(
+                serde_json::json!({ "prompt": "go", "cwd": null, "timeout_secs": 10 }),
+            )
             .unwrap();
+            let Json(result) = start_session_sync(State(state), Json(req))
+                .await
+                .ok()
+                .unwrap();
+            result
+        };
 
-        let backlog = load_session_backlog(&state, &session_id, Some(50))
+        // With retries disabled the first failure is final.
+        assert!(!run(state.clone()).await.success);
+
+        let _ = tokio::fs::remove_file(data_dir.join("failed-once")).await;
+        state.init_retries = 1;
+        let result = run(state.clone()).await;
+        assert!(result.success);
+        let meta = read_meta(&meta_path(&state, &result.session_id))
             .await
             .unwrap();
-        assert_eq!(backlog.len(), 2);
-        assert_eq!(backlog[0].stream, "stdout");
-        assert_eq!(backlog[0].ts_ms, 100);
-        assert_eq!(backlog[0].raw, line1);
-        assert_eq!(backlog[1].ts_ms, 200);
-        assert_eq!(backlog[1].raw, line2);
+        assert_eq!(meta.codex_session_id.as_deref(), Some("t1"));
 
         let _ = tokio::fs::remove_dir_all(&data_dir).await;
     }
-}
 
-async fn read_tail_lines(path: &Path, max_lines: usize) -> Vec<String> {
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn approval_requests_get_the_configured_answer() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        // The turn only completes once the approval request is answered.
+        let body = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{\"id\":$id,\"result\":{}}" ;;
+    *'"thread/start"'*) echo "{\"id\":$id,\"result\":{\"thread\":{\"id\":\"t1\"}}}" ;;
+    *'"turn/start"'*)
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u1\"}}}"
+      echo '{"id":"a1","method":"execCommandApproval","params":{"command":["make"]}}'
+      ;;
+    *'"id":"a1"'*'"decision":"approved"'*)
+      echo '{"method":"turn/completed","params":{"turn":{"status":"completed"}}}'
+      ;;
+  esac
+done
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+        state.approval_decision = "approved".to_string();
+
+        let req: SyncStartRequest = serde_json::from_value(
+            serde_json::json!({ "prompt": "build", "cwd": null, "timeout_secs": 10 }),
+        )
+        .unwrap();
+        let Json(result) = start_session_sync(State(state.clone()), Json(req))
+            .await
+            .ok()
+            .unwrap();
+        assert!(result.success);
+        let events =
+            tokio::fs::read_to_string(session_dir(&state, &result.session_id).join("events.jsonl"))
+                .await
+                .unwrap();
+        assert!(events.contains("execCommandApproval"));
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn title_suggestion_runs_a_side_turn_without_touching_the_session() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        let body = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{\"id\":$id,\"result\":{}}" ;;
+    *'"thread/start"'*'"ephemeral":true'*)
+      [ "$TITLE_ENV" = on ] && echo "{\"id\":$id,\"result\":{\"thread\":{\"id\":\"t2\"}}}" ;;
+    *'"turn/start"'*'5-word title'*'flaky login'*)
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u2\"}}}"
+      echo '{"method":"item/completed","params":{"item":{"id":"m1","type":"agentMessage","text":"\"Fix flaky login test\""}}}'
+      echo '{"method":"turn/completed","params":{"turn":{"status":"completed"}}}'
+      ;;
+  esac
+done
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+        state.codex_env = vec![("TITLE_ENV".to_string(), "on".to_string())];
+
+        let id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let events = dir.join("events.jsonl");
+        append_prompt_event(
+            &state,
+            &id,
+            &events,
+            "the flaky login test keeps failing",
+            None,
+        )
+        .await
+        .unwrap();
+        let before = tokio::fs::read_to_string(&events).await.unwrap();
+
+        let Json(suggestion) = suggest_title(State(state.clone()), AxumPath(id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(suggestion.title, "Fix flaky login test");
+        assert_eq!(tokio::fs::read_to_string(&events).await.unwrap(), before);
+
+        let (cancel_tx, _cancel_rx) = oneshot::channel();
+        state.runs.lock().await.insert(
+            id.clone(),
+            RunHandle {
+                cancel: Some(cancel_tx),
+                pid: None,
+                started_at_ms: now_ms(),
+                webhook: None,
+            },
+        );
+        let status = suggest_title(State(state.clone()), AxumPath(id))
+            .await
+            .err()
+            .unwrap()
+            .status();
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn finished_turn_posts_a_signed_webhook() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        let body = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{\"id\":$id,\"result\":{}}" ;;
+    *'"thread/start"'*) echo "{\"id\":$id,\"result\":{\"thread\":{\"id\":\"t1\"}}}" ;;
+    *'"turn/start"'*)
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u1\"}}}"
+      echo '{"method":"item/completed","params":{"item":{"type":"agentMessage","id":"m1","text":"Shipped."}}}'
+      echo '{"method":"turn/completed","params":{"turn":{"status":"completed"}}}'
+      ;;
+  esac
+done
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let (hook_tx, mut hook_rx) = tokio::sync::mpsc::unbounded_channel::<(HeaderMap, String)>();
+        let receiver = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: String| {
+                let hook_tx = hook_tx.clone();
+                async move {
+                    let _ = hook_tx.send((headers, body));
+                    StatusCode::OK
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+        state.webhook_secret = Some("hush".to_string());
+        assert!(parse_webhook_arg("ftp://x").is_err());
+        assert!(parse_webhook_arg("http://").is_err());
+        let hook = || Some(format!("http://{addr}/hook"));
+        assert!(request_webhook(&state, hook())
+            .unwrap_err()
+            .contains("disabled"));
+        state.webhook_hosts = vec!["hooks.example".to_string()];
+        assert!(request_webhook(&state, hook())
+            .unwrap_err()
+            .contains("not allowed"));
+        state.webhook_hosts.push("127.0.0.1".to_string());
+
+        let req: SyncStartRequest = serde_json::from_value(serde_json::json!({
+            "prompt": "ship it",
+            "cwd": null,
+            "timeout_secs": 10,
+            "webhook": format!("http://{addr}/hook"),
+        }))
+        .unwrap();
+        let Json(result) = start_session_sync(State(state.clone()), Json(req))
+            .await
+            .ok()
+            .unwrap();
+        assert!(result.success);
+
+        let (headers, body) = timeout(Duration::from_secs(5), hook_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload["session_id"], result.session_id.as_str());
+        assert_eq!(payload["success"], true);
+        assert_eq!(payload["conclusion"], "Shipped.");
+        let expected = format!("sha256={}", webhook_signature("hush", body.as_bytes()));
+        assert_eq!(headers["x-codex-warp-signature"], expected.as_str());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn webhook_client_does_not_follow_redirects() {
+        let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let hits_for_target = hits.clone();
+        let receiver = Router::new()
+            .route(
+                "/hop",
+                post(|| async {
+                    (
+                        StatusCode::TEMPORARY_REDIRECT,
+                        [(header::LOCATION, "/internal")],
+                    )
+                }),
+            )
+            .route(
+                "/internal",
+                post(move || {
+                    hits_for_target.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    async { StatusCode::OK }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        let res = webhook_client()
+            .post(format!("http://{addr}/hop"))
+            .body("{}")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn latest_turn_starts_then_continues_the_most_recent_session() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        let body = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{\"id\":$id,\"result\":{}}" ;;
+    *'"thread/start"'*|*'"thread/resume"'*)
+      printf '%s\n' "$line" >> "$(dirname "$0")/threads.log"
+      echo "{\"id\":$id,\"result\":{\"thread\":{\"id\":\"t1\"}}}"
+      ;;
+    *'"turn/start"'*)
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u1\"}}}"
+      echo '{"method":"turn/completed","params":{"turn":{"status":"completed"}}}'
+      ;;
+  esac
+done
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+
+        // The first turn only reads; the resumed one writes again.
+        let turn = |prompt: &str| {
+            let read_only = prompt == "hello";
+            serde_json::from_value::<ContinueRequest>(
+                serde_json::json!({ "prompt": prompt, "cwd": null, "read_only": read_only }),
+            )
+            .unwrap()
+        };
+        let wait_idle = || async {
+            for _ in 0..200 {
+                if state.runs.lock().await.is_empty() {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(25)).await;
+            }
+            panic!("run did not finish");
+        };
+
+        let Json(first) = continue_latest_session(State(state.clone()), Json(turn("hello")))
+            .await
+            .ok()
+            .unwrap();
+        wait_idle().await;
+        let Json(second) = continue_latest_session(State(state.clone()), Json(turn("and again")))
+            .await
+            .ok()
+            .unwrap();
+        wait_idle().await;
+        assert_eq!(second.id, first.id);
+        let Json(prompts) = session_prompts(State(state.clone()), AxumPath(first.id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        let prompts: Vec<&str> = prompts.iter().map(|p| p.prompt.as_str()).collect();
+        assert_eq!(prompts, vec!["hello", "and again"]);
+        assert!(first.last_turn_read_only);
+        assert!(
+            !read_meta(&meta_path(&state, &first.id))
+                .await
+                .unwrap()
+                .last_turn_read_only
+        );
+        let log = tokio::fs::read_to_string(data_dir.join("threads.log"))
+            .await
+            .unwrap();
+        let requests: Vec<serde_json::Value> = log
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(requests[0]["method"], "thread/start");
+        assert_eq!(requests[0]["params"]["sandbox"], "read-only");
+        assert_eq!(requests[0]["params"]["approvalPolicy"], "never");
+        assert_eq!(requests[1]["method"], "thread/resume");
+        assert_eq!(requests[1]["params"]["sandbox"], DEFAULT_SANDBOX);
+        assert_eq!(
+            requests[1]["params"]["approvalPolicy"],
+            DEFAULT_APPROVAL_POLICY
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn pinned_instructions_precede_every_turn_prompt() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        let body = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{\"id\":$id,\"result\":{}}" ;;
+    *'"thread/start"'*|*'"thread/resume"'*) echo "{\"id\":$id,\"result\":{\"thread\":{\"id\":\"t1\"}}}" ;;
+    *'"turn/start"'*)
+      printf '%s\n' "$line" >> "$(dirname "$0")/turns.log"
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u1\"}}}"
+      echo '{"method":"turn/completed","params":{"turn":{"status":"completed"}}}'
+      ;;
+  esac
+done
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+        state.default_instructions = Some("Use tabs.".to_string());
+
+        let wait_idle = || async {
+            for _ in 0..200 {
+                if state.runs.lock().await.is_empty() {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(25)).await;
+            }
+            panic!("run did not finish");
+        };
+        let req: StartRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": "hello", "cwd": null })).unwrap();
+        let Json(meta) = create_session(state.clone(), req).await.ok().unwrap();
+        wait_idle().await;
+
+        let pin = |text: Option<&str>| {
+            let req = InstructionsRequest {
+                instructions: text.map(str::to_string),
+            };
+            set_session_instructions(State(state.clone()), AxumPath(meta.id.clone()), Json(req))
+        };
+        let Json(pinned) = pin(Some(" Never touch vendor/. ")).await.ok().unwrap();
+        assert_eq!(pinned.instructions.as_deref(), Some("Never touch vendor/."));
+        let turn: ContinueRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": "again", "cwd": null })).unwrap();
+        assert!(
+            continue_session(State(state.clone()), AxumPath(meta.id.clone()), Json(turn))
+                .await
+                .is_ok()
+        );
+        wait_idle().await;
+        let Json(cleared) = pin(Some("")).await.ok().unwrap();
+        assert!(cleared.instructions.is_none());
+
+        let log = tokio::fs::read_to_string(data_dir.join("turns.log"))
+            .await
+            .unwrap();
+        let texts: Vec<String> = log
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .map(|v| {
+                v["params"]["input"][0]["text"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["Use tabs.\n\nhello", "Never touch vendor/.\n\nagain"]
+        );
+        let events = tokio::fs::read_to_string(session_dir(&state, &meta.id).join("events.jsonl"))
+            .await
+            .unwrap();
+        let markers: Vec<serde_json::Value> = events
+            .lines()
+            .filter_map(|l| serde_json::from_str::<serde_json::Value>(l).ok())
+            .filter(|v| v["type"] == "app.instructions")
+            .collect();
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[1]["instructions"], "Never touch vendor/.");
+        let Json(prompts) = session_prompts(State(state.clone()), AxumPath(meta.id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(
+            prompts
+                .iter()
+                .map(|p| p.prompt.as_str())
+                .collect::<Vec<_>>(),
+            vec!["hello", "again"]
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_finished_reports_the_failed_turn_status_and_reason() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        let body = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{\"id\":$id,\"result\":{}}" ;;
+    *'"thread/start"'*|*'"thread/resume"'*) echo "{\"id\":$id,\"result\":{\"thread\":{\"id\":\"t1\"}}}" ;;
+    *'"turn/start"'*)
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u1\"}}}"
+      case "$line" in
+        *'"again"'*)
+          error='{"message":"Quota exceeded.","codexErrorInfo":"usageLimitExceeded"}'
+          echo "{\"method\":\"turn/completed\",\"params\":{\"turn\":{\"status\":\"failed\",\"error\":$error}}}"
+          ;;
+        *) echo '{"method":"turn/completed","params":{"turn":{"status":"completed"}}}' ;;
+      esac
+      ;;
+  esac
+done
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+
+        let req: StartRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": "hello", "cwd": null })).unwrap();
+        let Json(meta) = create_session(state.clone(), req).await.ok().unwrap();
+        while state.runs.lock().await.contains_key(&meta.id) {
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+
+        let mut rx = ensure_stream(&state, &meta.id).await.subscribe();
+        let turn: ContinueRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": "again", "cwd": null })).unwrap();
+        assert!(
+            continue_session(State(state.clone()), AxumPath(meta.id.clone()), Json(turn))
+                .await
+                .is_ok()
+        );
+        let finished = loop {
+            let msg = timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if msg.event == "codex_run_finished" {
+                break serde_json::from_str::<serde_json::Value>(&msg.data).unwrap();
+            }
+        };
+        assert_eq!(finished["success"], false);
+        assert_eq!(finished["exit_code"], 1);
+        assert_eq!(finished["status"], "failed");
+        assert_eq!(finished["reason"], "Quota exceeded.");
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn turn_stops_when_its_events_file_is_deleted() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        // The second turn unlinks events.jsonl while it's open, then keeps the turn running.
+        let body = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{\"id\":$id,\"result\":{}}" ;;
+    *'"thread/start"'*|*'"thread/resume"'*) echo "{\"id\":$id,\"result\":{\"thread\":{\"id\":\"t1\"}}}" ;;
+    *'"turn/start"'*'"again"'*)
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u2\"}}}"
+      rm -f "$(dirname "$0")"/sessions/*/events.jsonl
+      sleep 0.2
+      echo '{"method":"item/agentMessage/delta","params":{"itemId":"m2","delta":"Still"}}'
+      ;;
+    *'"turn/start"'*)
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u1\"}}}"
+      echo '{"method":"turn/completed","params":{"turn":{"status":"completed"}}}'
+      ;;
+    *'"turn/interrupt"'*) printf 'interrupted\n' >> "$(dirname "$0")/interrupt.log" ;;
+  esac
+done
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+        // Also paces the check for a deleted events.jsonl.
+        state.heartbeat_interval = Some(Duration::from_millis(50));
+
+        let req: StartRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": "hello", "cwd": null })).unwrap();
+        let Json(meta) = create_session(state.clone(), req).await.ok().unwrap();
+        while state.runs.lock().await.contains_key(&meta.id) {
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+
+        let mut rx = ensure_stream(&state, &meta.id).await.subscribe();
+        let turn: ContinueRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": "again", "cwd": null })).unwrap();
+        assert!(
+            continue_session(State(state.clone()), AxumPath(meta.id.clone()), Json(turn))
+                .await
+                .is_ok()
+        );
+        let mut app_error = None;
+        let finished = loop {
+            let msg = timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            let data: serde_json::Value = serde_json::from_str(&msg.data).unwrap();
+            match msg.event {
+                "codex_event" if data["json"]["type"] == "app.error" => app_error = Some(data),
+                "codex_run_finished" => break data,
+                _ => {}
+            }
+        };
+        assert_eq!(app_error.unwrap()["json"]["message"], SESSION_FILES_REMOVED);
+        assert_eq!(finished["success"], false);
+        assert_eq!(finished["status"], "error");
+        assert_eq!(finished["reason"], SESSION_FILES_REMOVED);
+        assert!(state.runs.lock().await.is_empty());
+        let interrupts = tokio::fs::read_to_string(data_dir.join("interrupt.log"))
+            .await
+            .unwrap_or_default();
+        assert_eq!(interrupts, "interrupted\n");
+        assert!(
+            tokio::fs::metadata(session_dir(&state, &meta.id).join("events.jsonl"))
+                .await
+                .is_err()
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn lingering_app_server_is_killed_with_a_stderr_note() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        let body = r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) echo "{\"id\":$id,\"result\":{}}" ;;
+    *'"thread/start"'*) echo "{\"id\":$id,\"result\":{\"thread\":{\"id\":\"t1\"}}}" ;;
+    *'"turn/start"'*)
+      echo "{\"id\":$id,\"result\":{\"turn\":{\"id\":\"u1\"}}}"
+      echo '{"method":"turn/completed","params":{"turn":{"status":"completed"}}}'
+      ;;
+  esac
+done
+exec sleep 30
+"#;
+        tokio::fs::write(&script, body).await.unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+        state.child_exit_timeout = Duration::from_millis(200);
+
+        let req: SyncStartRequest = serde_json::from_value(
+            serde_json::json!({ "prompt": "do it", "cwd": null, "timeout_secs": 10 }),
+        )
+        .unwrap();
+        let Json(result) = start_session_sync(State(state.clone()), Json(req))
+            .await
+            .ok()
+            .unwrap();
+        assert!(result.success);
+        let stderr =
+            tokio::fs::read_to_string(session_dir(&state, &result.session_id).join("stderr.log"))
+                .await
+                .unwrap();
+        assert!(stderr.contains("force-killed"), "{stderr}");
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[test]
+    fn search_snippet_centers_on_case_insensitive_match() {
+        let needle: Vec<char> = "AUTH bug".chars().collect();
+        let text = format!(
+            "{}Fixed the auth bug\nin login.{}",
+            "x ".repeat(100),
+            " y".repeat(200)
+        );
+        let range = find_case_insensitive(&text, &needle).unwrap();
+        assert_eq!(&text[range.0..range.1], "auth bug");
+        let snippet = search_snippet(&text, range);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("Fixed the auth bug in login."));
+        assert!(snippet.chars().count() <= SEARCH_SNIPPET_CHARS + 2);
+
+        assert_eq!(
+            find_case_insensitive("Émoji ÉTÉ", &"été".chars().collect::<Vec<_>>()),
+            Some((7, 12))
+        );
+        assert_eq!(
+            find_case_insensitive("short", &"shorter".chars().collect::<Vec<_>>()),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn compact_session_drops_noisy_events_and_keeps_a_backup() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+
+        let lines = [
+            serde_json::json!({ "type": "app.prompt", "prompt": "hi", "_ts_ms": 1u64 }),
+            serde_json::json!({ "method": "item/reasoning/summaryTextDelta", "params": { "delta": "th" } }),
+            serde_json::json!({ "method": "thread/tokenUsage/updated", "params": {} }),
+            serde_json::json!({ "method": "item/completed", "params": { "item": { "type": "agentMessage" } } }),
+            serde_json::json!({ "method": "turn/completed", "params": {} }),
+        ];
+        let original: String = lines.iter().map(|l| format!("{l}\n")).collect();
+        tokio::fs::write(dir.join("events.jsonl"), &original)
+            .await
+            .unwrap();
+
+        let Json(result) = compact_session(State(state.clone()), AxumPath(id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!((result.before_lines, result.after_lines), (5, 3));
+        let compacted = tokio::fs::read_to_string(dir.join("events.jsonl"))
+            .await
+            .unwrap();
+        assert!(compacted.contains("app.prompt") && compacted.contains("turn/completed"));
+        assert!(!compacted.contains("tokenUsage") && !compacted.contains("summaryTextDelta"));
+        assert_eq!(
+            tokio::fs::read_to_string(dir.join("events.jsonl.bak"))
+                .await
+                .unwrap(),
+            original
+        );
+
+        // Compacting again keeps the first backup and writes a timestamped one.
+        let _ = compact_session(State(state.clone()), AxumPath(id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(
+            tokio::fs::read_to_string(dir.join("events.jsonl.bak"))
+                .await
+                .unwrap(),
+            original
+        );
+        let mut names = Vec::new();
+        let mut rd = tokio::fs::read_dir(&dir).await.unwrap();
+        while let Some(entry) = rd.next_entry().await.unwrap() {
+            names.push(entry.file_name().to_string_lossy().to_string());
+        }
+        names.sort();
+        assert_eq!(names.len(), 3, "{names:?}");
+        assert!(
+            names[1].starts_with("events.jsonl.")
+                && names[1].ends_with(".bak")
+                && names[1] != "events.jsonl.bak"
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn cancel_runs_for_shutdown_cancels_and_marks_runs_failed() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let meta = SessionMeta {
+            title: "Busy".to_string(),
+            status: SessionStatus::Running,
+            ..test_meta(&id, &dir)
+        };
+        write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        state.runs.lock().await.insert(
+            id.clone(),
+            RunHandle {
+                cancel: Some(cancel_tx),
+                pid: None,
+                started_at_ms: now_ms(),
+                webhook: None,
+            },
+        );
+        let run_state = state.clone();
+        let run_id = id.clone();
+        let run = tokio::spawn(async move {
+            let _ = cancel_rx.await;
+            run_state.runs.lock().await.remove(&run_id);
+        });
+
+        cancel_runs_for_shutdown(&state).await;
+        run.await.unwrap();
+        assert!(state.runs.lock().await.is_empty());
+        let meta = read_meta(&meta_path(&state, &id)).await.unwrap();
+        assert!(matches!(meta.status, SessionStatus::Error));
+        let conclusion = tokio::fs::read_to_string(dir.join("conclusion.md"))
+            .await
+            .unwrap();
+        assert!(conclusion.contains("Server shutdown."));
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn reconcile_stale_runs_flips_orphaned_running_sessions_to_error() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+
+        let mut ids = Vec::new();
+        for (title, status) in [
+            ("stale", SessionStatus::Running),
+            ("live", SessionStatus::Running),
+            ("done", SessionStatus::Done),
+            ("crashed", SessionStatus::Running),
+        ] {
+            let id = Uuid::new_v4().to_string();
+            let dir = session_dir(&state, &id);
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            let meta = SessionMeta {
+                title: title.to_string(),
+                status,
+                ..test_meta(&id, &dir)
+            };
+            write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+            ids.push(id);
+        }
+        tokio::fs::write(
+            session_dir(&state, &ids[0]).join("conclusion.md"),
+            "Half an answer",
+        )
+        .await
+        .unwrap();
+        // A later turn's completed message beats the previous turn's conclusion.
+        let crashed_dir = session_dir(&state, &ids[3]);
+        tokio::fs::write(crashed_dir.join("conclusion.md"), "Old answer")
+            .await
+            .unwrap();
+        tokio::fs::write(crashed_dir.join(PARTIAL_CONCLUSION_FILE), "Fresh answer")
+            .await
+            .unwrap();
+        tokio::fs::write(
+            session_dir(&state, &ids[1]).join(PARTIAL_CONCLUSION_FILE),
+            "Streaming",
+        )
+        .await
+        .unwrap();
+
+        let running: HashSet<String> = [ids[1].clone()].into_iter().collect();
+        assert_eq!(
+            reconcile_stale_runs(&sessions_root(&state), &running).await,
+            2
+        );
+
+        let stale = read_meta(&meta_path(&state, &ids[0])).await.unwrap();
+        assert!(matches!(stale.status, SessionStatus::Error));
+        let conclusion = tokio::fs::read_to_string(&stale.conclusion_path)
+            .await
+            .unwrap();
+        assert!(conclusion.starts_with("Half an answer"));
+        assert!(conclusion.contains(INTERRUPTED_NOTE));
+        let live = read_meta(&meta_path(&state, &ids[1])).await.unwrap();
+        assert!(matches!(live.status, SessionStatus::Running));
+        let done = read_meta(&meta_path(&state, &ids[2])).await.unwrap();
+        assert!(matches!(done.status, SessionStatus::Done));
+
+        let crashed = tokio::fs::read_to_string(crashed_dir.join("conclusion.md"))
+            .await
+            .unwrap();
+        assert!(crashed.starts_with("Fresh answer"));
+        assert!(
+            tokio::fs::metadata(crashed_dir.join(PARTIAL_CONCLUSION_FILE))
+                .await
+                .is_err()
+        );
+        let streaming = read_conclusion(State(state.clone()), AxumPath(ids[1].clone()))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(streaming, "Streaming");
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[test]
+    fn parse_codex_env_requires_a_named_assignment() {
+        assert_eq!(
+            parse_codex_env("OPENAI_BASE_URL=http://localhost:8080/v1?a=b").unwrap(),
+            (
+                "OPENAI_BASE_URL".to_string(),
+                "http://localhost:8080/v1?a=b".to_string()
+            )
+        );
+        assert_eq!(
+            parse_codex_env("EMPTY=").unwrap(),
+            ("EMPTY".to_string(), String::new())
+        );
+        assert!(parse_codex_env("NO_EQUALS").is_err());
+        assert!(parse_codex_env(" =value").is_err());
+    }
+
+    #[tokio::test]
+    async fn read_stderr_page_pages_lines_and_tolerates_missing_file() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let id = Uuid::new_v4().to_string();
+
+        let query = |offset, limit| {
+            Query(EventsPageQuery {
+                offset: Some(offset),
+                limit: Some(limit),
+            })
+        };
+        let Json(empty) =
+            read_stderr_page(State(state.clone()), AxumPath(id.clone()), query(0, 10)).await;
+        assert!(empty.lines.is_empty());
+        assert_eq!((empty.total, empty.next_offset), (0, None));
+
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("stderr.log"), "one\ntwo\nthree\n")
+            .await
+            .unwrap();
+        let Json(page) =
+            read_stderr_page(State(state.clone()), AxumPath(id.clone()), query(1, 1)).await;
+        assert_eq!(page.lines, vec!["two".to_string()]);
+        assert_eq!((page.total, page.next_offset), (3, Some(2)));
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[test]
+    fn tool_update_reports_commands_and_file_changes_only() {
+        let command = serde_json::json!({
+            "method": "item/completed",
+            "params": { "item": {
+                "type": "commandExecution", "id": "c1", "command": "cargo test",
+                "aggregatedOutput": "lots of output", "exitCode": 0, "status": "completed"
+            } }
+        });
+        let data = serde_json::to_value(tool_update("s1", &command).unwrap()).unwrap();
+        assert_eq!(data["session_id"], "s1");
+        assert_eq!(data["kind"], "command");
+        assert_eq!(data["item_id"], "c1");
+        assert_eq!(data["command"], "cargo test");
+        assert_eq!(data["exit_code"], 0);
+        assert_eq!(data["status"], "completed");
+        assert!(data.get("text").is_none());
+
+        let patch = serde_json::json!({
+            "method": "item/completed",
+            "params": { "item": { "type": "fileChange", "id": "f1", "changes": [{ "path": "src/lib.rs" }] } }
+        });
+        let data = serde_json::to_value(tool_update("s1", &patch).unwrap()).unwrap();
+        assert_eq!(data["kind"], "file_change");
+        assert_eq!(data["paths"], serde_json::json!(["src/lib.rs"]));
+
+        let message = serde_json::json!({
+            "method": "item/completed",
+            "params": { "item": { "type": "agentMessage", "id": "m1", "text": "hi" } }
+        });
+        assert!(tool_update("s1", &message).is_none());
+    }
+
+    #[tokio::test]
+    async fn session_diff_aggregates_file_changes_and_skips_malformed_lines() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let id = "diff-session".to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let change = |changes: serde_json::Value| {
+            serde_json::json!({
+                "method": "item/completed",
+                "params": { "item": { "type": "fileChange", "id": "f", "status": "completed", "changes": changes } }
+            })
+            .to_string()
+        };
+        let lines = [
+            change(serde_json::json!([
+                { "path": "src/new.rs", "kind": { "type": "add" }, "diff": "fn a() {}\nfn b() {}\n" },
+                { "path": "src/lib.rs", "kind": { "type": "update" }, "diff": "--- a\n+++ b\n@@\n-old\n+new\n+more\n" },
+            ])),
+            "{not json".to_string(),
+            change(serde_json::json!([
+                { "path": "src/lib.rs", "kind": "update", "diff": "@@\n-more\n" },
+                { "path": "old.txt", "kind": { "type": "delete" }, "diff": "bye\n" },
+            ])),
+        ];
+        tokio::fs::write(dir.join("events.jsonl"), lines.join("\n"))
+            .await
+            .unwrap();
+
+        let Json(entries) = session_diff(State(state.clone()), AxumPath(id))
+            .await
+            .unwrap();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.additions, e.deletions, e.status))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("src/new.rs", 2, 0, FileChangeStatus::Added),
+                ("src/lib.rs", 2, 2, FileChangeStatus::Modified),
+                ("old.txt", 0, 1, FileChangeStatus::Deleted),
+            ]
+        );
+
+        let mut native = FileDiffSummary::default();
+        native.add_event(&serde_json::json!({
+            "type": "response_item",
+            "payload": {
+                "type": "custom_tool_call",
+                "name": "apply_patch",
+                "input": "*** Begin Patch\n*** Add File: a.txt\n+one\n+two\n*** Update File: b.rs\n@@\n-x\n+y\n*** Delete File: c.md\n*** End Patch"
+            }
+        }));
+        let summary: Vec<_> = native
+            .entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.additions, e.deletions, e.status))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a.txt", 2, 0, FileChangeStatus::Added),
+                ("b.rs", 1, 1, FileChangeStatus::Modified),
+                ("c.md", 0, 0, FileChangeStatus::Deleted),
+            ]
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn export_all_streams_warp_and_native_sessions_as_ndjson() {
+        use tower::ServiceExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let codex_home = data_dir.join("codex-home");
+        let native_id = Uuid::new_v4().to_string();
+        let day_dir = codex_home.join("sessions/2025/01/01");
+        tokio::fs::create_dir_all(&day_dir).await.unwrap();
+        tokio::fs::write(
+            day_dir.join(format!("rollout-2025-01-01T10-00-00-{native_id}.jsonl")),
+            format!(
+                "{{\"timestamp\":\"2025-01-01T10:00:00Z\",\"type\":\"session_meta\",\"payload\":{{\"id\":\"{native_id}\",\"cwd\":\"/work/native\"}}}}\n"
+            ),
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            codex_home.join(".codex-global-state.json"),
+            format!(r#"{{"thread-titles":{{"titles":{{"{native_id}":"Native"}},"order":["{native_id}"]}}}}"#),
+        )
+        .await
+        .unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_home = Some(codex_home);
+
+        let warp_id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &warp_id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let meta = SessionMeta {
+            title: "Warp".to_string(),
+            ..test_meta(&warp_id, &dir)
+        };
+        write_meta(&meta_path(&state, &warp_id), &meta)
+            .await
+            .unwrap();
+
+        let req = axum::http::Request::builder()
+            .uri("/api/sessions/export_all.ndjson")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = api_router(state.clone()).oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut metas: Vec<SessionMeta> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        metas.sort_by_key(|m| m.id != warp_id);
+        assert_eq!(metas.len(), 2);
+        assert_eq!(metas[0].title, "Warp");
+        assert_eq!(metas[1].id, native_id);
+        assert_eq!(metas[1].cwd.as_deref(), Some("/work/native"));
+
+        // A stale archived copy loses to the live session, in the export as in the list.
+        let archived_dir = archived_session_dir(&state, &warp_id);
+        tokio::fs::create_dir_all(&archived_dir).await.unwrap();
+        let stale = SessionMeta {
+            title: "Archived".to_string(),
+            archived: true,
+            ..meta
+        };
+        write_meta(&archived_dir.join("meta.json"), &stale)
+            .await
+            .unwrap();
+        let req = axum::http::Request::builder()
+            .uri("/api/sessions/export_all.ndjson?include_archived=true")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = api_router(state.clone()).oneshot(req).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let exported: Vec<SessionMeta> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let listed = collect_sessions(&state, true).await.unwrap();
+        for metas in [&exported, &listed] {
+            let titles: Vec<&str> = metas
+                .iter()
+                .filter(|m| m.id == warp_id)
+                .map(|m| m.title.as_str())
+                .collect();
+            assert_eq!(titles, vec!["Warp"]);
+        }
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn last_message_reads_the_latest_turn_from_the_end() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let dir = session_dir(&state, "s1");
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let message = |text: &str, ts: u64| {
+            serde_json::json!({
+                "method": "item/completed",
+                "params": { "item": { "type": "agentMessage", "id": "m", "text": text } },
+                "_ts_ms": ts,
+            })
+            .to_string()
+        };
+        let prompt = |ts: u64| {
+            serde_json::json!({ "type": "app.prompt", "prompt": "go", "_ts_ms": ts }).to_string()
+        };
+        let completed = r#"{"method":"turn/completed","params":{"turn":{"status":"completed"}}}"#;
+        // Longer than one backward read, so the message spans chunk boundaries.
+        let long = "x".repeat(150 * 1024);
+        let events = [
+            prompt(1),
+            message("first", 2),
+            completed.to_string(),
+            prompt(3),
+            message(&long, 4),
+        ]
+        .join("\n");
+        tokio::fs::write(dir.join("events.jsonl"), format!("{events}\n"))
+            .await
+            .unwrap();
+
+        let get = || last_message(State(state.clone()), AxumPath("s1".to_string()));
+        let Json(last) = get().await.ok().unwrap();
+        assert_eq!(
+            (last.text.len(), last.ts_ms, last.complete),
+            (long.len(), 4, false)
+        );
+
+        let events = [
+            events,
+            message("Done. \u{E200}cite\u{E202}turn0file1\u{E201}", 5),
+            completed.to_string(),
+        ]
+        .join("\n");
+        tokio::fs::write(dir.join("events.jsonl"), events)
+            .await
+            .unwrap();
+        let Json(last) = get().await.ok().unwrap();
+        assert_eq!(
+            last,
+            LastMessage {
+                text: "Done.".to_string(),
+                ts_ms: 5,
+                complete: true
+            }
+        );
+
+        let mut file = std::fs::File::options()
+            .append(true)
+            .open(dir.join("events.jsonl"))
+            .unwrap();
+        std::io::Write::write_all(&mut file, format!("\n{}\n", prompt(6)).as_bytes()).unwrap();
+        let Json(last) = get().await.ok().unwrap();
+        assert_eq!(
+            last,
+            LastMessage {
+                text: String::new(),
+                ts_ms: 0,
+                complete: false
+            }
+        );
+        assert_eq!(
+            last_message(State(state.clone()), AxumPath("missing".to_string()))
+                .await
+                .err()
+                .unwrap()
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn rename_session_updates_meta_and_codex_thread_title() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let codex_home = data_dir.join("codex-home");
+        tokio::fs::create_dir_all(&codex_home).await.unwrap();
+        tokio::fs::write(
+            codex_home.join(".codex-global-state.json"),
+            r#"{"thread-titles":{"titles":{"other":"Other"},"order":["other"]}}"#,
+        )
+        .await
+        .unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_home = Some(codex_home.clone());
+        let id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let meta = SessionMeta {
+            title: "Old".to_string(),
+            codex_session_id: Some("thread-1".to_string()),
+            ..test_meta(&id, &dir)
+        };
+        write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+
+        let payload = HashMap::from([("title".to_string(), "New".to_string())]);
+        let status = rename_session(State(state.clone()), AxumPath(id.clone()), Json(payload))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        assert_eq!(
+            read_meta(&meta_path(&state, &id)).await.unwrap().title,
+            "New"
+        );
+        let titles = load_codex_thread_titles(&codex_home);
+        assert_eq!(titles.get("thread-1").map(String::as_str), Some("New"));
+        assert_eq!(titles.get("other").map(String::as_str), Some("Other"));
+        let root: serde_json::Value = serde_json::from_slice(
+            &std::fs::read(codex_home.join(".codex-global-state.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            root["thread-titles"]["order"],
+            serde_json::json!(["thread-1", "other"])
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn ndjson_stream_replays_backlog_then_live_events_until_run_finishes() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(
+            dir.join("events.jsonl"),
+            "{\"_ts_ms\":1,\"method\":\"turn/started\"}\n",
+        )
+        .await
+        .unwrap();
+
+        let query = Query(StreamQuery {
+            tail: None,
+            stop_on_disconnect: None,
+        });
+        let response = stream_session_ndjson(State(state.clone()), AxumPath(id.clone()), query)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+
+        let live = UiEvent {
+            session_id: id.clone(),
+            ts_ms: 2,
+            stream: "stdout".to_string(),
+            raw: "live".to_string(),
+            json: None,
+        };
+        broadcast_ui_event(&state, live).await;
+        broadcast_metrics(
+            &state,
+            ContextMetrics {
+                session_id: id.clone(),
+                ts_ms: 3,
+                context_left_pct: 50,
+                context_used_tokens: 10,
+                context_window: 20,
+            },
+        )
+        .await;
+        broadcast_run_finished(
+            &state,
+            RunFinished {
+                session_id: id.clone(),
+                ts_ms: 4,
+                exit_code: Some(0),
+                success: true,
+                codex_session_id: None,
+                status: Some("completed".to_string()),
+                reason: None,
+            },
+        )
+        .await;
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["json"]["method"], "turn/started");
+        assert_eq!(lines[1]["raw"], "live");
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn websocket_streams_events_and_answers_commands() {
+        use futures::SinkExt;
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(
+            dir.join("events.jsonl"),
+            "{\"_ts_ms\":1,\"method\":\"turn/started\"}\n",
+        )
+        .await
+        .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = api_router(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (mut ws, _) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/api/sessions/{id}/ws"))
+                .await
+                .unwrap();
+        async fn next_frame<S>(ws: &mut S) -> serde_json::Value
+        where
+            S: futures::Stream<Item = Result<WsMessage, tokio_tungstenite::tungstenite::Error>>
+                + Unpin,
+        {
+            loop {
+                if let WsMessage::Text(text) = ws.next().await.unwrap().unwrap() {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+        let backlog = next_frame(&mut ws).await;
+        assert_eq!(backlog["event"], "codex_event");
+        assert_eq!(backlog["data"]["json"]["method"], "turn/started");
+
+        broadcast_ui_event(
+            &state,
+            UiEvent {
+                session_id: id.clone(),
+                ts_ms: 2,
+                stream: "stdout".to_string(),
+                raw: "live".to_string(),
+                json: None,
+            },
+        )
+        .await;
+        let live = next_frame(&mut ws).await;
+        assert_eq!(live["event"], "codex_event");
+        assert_eq!(live["data"]["raw"], "live");
+
+        ws.send(WsMessage::Text("{\"type\":\"bogus\"}".to_string()))
+            .await
+            .unwrap();
+        let rejected = next_frame(&mut ws).await;
+        assert_eq!(rejected["event"], "ws_error");
+        assert_eq!(rejected["data"]["status"], 400);
+
+        // No meta on disk, so the turn is refused the same way POST /continue would refuse it.
+        ws.send(WsMessage::Text(
+            "{\"type\":\"turn\",\"prompt\":\"hi\"}".to_string(),
+        ))
+        .await
+        .unwrap();
+        let refused = next_frame(&mut ws).await;
+        assert_eq!(refused["event"], "ws_error");
+        assert_eq!(refused["data"]["status"], 404);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn concurrent_continues_start_exactly_one_run() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        tokio::fs::write(&script, "#!/bin/sh\nexec sleep 30\n")
+            .await
+            .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+
+        let id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let meta = SessionMeta {
+            title: "Idle".to_string(),
+            ..test_meta(&id, &dir)
+        };
+        write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+
+        let request = || {
+            let req: ContinueRequest =
+                serde_json::from_value(serde_json::json!({ "prompt": "again", "cwd": null }))
+                    .unwrap();
+            continue_session(State(state.clone()), AxumPath(id.clone()), Json(req))
+        };
+        let (a, b) = tokio::join!(request(), request());
+        let statuses: Vec<StatusCode> = [a, b]
+            .into_iter()
+            .map(|r| match r {
+                Ok(_) => StatusCode::OK,
+                Err(resp) => resp.status(),
+            })
+            .collect();
+        assert!(statuses.contains(&StatusCode::OK));
+        assert!(statuses.contains(&StatusCode::CONFLICT));
+
+        stop_session(State(state.clone()), AxumPath(id.clone()))
+            .await
+            .unwrap();
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn export_then_import_recreates_session_under_a_new_id() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let meta = SessionMeta {
+            title: "Shared".to_string(),
+            last_used_at_ms: 2,
+            cwd: Some("/work".to_string()),
+            codex_session_id: Some("thread-1".to_string()),
+            ..test_meta(&id, &dir)
+        };
+        write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+        tokio::fs::write(
+            dir.join("events.jsonl"),
+            "{\"type\":\"app.prompt\",\"prompt\":\"hi\"}\nnot json\n",
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(dir.join("stderr.log"), "warn one\n")
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join("conclusion.md"), "Done.")
+            .await
+            .unwrap();
+        for session_id in [id.as_str(), "someone-else"] {
+            let record = UsageRecord {
+                ts_ms: 3,
+                session_id: session_id.to_string(),
+                thread_id: None,
+                total_tokens: 10,
+                input_tokens: 8,
+                output_tokens: 2,
+                reasoning_output_tokens: 0,
+                cached_input_tokens: 0,
+                context_window: 100,
+                model: None,
+            };
+            append_usage_record(&state, &record).await.unwrap();
+        }
+
+        let Json(bundle) = export_session(State(state.clone()), AxumPath(id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(bundle.events.len(), 1);
+        assert_eq!(bundle.stderr, vec!["warn one".to_string()]);
+        assert_eq!(bundle.conclusion, "Done.");
+        assert_eq!(bundle.usage.len(), 1);
+
+        let mut bundle: SessionBundle =
+            serde_json::from_value(serde_json::to_value(&bundle).unwrap()).unwrap();
+        bundle.version += 1;
+        let rejected = import_session(State(state.clone()), Json(bundle))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+
+        let Json(bundle) = export_session(State(state.clone()), AxumPath(id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        let Json(imported) = import_session(State(state.clone()), Json(bundle))
+            .await
+            .ok()
+            .unwrap();
+        assert_ne!(imported.id, id);
+        assert_eq!(imported.title, "Shared");
+        assert_eq!(imported.codex_session_id.as_deref(), Some("thread-1"));
+        let new_dir = session_dir(&state, &imported.id);
+        assert_eq!(
+            imported.events_path,
+            new_dir.join("events.jsonl").to_string_lossy()
+        );
+        assert_eq!(
+            read_meta(&meta_path(&state, &imported.id))
+                .await
+                .unwrap()
+                .id,
+            imported.id
+        );
+        assert_eq!(
+            tokio::fs::read_to_string(new_dir.join("conclusion.md"))
+                .await
+                .unwrap(),
+            "Done."
+        );
+        let events = tokio::fs::read_to_string(new_dir.join("events.jsonl"))
+            .await
+            .unwrap();
+        assert_eq!(events.lines().count(), 1);
+        assert!(events.contains("app.prompt"));
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn session_paths_reports_absolute_file_locations() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let missing = session_paths(State(state.clone()), AxumPath("nope".to_string()))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let id = Uuid::new_v4().to_string();
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let meta = SessionMeta {
+            title: "Paths".to_string(),
+            ..test_meta(&id, &dir)
+        };
+        write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+
+        let Json(paths) = session_paths(State(state.clone()), AxumPath(id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(paths.dir.as_deref(), Some(dir.to_string_lossy().as_ref()));
+        assert_eq!(paths.events_path, meta.events_path);
+        assert_eq!(
+            paths.conclusion_path.as_deref(),
+            Some(meta.conclusion_path.as_str())
+        );
+        assert!(paths.rollout_path.is_none());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn continue_recovers_thread_id_or_warns_about_a_new_thread() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let mut state = test_state(data_dir.clone());
+        // Turns fail to spawn, but only after the thread id is resolved and events are written.
+        state.codex_path = Some(data_dir.join("missing-codex"));
+        // codex still has a rollout for t-42, but t-gone's was deleted.
+        let day_dir = data_dir.join("codex-home/sessions/2025/01/01");
+        tokio::fs::create_dir_all(&day_dir).await.unwrap();
+        tokio::fs::write(day_dir.join("rollout-2025-01-01T10-00-00-t-42.jsonl"), "")
+            .await
+            .unwrap();
+        state.codex_home = Some(data_dir.join("codex-home"));
+
+        for (id, events) in [
+            (
+                "recovered",
+                "{\"method\":\"thread/started\",\"params\":{\"thread\":{\"id\":\"t-42\"}}}\n",
+            ),
+            ("lost", "{\"type\":\"app.prompt\",\"prompt\":\"first\"}\n"),
+            (
+                "stale",
+                "{\"method\":\"thread/started\",\"params\":{\"thread\":{\"id\":\"t-gone\"}}}\n",
+            ),
+        ] {
+            let dir = session_dir(&state, id);
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            tokio::fs::write(dir.join("events.jsonl"), events)
+                .await
+                .unwrap();
+            let meta = test_meta(id, &dir);
+            write_meta(&meta_path(&state, id), &meta).await.unwrap();
+            let req: ContinueRequest =
+                serde_json::from_value(serde_json::json!({ "prompt": "again", "cwd": null }))
+                    .unwrap();
+            let _ =
+                continue_session(State(state.clone()), AxumPath(id.to_string()), Json(req)).await;
+        }
+
+        let recovered = read_meta(&meta_path(&state, "recovered")).await.unwrap();
+        assert_eq!(recovered.codex_session_id.as_deref(), Some("t-42"));
+        let events =
+            tokio::fs::read_to_string(session_dir(&state, "recovered").join("events.jsonl"))
+                .await
+                .unwrap();
+        assert!(!events.contains("app.warning"));
+
+        for id in ["lost", "stale"] {
+            assert!(read_meta(&meta_path(&state, id))
+                .await
+                .unwrap()
+                .codex_session_id
+                .is_none());
+            let events = tokio::fs::read_to_string(session_dir(&state, id).join("events.jsonl"))
+                .await
+                .unwrap();
+            let warning: serde_json::Value =
+                serde_json::from_str(events.lines().last().unwrap()).unwrap();
+            assert_eq!(warning["type"], "app.warning");
+            assert_eq!(warning["message"], NEW_THREAD_WARNING);
+        }
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn usage_responses_are_gzipped_when_accepted() {
+        use std::io::Read;
+        use tower::ServiceExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        for i in 0..500u64 {
+            let record = UsageRecord {
+                ts_ms: i,
+                session_id: "s1".to_string(),
+                thread_id: None,
+                total_tokens: i * 10,
+                input_tokens: i * 8,
+                output_tokens: i * 2,
+                reasoning_output_tokens: 0,
+                cached_input_tokens: 0,
+                context_window: 200_000,
+                model: Some("gpt-5-codex".to_string()),
+            };
+            append_usage_record(&state, &record).await.unwrap();
+        }
+
+        let request = |encoding: Option<&str>| {
+            let mut req = axum::http::Request::builder().uri("/api/usage");
+            if let Some(encoding) = encoding {
+                req = req.header(header::ACCEPT_ENCODING, encoding);
+            }
+            req.body(axum::body::Body::empty()).unwrap()
+        };
+        let app = api_router(state);
+        let plain = app.clone().oneshot(request(None)).await.unwrap();
+        assert!(plain.headers().get(header::CONTENT_ENCODING).is_none());
+        let plain = axum::body::to_bytes(plain.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let gzipped = app.oneshot(request(Some("gzip"))).await.unwrap();
+        assert_eq!(gzipped.headers()[header::CONTENT_ENCODING], "gzip");
+        let gzipped = axum::body::to_bytes(gzipped.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(
+            gzipped.len() < plain.len() / 4,
+            "{} vs {}",
+            gzipped.len(),
+            plain.len()
+        );
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&gzipped[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        let records: Vec<UsageRecord> = serde_json::from_str(&decoded).unwrap();
+        assert_eq!(records.len(), 500);
+        assert_eq!(records[499].total_tokens, 4990);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn usage_records_past_retention_are_pruned_on_append() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let mut state = test_state(data_dir.clone());
+        let day_ms = 24 * 60 * 60 * 1000;
+        let now = now_ms();
+        let record = |ts_ms: u64, session_id: &str| UsageRecord {
+            ts_ms,
+            session_id: session_id.to_string(),
+            thread_id: None,
+            total_tokens: 100,
+            input_tokens: 80,
+            output_tokens: 20,
+            reasoning_output_tokens: 0,
+            cached_input_tokens: 0,
+            context_window: 200_000,
+            model: None,
+        };
+        for (ts_ms, id) in [
+            (now - 10 * day_ms, "old"),
+            (now - day_ms, "kept"),
+            (now - 9 * day_ms, "older"),
+        ] {
+            append_usage_record(&state, &record(ts_ms, id))
+                .await
+                .unwrap();
+        }
+
+        state.usage_retention = Some(Duration::from_secs(7 * 24 * 60 * 60));
+        append_usage_record(&state, &record(now, "new"))
+            .await
+            .unwrap();
+        let Json(records) = list_usage_records(
+            State(state.clone()),
+            Query(UsageQuery { max_records: None }),
+        )
+        .await
+        .unwrap();
+        let ids: Vec<_> = records.iter().map(|r| r.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["kept", "new"]);
+        assert!(!data_dir.join("usage.jsonl.tmp").exists());
+
+        assert_eq!(
+            clear_usage(State(state.clone())).await.ok(),
+            Some(StatusCode::NO_CONTENT)
+        );
+        let Json(records) = list_usage_records(
+            State(state.clone()),
+            Query(UsageQuery { max_records: None }),
+        )
+        .await
+        .unwrap();
+        assert!(records.is_empty());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn start_session_replays_requests_with_the_same_idempotency_key() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let script = data_dir.join("codex");
+        tokio::fs::write(&script, "#!/bin/sh\nexit 0\n")
+            .await
+            .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+
+        let start = |key: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(key) = key {
+                headers.insert("idempotency-key", key.parse().unwrap());
+            }
+            let req: StartRequest =
+                serde_json::from_value(serde_json::json!({ "prompt": "do it", "cwd": null }))
+                    .unwrap();
+            start_session(State(state.clone()), headers, Json(req))
+        };
+        let Json(first) = start(Some("retry-1")).await.ok().unwrap();
+        let Json(replayed) = start(Some("retry-1")).await.ok().unwrap();
+        assert_eq!(first.id, replayed.id);
+        let Json(other) = start(Some("retry-2")).await.ok().unwrap();
+        assert_ne!(other.id, first.id);
+        let Json(unkeyed) = start(None).await.ok().unwrap();
+        assert_ne!(unkeyed.id, first.id);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn prune_sessions_keeps_recent_and_running_sessions() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let now = now_ms();
+        // Newest first: "running" is oldest but must survive.
+        for (id, last_used_at_ms) in [
+            ("new", now),
+            ("mid", now - 1_000),
+            ("old", now - 10_000),
+            ("running", now - 20_000),
+        ] {
+            let dir = session_dir(&state, id);
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            tokio::fs::write(dir.join("events.jsonl"), "0123456789")
+                .await
+                .unwrap();
+            let meta = SessionMeta {
+                created_at_ms: last_used_at_ms,
+                last_used_at_ms,
+                ..test_meta(id, &dir)
+            };
+            write_meta(&meta_path(&state, id), &meta).await.unwrap();
+        }
+        state.runs.lock().await.insert(
+            "running".to_string(),
+            RunHandle {
+                cancel: None,
+                pid: None,
+                started_at_ms: now_ms(),
+                webhook: None,
+            },
+        );
+
+        let prune = |keep_last: Option<usize>, older_than_ms: Option<u64>, dry_run: bool| {
+            prune_sessions(
+                State(state.clone()),
+                Json(PruneRequest {
+                    keep_last,
+                    older_than_ms,
+                    dry_run,
+                    include_native: false,
+                }),
+            )
+        };
+        let Json(preview) = prune(Some(1), None, true).await.ok().unwrap();
+        assert_eq!(preview.pruned, vec!["mid".to_string(), "old".to_string()]);
+        assert!(preview.reclaimed_bytes >= 20);
+        assert!(tokio::fs::metadata(session_dir(&state, "mid"))
+            .await
+            .is_ok());
+
+        let Json(result) = prune(None, Some(5_000), false).await.ok().unwrap();
+        assert_eq!(result.pruned, vec!["old".to_string()]);
+        assert!(tokio::fs::metadata(session_dir(&state, "old"))
+            .await
+            .is_err());
+        assert!(tokio::fs::metadata(session_dir(&state, "running"))
+            .await
+            .is_ok());
+        assert!(tokio::fs::metadata(session_dir(&state, "mid"))
+            .await
+            .is_ok());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn reasoning_effort_is_validated_and_persisted() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(data_dir.join("missing-codex"));
+
+        let start = |effort: &str| {
+            let req: StartRequest = serde_json::from_value(
+                serde_json::json!({ "prompt": "think", "cwd": null, "reasoning_effort": effort }),
+            )
+            .unwrap();
+            start_session(State(state.clone()), HeaderMap::new(), Json(req))
+        };
+        let rejected = start("hgih").await.err().unwrap();
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+        assert!(tokio::fs::metadata(sessions_root(&state)).await.is_err());
+
+        // Spawning fails without a codex binary, but only after meta.json is written.
+        let _ = start(" High ").await;
+        let mut rd = tokio::fs::read_dir(sessions_root(&state)).await.unwrap();
+        let dir = rd.next_entry().await.unwrap().unwrap().path();
+        let meta = read_meta(&dir.join("meta.json")).await.unwrap();
+        assert_eq!(meta.reasoning_effort.as_deref(), Some("high"));
+        let config = thread_config(meta.model.as_deref(), meta.reasoning_effort.as_deref());
+        assert_eq!(config["model_reasoning_effort"], "high");
+        assert_eq!(config["skip_git_repo_check"], true);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn per_session_codex_home_is_validated_and_passed_to_the_child() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let home = data_dir.join("work-account");
+        tokio::fs::create_dir_all(&home).await.unwrap();
+        let seen = data_dir.join("seen-home");
+        let script = data_dir.join("codex");
+        tokio::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\nprintf %s \"$CODEX_HOME\" > '{}'\n",
+                seen.display()
+            ),
+        )
+        .await
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(script);
+
+        let start = |home: String| {
+            let req: StartRequest = serde_json::from_value(
+                serde_json::json!({ "prompt": "hi", "cwd": null, "codex_home": home }),
+            )
+            .unwrap();
+            start_session(State(state.clone()), HeaderMap::new(), Json(req))
+        };
+        let missing = data_dir.join("nope").to_string_lossy().to_string();
+        assert_eq!(
+            start(missing).await.err().unwrap().status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let home_str = home.to_string_lossy().to_string();
+        let Json(meta) = start(home_str.clone()).await.unwrap();
+        assert_eq!(meta.codex_home.as_deref(), Some(home_str.as_str()));
+        let persisted = read_meta(&meta_path(&state, &meta.id)).await.unwrap();
+        assert_eq!(persisted.codex_home.as_deref(), Some(home_str.as_str()));
+
+        let mut observed = None;
+        for _ in 0..100 {
+            if let Ok(text) = tokio::fs::read_to_string(&seen).await {
+                observed = Some(text);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(observed.as_deref(), Some(home_str.as_str()));
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn missing_cwd_is_rejected_unless_created() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(data_dir.join("missing-codex"));
+        let cwd = data_dir
+            .join("project")
+            .join("sub")
+            .to_string_lossy()
+            .to_string();
+
+        let start = |create_cwd: bool| {
+            let req: StartRequest = serde_json::from_value(
+                serde_json::json!({ "prompt": "hi", "cwd": cwd.clone(), "create_cwd": create_cwd }),
+            )
+            .unwrap();
+            start_session(State(state.clone()), HeaderMap::new(), Json(req))
+        };
+        let rejected = start(false).await.err().unwrap();
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(rejected.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&body),
+            format!("cwd does not exist: {cwd}")
+        );
+        assert!(tokio::fs::metadata(sessions_root(&state)).await.is_err());
+
+        let _ = start(true).await;
+        assert!(tokio::fs::metadata(&cwd).await.unwrap().is_dir());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn oversized_prompts_are_rejected_before_anything_is_created() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(data_dir.join("missing-codex"));
+        state.max_prompt_bytes = Some(8);
+
+        let req: StartRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": " 123456789 ", "cwd": null }))
+                .unwrap();
+        let rejected = create_session(state.clone(), req).await.err().unwrap();
+        assert_eq!(rejected.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = axum::body::to_bytes(rejected.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&body),
+            "prompt is 9 bytes; the limit is 8 bytes"
+        );
+        assert!(tokio::fs::metadata(sessions_root(&state)).await.is_err());
+
+        let id = Uuid::new_v4().to_string();
+        let req: ContinueRequest =
+            serde_json::from_value(serde_json::json!({ "prompt": "ééééé", "cwd": null })).unwrap();
+        let rejected = continue_session(State(state.clone()), AxumPath(id), Json(req))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(rejected.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(tokio::fs::metadata(sessions_root(&state)).await.is_err());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn turn_routes_accept_bodies_up_to_the_prompt_limit() {
+        use tower::ServiceExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let mut state = test_state(data_dir.clone());
+        state.codex_path = Some(data_dir.join("missing-codex"));
+
+        let post = |state: AppState, uri: String, prompt_bytes: usize| async move {
+            let body =
+                serde_json::json!({ "prompt": "x".repeat(prompt_bytes), "cwd": null }).to_string();
+            let req = axum::http::Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(body))
+                .unwrap();
+            api_router(state).oneshot(req).await.unwrap().status()
+        };
+        let id = Uuid::new_v4();
+        for max_prompt_bytes in [Some(4 * 1024 * 1024), None] {
+            state.max_prompt_bytes = max_prompt_bytes;
+            let uris = [
+                "/api/sessions".to_string(),
+                format!("/api/sessions/{id}/turn"),
+                "/api/sessions/latest/turn".to_string(),
+            ];
+            for uri in uris {
+                let status = post(state.clone(), uri.clone(), 3 * 1024 * 1024).await;
+                assert_ne!(
+                    status,
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "{uri} with {max_prompt_bytes:?}"
+                );
+            }
+        }
+        state.max_prompt_bytes = Some(1024);
+        let status = post(state.clone(), "/api/sessions".to_string(), 3 * 1024 * 1024).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn session_notes_live_in_meta_or_a_sidecar_for_native_sessions() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let codex_home = data_dir.join("codex-home");
+        let native_id = Uuid::new_v4().to_string();
+        let day_dir = codex_home.join("sessions/2025/01/01");
+        tokio::fs::create_dir_all(&day_dir).await.unwrap();
+        tokio::fs::write(
+            day_dir.join(format!("rollout-2025-01-01T10-00-00-{native_id}.jsonl")),
+            format!(
+                "{{\"timestamp\":\"2025-01-01T10:00:00Z\",\"type\":\"session_meta\",\"payload\":{{\"id\":\"{native_id}\"}}}}\n"
+            ),
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            codex_home.join(".codex-global-state.json"),
+            format!(r#"{{"thread-titles":{{"titles":{{"{native_id}":"Native"}},"order":["{native_id}"]}}}}"#),
+        )
+        .await
+        .unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_home = Some(codex_home);
+
+        let note = |id: &str, text: Option<&str>| {
+            let req = NoteRequest {
+                note: text.map(str::to_string),
+            };
+            set_session_note(State(state.clone()), AxumPath(id.to_string()), Json(req))
+        };
+        let Json(native) = note(&native_id, Some(" flaky test repro "))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(native.note.as_deref(), Some("flaky test repro"));
+        let sidecar = data_dir.join("notes").join(format!("{native_id}.txt"));
+        assert_eq!(
+            tokio::fs::read_to_string(&sidecar).await.unwrap(),
+            "flaky test repro"
+        );
+        let Json(listed) = list_sessions(State(state.clone()), Query(ListSessionsQuery::default()))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(
+            listed
+                .iter()
+                .find(|m| m.id == native_id)
+                .unwrap()
+                .note
+                .as_deref(),
+            Some("flaky test repro")
+        );
+        let Json(cleared) = note(&native_id, Some("")).await.ok().unwrap();
+        assert!(cleared.note.is_none());
+        assert!(tokio::fs::metadata(&sidecar).await.is_err());
+
+        let warp: SessionMeta = serde_json::from_value(serde_json::json!({
+            "id": Uuid::new_v4().to_string(), "title": "Warp", "created_at_ms": 1, "status": "done",
+            "codex_session_id": null, "events_path": "", "stderr_path": "", "conclusion_path": "",
+        }))
+        .unwrap();
+        tokio::fs::create_dir_all(session_dir(&state, &warp.id))
+            .await
+            .unwrap();
+        write_meta(&meta_path(&state, &warp.id), &warp)
+            .await
+            .unwrap();
+        let Json(noted) = note(&warp.id, Some("waiting on review"))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(noted.note.as_deref(), Some("waiting on review"));
+        let Json(fetched) = get_session(State(state.clone()), AxumPath(warp.id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(fetched.note.as_deref(), Some("waiting on review"));
+        assert!(tokio::fs::metadata(native_note_path(&state, &warp.id))
+            .await
+            .is_err());
+
+        let missing = note(&Uuid::new_v4().to_string(), Some("x"))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn heartbeat_ticks_until_aborted() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir);
+        let id = Uuid::new_v4().to_string();
+        let mut rx = ensure_stream(&state, &id).await.subscribe();
+
+        let heartbeat = spawn_heartbeat(
+            &state,
+            &id,
+            std::time::Instant::now(),
+            Duration::from_millis(20),
+        );
+        for _ in 0..2 {
+            let msg = timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(msg.event, "codex_heartbeat");
+            let data: serde_json::Value = serde_json::from_str(&msg.data).unwrap();
+            assert_eq!(data["session_id"], id.as_str());
+            assert!(data["elapsed_ms"].as_u64().unwrap() >= 20);
+        }
+        heartbeat.abort();
+        let _ = heartbeat.await;
+        while rx.try_recv().is_ok() {}
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn codex_auth_status_reads_auth_json_without_the_secret() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let mut state = test_state(data_dir.clone());
+        state.codex_home = Some(data_dir.clone());
+        let Json(status) = codex_auth_status(State(state.clone())).await;
+        assert!(!status.logged_in);
+
+        tokio::fs::write(
+            data_dir.join("auth.json"),
+            r#"{"OPENAI_API_KEY":"sk-secret"}"#,
+        )
+        .await
+        .unwrap();
+        let Json(status) = codex_auth_status(State(state)).await;
+        assert!(status.logged_in);
+        let body = serde_json::to_string(&status).unwrap();
+        assert!(
+            body.contains("api_key") && !body.contains("sk-secret"),
+            "{body}"
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn data_dir_lock_rejects_a_second_server() {
+        let data_dir = std::env::temp_dir().join(format!("codex-warp-data-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        let lock = lock_data_dir(&data_dir).unwrap();
+        let err = lock_data_dir(&data_dir).err().unwrap().to_string();
+        assert!(err.contains("already in use"), "{err}");
+        assert!(
+            err.contains(&format!("pid {}", std::process::id())),
+            "{err}"
+        );
+        drop(lock);
+        assert!(lock_data_dir(&data_dir).is_ok());
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[test]
+    fn is_ide_origin_matches_known_extensions_only() {
+        assert!(is_ide_origin(Some("codex_vscode"), None));
+        assert!(is_ide_origin(None, Some("VSCode")));
+        assert!(!is_ide_origin(Some("guide"), Some("side-car")));
+        assert!(!is_ide_origin(Some("codex_cli_rs"), Some("cli")));
+    }
+
+    #[test]
+    fn parse_semver_finds_version_token() {
+        let v = parse_semver("codex-cli 0.46.0").unwrap();
+        assert_eq!((v.major, v.minor, v.patch), (0, 46, 0));
+        let v = parse_semver("codex v1.2.3-alpha.1").unwrap();
+        assert_eq!((v.major, v.minor, v.patch), (1, 2, 3));
+        assert!(parse_semver("codex dev build").is_none());
+    }
+
+    #[test]
+    fn split_skill_front_matter_returns_instruction_body() {
+        let text =
+            "---\nname: review\ndescription: \"Review a diff\"\n---\n# Review\n\nRead the diff.\n";
+        let (front, body) = split_skill_front_matter(text);
+        assert_eq!(
+            front,
+            Some("name: review\ndescription: \"Review a diff\"\n")
+        );
+        assert_eq!(body, "# Review\n\nRead the diff.\n");
+        assert_eq!(
+            parse_skill_front_matter(text),
+            (
+                Some("review".to_string()),
+                Some("Review a diff".to_string())
+            )
+        );
+
+        assert_eq!(
+            split_skill_front_matter("# No front matter\n"),
+            (None, "# No front matter\n")
+        );
+        assert_eq!(
+            split_skill_front_matter("---\nname: open\n"),
+            (Some("name: open\n"), "")
+        );
+    }
+
+    #[test]
+    fn skill_preview_reports_front_matter_problems() {
+        let ok = skill_preview(
+            "dir",
+            "---\nname: review\ndescription: Check it\n---\nRead the diff.\n",
+        );
+        assert!(ok.valid);
+        assert_eq!(
+            (ok.name.as_str(), ok.description.as_str()),
+            ("review", "Check it")
+        );
+        assert_eq!(ok.body, "Read the diff.\n");
+
+        let bare = skill_preview("dir", "Read the diff.\n");
+        assert!(!bare.valid);
+        assert_eq!(bare.errors, vec!["missing front matter"]);
+        assert_eq!(
+            (bare.name.as_str(), bare.body.as_str()),
+            ("dir", "Read the diff.\n")
+        );
+
+        let nameless = skill_preview("dir", "---\ndescription: x\n---\nbody\n");
+        assert_eq!(nameless.errors, vec!["missing name field"]);
+
+        let open = skill_preview("dir", "---\nname: open\n");
+        assert_eq!(open.errors, vec!["missing closing --- after front matter"]);
+    }
+
+    #[test]
+    fn strip_tool_citations_removes_markup_spans() {
+        let raw = "Done. \u{E200}cite\u{E202}turn0file1\u{E201} See notes \u{E200}x\u{E201}.";
+        assert_eq!(strip_tool_citations(raw), "Done. See notes.");
+        assert_eq!(strip_tool_citations("plain text"), "plain text");
+        // Matches the desktop app: an unterminated marker drops the rest of the text.
+        assert_eq!(strip_tool_citations("open \u{E200}never closed"), "open");
+    }
+
+    #[tokio::test]
+    async fn update_conclusion_from_events_uses_last_agent_message() {
+        let dir = std::env::temp_dir().join(format!("codex-warp-conclusion-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let completed = |text: &str| {
+            serde_json::json!({
+                "method": "item/completed",
+                "params": { "item": { "type": "agentMessage", "id": "m", "text": text } }
+            })
+            .to_string()
+        };
+        let lines = [
+            completed("first"),
+            serde_json::json!({ "method": "turn/started", "params": {} }).to_string(),
+            completed("Final answer \u{E200}cite\u{E201}."),
+        ];
+        tokio::fs::write(dir.join("events.jsonl"), lines.join("\n"))
+            .await
+            .unwrap();
+
+        update_conclusion_from_events(&dir).await.unwrap();
+        assert_eq!(
+            tokio::fs::read_to_string(dir.join("conclusion.md"))
+                .await
+                .unwrap(),
+            "Final answer."
+        );
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+
+    #[test]
+    fn summarize_usage_buckets_by_day_session_and_model() {
+        let record =
+            |ts_ms: u64, session_id: &str, model: Option<&str>, total_tokens: u64| UsageRecord {
+                ts_ms,
+                session_id: session_id.to_string(),
+                thread_id: None,
+                total_tokens,
+                input_tokens: total_tokens - 10,
+                output_tokens: 10,
+                reasoning_output_tokens: 0,
+                cached_input_tokens: 5,
+                context_window: 200_000,
+                model: model.map(|m| m.to_string()),
+            };
+        // 2026-01-31T09:11:23Z and 2026-02-01T00:00:00Z
+        let records = vec![
+            record(1_769_850_683_000, "a", Some("gpt-5-codex"), 100),
+            record(1_769_850_683_500, "b", None, 200),
+            record(1_769_904_000_000, "a", Some("gpt-5-codex"), 300),
+        ];
+
+        let by_day = summarize_usage(records.clone(), UsageGroupBy::Day);
+        assert_eq!(by_day.len(), 2);
+        assert_eq!(by_day[0].key, "2026-01-31");
+        assert_eq!((by_day[0].records, by_day[0].total_tokens), (2, 300));
+        assert_eq!(by_day[1].key, "2026-02-01");
+        assert_eq!(
+            (by_day[1].input_tokens, by_day[1].cached_input_tokens),
+            (290, 5)
+        );
+
+        let by_session = summarize_usage(records.clone(), UsageGroupBy::Session);
+        assert_eq!(by_session[0].key, "a");
+        assert_eq!(
+            (by_session[0].records, by_session[0].output_tokens),
+            (2, 20)
+        );
+
+        let by_model = summarize_usage(records, UsageGroupBy::Model);
+        let keys: Vec<&str> = by_model.iter().map(|b| b.key.as_str()).collect();
+        assert_eq!(keys, vec!["gpt-5-codex", "unknown"]);
+
+        assert!(summarize_usage(Vec::new(), UsageGroupBy::Day).is_empty());
+    }
+
+    #[test]
+    fn context_usage_dropped_detects_large_drops_only() {
+        let snap = |total_tokens: u64| TokenUsageSnapshot {
+            window: 200_000,
+            total_tokens,
+            input_tokens: total_tokens,
+            output_tokens: 0,
+            reasoning_output_tokens: 0,
+            cached_input_tokens: 0,
+            pct_left: 0,
+        };
+        assert!(context_usage_dropped(snap(180_000), snap(30_000)));
+        assert!(!context_usage_dropped(snap(180_000), snap(170_000)));
+        assert!(!context_usage_dropped(snap(10_000), snap(2_000)));
+        assert!(!context_usage_dropped(snap(30_000), snap(180_000)));
+    }
+
+    #[tokio::test]
+    async fn find_last_prompt_handles_small_rollout_without_trailing_newline() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("codex-warp-rollout-{}.jsonl", Uuid::new_v4()));
+
+        let meta =
+            serde_json::json!({ "type": "session_meta", "payload": { "cwd": "/tmp" } }).to_string();
+        let prompt = serde_json::json!({
+            "type": "event_msg",
+            "payload": { "type": "user_message", "message": "fix the flaky test" }
+        })
+        .to_string();
+        // No trailing newline after the last record.
+        tokio::fs::write(&path, format!("{meta}\n{prompt}"))
+            .await
+            .unwrap();
+
+        let lines = read_tail_bytes(&path, 96 * 1024).await;
+        assert_eq!(lines, vec![meta.clone(), prompt.clone()]);
+        assert_eq!(
+            find_last_prompt_from_rollout(&path).await.as_deref(),
+            Some("fix the flaky test")
+        );
+
+        // Seeking exactly onto a line boundary must keep the first whole line.
+        let tail_len = prompt.len() as u64;
+        assert_eq!(read_tail_bytes(&path, tail_len).await, vec![prompt.clone()]);
+        assert!(read_tail_bytes(&path, tail_len - 1).await.is_empty());
+
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+
+    #[tokio::test]
+    async fn native_rollout_watcher_broadcasts_appended_lines_while_subscribed() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let state = test_state(data_dir.clone());
+        let rollout = data_dir.join("rollout.jsonl");
+        tokio::fs::write(&rollout, "{\"type\":\"session_meta\"}\n")
+            .await
+            .unwrap();
+        state
+            .native_cache
+            .lock()
+            .await
+            .rollouts_by_session
+            .insert("n1".to_string(), vec![rollout.clone()]);
+
+        let mut rx = ensure_stream(&state, "n1").await.subscribe();
+        ensure_rollout_watcher(&state, "n1").await;
+        ensure_rollout_watcher(&state, "n1").await;
+        assert_eq!(state.rollout_watchers.lock().await.len(), 1);
+
+        // Only complete lines are sent; the partial one waits for its newline.
+        let line = r#"{"timestamp":"2025-01-01T00:00:00Z","type":"event_msg"}"#;
+        let mut file = std::fs::File::options()
+            .append(true)
+            .open(&rollout)
+            .unwrap();
+        std::io::Write::write_all(&mut file, format!("{line}\n{{\"type\":").as_bytes()).unwrap();
+        let msg = timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg.event, "codex_event");
+        let data: serde_json::Value = serde_json::from_str(&msg.data).unwrap();
+        assert_eq!(data["raw"], line);
+        assert_eq!(data["ts_ms"], 1_735_689_600_000u64);
+        std::io::Write::write_all(&mut file, b"\"turn_context\"}\n").unwrap();
+        let msg = timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let data: serde_json::Value = serde_json::from_str(&msg.data).unwrap();
+        assert_eq!(data["json"]["type"], "turn_context");
+
+        drop(rx);
+        for _ in 0..100 {
+            if state.rollout_watchers.lock().await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(state.rollout_watchers.lock().await.is_empty());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[test]
+    fn rollout_scan_only_relists_changed_directories() {
+        let root = std::env::temp_dir().join(format!("codex-warp-scan-{}", Uuid::new_v4()));
+        let rollout = |day: u32, id: &str| {
+            format!("2025/01/{day:02}/rollout-2025-01-{day:02}T10-00-00-{id}.jsonl")
+        };
+        for day in 1..=5 {
+            let path = root.join(rollout(day, &format!("s{day}")));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "{}\n").unwrap();
+        }
+        // Backdate every directory so its listing can be trusted on the next scan.
+        let old = std::time::SystemTime::now() - Duration::from_secs(3600);
+        let mut dirs = vec![root.clone(), root.join("2025"), root.join("2025/01")];
+        dirs.extend((1..=5).map(|day| root.join(format!("2025/01/{day:02}"))));
+        for dir in &dirs {
+            std::fs::File::open(dir).unwrap().set_modified(old).unwrap();
+        }
+
+        let mut first = HashMap::new();
+        let (found, listed) = scan_codex_rollouts(&root, &HashMap::new(), &mut first);
+        assert_eq!((found.len(), listed), (5, 8));
+
+        let mut second = HashMap::new();
+        let (again, listed) = scan_codex_rollouts(&root, &first, &mut second);
+        assert_eq!((again, listed), (found, 0));
+
+        std::fs::write(root.join(rollout(3, "late")), "{}\n").unwrap();
+        let mut third = HashMap::new();
+        let (found, listed) = scan_codex_rollouts(&root, &second, &mut third);
+        assert_eq!(listed, 1);
+        assert!(found.contains_key("late") && found.contains_key("s3"));
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn native_derived_cache_hits_until_mtime_changes_and_evicts_lru() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-derived-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let state = test_state(data_dir.clone());
+        let rollout = data_dir.join("rollout.jsonl");
+        let prompt_line = |text: &str| {
+            serde_json::json!({ "type": "event_msg", "payload": { "type": "user_message", "message": text } })
+                .to_string()
+        };
+
+        tokio::fs::write(&rollout, prompt_line("first prompt"))
+            .await
+            .unwrap();
+        let mtime = std::fs::metadata(&rollout).unwrap().modified().unwrap();
+        let first = get_or_compute_native_derived(&state, "s1", &rollout).await;
+        assert_eq!(first.last_prompt.as_deref(), Some("first prompt"));
+
+        // Same path and mtime: served from the cache even though the file changed underneath.
+        tokio::fs::write(&rollout, prompt_line("second prompt"))
+            .await
+            .unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&rollout)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        let cached = get_or_compute_native_derived(&state, "s1", &rollout).await;
+        assert_eq!(cached.last_prompt.as_deref(), Some("first prompt"));
+
+        let later = mtime + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&rollout)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        let refreshed = get_or_compute_native_derived(&state, "s1", &rollout).await;
+        assert_eq!(refreshed.last_prompt.as_deref(), Some("second prompt"));
+
+        let mut cache = state.native_cache.lock().await;
+        for id in ["a", "b"] {
+            cache.insert_derived(id.to_string(), refreshed.clone(), 3);
+        }
+        // Touch s1 so "a" becomes the least recently used entry.
+        assert!(cache
+            .get_derived("s1", &rollout, refreshed.latest_mtime_ms)
+            .is_some());
+        cache.insert_derived("c".to_string(), refreshed.clone(), 3);
+        let mut keys: Vec<&str> = cache
+            .derived_by_session
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["b", "c", "s1"]);
+        drop(cache);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn list_cwds_groups_sessions_by_directory() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        for (cwd, last_used_at_ms) in [
+            ("/work/app/sub", 2_000),
+            ("/work/app/sub/", 5_000),
+            ("/work/lib", 3_000),
+        ] {
+            let id = Uuid::new_v4().to_string();
+            let dir = session_dir(&state, &id);
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            let meta = SessionMeta {
+                cwd: Some(cwd.to_string()),
+                last_used_at_ms,
+                ..test_meta(&id, &dir)
+            };
+            write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+        }
+
+        let Json(cwds) = list_cwds(State(state.clone())).await.unwrap();
+        let got: Vec<(&str, usize, u64)> = cwds
+            .iter()
+            .map(|c| (c.cwd.as_str(), c.session_count, c.last_used_at_ms))
+            .collect();
+        assert_eq!(
+            got,
+            vec![("/work/app/sub", 2, 5_000), ("/work/lib", 1, 3_000)]
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn list_sessions_filters_by_status_and_cwd() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        let state = test_state(data_dir.clone());
+        let mut ids = Vec::new();
+        for (cwd, status) in [
+            (Some("/work/app/sub"), SessionStatus::Error),
+            (None, SessionStatus::Done),
+        ] {
+            let id = Uuid::new_v4().to_string();
+            let dir = session_dir(&state, &id);
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            let meta = SessionMeta {
+                cwd: cwd.map(str::to_string),
+                status,
+                ..test_meta(&id, &dir)
+            };
+            write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+            ids.push(id);
+        }
+
+        let list = |status: Option<SessionStatus>, cwd: &str| {
+            let q = ListSessionsQuery {
+                status,
+                cwd: Some(cwd.to_string()),
+                ..Default::default()
+            };
+            let state = state.clone();
+            async move {
+                let Json(sessions) = list_sessions(State(state), Query(q)).await.unwrap();
+                sessions.into_iter().map(|s| s.id).collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(list(None, "/work/app/").await, vec![ids[0].clone()]);
+        assert_eq!(
+            list(Some(SessionStatus::Error), "").await,
+            vec![ids[0].clone()]
+        );
+        assert_eq!(
+            list(Some(SessionStatus::Done), "").await,
+            vec![ids[1].clone()]
+        );
+        assert!(list(None, "/work/ap").await.is_empty());
+        assert!(list(Some(SessionStatus::Done), "/work/app")
+            .await
+            .is_empty());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn list_sessions_sorts_by_last_used_then_created() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+
+        let state = test_state(data_dir.clone());
+
+        let id_a = format!("s-{}", Uuid::new_v4());
+        let id_b = format!("s-{}", Uuid::new_v4());
+
+        tokio::fs::create_dir_all(session_dir(&state, &id_a))
+            .await
+            .unwrap();
+        tokio::fs::create_dir_all(session_dir(&state, &id_b))
+            .await
+            .unwrap();
+
+        let a_dir = session_dir(&state, &id_a);
+        let b_dir = session_dir(&state, &id_b);
+
+        let meta_a = SessionMeta {
+            id: id_a.clone(),
+            title: "A".to_string(),
+            created_at_ms: 1_000,
+            last_used_at_ms: 2_000,
+            cwd: None,
+            status: SessionStatus::Done,
+            codex_session_id: None,
+            context_window: None,
+            context_used_tokens: None,
+            context_left_pct: None,
+            events_path: a_dir.join("events.jsonl").to_string_lossy().to_string(),
+            stderr_path: a_dir.join("stderr.log").to_string_lossy().to_string(),
+            conclusion_path: a_dir.join("conclusion.md").to_string_lossy().to_string(),
+            origin: None,
+            git_branch: None,
+            ide_workspace: None,
+            model: None,
+            reasoning_effort: None,
+            codex_home: None,
+            turn_count: 0,
+            cumulative_tokens: 0,
+            last_turn_read_only: false,
+            instructions: None,
+            note: None,
+            archived: false,
+            rate_limit: None,
+            rollout_path: None,
+        };
+        write_meta(&meta_path(&state, &id_a), &meta_a).await.unwrap();
+
+        // last_used_at_ms=0 should be treated as created_at_ms for ordering.
+        let meta_b = SessionMeta {
+            id: id_b.clone(),
+            title: "B".to_string(),
+            created_at_ms: 3_000,
+            last_used_at_ms: 0,
+            cwd: None,
+            status: SessionStatus::Done,
+            codex_session_id: None,
+            context_window: None,
+            context_used_tokens: None,
+            context_left_pct: None,
+            events_path: b_dir.join("events.jsonl").to_string_lossy().to_string(),
+            stderr_path: b_dir.join("stderr.log").to_string_lossy().to_string(),
+            conclusion_path: b_dir.join("conclusion.md").to_string_lossy().to_string(),
+            origin: None,
+            git_branch: None,
+            ide_workspace: None,
+            model: None,
+            reasoning_effort: None,
+            codex_home: None,
+            turn_count: 0,
+            cumulative_tokens: 0,
+            last_turn_read_only: false,
+            instructions: None,
+            note: None,
+            archived: false,
+            rate_limit: None,
+            rollout_path: None,
+        };
+        write_meta(&meta_path(&state, &id_b), &meta_b).await.unwrap();
+
+        let Json(sessions) =
+            list_sessions(State(state.clone()), Query(ListSessionsQuery::default()))
+                .await
+                .unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].id, id_b);
+        assert_eq!(sessions[1].id, id_a);
+
+        let b = sessions.iter().find(|s| s.id == id_b).unwrap();
+        assert_eq!(b.last_used_at_ms, 3_000);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn list_runs_reports_in_progress_runs_oldest_first() {
+        let state =
+            test_state(std::env::temp_dir().join(format!("codex-warp-data-{}", Uuid::new_v4())));
+        for (id, pid, started_at_ms) in [("b", Some(42), 2_000), ("a", None, 1_000)] {
+            state.runs.lock().await.insert(
+                id.to_string(),
+                RunHandle {
+                    cancel: None,
+                    pid,
+                    started_at_ms,
+                    webhook: None,
+                },
+            );
+        }
+        let Json(runs) = list_runs(State(state)).await;
+        let runs: Vec<_> = runs
+            .iter()
+            .map(|r| (r.session_id.as_str(), r.pid, r.started_at_ms))
+            .collect();
+        assert_eq!(runs, vec![("a", None, 1_000), ("b", Some(42), 2_000)]);
+    }
+
+    #[tokio::test]
+    async fn list_sessions_sorts_by_title_ignoring_case() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+
+        let state = test_state(data_dir.clone());
+
+        for (i, title) in ["beta", "Alpha", "gamma", "Beta2"].into_iter().enumerate() {
+            let id = format!("s-{i}-{}", Uuid::new_v4());
+            let dir = session_dir(&state, &id);
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            let meta = SessionMeta {
+                title: title.to_string(),
+                created_at_ms: 1_000 + i as u64,
+                last_used_at_ms: 0,
+                ..test_meta(&id, &dir)
+            };
+            write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+        }
+
+        let titles = |uri: &'static str| {
+            let state = state.clone();
+            async move {
+                let q: Query<ListSessionsQuery> =
+                    Query::try_from_uri(&uri.parse().unwrap()).unwrap();
+                let Json(sessions) = list_sessions(State(state), q).await.unwrap();
+                sessions.into_iter().map(|s| s.title).collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(
+            titles("/api/sessions?sort=title").await,
+            vec!["Alpha", "beta", "Beta2", "gamma"]
+        );
+        assert_eq!(
+            titles("/api/sessions?sort=title&order=desc").await,
+            vec!["gamma", "Beta2", "beta", "Alpha"]
+        );
+        assert_eq!(
+            titles("/api/sessions?sort=created&order=asc").await,
+            vec!["beta", "Alpha", "gamma", "Beta2"]
+        );
+        assert_eq!(
+            titles("/api/sessions").await,
+            vec!["Beta2", "gamma", "Alpha", "beta"]
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn evict_sessions_over_cap_removes_oldest_finished_sessions() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+
+        let mut state = test_state(data_dir.clone());
+        state.max_sessions = Some(2);
+
+        let mut ids = Vec::new();
+        for (i, status) in [
+            SessionStatus::Done,
+            SessionStatus::Running,
+            SessionStatus::Done,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let id = format!("s-{}", Uuid::new_v4());
+            let dir = session_dir(&state, &id);
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            let meta = SessionMeta {
+                title: format!("S{i}"),
+                created_at_ms: 1_000 + i as u64,
+                last_used_at_ms: 1_000 + i as u64,
+                status,
+                ..test_meta(&id, &dir)
+            };
+            write_meta(&meta_path(&state, &id), &meta).await.unwrap();
+            ids.push(id);
+        }
+
+        // Three sessions with a cap of two: making room for one more must drop two, but the
+        // running one is never evicted.
+        evict_sessions_over_cap(&state).await;
+        assert!(tokio::fs::metadata(session_dir(&state, &ids[0]))
+            .await
+            .is_err());
+        assert!(tokio::fs::metadata(session_dir(&state, &ids[1]))
+            .await
+            .is_ok());
+        assert!(tokio::fs::metadata(session_dir(&state, &ids[2]))
+            .await
+            .is_err());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn fork_session_copies_thread_into_a_fresh_session() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let state = test_state(data_dir.clone());
+
+        let id = format!("s-{}", Uuid::new_v4());
+        let dir = session_dir(&state, &id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let source = SessionMeta {
+            title: "Fix the build".to_string(),
+            created_at_ms: 1_000,
+            last_used_at_ms: 2_000,
+            cwd: Some("/tmp/repo".to_string()),
+            codex_session_id: Some("thread-1".to_string()),
+            model: Some("gpt-5-codex".to_string()),
+            ..test_meta(&id, &dir)
+        };
+        write_meta(&meta_path(&state, &id), &source).await.unwrap();
+        let before = tokio::fs::read(meta_path(&state, &id)).await.unwrap();
+
+        let Json(fork) = fork_session(State(state.clone()), AxumPath(id.clone()))
+            .await
+            .ok()
+            .unwrap();
+        assert_ne!(fork.id, id);
+        assert_eq!(fork.title, "Fix the build (fork)");
+        assert!(matches!(fork.status, SessionStatus::Done));
+        assert_eq!(fork.codex_session_id.as_deref(), Some("thread-1"));
+        assert_eq!(fork.cwd.as_deref(), Some("/tmp/repo"));
+        assert_eq!(fork.model.as_deref(), Some("gpt-5-codex"));
+        assert!(fork
+            .events_path
+            .starts_with(&*session_dir(&state, &fork.id).to_string_lossy()));
+        assert_eq!(
+            read_meta(&meta_path(&state, &fork.id)).await.unwrap().title,
+            fork.title
+        );
+        assert_eq!(
+            tokio::fs::read(meta_path(&state, &id)).await.unwrap(),
+            before
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[test]
+    fn normalize_event_maps_app_server_and_rollout_shapes() {
+        let event = |json: serde_json::Value| UiEvent {
+            session_id: "s".to_string(),
+            ts_ms: 10,
+            stream: "stdout".to_string(),
+            raw: json.to_string(),
+            json: Some(json),
+        };
+
+        let agent = normalize_event(&event(serde_json::json!({
+            "method": "item/completed",
+            "params": { "item": { "type": "agentMessage", "id": "m1", "text": "hi" } }
+        })))
+        .unwrap();
+        assert_eq!(agent.kind, NormalizedKind::AgentMessage);
+        assert_eq!(agent.item_id.as_deref(), Some("m1"));
+        assert_eq!(agent.text.as_deref(), Some("hi"));
+
+        let command = normalize_event(&event(serde_json::json!({
+            "method": "item/completed",
+            "params": { "item": { "type": "commandExecution", "id": "c1", "command": "ls", "exitCode": 2 } }
+        })))
+        .unwrap();
+        assert_eq!(command.kind, NormalizedKind::Command);
+        assert_eq!(command.command.as_deref(), Some("ls"));
+        assert_eq!(command.exit_code, Some(2));
+
+        let rollout_agent = normalize_event(&event(serde_json::json!({
+            "type": "event_msg",
+            "payload": { "type": "agent_message", "message": "hi" }
+        })))
+        .unwrap();
+        assert_eq!(rollout_agent.kind, NormalizedKind::AgentMessage);
+
+        let prompt = normalize_event(&event(
+            serde_json::json!({ "type": "app.prompt", "prompt": "do it" }),
+        ))
+        .unwrap();
+        assert_eq!(prompt.kind, NormalizedKind::Prompt);
+
+        assert!(normalize_event(&event(
+            serde_json::json!({ "method": "item/agentMessage/delta" })
+        ))
+        .is_none());
+    }
+
+    #[test]
+    fn transcript_messages_merge_deltas_and_pair_prompts() {
+        let events: Vec<UiEvent> = [
+            serde_json::json!({ "type": "app.prompt", "prompt": "fix it" }),
+            serde_json::json!({ "method": "item/agentMessage/delta", "params": { "itemId": "m1", "delta": "Look" } }),
+            serde_json::json!({ "method": "item/agentMessage/delta", "params": { "itemId": "m1", "delta": "ing" } }),
+            serde_json::json!({ "method": "item/completed", "params": { "item": { "type": "reasoning", "id": "r1" } } }),
+            serde_json::json!({
+                "method": "item/completed",
+                "params": { "item": { "type": "agentMessage", "id": "m1", "text": "Looking now." } }
+            }),
+            serde_json::json!({ "type": "app.prompt", "prompt": "and?" }),
+            serde_json::json!({ "method": "item/agentMessage/delta", "params": { "itemId": "m2", "delta": "Done, " } }),
+            serde_json::json!({ "method": "item/agentMessage/delta", "params": { "itemId": "m2", "delta": "so far" } }),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, json)| UiEvent {
+            session_id: "s".to_string(),
+            ts_ms: i as u64,
+            stream: "stdout".to_string(),
+            raw: json.to_string(),
+            json: Some(json),
+        })
+        .collect();
+        let message = |role, text: &str, ts_ms| TranscriptMessage {
+            role,
+            text: text.to_string(),
+            ts_ms,
+        };
+        assert_eq!(
+            transcript_messages(&events),
+            vec![
+                message(MessageRole::User, "fix it", 0),
+                message(MessageRole::Assistant, "Looking now.", 1),
+                message(MessageRole::User, "and?", 5),
+                message(MessageRole::Assistant, "Done, so far", 6),
+            ]
+        );
+
+        let rollout: Vec<UiEvent> = [
+            r#"{"type":"event_msg","payload":{"type":"user_message","message":"hi"}}"#,
+            r#"{"type":"response_item","payload":{"type":"message","role":"user"}}"#,
+            r#"{"type":"event_msg","payload":{"type":"agent_message","message":"hello"}}"#,
+        ]
+        .into_iter()
+        .map(|line| rollout_line_event("s", line.to_string()))
+        .collect();
+        let messages = transcript_messages(&rollout);
+        let turns: Vec<(MessageRole, &str)> =
+            messages.iter().map(|m| (m.role, m.text.as_str())).collect();
+        assert_eq!(
+            turns,
+            vec![(MessageRole::User, "hi"), (MessageRole::Assistant, "hello")]
+        );
+
+        // A warp turn on a native thread shows up in both sources; a re-sent prompt is kept.
+        let mut overlap: Vec<UiEvent> = [
+            r#"{"type":"event_msg","payload":{"type":"user_message","message":"fix it"}}"#,
+            r#"{"type":"event_msg","payload":{"type":"agent_message","message":"ok"}}"#,
+        ]
+        .into_iter()
+        .map(|line| rollout_line_event("s", line.to_string()))
+        .collect();
+        overlap.extend(
+            [
+                serde_json::json!({ "type": "app.prompt", "prompt": "fix it" }),
+                serde_json::json!({
+                    "method": "item/completed",
+                    "params": { "item": { "type": "agentMessage", "id": "m1", "text": "ok" } }
+                }),
+                serde_json::json!({ "type": "app.prompt", "prompt": "fix it" }),
+                serde_json::json!({
+                    "method": "item/completed",
+                    "params": { "item": { "type": "agentMessage", "id": "m2", "text": "ok" } }
+                }),
+            ]
+            .into_iter()
+            .map(|json| UiEvent {
+                session_id: "s".to_string(),
+                ts_ms: 0,
+                stream: "stdout".to_string(),
+                raw: json.to_string(),
+                json: Some(json),
+            }),
+        );
+        let messages = transcript_messages(&overlap);
+        let turns: Vec<(MessageRole, &str)> =
+            messages.iter().map(|m| (m.role, m.text.as_str())).collect();
+        assert_eq!(
+            turns,
+            vec![
+                (MessageRole::User, "fix it"),
+                (MessageRole::Assistant, "ok"),
+                (MessageRole::User, "fix it"),
+                (MessageRole::Assistant, "ok"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn load_session_backlog_replays_warp_stdout_events_in_order() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+
+        let state = test_state(data_dir.clone());
+        let session_id = format!("s-{}", Uuid::new_v4());
+        let dir = session_dir(&state, &session_id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+
+        let events_path = dir.join("events.jsonl");
+        let line1 =
+            serde_json::json!({ "type": "app.prompt", "prompt": "Hello", "_ts_ms": 100u64 })
+                .to_string();
+        let line2 = serde_json::json!({
+            "type": "event_msg",
+            "payload": { "type": "assistant_message", "message": "World" },
+            "_ts_ms": 200u64
+        })
+        .to_string();
+        tokio::fs::write(&events_path, format!("{line1}\n{line2}\n"))
+            .await
+            .unwrap();
+
+        let backlog = load_session_backlog(&state, &session_id, Some(50))
+            .await
+            .unwrap();
+        assert_eq!(backlog.len(), 2);
+        assert_eq!(backlog[0].stream, "stdout");
+        assert_eq!(backlog[0].ts_ms, 100);
+        assert_eq!(backlog[0].raw, line1);
+        assert_eq!(backlog[1].ts_ms, 200);
+        assert_eq!(backlog[1].raw, line2);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn session_prompts_lists_deduped_prompts_in_order() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let state = test_state(data_dir.clone());
+        let session_id = format!("s-{}", Uuid::new_v4());
+        let dir = session_dir(&state, &session_id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+
+        let lines = [
+            serde_json::json!({ "type": "app.prompt", "prompt": "run tests", "_ts_ms": 300u64 }),
+            serde_json::json!({ "method": "turn/started", "params": {}, "_ts_ms": 301u64 }),
+            serde_json::json!({ "type": "app.prompt", "prompt": "fix lint", "_ts_ms": 100u64,
+                "turn_metadata": { "source": "palette" } }),
+            serde_json::json!({ "type": "app.prompt", "prompt": "run tests", "_ts_ms": 400u64 }),
+            serde_json::json!({ "type": "app.prompt", "prompt": "fix lint", "_ts_ms": 500u64 }),
+        ]
+        .map(|v| v.to_string());
+        tokio::fs::write(dir.join("events.jsonl"), lines.join("\n"))
+            .await
+            .unwrap();
+
+        let Json(prompts) = session_prompts(State(state.clone()), AxumPath(session_id))
+            .await
+            .ok()
+            .unwrap();
+        let got: Vec<(u64, &str)> = prompts
+            .iter()
+            .map(|p| (p.ts_ms, p.prompt.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![(100, "fix lint"), (300, "run tests"), (500, "fix lint")]
+        );
+        assert_eq!(
+            prompts[0].turn_metadata,
+            Some(serde_json::json!({ "source": "palette" }))
+        );
+
+        let missing = session_prompts(State(state), AxumPath("missing".to_string())).await;
+        assert_eq!(missing.err().unwrap().status(), StatusCode::NOT_FOUND);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn resume_backlog_skips_events_up_to_last_event_id() {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+
+        let state = test_state(data_dir.clone());
+        let session_id = format!("s-{}", Uuid::new_v4());
+        let dir = session_dir(&state, &session_id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let lines: Vec<String> = [100u64, 200, 200, 300]
+            .iter()
+            .enumerate()
+            .map(|(i, ts)| {
+                serde_json::json!({ "type": "app.prompt", "prompt": format!("p{i}"), "_ts_ms": ts })
+                    .to_string()
+            })
+            .collect();
+        tokio::fs::write(dir.join("events.jsonl"), format!("{}\n", lines.join("\n")))
+            .await
+            .unwrap();
+
+        let load = || async {
+            load_session_backlog(&state, &session_id, Some(50))
+                .await
+                .unwrap()
+        };
+        let first = resume_backlog(load().await, &mut EventSeq::default(), None);
+        let ids: Vec<u64> = first.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![100_000, 200_000, 200_001, 300_000]);
+
+        // Reconnecting after the first event at ts 200 replays only what came after it.
+        let resumed = resume_backlog(load().await, &mut EventSeq::default(), Some(200_000));
+        let raws: Vec<&str> = resumed.iter().map(|(_, evt)| evt.raw.as_str()).collect();
+        assert_eq!(raws, vec![lines[2].as_str(), lines[3].as_str()]);
+
+        // Live events keep numbering after the resumed backlog.
+        let mut seq = EventSeq::default();
+        resume_backlog(load().await, &mut seq, Some(300_000));
+        assert_eq!(seq.next(300), 300_001);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn live_event_ids_match_the_resumed_backlog() {
+        use tower::ServiceExt;
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("codex-warp-data-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+
+        let state = test_state(data_dir.clone());
+        let session_id = format!("s-{}", Uuid::new_v4());
+        let dir = session_dir(&state, &session_id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let events_path = dir.join("events.jsonl");
+        let prompt = |text: &str| serde_json::json!({ "type": "app.prompt", "prompt": text, "_ts_ms": 200u64 });
+        append_app_event(&state, &session_id, &events_path, 200, prompt("first"))
+            .await
+            .unwrap();
+
+        // (id, data) of each SSE frame carrying `codex_event`, read until `until` shows up.
+        async fn frames(
+            app: Router,
+            uri: &str,
+            last_event_id: Option<&str>,
+            until: &str,
+        ) -> Vec<(Option<String>, String)> {
+            let mut req = axum::http::Request::builder().uri(uri);
+            if let Some(id) = last_event_id {
+                req = req.header("last-event-id", id);
+            }
+            let res = app
+                .oneshot(req.body(axum::body::Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let mut body = res.into_body().into_data_stream();
+            let mut text = String::new();
+            while !text.contains(until) {
+                let chunk = timeout(Duration::from_secs(2), body.next())
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+                text.push_str(&String::from_utf8_lossy(&chunk));
+            }
+            text.split("\n\n")
+                .filter(|frame| frame.contains("event: codex_event"))
+                .map(|frame| {
+                    let field = |name: &str| {
+                        frame
+                            .lines()
+                            .find_map(|l| l.strip_prefix(name))
+                            .map(|v| v.trim_start().to_string())
+                    };
+                    (field("id:"), field("data:").unwrap_or_default())
+                })
+                .collect()
+        }
+
+        let uri = format!("/api/sessions/{session_id}/stream");
+        let app = api_router(state.clone());
+        let live = {
+            let uri = uri.clone();
+            tokio::spawn(async move { frames(app, &uri, None, "third").await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        append_app_event(&state, &session_id, &events_path, 200, prompt("second"))
+            .await
+            .unwrap();
+        let notice = serde_json::json!({ "type": "app.agent_message", "text": "live only" });
+        broadcast_ui_event(
+            &state,
+            UiEvent {
+                session_id: session_id.clone(),
+                ts_ms: 200,
+                stream: "stdout".to_string(),
+                raw: notice.to_string(),
+                json: Some(notice),
+            },
+        )
+        .await;
+        append_app_event(&state, &session_id, &events_path, 200, prompt("third"))
+            .await
+            .unwrap();
+        let live = live.await.unwrap();
+        let ids: Vec<Option<&str>> = live.iter().map(|(id, _)| id.as_deref()).collect();
+        assert_eq!(
+            ids,
+            vec![Some("200000"), Some("200001"), None, Some("200002")]
+        );
+
+        // Resuming after "second" replays "third" under the id it had live.
+        let resumed = frames(api_router(state.clone()), &uri, Some("200001"), "third").await;
+        assert_eq!(resumed.len(), 1);
+        assert_eq!(resumed[0].0.as_deref(), Some("200002"));
+        assert!(resumed[0].1.contains("third"));
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+}
+
+async fn read_tail_lines(path: &Path, max_lines: usize) -> Vec<String> {
     if max_lines == 0 {
         return Vec::new();
     }
-    let file = match tokio::fs::File::open(path).await {
-        Ok(f) => f,
-        Err(_) => return Vec::new(),
+    let file = match tokio::fs::File::open(path).await {
+        Ok(f) => f,
+        Err(_) => return Vec::new(),
+    };
+    let mut out: VecDeque<String> = VecDeque::new();
+    let mut lines = BufReader::new(file).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        while out.len() >= max_lines {
+            out.pop_front();
+        }
+        out.push_back(line);
+    }
+    out.into_iter().collect()
+}
+
+#[derive(Deserialize)]
+struct CodexHistoryLine {
+    session_id: String,
+    ts: i64,
+    text: String,
+}
+
+#[derive(Clone)]
+struct CodexHistoryAgg {
+    first_ts_ms: u64,
+    last_ts_ms: u64,
+    last_text: String,
+}
+
+fn parse_rollout_session_id(file_name: &str) -> Option<String> {
+    if !file_name.starts_with("rollout-") || !file_name.ends_with(".jsonl") {
+        return None;
+    }
+    let base = &file_name["rollout-".len()..file_name.len() - ".jsonl".len()];
+    if base.len() <= 20 {
+        return None;
+    }
+    // "YYYY-MM-DDTHH-MM-SS-<session_id>"
+    if base.as_bytes().get(19) != Some(&b'-') {
+        return None;
+    }
+    let id = base[20..].trim();
+    if id.is_empty() {
+        return None;
+    }
+    Some(id.to_string())
+}
+
+const SCAN_MTIME_SLACK: Duration = Duration::from_secs(1);
+
+/// Walks a rollout tree. Adding or removing a rollout bumps its directory's mtime, so a directory
+/// whose mtime matches `previous` is taken from there instead of being listed again; `next`
+/// receives every directory visited. Returns the rollouts and how many directories were listed.
+fn scan_codex_rollouts(
+    root: &Path,
+    previous: &HashMap<PathBuf, ScannedDir>,
+    next: &mut HashMap<PathBuf, ScannedDir>,
+) -> (HashMap<String, Vec<PathBuf>>, usize) {
+    let mut out: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut listed = 0;
+
+    let mut stack: Vec<PathBuf> = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(mtime) = std::fs::metadata(&dir).and_then(|m| m.modified()) else {
+            continue;
+        };
+        let scanned = match previous.get(&dir) {
+            Some(cached) if cached.mtime == mtime && mtime + SCAN_MTIME_SLACK <= cached.read_at => {
+                cached.clone()
+            }
+            _ => {
+                let read_at = std::time::SystemTime::now();
+                let rd = match std::fs::read_dir(&dir) {
+                    Ok(r) => r,
+                    Err(_) => continue,
+                };
+                listed += 1;
+                let mut scanned = ScannedDir {
+                    mtime,
+                    read_at,
+                    subdirs: Vec::new(),
+                    rollouts: Vec::new(),
+                };
+                for entry in rd.flatten() {
+                    let ty = match entry.file_type() {
+                        Ok(t) => t,
+                        Err(_) => continue,
+                    };
+                    if ty.is_dir() {
+                        scanned.subdirs.push(entry.path());
+                        continue;
+                    }
+                    if !ty.is_file() {
+                        continue;
+                    }
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    if let Some(session_id) = parse_rollout_session_id(&name) {
+                        scanned.rollouts.push((session_id, entry.path()));
+                    }
+                }
+                scanned
+            }
+        };
+        stack.extend(scanned.subdirs.iter().cloned());
+        for (session_id, path) in &scanned.rollouts {
+            out.entry(session_id.clone())
+                .or_default()
+                .push(path.clone());
+        }
+        next.insert(dir, scanned);
+    }
+
+    for paths in out.values_mut() {
+        paths.sort_by_key(|p| p.file_name().map(|s| s.to_string_lossy().to_string()));
+    }
+
+    (out, listed)
+}
+
+async fn ensure_native_cache(state: &AppState) {
+    let Some(codex_home) = state.codex_home.clone() else {
+        return;
+    };
+
+    let previous_dirs = {
+        let locked = state.native_cache.lock().await;
+        if locked.built_at_ms > 0 && locked.built_at_ms.saturating_add(3_000) > now_ms() {
+            return;
+        }
+        locked.scanned_dirs.clone()
+    };
+
+    let codex_home_for_scan = codex_home.clone();
+    let scanned = tokio::task::spawn_blocking(move || {
+        let mut merged: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        let mut dirs = HashMap::new();
+        let sessions_dir = codex_home_for_scan.join("sessions");
+        if sessions_dir.is_dir() {
+            merged.extend(scan_codex_rollouts(&sessions_dir, &previous_dirs, &mut dirs).0);
+        }
+        let archived_dir = codex_home_for_scan.join("archived_sessions");
+        if archived_dir.is_dir() {
+            for (k, v) in scan_codex_rollouts(&archived_dir, &previous_dirs, &mut dirs).0 {
+                merged.entry(k).or_default().extend(v);
+            }
+        }
+        for paths in merged.values_mut() {
+            paths.sort_by_key(|p| p.file_name().map(|s| s.to_string_lossy().to_string()));
+        }
+        let mtimes: HashMap<String, u64> = merged
+            .iter()
+            .filter_map(|(k, paths)| {
+                let modified = std::fs::metadata(paths.last()?).ok()?.modified().ok()?;
+                let ms = modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64;
+                Some((k.clone(), ms))
+            })
+            .collect();
+        (merged, mtimes, dirs)
+    })
+    .await
+    .unwrap_or_default();
+    let (scanned, mtimes, dirs) = scanned;
+
+    {
+        let mut locked = state.native_cache.lock().await;
+        locked.built_at_ms = now_ms();
+        locked
+            .derived_by_session
+            .retain(|k, _| scanned.contains_key(k));
+        locked.rollouts_by_session = scanned;
+        locked.latest_mtime_by_session = mtimes;
+        locked.scanned_dirs = dirs;
+    }
+}
+
+/// Forces the next [`ensure_native_cache`] to rescan, e.g. after deleting rollouts.
+async fn invalidate_native_cache(state: &AppState) {
+    state.native_cache.lock().await.built_at_ms = 0;
+}
+
+async fn read_prefix(path: &Path, max_bytes: usize) -> anyhow::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buf = vec![0u8; max_bytes];
+    let n = file.read(&mut buf).await?;
+    buf.truncate(n);
+    Ok(buf)
+}
+
+fn extract_json_string_field(prefix: &str, key: &str) -> Option<String> {
+    let needle = format!("\"{}\":\"", key);
+    let start = prefix.find(&needle)? + needle.len();
+    let mut out = String::new();
+    let mut chars = prefix[start..].chars();
+    while let Some(ch) = chars.next() {
+        if ch == '"' {
+            return Some(out);
+        }
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        let Some(next) = chars.next() else {
+            break;
+        };
+        match next {
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            '\\' => out.push('\\'),
+            '"' => out.push('"'),
+            'u' => {
+                let mut hex = String::new();
+                for _ in 0..4 {
+                    let Some(h) = chars.next() else {
+                        break;
+                    };
+                    hex.push(h);
+                }
+                if let Ok(code) = u32::from_str_radix(&hex, 16) {
+                    if let Some(c) = char::from_u32(code) {
+                        out.push(c);
+                    }
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    None
+}
+
+async fn extract_cwd_from_rollout(path: &Path) -> Option<String> {
+    extract_session_meta_field_from_rollout(path, "cwd").await
+}
+
+async fn extract_session_meta_field_from_rollout(path: &Path, key: &str) -> Option<String> {
+    let prefix = read_prefix(path, 16_384).await.ok()?;
+    let text = String::from_utf8_lossy(&prefix);
+    let clipped = match text.find("\"base_instructions\"") {
+        Some(idx) => &text[..idx],
+        None => &text,
+    };
+    extract_json_string_field(clipped, key)
+}
+
+async fn extract_session_meta_triplet_from_rollout(
+    path: &Path,
+) -> (Option<String>, Option<String>, Option<String>) {
+    let cwd = extract_session_meta_field_from_rollout(path, "cwd").await;
+    let originator = extract_session_meta_field_from_rollout(path, "originator").await;
+    let source = extract_session_meta_field_from_rollout(path, "source").await;
+
+    // Fast path: most rollouts have originator at the beginning of the meta line.
+    if originator.is_some() {
+        return (cwd, originator, source);
+    }
+
+    // Slow path: handle cases where the session_meta line puts huge fields (like base_instructions)
+    // before originator/source, making prefix-scanning unreliable.
+    #[derive(Deserialize)]
+    struct RolloutMetaLine {
+        #[serde(rename = "type")]
+        ty: Option<String>,
+        payload: Option<RolloutMetaPayload>,
+    }
+    #[derive(Deserialize)]
+    struct RolloutMetaPayload {
+        cwd: Option<String>,
+        originator: Option<String>,
+        source: Option<String>,
+    }
+
+    let path_for_parse = path.to_path_buf();
+    let parsed = tokio::task::spawn_blocking(
+        move || -> Option<(Option<String>, Option<String>, Option<String>)> {
+            let file = std::fs::File::open(path_for_parse).ok()?;
+            let mut de = serde_json::Deserializer::from_reader(file);
+            let meta = RolloutMetaLine::deserialize(&mut de).ok()?;
+            if meta.ty.as_deref() != Some("session_meta") {
+                return None;
+            }
+            let payload = meta.payload?;
+            Some((payload.cwd, payload.originator, payload.source))
+        },
+    )
+    .await
+    .ok()
+    .flatten();
+
+    if let Some((cwd2, originator2, source2)) = parsed {
+        return (cwd.or(cwd2), originator.or(originator2), source.or(source2));
+    }
+
+    (cwd, originator, source)
+}
+
+/// Rollout `originator` / `source` values written by codex's IDE extensions.
+const IDE_ORIGINS: &[&str] = &[
+    "vscode",
+    "codex_vscode",
+    "cursor",
+    "windsurf",
+    "jetbrains",
+    "codex_jetbrains",
+    "zed",
+];
+
+fn is_ide_origin(originator: Option<&str>, source: Option<&str>) -> bool {
+    [originator, source].into_iter().flatten().any(|s| {
+        IDE_ORIGINS
+            .iter()
+            .any(|ide| s.trim().eq_ignore_ascii_case(ide))
+    })
+}
+
+/// Reads the optional `git` / workspace details from the rollout's `session_meta` line. These can
+/// sit after `base_instructions`, so a full parse of the first line is required.
+async fn extract_session_meta_extras_from_rollout(path: &Path) -> (Option<String>, Option<String>) {
+    #[derive(Deserialize)]
+    struct RolloutMetaLine {
+        #[serde(rename = "type")]
+        ty: Option<String>,
+        payload: Option<serde_json::Value>,
+    }
+
+    let path_for_parse = path.to_path_buf();
+    tokio::task::spawn_blocking(move || -> Option<(Option<String>, Option<String>)> {
+        let file = std::fs::File::open(path_for_parse).ok()?;
+        let mut de = serde_json::Deserializer::from_reader(std::io::BufReader::new(file));
+        let meta = RolloutMetaLine::deserialize(&mut de).ok()?;
+        if meta.ty.as_deref() != Some("session_meta") {
+            return None;
+        }
+        let payload = meta.payload?;
+        let non_empty = |v: Option<&serde_json::Value>| {
+            v.and_then(|x| x.as_str())
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+        };
+        let git_branch = non_empty(payload.get("git").and_then(|g| g.get("branch")));
+        let ide_workspace =
+            non_empty(payload.get("ide_workspace")).or_else(|| non_empty(payload.get("workspace")));
+        Some((git_branch, ide_workspace))
+    })
+    .await
+    .ok()
+    .flatten()
+    .unwrap_or_default()
+}
+
+async fn file_mtime_ms(path: &Path) -> Option<u64> {
+    let meta = tokio::fs::metadata(path).await.ok()?;
+    let m = meta.modified().ok()?;
+    let dur = m.duration_since(UNIX_EPOCH).ok()?;
+    Some(dur.as_millis() as u64)
+}
+
+fn load_codex_history(codex_home: &Path) -> HashMap<String, CodexHistoryAgg> {
+    use std::io::BufRead;
+
+    let mut out: HashMap<String, CodexHistoryAgg> = HashMap::new();
+    let path = codex_home.join("history.jsonl");
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(_) => return out,
+    };
+    let reader = std::io::BufReader::new(file);
+    for line in reader.lines().flatten() {
+        let rec: CodexHistoryLine = match serde_json::from_str(&line) {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        if rec.session_id.trim().is_empty() {
+            continue;
+        }
+        let ts_ms = rec.ts.max(0) as u64 * 1000;
+        out.entry(rec.session_id.clone())
+            .and_modify(|a| {
+                a.first_ts_ms = a.first_ts_ms.min(ts_ms);
+                if ts_ms >= a.last_ts_ms {
+                    a.last_ts_ms = ts_ms;
+                    a.last_text = rec.text.clone();
+                }
+            })
+            .or_insert_with(|| CodexHistoryAgg {
+                first_ts_ms: ts_ms,
+                last_ts_ms: ts_ms,
+                last_text: rec.text.clone(),
+            });
+    }
+    out
+}
+
+fn load_codex_thread_titles(codex_home: &Path) -> HashMap<String, String> {
+    let mut out: HashMap<String, String> = HashMap::new();
+    let path = codex_home.join(".codex-global-state.json");
+    let text = match std::fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(_) => return out,
+    };
+    let value: serde_json::Value = match serde_json::from_str(&text) {
+        Ok(v) => v,
+        Err(_) => return out,
+    };
+    let Some(obj) = value
+        .get("thread-titles")
+        .and_then(|v| v.get("titles"))
+        .and_then(|v| v.as_object())
+    else {
+        return out;
+    };
+    for (k, v) in obj {
+        let Some(title) = v.as_str() else {
+            continue;
+        };
+        if !k.trim().is_empty() && !title.trim().is_empty() {
+            out.insert(k.clone(), title.trim().to_string());
+        }
+    }
+    out
+}
+
+fn should_show_rollout_user_text(text: &str) -> bool {
+    let t = text.trim();
+    if t.is_empty() {
+        return false;
+    }
+    if t.starts_with("# AGENTS.md") {
+        return false;
+    }
+    if t.starts_with("<environment_context") {
+        return false;
+    }
+    if t.contains("<INSTRUCTIONS>") {
+        return false;
+    }
+    true
+}
+
+fn extract_rollout_content_text(content: &serde_json::Value) -> String {
+    let Some(arr) = content.as_array() else {
+        return String::new();
+    };
+    let mut out = String::new();
+    for item in arr {
+        let Some(obj) = item.as_object() else {
+            continue;
+        };
+        let ty = obj.get("type").and_then(|v| v.as_str()).unwrap_or_default();
+        if ty != "input_text" && ty != "output_text" {
+            continue;
+        }
+        let Some(text) = obj.get("text").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        out.push_str(text);
+    }
+    out
+}
+
+async fn read_tail_bytes(path: &Path, max_bytes: u64) -> Vec<String> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = match tokio::fs::File::open(path).await {
+        Ok(f) => f,
+        Err(_) => return Vec::new(),
+    };
+    let len = match file.metadata().await {
+        Ok(m) => m.len(),
+        Err(_) => return Vec::new(),
+    };
+    let start = len.saturating_sub(max_bytes);
+    if start > 0 {
+        // Read one byte early so we can tell whether `start` lands exactly on a line boundary.
+        let _ = file.seek(std::io::SeekFrom::Start(start - 1)).await;
+    }
+    let mut buf = Vec::new();
+    if file.read_to_end(&mut buf).await.is_err() {
+        return Vec::new();
+    }
+    let mut partial_first_line = false;
+    if start > 0 {
+        partial_first_line = buf.first() != Some(&b'\n');
+        if !buf.is_empty() {
+            buf.remove(0);
+        }
+    }
+    let text = String::from_utf8_lossy(&buf);
+    let mut iter = text.lines();
+    if partial_first_line {
+        // Drop the partial line due to seeking into the middle.
+        let _ = iter.next();
+    }
+    iter.map(|l| l.to_string()).collect()
+}
+
+/// Returns the user prompt carried by a rollout record, if it's one worth showing.
+fn rollout_user_prompt(v: &serde_json::Value) -> Option<String> {
+    let kind = v.get("type").and_then(|x| x.as_str())?;
+    let payload = v.get("payload").and_then(|x| x.as_object())?;
+    let text = match kind {
+        "event_msg" => {
+            if payload.get("type").and_then(|x| x.as_str()) != Some("user_message") {
+                return None;
+            }
+            payload.get("message").and_then(|x| x.as_str())?.to_string()
+        }
+        "response_item" => {
+            if payload.get("type").and_then(|x| x.as_str()) != Some("message") {
+                return None;
+            }
+            if payload.get("role").and_then(|x| x.as_str()) != Some("user") {
+                return None;
+            }
+            extract_rollout_content_text(payload.get("content")?)
+        }
+        _ => return None,
+    };
+    should_show_rollout_user_text(&text).then(|| text.trim().to_string())
+}
+
+#[derive(Serialize)]
+struct PromptEntry {
+    ts_ms: u64,
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    turn_metadata: Option<serde_json::Value>,
+}
+
+/// Most recent prompts returned by `GET /api/sessions/:id/prompts`.
+const PROMPT_HISTORY_LIMIT: usize = 200;
+
+/// Reads user prompts from a warp `events.jsonl` (`app.prompt` lines) or, with `rollout`, from a
+/// native rollout file.
+async fn read_prompt_entries(path: &Path, rollout: bool) -> Vec<PromptEntry> {
+    let Ok(file) = tokio::fs::File::open(path).await else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    let mut lines = BufReader::new(file).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(&line) else {
+            continue;
+        };
+        let entry = if rollout {
+            rollout_user_prompt(&v).map(|prompt| PromptEntry {
+                ts_ms: v
+                    .get("timestamp")
+                    .and_then(|x| x.as_str())
+                    .and_then(parse_rfc3339_ms)
+                    .unwrap_or(0),
+                prompt,
+                turn_metadata: None,
+            })
+        } else if v.get("type").and_then(|x| x.as_str()) == Some("app.prompt") {
+            v.get("prompt")
+                .and_then(|x| x.as_str())
+                .map(|prompt| PromptEntry {
+                    ts_ms: v.get("_ts_ms").and_then(|x| x.as_u64()).unwrap_or(0),
+                    prompt: prompt.to_string(),
+                    turn_metadata: v.get("turn_metadata").cloned(),
+                })
+        } else {
+            None
+        };
+        out.extend(entry);
+    }
+    out
+}
+
+async fn session_prompts(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<Json<Vec<PromptEntry>>, Response> {
+    let dir = session_dir(&state, &session_id);
+    let warp_exists = tokio::fs::metadata(&dir)
+        .await
+        .ok()
+        .is_some_and(|m| m.is_dir());
+    let native_paths = {
+        ensure_native_cache(&state).await;
+        let locked = state.native_cache.lock().await;
+        locked.rollouts_by_session.get(&session_id).cloned()
+    };
+    if !warp_exists && native_paths.is_none() {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    }
+
+    let mut entries = Vec::new();
+    for path in native_paths.unwrap_or_default() {
+        entries.extend(read_prompt_entries(&path, true).await);
+    }
+    if warp_exists {
+        entries.extend(read_prompt_entries(&dir.join("events.jsonl"), false).await);
+    }
+
+    // Rollouts record each prompt twice (event + response item), and a warp turn on a native
+    // thread shows up in both sources, so collapse runs of the same text.
+    entries.sort_by_key(|e| e.ts_ms);
+    entries.dedup_by(|next, prev| next.prompt == prev.prompt);
+    let skip = entries.len().saturating_sub(PROMPT_HISTORY_LIMIT);
+    Ok(Json(entries.into_iter().skip(skip).collect()))
+}
+
+async fn find_last_prompt_from_rollout(path: &Path) -> Option<String> {
+    const MAX_BYTES: u64 = 96 * 1024;
+    let lines = read_tail_bytes(path, MAX_BYTES).await;
+    for raw in lines.into_iter().rev() {
+        let t = raw.trim();
+        if t.is_empty() {
+            continue;
+        }
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(t) else {
+            // A truncated or still-being-written record shouldn't hide earlier prompts.
+            continue;
+        };
+        if let Some(prompt) = rollout_user_prompt(&v) {
+            return Some(prompt);
+        }
+    }
+    None
+}
+
+async fn find_first_prompt_from_rollout(path: &Path) -> Option<String> {
+    let file = tokio::fs::File::open(path).await.ok()?;
+    let mut lines = BufReader::new(file).lines();
+    while let Ok(Some(raw)) = lines.next_line().await {
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(raw.trim()) else {
+            continue;
+        };
+        if let Some(prompt) = rollout_user_prompt(&v) {
+            return Some(prompt);
+        }
+    }
+    None
+}
+
+async fn get_or_compute_native_derived(
+    state: &AppState,
+    session_id: &str,
+    latest_path: &Path,
+) -> NativeDerived {
+    let latest_mtime_ms = file_mtime_ms(latest_path).await.unwrap_or(0);
+    {
+        let mut locked = state.native_cache.lock().await;
+        if let Some(cached) = locked.get_derived(session_id, latest_path, latest_mtime_ms) {
+            return cached;
+        }
+    }
+
+    let (cwd, originator, source) = extract_session_meta_triplet_from_rollout(latest_path).await;
+    let (git_branch, mut ide_workspace) =
+        extract_session_meta_extras_from_rollout(latest_path).await;
+    if ide_workspace.is_none() && is_ide_origin(originator.as_deref(), source.as_deref()) {
+        // IDE sessions don't always record the workspace name; the folder they ran in is the
+        // closest stand-in.
+        ide_workspace = cwd
+            .as_deref()
+            .map(|c| c.trim_end_matches(['/', '\\']))
+            .and_then(|c| Path::new(c).file_name())
+            .map(|n| n.to_string_lossy().to_string());
+    }
+    let last_prompt = find_last_prompt_from_rollout(latest_path).await;
+
+    let derived = NativeDerived {
+        latest_path: latest_path.to_path_buf(),
+        latest_mtime_ms,
+        last_access: 0,
+        cwd,
+        originator,
+        source,
+        git_branch,
+        ide_workspace,
+        last_prompt,
+    };
+
+    let mut locked = state.native_cache.lock().await;
+    locked.insert_derived(
+        session_id.to_string(),
+        derived.clone(),
+        NATIVE_DERIVED_CACHE_CAP,
+    );
+    derived
+}
+
+async fn load_native_titles_and_history(
+    codex_home: PathBuf,
+) -> (HashMap<String, String>, HashMap<String, CodexHistoryAgg>) {
+    tokio::task::spawn_blocking(move || {
+        (
+            load_codex_thread_titles(&codex_home),
+            load_codex_history(&codex_home),
+        )
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Builds the list entry for a native session from its rollout files, or `None` when the session
+/// should stay hidden (exec runs, or no meaningful user prompt).
+async fn build_native_meta(
+    state: &AppState,
+    session_id: &str,
+    paths: &[PathBuf],
+    titles: &HashMap<String, String>,
+    history: &HashMap<String, CodexHistoryAgg>,
+) -> Option<SessionMeta> {
+    let earliest_path = paths.first()?.clone();
+    let latest_path = paths.last()?.clone();
+
+    let derived = get_or_compute_native_derived(state, session_id, &latest_path).await;
+    if derived.source.as_deref() == Some("exec") || derived.originator.as_deref() == Some("codex_exec") {
+        return None;
+    }
+
+    let mut created_at_ms = file_mtime_ms(&earliest_path).await.unwrap_or_else(now_ms);
+    let mut last_used_at_ms = if derived.latest_mtime_ms > 0 {
+        derived.latest_mtime_ms
+    } else {
+        file_mtime_ms(&latest_path).await.unwrap_or_else(now_ms)
+    };
+    if let Some(h) = history.get(session_id) {
+        created_at_ms = created_at_ms.min(h.first_ts_ms);
+        last_used_at_ms = last_used_at_ms.max(h.last_ts_ms);
+    }
+
+    // Hide sessions with no meaningful user prompt to keep the list close to the official app.
+    let title = titles
+        .get(session_id)
+        .cloned()
+        .or_else(|| {
+            derived
+                .last_prompt
+                .as_deref()
+                .filter(|t| should_show_rollout_user_text(t))
+                .map(safe_title)
+        })
+        .or_else(|| {
+            history.get(session_id).and_then(|h| {
+                should_show_rollout_user_text(&h.last_text).then(|| safe_title(&h.last_text))
+            })
+        })?;
+
+    Some(SessionMeta {
+        id: session_id.to_string(),
+        title,
+        created_at_ms,
+        last_used_at_ms,
+        cwd: derived.cwd.clone(),
+        status: SessionStatus::Done,
+        codex_session_id: Some(session_id.to_string()),
+        context_window: None,
+        context_used_tokens: None,
+        context_left_pct: None,
+        events_path: latest_path.to_string_lossy().to_string(),
+        stderr_path: String::new(),
+        conclusion_path: String::new(),
+        origin: derived.source.clone().or(derived.originator.clone()),
+        git_branch: derived.git_branch.clone(),
+        ide_workspace: derived.ide_workspace.clone(),
+        model: None,
+        reasoning_effort: None,
+        codex_home: None,
+        turn_count: 0,
+        cumulative_tokens: 0,
+        last_turn_read_only: false,
+        instructions: None,
+        note: read_native_note(state, session_id).await,
+        archived: false,
+        rate_limit: None,
+        rollout_path: Some(latest_path.to_string_lossy().to_string()),
+    })
+}
+
+/// Folds a native entry into the warp-keyed map; warp metadata wins except for fields it lacks.
+fn merge_native_meta(merged: &mut HashMap<String, SessionMeta>, native: SessionMeta) {
+    merged
+        .entry(native.id.clone())
+        .and_modify(|s| merge_native_fields(s, &native))
+        .or_insert(native);
+}
+
+/// Fills a warp session's gaps from the native rollout of the same id.
+fn merge_native_fields(s: &mut SessionMeta, native: &SessionMeta) {
+    if s.cwd.is_none() {
+        s.cwd = native.cwd.clone();
+    }
+    if s.git_branch.is_none() {
+        s.git_branch = native.git_branch.clone();
+    }
+    if s.ide_workspace.is_none() {
+        s.ide_workspace = native.ide_workspace.clone();
+    }
+    if native.created_at_ms < s.created_at_ms {
+        s.created_at_ms = native.created_at_ms;
+    }
+    s.last_used_at_ms = s.last_used_at_ms.max(native.last_used_at_ms);
+    if s.note.is_none() {
+        s.note = native.note.clone();
+    }
+}
+
+/// Points each session's `rollout_path` at the latest rollout indexed for its codex thread.
+async fn attach_rollout_paths(state: &AppState, sessions: &mut [SessionMeta]) {
+    if state.codex_home.is_none() {
+        return;
+    }
+    ensure_native_cache(state).await;
+    let locked = state.native_cache.lock().await;
+    for meta in sessions {
+        let latest = meta
+            .codex_session_id
+            .as_deref()
+            .and_then(|thread_id| locked.rollouts_by_session.get(thread_id))
+            .and_then(|paths| paths.last());
+        if let Some(path) = latest {
+            meta.rollout_path = Some(path.to_string_lossy().to_string());
+        }
+    }
+}
+
+async fn native_session_meta(state: &AppState, session_id: &str) -> Option<SessionMeta> {
+    let codex_home = state.codex_home.clone()?;
+    ensure_native_cache(state).await;
+
+    let paths = {
+        let locked = state.native_cache.lock().await;
+        locked.rollouts_by_session.get(session_id).cloned()
+    }?;
+    let (titles, history) = load_native_titles_and_history(codex_home).await;
+    build_native_meta(state, session_id, &paths, &titles, &history).await
+}
+
+#[derive(Deserialize, Default)]
+struct ListSessionsQuery {
+    /// Only include sessions with this origin (e.g. `vscode`, `cli`); `warp` selects sessions
+    /// created by this server.
+    #[serde(default)]
+    origin: Option<String>,
+    #[serde(default)]
+    include_archived: bool,
+    #[serde(default)]
+    status: Option<SessionStatus>,
+    /// Only include sessions whose cwd is this directory or inside it.
+    #[serde(default)]
+    cwd: Option<String>,
+    #[serde(default)]
+    sort: SessionSort,
+    /// Defaults to newest first for `recent`/`created` and A-Z for `title`.
+    #[serde(default)]
+    order: Option<SortOrder>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum SessionSort {
+    #[default]
+    Recent,
+    Created,
+    Title,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    Asc,
+    Desc,
+}
+
+fn sort_sessions(sessions: &mut [SessionMeta], sort: SessionSort, order: Option<SortOrder>) {
+    match sort {
+        SessionSort::Recent => sessions.sort_by_key(|s| s.last_used_at_ms.max(s.created_at_ms)),
+        SessionSort::Created => sessions.sort_by_key(|s| s.created_at_ms),
+        SessionSort::Title => sessions.sort_by_cached_key(|s| s.title.to_lowercase()),
+    }
+    let default_order = match sort {
+        SessionSort::Title => SortOrder::Asc,
+        SessionSort::Recent | SessionSort::Created => SortOrder::Desc,
+    };
+    if order.unwrap_or(default_order) == SortOrder::Desc {
+        sessions.reverse();
+    }
+}
+
+/// Component-wise prefix match, so `/work/app/` matches `/work/app/sub` but not `/work/apple`.
+fn session_matches_cwd(meta: &SessionMeta, prefix: &str) -> bool {
+    let want = prefix.trim();
+    if want.is_empty() {
+        return true;
+    }
+    meta.cwd
+        .as_deref()
+        .is_some_and(|cwd| Path::new(cwd).starts_with(want))
+}
+
+fn session_matches_origin(meta: &SessionMeta, origin: &str) -> bool {
+    let want = origin.trim();
+    if want.is_empty() {
+        return true;
+    }
+    meta.origin
+        .as_deref()
+        .unwrap_or("warp")
+        .eq_ignore_ascii_case(want)
+}
+
+/// Streams warp sessions from `data_dir`, then the native sessions from `codex_home` that no warp
+/// session already covers, in no particular order. A warp session takes missing fields from the
+/// native rollout of the same id, and a live session wins over an archived copy of it. Native
+/// metas are derived as the stream is polled, so callers that write them out as they go never
+/// hold the whole list in memory.
+async fn session_metas(
+    state: AppState,
+    include_archived: bool,
+) -> Result<impl futures::Stream<Item = SessionMeta>, StatusCode> {
+    let root = sessions_root(&state);
+    tokio::fs::create_dir_all(&root)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let archived = archived_session_ids(&state).await;
+    let (rollouts, titles, history) = match state.codex_home.clone() {
+        Some(codex_home) => {
+            ensure_native_cache(&state).await;
+            let rollouts = state.native_cache.lock().await.rollouts_by_session.clone();
+            let (titles, history) = load_native_titles_and_history(codex_home).await;
+            (rollouts, titles, history)
+        }
+        None => Default::default(),
+    };
+
+    Ok(stream! {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        if let Ok(mut rd) = tokio::fs::read_dir(&root).await {
+            while let Ok(Some(entry)) = rd.next_entry().await {
+                if entry.file_type().await.is_ok_and(|ty| ty.is_dir()) {
+                    dirs.push(entry.path());
+                }
+            }
+        }
+        if include_archived {
+            dirs.extend(archived.iter().map(|id| archived_session_dir(&state, id)));
+        }
+
+        let mut warp_ids: HashSet<String> = HashSet::new();
+        for dir in dirs {
+            let Some(mut meta) = read_meta(&dir.join("meta.json")).await else {
+                continue;
+            };
+            if !warp_ids.insert(meta.id.clone()) {
+                continue;
+            }
+            let native_paths = rollouts.get(&meta.id).filter(|_| include_archived || !archived.contains(&meta.id));
+            if let Some(paths) = native_paths {
+                if let Some(native) = build_native_meta(&state, &meta.id, paths, &titles, &history).await {
+                    merge_native_fields(&mut meta, &native);
+                }
+            }
+            yield meta;
+        }
+
+        let (state, titles, history) = (&state, &titles, &history);
+        let derivations = rollouts
+            .into_iter()
+            .filter(|(session_id, _)| {
+                !warp_ids.contains(session_id) && (include_archived || !archived.contains(session_id))
+            })
+            .map(|(session_id, paths)| async move {
+                build_native_meta(state, &session_id, &paths, titles, history).await
+            });
+        let mut natives =
+            futures::stream::iter(derivations).buffer_unordered(NATIVE_DERIVE_CONCURRENCY);
+        while let Some(native) = natives.next().await {
+            if let Some(meta) = native {
+                yield meta;
+            }
+        }
+    })
+}
+
+/// Gathers warp sessions from `data_dir` and native sessions from `codex_home`, merged by id and
+/// in no particular order.
+async fn collect_sessions(
+    state: &AppState,
+    include_archived: bool,
+) -> Result<Vec<SessionMeta>, StatusCode> {
+    let metas = session_metas(state.clone(), include_archived).await?;
+    Ok(metas.collect().await)
+}
+
+#[derive(Deserialize, Default)]
+struct ExportAllQuery {
+    #[serde(default)]
+    include_archived: bool,
+}
+
+/// Every session's meta, warp and native merged as in `list_sessions` (unsorted), as one JSON
+/// line per session, written as [`session_metas`] yields them.
+async fn export_all_sessions(
+    State(state): State<AppState>,
+    Query(q): Query<ExportAllQuery>,
+) -> Result<Response, StatusCode> {
+    let rollouts = match state.codex_home {
+        Some(_) => state.native_cache.lock().await.rollouts_by_session.clone(),
+        None => HashMap::new(),
+    };
+    let metas = session_metas(state, q.include_archived).await?;
+    let stream = stream! {
+        for await mut meta in metas {
+            let latest = meta
+                .codex_session_id
+                .as_deref()
+                .and_then(|thread_id| rollouts.get(thread_id))
+                .and_then(|paths| paths.last());
+            if let Some(path) = latest {
+                meta.rollout_path = Some(path.to_string_lossy().to_string());
+            }
+            if let Ok(line) = serde_json::to_string(&meta) {
+                yield Ok::<_, std::convert::Infallible>(format!("{line}\n"));
+            }
+        }
+    };
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        axum::body::Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+async fn list_sessions(
+    State(state): State<AppState>,
+    Query(q): Query<ListSessionsQuery>,
+) -> Result<Json<Vec<SessionMeta>>, StatusCode> {
+    let mut sessions = collect_sessions(&state, q.include_archived).await?;
+    if let Some(origin) = q.origin.as_deref() {
+        sessions.retain(|s| session_matches_origin(s, origin));
+    }
+    if let Some(status) = q.status.as_ref() {
+        sessions.retain(|s| s.status == *status);
+    }
+    if let Some(cwd) = q.cwd.as_deref() {
+        sessions.retain(|s| session_matches_cwd(s, cwd));
+    }
+    sort_sessions(&mut sessions, q.sort, q.order);
+    attach_rollout_paths(&state, &mut sessions).await;
+    Ok(Json(sessions))
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SearchHit {
+    #[serde(flatten)]
+    meta: SessionMeta,
+    /// Text around the first match, from the title or else the conclusion.
+    snippet: String,
+}
+
+const SEARCH_DEFAULT_LIMIT: usize = 50;
+const SEARCH_MAX_LIMIT: usize = 200;
+const SEARCH_SNIPPET_CHARS: usize = 160;
+/// How many characters of context to keep before the match in a snippet.
+const SEARCH_SNIPPET_LEAD: usize = 60;
+const SEARCH_CONCURRENCY: usize = 16;
+
+/// Case-insensitive substring search, returning the byte range of the first match.
+fn find_case_insensitive(haystack: &str, needle: &[char]) -> Option<(usize, usize)> {
+    if needle.is_empty() {
+        return None;
+    }
+    for (start, _) in haystack.char_indices() {
+        let mut chars = haystack[start..].char_indices();
+        let mut end = start;
+        let matched = needle.iter().all(|want| match chars.next() {
+            Some((offset, got)) if got.to_lowercase().eq(want.to_lowercase()) => {
+                end = start + offset + got.len_utf8();
+                true
+            }
+            _ => false,
+        });
+        if matched {
+            return Some((start, end));
+        }
+    }
+    None
+}
+
+/// Builds a single-line snippet of at most `SEARCH_SNIPPET_CHARS` characters around `range`.
+fn search_snippet(text: &str, range: (usize, usize)) -> String {
+    let before: Vec<char> = text[..range.0].chars().collect();
+    let lead_start = before.len().saturating_sub(SEARCH_SNIPPET_LEAD);
+    let lead: String = before[lead_start..].iter().collect();
+    let rest = &text[range.0..];
+    let tail: String = rest
+        .chars()
+        .take(SEARCH_SNIPPET_CHARS - (before.len() - lead_start))
+        .collect();
+    let mut out = String::new();
+    if lead_start > 0 {
+        out.push('…');
+    }
+    out.push_str(&lead);
+    out.push_str(&tail);
+    if tail.len() < rest.len() {
+        out.push('…');
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+async fn search_session(meta: SessionMeta, needle: &[char]) -> Option<SearchHit> {
+    if let Some(range) = find_case_insensitive(&meta.title, needle) {
+        let snippet = search_snippet(&meta.title, range);
+        return Some(SearchHit { meta, snippet });
+    }
+    if meta.conclusion_path.is_empty() {
+        return None;
+    }
+    let conclusion = tokio::fs::read_to_string(&meta.conclusion_path)
+        .await
+        .ok()?;
+    let range = find_case_insensitive(&conclusion, needle)?;
+    let snippet = search_snippet(&conclusion, range);
+    Some(SearchHit { meta, snippet })
+}
+
+/// Searches session titles and conclusions for `q`, most recently used first. Stops reading
+/// further conclusions once `limit` matches have been found.
+async fn search_sessions(
+    State(state): State<AppState>,
+    Query(q): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchHit>>, StatusCode> {
+    let needle: Vec<char> = q.q.trim().chars().collect();
+    if needle.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = q
+        .limit
+        .unwrap_or(SEARCH_DEFAULT_LIMIT)
+        .clamp(1, SEARCH_MAX_LIMIT);
+
+    let mut sessions = collect_sessions(&state, false).await?;
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.last_used_at_ms.max(s.created_at_ms)));
+
+    let needle = &needle;
+    let mut searches = futures::stream::iter(
+        sessions
+            .into_iter()
+            .map(|meta| search_session(meta, needle)),
+    )
+    .buffer_unordered(SEARCH_CONCURRENCY);
+    let mut hits = Vec::new();
+    while let Some(found) = searches.next().await {
+        if let Some(hit) = found {
+            hits.push(hit);
+            if hits.len() >= limit {
+                break;
+            }
+        }
+    }
+    drop(searches);
+
+    hits.sort_by_key(|h| std::cmp::Reverse(h.meta.last_used_at_ms.max(h.meta.created_at_ms)));
+    Ok(Json(hits))
+}
+
+/// Persists and broadcasts the `app.prompt` marker that opens each turn. `turn_metadata` is an
+/// opaque caller-supplied label kept alongside the prompt; it is never sent to codex.
+async fn append_prompt_event(
+    state: &AppState,
+    session_id: &str,
+    events_path: &Path,
+    prompt: &str,
+    turn_metadata: Option<serde_json::Value>,
+) -> anyhow::Result<()> {
+    let ts = now_ms();
+    let mut prompt_event = serde_json::json!({
+        "type": "app.prompt",
+        "prompt": prompt,
+        "_ts_ms": ts,
+    });
+    if let Some(metadata) = turn_metadata.filter(|v| !v.is_null()) {
+        prompt_event["turn_metadata"] = metadata;
+    }
+    append_app_event(state, session_id, events_path, ts, prompt_event).await
+}
+
+/// Records which pinned instructions a turn was sent with, apart from its prompt.
+async fn append_instructions_event(
+    state: &AppState,
+    session_id: &str,
+    events_path: &Path,
+    instructions: &str,
+) {
+    let ts = now_ms();
+    let event = serde_json::json!({ "type": "app.instructions", "instructions": instructions, "_ts_ms": ts });
+    let _ = append_app_event(state, session_id, events_path, ts, event).await;
+}
+
+/// Persists one of our own `app.*` events to events.jsonl and broadcasts it as a `codex_event`.
+async fn append_app_event(
+    state: &AppState,
+    session_id: &str,
+    events_path: &Path,
+    ts_ms: u64,
+    event: serde_json::Value,
+) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(events_path)
+        .await?;
+    file.write_all(event.to_string().as_bytes()).await?;
+    file.write_all(b"\n").await?;
+
+    broadcast_persisted_event(
+        state,
+        UiEvent {
+            session_id: session_id.to_string(),
+            ts_ms,
+            stream: "stdout".to_string(),
+            raw: event.to_string(),
+            json: Some(event),
+        },
+    )
+    .await;
+    Ok(())
+}
+
+/// Recovers the codex thread id from a `thread/started` notification in events.jsonl, for
+/// sessions whose meta.json lost it.
+async fn find_thread_id_in_events(events_path: &Path) -> Option<String> {
+    let file = tokio::fs::File::open(events_path).await.ok()?;
+    let mut lines = BufReader::new(file).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if !line.contains("\"thread/started\"") {
+            continue;
+        }
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) else {
+            continue;
+        };
+        let params = json.get("params");
+        let thread_id = params
+            .and_then(|p| p.get("thread"))
+            .and_then(|t| json_str(t, "id"))
+            .or_else(|| params.and_then(|p| json_str(p, "threadId")));
+        if let Some(thread_id) = thread_id {
+            return Some(thread_id.to_string());
+        }
+    }
+    None
+}
+
+/// Whether codex still has rollouts for `thread_id`, so `thread/resume` can pick it up. Unknown
+/// (`None`) when the thread lives under a `CODEX_HOME` other than the indexed one.
+async fn native_thread_exists(
+    state: &AppState,
+    meta: &SessionMeta,
+    thread_id: &str,
+) -> Option<bool> {
+    if state.codex_home.is_none() || meta.codex_home.is_some() {
+        return None;
+    }
+    ensure_native_cache(state).await;
+    let locked = state.native_cache.lock().await;
+    Some(locked.rollouts_by_session.contains_key(thread_id))
+}
+
+const NEW_THREAD_WARNING: &str = "starting new thread; prior context lost";
+
+#[derive(Deserialize)]
+struct RecentQuery {
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Keeps only the `limit` most recently used sessions seen so far.
+struct RecentSessions {
+    limit: usize,
+    heap: BinaryHeap<Reverse<(u64, String)>>,
+    metas: HashMap<String, SessionMeta>,
+}
+
+impl RecentSessions {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            heap: BinaryHeap::new(),
+            metas: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, meta: SessionMeta) {
+        let key = meta.last_used_at_ms.max(meta.created_at_ms);
+        self.heap.push(Reverse((key, meta.id.clone())));
+        self.metas.insert(meta.id.clone(), meta);
+        while self.heap.len() > self.limit {
+            if let Some(Reverse((_, id))) = self.heap.pop() {
+                self.metas.remove(&id);
+            }
+        }
+    }
+}
+
+async fn recent_sessions(
+    State(state): State<AppState>,
+    Query(q): Query<RecentQuery>,
+) -> Result<Json<Vec<SessionMeta>>, StatusCode> {
+    let limit = q.limit.unwrap_or(10).clamp(1, 200);
+
+    let root = sessions_root(&state);
+    tokio::fs::create_dir_all(&root)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut warp = RecentSessions::new(limit);
+    let mut rd = tokio::fs::read_dir(&root)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    while let Ok(Some(entry)) = rd.next_entry().await {
+        if let Some(meta) = read_meta(&entry.path().join("meta.json")).await {
+            warp.push(meta);
+        }
+    }
+    let mut merged = warp.metas;
+
+    if let Some(codex_home) = state.codex_home.clone() {
+        ensure_native_cache(&state).await;
+        let (rollouts, mut by_mtime) = {
+            let locked = state.native_cache.lock().await;
+            let heap: BinaryHeap<(u64, String)> = locked
+                .latest_mtime_by_session
+                .iter()
+                .map(|(id, mtime)| (*mtime, id.clone()))
+                .collect();
+            (locked.rollouts_by_session.clone(), heap)
+        };
+        let (titles, history) = load_native_titles_and_history(codex_home).await;
+        let archived = archived_session_ids(&state).await;
+
+        // Only derive sessions in recency order until enough visible ones were found.
+        let mut accepted = 0;
+        while accepted < limit {
+            let Some((_, session_id)) = by_mtime.pop() else {
+                break;
+            };
+            if archived.contains(&session_id) {
+                continue;
+            }
+            let Some(paths) = rollouts.get(&session_id) else {
+                continue;
+            };
+            let Some(native) =
+                build_native_meta(&state, &session_id, paths, &titles, &history).await
+            else {
+                continue;
+            };
+            if !merged.contains_key(&session_id) {
+                if let Some(meta) = read_meta(&meta_path(&state, &session_id)).await {
+                    merged.insert(session_id.clone(), meta);
+                }
+            }
+            merge_native_meta(&mut merged, native);
+            accepted += 1;
+        }
+    }
+
+    let mut sessions: Vec<SessionMeta> = merged.into_values().collect();
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.last_used_at_ms.max(s.created_at_ms)));
+    sessions.truncate(limit);
+    attach_rollout_paths(&state, &mut sessions).await;
+    Ok(Json(sessions))
+}
+
+#[derive(Deserialize)]
+struct CountQuery {
+    #[serde(default)]
+    since_ms: u64,
+}
+
+#[derive(Serialize)]
+struct SessionCount {
+    since_ms: u64,
+    count: usize,
+}
+
+async fn count_sessions(
+    State(state): State<AppState>,
+    Query(q): Query<CountQuery>,
+) -> Result<Json<SessionCount>, StatusCode> {
+    let sessions = collect_sessions(&state, false).await?;
+    let count = sessions
+        .iter()
+        .filter(|s| s.created_at_ms > q.since_ms || s.last_used_at_ms > q.since_ms)
+        .count();
+    Ok(Json(SessionCount {
+        since_ms: q.since_ms,
+        count,
+    }))
+}
+
+#[derive(Serialize)]
+struct RunInfo {
+    session_id: String,
+    pid: Option<u32>,
+    started_at_ms: u64,
+}
+
+/// Turns in progress right now, oldest first.
+async fn list_runs(State(state): State<AppState>) -> Json<Vec<RunInfo>> {
+    let mut runs: Vec<RunInfo> = state
+        .runs
+        .lock()
+        .await
+        .iter()
+        .map(|(session_id, run)| RunInfo {
+            session_id: session_id.clone(),
+            pid: run.pid,
+            started_at_ms: run.started_at_ms,
+        })
+        .collect();
+    runs.sort_by(|a, b| {
+        a.started_at_ms
+            .cmp(&b.started_at_ms)
+            .then_with(|| a.session_id.cmp(&b.session_id))
+    });
+    Json(runs)
+}
+
+#[derive(Serialize)]
+struct CwdEntry {
+    cwd: String,
+    session_count: usize,
+    last_used_at_ms: u64,
+}
+
+/// Distinct working directories across warp and native sessions, most recently used first, for
+/// project pickers that don't need the full session list.
+async fn list_cwds(State(state): State<AppState>) -> Result<Json<Vec<CwdEntry>>, StatusCode> {
+    let sessions = collect_sessions(&state, false).await?;
+    let mut by_cwd: HashMap<PathBuf, CwdEntry> = HashMap::new();
+    for meta in &sessions {
+        let Some(cwd) = meta.cwd.as_deref().map(str::trim).filter(|c| !c.is_empty()) else {
+            continue;
+        };
+        // Collapses trailing slashes and `.` segments so the same directory is counted once.
+        let normalized: PathBuf = Path::new(cwd).components().collect();
+        let used = meta.last_used_at_ms.max(meta.created_at_ms);
+        let entry = by_cwd
+            .entry(normalized.clone())
+            .or_insert_with(|| CwdEntry {
+                cwd: normalized.to_string_lossy().to_string(),
+                session_count: 0,
+                last_used_at_ms: 0,
+            });
+        entry.session_count += 1;
+        entry.last_used_at_ms = entry.last_used_at_ms.max(used);
+    }
+    let mut cwds: Vec<CwdEntry> = by_cwd.into_values().collect();
+    cwds.sort_by(|a, b| {
+        b.last_used_at_ms
+            .cmp(&a.last_used_at_ms)
+            .then_with(|| a.cwd.cmp(&b.cwd))
+    });
+    Ok(Json(cwds))
+}
+
+#[derive(Deserialize)]
+struct StartRequest {
+    prompt: String,
+    cwd: Option<String>,
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(default)]
+    turn_metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// `minimal`, `low`, `medium` or `high`.
+    #[serde(default)]
+    reasoning_effort: Option<String>,
+    /// Existing directory used as `CODEX_HOME` for this session, e.g. another account's login.
+    #[serde(default)]
+    codex_home: Option<String>,
+    /// Create `cwd` (and its parents) instead of rejecting it when it doesn't exist.
+    #[serde(default)]
+    create_cwd: bool,
+    /// Webhook notified when this turn finishes, instead of `--webhook-url`.
+    #[serde(default)]
+    webhook: Option<String>,
+    /// Run this turn with a read-only sandbox and no approvals, e.g. to have codex only analyze.
+    #[serde(default)]
+    read_only: bool,
+    /// Alternative to the `Idempotency-Key` header for clients that can't set headers.
+    #[serde(default)]
+    idempotency_key: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum AttachmentKind {
+    Image,
+    File,
+}
+
+#[derive(Deserialize, Clone)]
+struct Attachment {
+    kind: AttachmentKind,
+    path: String,
+}
+
+/// User input for one turn: the prompt plus attachments already validated against the cwd.
+struct TurnInput {
+    prompt: String,
+    /// Skill instructions sent ahead of the prompt; kept apart so the prompt stays what the user
+    /// typed.
+    instructions: Option<String>,
+    attachments: Vec<(AttachmentKind, PathBuf)>,
+    read_only: bool,
+}
+
+/// Where and how codex runs one turn.
+struct TurnOptions {
+    cwd: Option<String>,
+    /// Thread to resume; a new one is started when unset.
+    thread_id: Option<String>,
+    model: Option<String>,
+    reasoning_effort: Option<String>,
+    codex_home: Option<String>,
+}
+
+/// The files a turn writes in its session directory.
+struct TurnPaths {
+    events: PathBuf,
+    stderr: PathBuf,
+    conclusion: PathBuf,
+    meta: PathBuf,
+}
+
+impl TurnInput {
+    fn to_jsonrpc_input(&self) -> serde_json::Value {
+        let text = match self.instructions.as_deref() {
+            Some(body) => format!("{body}\n\n{}", self.prompt),
+            None => self.prompt.clone(),
+        };
+        let mut items = vec![serde_json::json!({ "type": "text", "text": text })];
+        for (kind, path) in &self.attachments {
+            let path = path.to_string_lossy().to_string();
+            items.push(match kind {
+                AttachmentKind::Image => serde_json::json!({ "type": "localImage", "path": path }),
+                // The app-server has no generic file input; point the agent at the file instead.
+                AttachmentKind::File => {
+                    serde_json::json!({ "type": "text", "text": format!("Attached file: {path}") })
+                }
+            });
+        }
+        serde_json::Value::Array(items)
+    }
+}
+
+/// Checks that every attachment exists inside `cwd` (relative paths resolve against it) and
+/// rejects anything reaching outside via `..`.
+fn resolve_attachments(
+    cwd: Option<&str>,
+    attachments: &[Attachment],
+) -> Result<Vec<(AttachmentKind, PathBuf)>, String> {
+    if attachments.is_empty() {
+        return Ok(Vec::new());
+    }
+    let cwd = cwd.ok_or("attachments require a cwd")?;
+    let root = std::fs::canonicalize(cwd).map_err(|e| format!("invalid cwd {cwd}: {e}"))?;
+    attachments
+        .iter()
+        .map(|a| {
+            let raw = Path::new(a.path.trim());
+            if raw
+                .components()
+                .any(|c| matches!(c, std::path::Component::ParentDir))
+            {
+                return Err(format!("attachment path must not contain '..': {}", a.path));
+            }
+            let joined = if raw.is_absolute() {
+                raw.to_path_buf()
+            } else {
+                root.join(raw)
+            };
+            let resolved = std::fs::canonicalize(&joined)
+                .map_err(|_| format!("attachment not found: {}", a.path))?;
+            if !resolved.starts_with(&root) {
+                return Err(format!("attachment is outside the cwd: {}", a.path));
+            }
+            if !resolved.is_file() {
+                return Err(format!("attachment is not a file: {}", a.path));
+            }
+            Ok((a.kind, resolved))
+        })
+        .collect()
+}
+
+fn normalize_model(raw: Option<String>) -> Option<String> {
+    raw.map(|m| m.trim().to_string()).filter(|m| !m.is_empty())
+}
+
+/// Serializes turn starts for one session. Locks nobody else holds are pruned on the way in.
+async fn lock_session(state: &AppState, session_id: &str) -> tokio::sync::OwnedMutexGuard<()> {
+    let lock = {
+        let mut locks = state.session_locks.lock().await;
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        locks.entry(session_id.to_string()).or_default().clone()
+    };
+    lock.lock_owned().await
+}
+
+/// How long an `Idempotency-Key` keeps mapping to the session it created.
+const IDEMPOTENCY_KEY_TTL_MS: u64 = 10 * 60 * 1000;
+
+/// Starts a new session. Retries carrying the same `Idempotency-Key` header (or
+/// `idempotency_key` body field) within the TTL get the already-created session back.
+async fn start_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut req): Json<StartRequest>,
+) -> Result<Json<SessionMeta>, Response> {
+    let key = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or(req.idempotency_key.take())
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty());
+    let Some(key) = key else {
+        return create_session(state, req).await;
+    };
+
+    // Settle on the id up front so the key can be reserved before anything is created.
+    let session_id = req
+        .session_id
+        .get_or_insert_with(|| Uuid::new_v4().to_string())
+        .clone();
+    let existing = {
+        let now = now_ms();
+        let mut keys = state.idempotency_keys.lock().await;
+        keys.retain(|_, (_, created_ms)| now.saturating_sub(*created_ms) < IDEMPOTENCY_KEY_TTL_MS);
+        match keys.get(&key) {
+            Some((id, _)) => Some(id.clone()),
+            None => {
+                keys.insert(key.clone(), (session_id, now));
+                None
+            }
+        }
+    };
+
+    if let Some(existing) = existing {
+        // Wait out a creation that is still in flight.
+        drop(lock_session(&state, &existing).await);
+        let Some(mut meta) = read_meta(&meta_path(&state, &existing)).await else {
+            return Err((
+                StatusCode::CONFLICT,
+                "a request with this idempotency key is in progress",
+            )
+                .into_response());
+        };
+        if state.runs.lock().await.contains_key(&existing) {
+            meta.status = SessionStatus::Running;
+        }
+        return Ok(Json(meta));
+    }
+
+    let result = create_session(state.clone(), req).await;
+    if result.is_err() {
+        state.idempotency_keys.lock().await.remove(&key);
+    }
+    result
+}
+
+/// Fails fast on prompts over `--max-prompt-bytes`, before anything is persisted or spawned.
+fn check_prompt_size(state: &AppState, prompt: &str) -> Result<(), String> {
+    match state.max_prompt_bytes {
+        Some(max) if prompt.len() > max => Err(format!(
+            "prompt is {} bytes; the limit is {max} bytes",
+            prompt.len()
+        )),
+        _ => Ok(()),
+    }
+}
+
+async fn create_session(state: AppState, req: StartRequest) -> Result<Json<SessionMeta>, Response> {
+    let prompt = req.prompt.trim().to_string();
+    if prompt.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "prompt is required").into_response());
+    }
+    check_prompt_size(&state, &prompt)
+        .map_err(|e| (StatusCode::PAYLOAD_TOO_LARGE, e).into_response())?;
+    let reasoning_effort = parse_reasoning_effort(req.reasoning_effort)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    let codex_home = parse_codex_home(req.codex_home)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    let webhook = request_webhook(&state, req.webhook)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?
+        .or_else(|| state.webhook_url.clone());
+    let requested_cwd = req.cwd.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    ensure_cwd(requested_cwd, req.create_cwd)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    let attachments = resolve_attachments(requested_cwd, &req.attachments)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+
+    let session_id = match req.session_id {
+        Some(raw) => Uuid::parse_str(raw.trim())
+            .map_err(|_| (StatusCode::BAD_REQUEST, "invalid session_id").into_response())?
+            .to_string(),
+        None => Uuid::new_v4().to_string(),
+    };
+
+    let _session_lock = lock_session(&state, &session_id).await;
+    let dir = session_dir(&state, &session_id);
+    if tokio::fs::metadata(&dir).await.is_ok() {
+        return Err((StatusCode::CONFLICT, "session already exists").into_response());
+    }
+    evict_sessions_over_cap(&state).await;
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+
+    let created_at_ms = now_ms();
+    let last_used_at_ms = created_at_ms;
+
+    let events_path = dir.join("events.jsonl");
+    let stderr_path = dir.join("stderr.log");
+    let conclusion_path = dir.join("conclusion.md");
+
+    let cwd = req.cwd.and_then(|s| {
+        let t = s.trim().to_string();
+        if t.is_empty() {
+            None
+        } else {
+            Some(t)
+        }
+    });
+
+    let model = normalize_model(req.model);
+
+    let meta = SessionMeta {
+        id: session_id.clone(),
+        title: safe_title(&prompt),
+        created_at_ms,
+        last_used_at_ms,
+        cwd: cwd.clone(),
+        status: SessionStatus::Running,
+        codex_session_id: None,
+        context_window: None,
+        context_used_tokens: None,
+        context_left_pct: None,
+        events_path: events_path.to_string_lossy().to_string(),
+        stderr_path: stderr_path.to_string_lossy().to_string(),
+        conclusion_path: conclusion_path.to_string_lossy().to_string(),
+        origin: None,
+        git_branch: None,
+        ide_workspace: None,
+        model: model.clone(),
+        reasoning_effort: reasoning_effort.clone(),
+        codex_home: codex_home.clone(),
+        turn_count: 0,
+        cumulative_tokens: 0,
+        last_turn_read_only: req.read_only,
+        instructions: None,
+        note: None,
+        archived: false,
+        rate_limit: None,
+        rollout_path: None,
+    };
+
+    write_meta(&dir.join("meta.json"), &meta)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+
+    append_prompt_event(
+        &state,
+        &session_id,
+        &events_path,
+        &prompt,
+        req.turn_metadata,
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    let instructions = state.default_instructions.clone();
+    if let Some(text) = instructions.as_deref() {
+        append_instructions_event(&state, &session_id, &events_path, text).await;
+    }
+
+    let codex = resolve_codex_executable(&state)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+    {
+        let mut runs = state.runs.lock().await;
+        runs.insert(
+            session_id.clone(),
+            RunHandle {
+                cancel: Some(cancel_tx),
+                pid: None,
+                started_at_ms: now_ms(),
+                webhook,
+            },
+        );
+    }
+
+    let state_for_run = state.clone();
+    let session_id_for_run = session_id.clone();
+    let events_path_for_run = events_path.clone();
+    let stderr_path_for_run = stderr_path.clone();
+    let conclusion_path_for_run = conclusion_path.clone();
+    let meta_path_for_run = dir.join("meta.json");
+    tokio::spawn(async move {
+        run_turn_via_app_server(
+            state_for_run,
+            session_id_for_run,
+            codex,
+            TurnOptions {
+                cwd,
+                thread_id: None,
+                model,
+                reasoning_effort,
+                codex_home,
+            },
+            TurnInput {
+                prompt,
+                instructions,
+                attachments,
+                read_only: req.read_only,
+            },
+            TurnPaths {
+                events: events_path_for_run,
+                stderr: stderr_path_for_run,
+                conclusion: conclusion_path_for_run,
+                meta: meta_path_for_run,
+            },
+            cancel_rx,
+        )
+        .await;
+    });
+
+    Ok(Json(meta))
+}
+
+#[derive(Deserialize)]
+struct ContinueRequest {
+    prompt: String,
+    cwd: Option<String>,
+    #[serde(default)]
+    turn_metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// Changes the session's reasoning effort from this turn on.
+    #[serde(default)]
+    reasoning_effort: Option<String>,
+    /// Changes the session's `CODEX_HOME` from this turn on.
+    #[serde(default)]
+    codex_home: Option<String>,
+    #[serde(default)]
+    create_cwd: bool,
+    /// Webhook notified when this turn finishes, instead of `--webhook-url`.
+    #[serde(default)]
+    webhook: Option<String>,
+    /// Run this turn with a read-only sandbox and no approvals; later turns go back to the
+    /// defaults.
+    #[serde(default)]
+    read_only: bool,
+    /// Name of a skill whose SKILL.md instructions are prepended to the prompt sent to codex.
+    #[serde(default)]
+    skill: Option<String>,
+}
+
+/// Continues the most recently used session, or starts a new one when there are none.
+async fn continue_latest_session(
+    State(state): State<AppState>,
+    Json(req): Json<ContinueRequest>,
+) -> Result<Json<SessionMeta>, Response> {
+    let Json(recent) = recent_sessions(State(state.clone()), Query(RecentQuery { limit: Some(1) }))
+        .await
+        .map_err(IntoResponse::into_response)?;
+    if let Some(latest) = recent.into_iter().next() {
+        return continue_session(State(state), AxumPath(latest.id), Json(req)).await;
+    }
+    if req.skill.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "skill requires an existing session",
+        )
+            .into_response());
+    }
+    let start = StartRequest {
+        prompt: req.prompt,
+        cwd: req.cwd,
+        session_id: None,
+        turn_metadata: req.turn_metadata,
+        model: req.model,
+        attachments: req.attachments,
+        reasoning_effort: req.reasoning_effort,
+        codex_home: req.codex_home,
+        create_cwd: req.create_cwd,
+        webhook: req.webhook,
+        read_only: req.read_only,
+        idempotency_key: None,
+    };
+    create_session(state, start).await
+}
+
+async fn continue_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+    Json(req): Json<ContinueRequest>,
+) -> Result<Json<SessionMeta>, Response> {
+    let prompt = req.prompt.trim().to_string();
+    if prompt.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "prompt is required").into_response());
+    }
+    check_prompt_size(&state, &prompt)
+        .map_err(|e| (StatusCode::PAYLOAD_TOO_LARGE, e).into_response())?;
+    let reasoning_effort = parse_reasoning_effort(req.reasoning_effort)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    let codex_home = parse_codex_home(req.codex_home)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    let webhook = request_webhook(&state, req.webhook)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?
+        .or_else(|| state.webhook_url.clone());
+    ensure_cwd(
+        req.cwd.as_deref().map(str::trim).filter(|s| !s.is_empty()),
+        req.create_cwd,
+    )
+    .await
+    .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+
+    // Held until the run is registered so a concurrent continue sees it and gets a 409.
+    let _session_lock = lock_session(&state, &session_id).await;
+    {
+        let runs = state.runs.lock().await;
+        if runs.contains_key(&session_id) {
+            return Err((StatusCode::CONFLICT, "session is already running").into_response());
+        }
+    }
+    if tokio::fs::metadata(archived_session_dir(&state, &session_id))
+        .await
+        .is_ok()
+    {
+        return Err((StatusCode::CONFLICT, "session is archived").into_response());
+    }
+
+    let skill_instructions = match req
+        .skill
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        Some(name) => match load_skill_instructions(name).await {
+            Some(body) => Some(body),
+            None => {
+                return Err(
+                    (StatusCode::BAD_REQUEST, format!("unknown skill: {name}")).into_response()
+                )
+            }
+        },
+        None => None,
+    };
+
+    let cwd = req.cwd.and_then(|s| {
+        let t = s.trim().to_string();
+        if t.is_empty() {
+            None
+        } else {
+            Some(t)
+        }
+    });
+
+    let dir = session_dir(&state, &session_id);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    let meta_path = dir.join("meta.json");
+    let mut meta = if let Some(meta) = read_meta(&meta_path).await {
+        meta
+    } else {
+        let Some(native) = native_session_meta(&state, &session_id).await else {
+            return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+        };
+        let now = now_ms();
+        let events_path = dir.join("events.jsonl");
+        let stderr_path = dir.join("stderr.log");
+        let conclusion_path = dir.join("conclusion.md");
+        let created_at_ms = native.created_at_ms;
+        let last_used_at_ms = now;
+
+        let meta = SessionMeta {
+            id: session_id.clone(),
+            title: native.title,
+            created_at_ms,
+            last_used_at_ms,
+            cwd: cwd.clone().or(native.cwd),
+            status: SessionStatus::Done,
+            codex_session_id: Some(session_id.clone()),
+            context_window: None,
+            context_used_tokens: None,
+            context_left_pct: None,
+            events_path: events_path.to_string_lossy().to_string(),
+            stderr_path: stderr_path.to_string_lossy().to_string(),
+            conclusion_path: conclusion_path.to_string_lossy().to_string(),
+            origin: None,
+            git_branch: None,
+            ide_workspace: None,
+            model: None,
+            reasoning_effort: None,
+            codex_home: None,
+            turn_count: 0,
+            cumulative_tokens: 0,
+            last_turn_read_only: false,
+            instructions: None,
+            note: native.note.clone(),
+            archived: false,
+            rate_limit: None,
+            rollout_path: None,
+        };
+        write_meta(&meta_path, &meta)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+        meta
+    };
+
+    let events_path = dir.join("events.jsonl");
+    let stderr_path = dir.join("stderr.log");
+    let conclusion_path = dir.join("conclusion.md");
+
+    if meta.codex_session_id.is_none() {
+        // A thread id recovered from old events is only worth resuming if codex still has it.
+        if let Some(thread_id) = find_thread_id_in_events(&events_path).await {
+            if native_thread_exists(&state, &meta, &thread_id).await != Some(false) {
+                meta.codex_session_id = Some(thread_id);
+            }
+        }
+    }
+    let starts_new_thread = meta.codex_session_id.is_none();
+
+    meta.status = SessionStatus::Running;
+    meta.cwd = cwd.clone().or(meta.cwd);
+    if let Some(model) = normalize_model(req.model) {
+        meta.model = Some(model);
+    }
+    if reasoning_effort.is_some() {
+        meta.reasoning_effort = reasoning_effort;
+    }
+    if codex_home.is_some() {
+        meta.codex_home = codex_home;
+    }
+    let attachments = resolve_attachments(meta.cwd.as_deref(), &req.attachments)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+    meta.last_turn_read_only = req.read_only;
+    meta.last_used_at_ms = now_ms();
+    meta.events_path = events_path.to_string_lossy().to_string();
+    meta.stderr_path = stderr_path.to_string_lossy().to_string();
+    meta.conclusion_path = conclusion_path.to_string_lossy().to_string();
+
+    write_meta(&meta_path, &meta)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+
+    append_prompt_event(
+        &state,
+        &session_id,
+        &events_path,
+        &prompt,
+        req.turn_metadata,
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    if starts_new_thread {
+        let ts = now_ms();
+        let warning = serde_json::json!({ "type": "app.warning", "message": NEW_THREAD_WARNING, "_ts_ms": ts });
+        let _ = append_app_event(&state, &session_id, &events_path, ts, warning).await;
+    }
+    let pinned = meta
+        .instructions
+        .clone()
+        .or_else(|| state.default_instructions.clone());
+    if let Some(text) = pinned.as_deref() {
+        append_instructions_event(&state, &session_id, &events_path, text).await;
+    }
+
+    let codex = resolve_codex_executable(&state)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+    {
+        let mut runs = state.runs.lock().await;
+        runs.insert(
+            session_id.clone(),
+            RunHandle {
+                cancel: Some(cancel_tx),
+                pid: None,
+                started_at_ms: now_ms(),
+                webhook,
+            },
+        );
+    }
+
+    let state_for_run = state.clone();
+    let session_id_for_run = session_id.clone();
+    let cwd_for_run = cwd.clone().or(meta.cwd.clone());
+    let thread_id_for_run = meta.codex_session_id.clone();
+    let model_for_run = meta.model.clone();
+    let reasoning_effort_for_run = meta.reasoning_effort.clone();
+    let codex_home_for_run = meta.codex_home.clone();
+    let events_path_for_run = events_path.clone();
+    let stderr_path_for_run = stderr_path.clone();
+    let conclusion_path_for_run = conclusion_path.clone();
+    // The persisted prompt stays what the user typed; only codex sees the instructions.
+    let instructions = [pinned, skill_instructions]
+        .into_iter()
+        .flatten()
+        .filter(|body| !body.is_empty())
+        .reduce(|pinned, skill| format!("{pinned}\n\n{skill}"));
+    tokio::spawn(async move {
+        run_turn_via_app_server(
+            state_for_run,
+            session_id_for_run,
+            codex,
+            TurnOptions {
+                cwd: cwd_for_run,
+                thread_id: thread_id_for_run,
+                model: model_for_run,
+                reasoning_effort: reasoning_effort_for_run,
+                codex_home: codex_home_for_run,
+            },
+            TurnInput {
+                prompt,
+                instructions,
+                attachments,
+                read_only: req.read_only,
+            },
+            TurnPaths {
+                events: events_path_for_run,
+                stderr: stderr_path_for_run,
+                conclusion: conclusion_path_for_run,
+                meta: meta_path,
+            },
+            cancel_rx,
+        )
+        .await;
+    });
+
+    Ok(Json(meta))
+}
+
+#[derive(Deserialize)]
+struct SyncStartRequest {
+    #[serde(flatten)]
+    turn: StartRequest,
+    /// Cancel the run if it hasn't finished after this many seconds.
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct SyncContinueRequest {
+    #[serde(flatten)]
+    turn: ContinueRequest,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct TurnSyncResult {
+    session_id: String,
+    success: bool,
+    conclusion: String,
+    exit_code: Option<i32>,
+}
+
+/// How long to wait for a timed-out run to acknowledge its cancellation.
+const TURN_SYNC_CANCEL_GRACE: Duration = Duration::from_secs(5);
+
+async fn wait_for_run_finished(
+    rx: &mut broadcast::Receiver<SseMessage>,
+) -> Option<(bool, Option<i32>)> {
+    loop {
+        match rx.recv().await {
+            Ok(msg) if msg.event == "codex_run_finished" => {
+                let finished = serde_json::from_str::<serde_json::Value>(&msg.data).ok()?;
+                let success = finished
+                    .get("success")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let exit_code = finished
+                    .get("exit_code")
+                    .and_then(|v| v.as_i64())
+                    .map(|c| c as i32);
+                return Some((success, exit_code));
+            }
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Awaits a run started after `rx` subscribed, cancelling it on timeout, then reports the
+/// conclusion the run wrote like any streamed turn.
+async fn finish_turn_sync(
+    state: AppState,
+    session_id: String,
+    mut rx: broadcast::Receiver<SseMessage>,
+    timeout_secs: Option<u64>,
+) -> Json<TurnSyncResult> {
+    let finished = match timeout_secs.filter(|secs| *secs > 0) {
+        Some(secs) => {
+            match timeout(Duration::from_secs(secs), wait_for_run_finished(&mut rx)).await {
+                Ok(finished) => finished,
+                Err(_) => {
+                    let _ = stop_session(State(state.clone()), AxumPath(session_id.clone())).await;
+                    timeout(TURN_SYNC_CANCEL_GRACE, wait_for_run_finished(&mut rx))
+                        .await
+                        .ok()
+                        .flatten()
+                        .map(|(_, exit_code)| (false, exit_code))
+                }
+            }
+        }
+        None => wait_for_run_finished(&mut rx).await,
+    };
+    let (success, exit_code) = finished.unwrap_or((false, None));
+    let conclusion =
+        tokio::fs::read_to_string(session_dir(&state, &session_id).join("conclusion.md"))
+            .await
+            .unwrap_or_default();
+    Json(TurnSyncResult {
+        session_id,
+        success,
+        conclusion,
+        exit_code,
+    })
+}
+
+async fn start_session_sync(
+    State(state): State<AppState>,
+    Json(mut req): Json<SyncStartRequest>,
+) -> Result<Json<TurnSyncResult>, Response> {
+    // Subscribe before the run starts so its completion can't slip past us, which means settling
+    // on the session id up front.
+    let session_id = match req
+        .turn
+        .session_id
+        .as_deref()
+        .map(|raw| Uuid::parse_str(raw.trim()))
+    {
+        Some(Ok(id)) => id.to_string(),
+        Some(Err(_)) => return Err((StatusCode::BAD_REQUEST, "invalid session_id").into_response()),
+        None => Uuid::new_v4().to_string(),
+    };
+    req.turn.session_id = Some(session_id.clone());
+    let rx = ensure_stream(&state, &session_id).await.subscribe();
+    let Json(meta) = start_session(State(state.clone()), HeaderMap::new(), Json(req.turn)).await?;
+    Ok(finish_turn_sync(state, meta.id, rx, req.timeout_secs).await)
+}
+
+async fn continue_session_sync(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+    Json(req): Json<SyncContinueRequest>,
+) -> Result<Json<TurnSyncResult>, Response> {
+    let rx = ensure_stream(&state, &session_id).await.subscribe();
+    let Json(meta) =
+        continue_session(State(state.clone()), AxumPath(session_id), Json(req.turn)).await?;
+    Ok(finish_turn_sync(state, meta.id, rx, req.timeout_secs).await)
+}
+
+async fn stop_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<StatusCode, Response> {
+    let (cancel, pid) = {
+        let mut runs = state.runs.lock().await;
+        let Some(handle) = runs.get_mut(&session_id) else {
+            return Ok(StatusCode::NO_CONTENT);
+        };
+        (handle.cancel.take(), handle.pid)
+    };
+
+    let mut receiver_dropped = false;
+    if let Some(cancel) = cancel {
+        if cancel.send(()).is_err() {
+            receiver_dropped = true;
+        }
+    }
+
+    // Windows has no signals; there the run task kills the child once the cancel lands.
+    #[cfg(unix)]
+    if let Some(pid) = pid {
+        unsafe {
+            libc::kill(pid as i32, libc::SIGINT);
+        }
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(800)).await;
+            unsafe {
+                // If the PID is still alive, force-kill it.
+                if libc::kill(pid as i32, 0) == 0 {
+                    libc::kill(pid as i32, libc::SIGKILL);
+                }
+            }
+        });
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+
+    if receiver_dropped {
+        {
+            let mut runs = state.runs.lock().await;
+            runs.remove(&session_id);
+        }
+        let mut codex_session_id = None;
+        if let Some(mut meta) = read_meta(&meta_path(&state, &session_id)).await {
+            meta.status = SessionStatus::Error;
+            let _ = write_meta(&meta_path(&state, &session_id), &meta).await;
+            codex_session_id = meta.codex_session_id;
+        }
+        broadcast_run_finished(
+            &state,
+            RunFinished {
+                session_id,
+                ts_ms: now_ms(),
+                exit_code: None,
+                success: false,
+                codex_session_id,
+                status: Some("cancelled".to_string()),
+                reason: None,
+            },
+        )
+        .await;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Makes room for one more warp session under `--max-sessions` by deleting the least recently
+/// used finished sessions. Running sessions are never evicted.
+async fn evict_sessions_over_cap(state: &AppState) {
+    let Some(max) = state.max_sessions else {
+        return;
+    };
+    let Ok(mut rd) = tokio::fs::read_dir(sessions_root(state)).await else {
+        return;
+    };
+    let mut total = 0usize;
+    let mut candidates: Vec<SessionMeta> = Vec::new();
+    while let Ok(Some(entry)) = rd.next_entry().await {
+        if !entry.file_type().await.is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        total += 1;
+        if let Some(meta) = read_meta(&entry.path().join("meta.json")).await {
+            if matches!(meta.status, SessionStatus::Done | SessionStatus::Error) {
+                candidates.push(meta);
+            }
+        }
+    }
+    if total < max {
+        return;
+    }
+
+    let running: HashSet<String> = state.runs.lock().await.keys().cloned().collect();
+    candidates.retain(|m| !running.contains(&m.id));
+    candidates.sort_by_key(|m| m.last_used_at_ms.max(m.created_at_ms));
+    for meta in candidates.into_iter().take(total + 1 - max) {
+        if tokio::fs::remove_dir_all(session_dir(state, &meta.id))
+            .await
+            .is_ok()
+        {
+            state.stop_on_disconnect.lock().await.remove(&meta.id);
+            info!(
+                "evicted session {} ({}) to stay within --max-sessions {max}",
+                meta.id, meta.title
+            );
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PruneRequest {
+    /// Keep this many most recently used sessions.
+    #[serde(default)]
+    keep_last: Option<usize>,
+    /// Prune sessions not used for this many milliseconds.
+    #[serde(default)]
+    older_than_ms: Option<u64>,
+    #[serde(default)]
+    dry_run: bool,
+    /// Also delete the codex rollouts of pruned sessions' threads.
+    #[serde(default)]
+    include_native: bool,
+}
+
+#[derive(Serialize)]
+struct PruneResult {
+    pruned: Vec<String>,
+    reclaimed_bytes: u64,
+    dry_run: bool,
+}
+
+/// Total size of the files under `path` (or of `path` itself when it is a file).
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(meta) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    std::fs::read_dir(path)
+        .map(|rd| rd.flatten().map(|entry| disk_usage(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+/// Deletes warp sessions beyond `keep_last` or idle for longer than `older_than_ms`. Running
+/// sessions are always kept, and native rollouts are only removed with `include_native`.
+async fn prune_sessions(
+    State(state): State<AppState>,
+    Json(req): Json<PruneRequest>,
+) -> Result<Json<PruneResult>, Response> {
+    let mut metas: Vec<SessionMeta> = Vec::new();
+    if let Ok(mut rd) = tokio::fs::read_dir(sessions_root(&state)).await {
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            if let Some(meta) = read_meta(&entry.path().join("meta.json")).await {
+                metas.push(meta);
+            }
+        }
+    }
+    metas.sort_by_key(|m| Reverse(m.last_used_at_ms.max(m.created_at_ms)));
+
+    let cutoff = req.older_than_ms.map(|age| now_ms().saturating_sub(age));
+    let running: HashSet<String> = state.runs.lock().await.keys().cloned().collect();
+    let doomed: Vec<SessionMeta> = metas
+        .into_iter()
+        .enumerate()
+        .filter(|(rank, meta)| {
+            let beyond_keep = req.keep_last.is_some_and(|keep| *rank >= keep);
+            let too_old =
+                cutoff.is_some_and(|cutoff| meta.last_used_at_ms.max(meta.created_at_ms) < cutoff);
+            (beyond_keep || too_old) && !running.contains(&meta.id)
+        })
+        .map(|(_, meta)| meta)
+        .collect();
+
+    let mut rollouts: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    if req.include_native {
+        ensure_native_cache(&state).await;
+        let locked = state.native_cache.lock().await;
+        for meta in &doomed {
+            let thread_id = meta.codex_session_id.as_deref().unwrap_or(&meta.id);
+            if let Some(paths) = locked.rollouts_by_session.get(thread_id) {
+                rollouts.insert(meta.id.clone(), paths.clone());
+            }
+        }
+    }
+
+    let mut pruned = Vec::new();
+    let mut reclaimed_bytes = 0u64;
+    for meta in doomed {
+        // A turn may have started since the snapshot above; turn starts hold the session lock
+        // while they register in `runs`.
+        let _session_lock = if req.dry_run {
+            None
+        } else {
+            Some(lock_session(&state, &meta.id).await)
+        };
+        if !req.dry_run && state.runs.lock().await.contains_key(&meta.id) {
+            continue;
+        }
+        let dir = session_dir(&state, &meta.id);
+        let native_paths = rollouts.remove(&meta.id).unwrap_or_default();
+        let targets: Vec<PathBuf> = std::iter::once(dir.clone())
+            .chain(native_paths.iter().cloned())
+            .collect();
+        let size =
+            tokio::task::spawn_blocking(move || targets.iter().map(|p| disk_usage(p)).sum::<u64>())
+                .await
+                .unwrap_or(0);
+
+        if !req.dry_run {
+            if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+                warn!(session_id = %meta.id, "prune failed: {e}");
+                continue;
+            }
+            state.stop_on_disconnect.lock().await.remove(&meta.id);
+            if !native_paths.is_empty() {
+                for path in native_paths {
+                    let _ = tokio::fs::remove_file(path).await;
+                }
+                invalidate_native_cache(&state).await;
+            }
+        }
+        reclaimed_bytes += size;
+        pruned.push(meta.id);
+    }
+
+    Ok(Json(PruneResult {
+        pruned,
+        reclaimed_bytes,
+        dry_run: req.dry_run,
+    }))
+}
+
+async fn delete_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<StatusCode, Response> {
+    let _ = stop_session(State(state.clone()), AxumPath(session_id.clone())).await;
+    state.stop_on_disconnect.lock().await.remove(&session_id);
+    let dir = session_dir(&state, &session_id);
+    let warp_exists = tokio::fs::metadata(&dir).await.ok().is_some_and(|m| m.is_dir());
+    if warp_exists {
+        tokio::fs::remove_dir_all(&dir)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    }
+    let archived_dir = archived_session_dir(&state, &session_id);
+    let archived_exists = tokio::fs::metadata(&archived_dir).await.is_ok();
+    if archived_exists {
+        tokio::fs::remove_dir_all(&archived_dir)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    }
+
+    ensure_native_cache(&state).await;
+    let native_paths = {
+        let locked = state.native_cache.lock().await;
+        locked.rollouts_by_session.get(&session_id).cloned()
+    };
+    let has_native = native_paths.is_some();
+    if let Some(paths) = native_paths {
+        for p in paths {
+            let _ = tokio::fs::remove_file(p).await;
+        }
+        invalidate_native_cache(&state).await;
+    }
+
+    if !warp_exists && !archived_exists && !has_native {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<Json<SessionMeta>, Response> {
+    let warp = match read_meta(&meta_path(&state, &session_id)).await {
+        Some(meta) => Some(meta),
+        None => read_meta(&archived_session_dir(&state, &session_id).join("meta.json")).await,
+    };
+    let native = native_session_meta(&state, &session_id).await;
+
+    // Same precedence as the list: warp metadata wins, native fills the gaps.
+    let mut merged: HashMap<String, SessionMeta> = HashMap::new();
+    if let Some(meta) = warp {
+        merged.insert(session_id.clone(), meta);
+    }
+    if let Some(native) = native {
+        merge_native_meta(&mut merged, native);
+    }
+    let Some(mut meta) = merged.remove(&session_id) else {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    };
+
+    // meta.json is only rewritten at run boundaries, so trust the live run table.
+    if state.runs.lock().await.contains_key(&session_id) {
+        meta.status = SessionStatus::Running;
+    }
+    attach_rollout_paths(&state, std::slice::from_mut(&mut meta)).await;
+    Ok(Json(meta))
+}
+
+/// Absolute on-disk locations of a session's files. Native-only sessions have no warp directory,
+/// stderr log or conclusion, so those are null.
+#[derive(Serialize)]
+struct SessionPaths {
+    dir: Option<String>,
+    events_path: String,
+    stderr_path: Option<String>,
+    conclusion_path: Option<String>,
+    rollout_path: Option<String>,
+}
+
+fn absolute_path_string(path: &str) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+    let path = std::path::absolute(path).unwrap_or_else(|_| PathBuf::from(path));
+    Some(path.to_string_lossy().to_string())
+}
+
+/// Lets a web UI on the same machine hand session files to an external tool.
+async fn session_paths(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<Json<SessionPaths>, Response> {
+    let Json(meta) = get_session(State(state.clone()), AxumPath(session_id.clone())).await?;
+    let mut dir = None;
+    for candidate in [
+        session_dir(&state, &session_id),
+        archived_session_dir(&state, &session_id),
+    ] {
+        if tokio::fs::metadata(candidate.join("meta.json"))
+            .await
+            .is_ok()
+        {
+            dir = absolute_path_string(&candidate.to_string_lossy());
+            break;
+        }
+    }
+    Ok(Json(SessionPaths {
+        dir,
+        events_path: absolute_path_string(&meta.events_path).unwrap_or_default(),
+        stderr_path: absolute_path_string(&meta.stderr_path),
+        conclusion_path: absolute_path_string(&meta.conclusion_path),
+        rollout_path: meta.rollout_path.as_deref().and_then(absolute_path_string),
+    }))
+}
+
+/// Branches a session into a new warp session that shares the source's codex thread. The fork
+/// starts `Done` with an empty timeline; its first `/turn` resumes the copied thread while the
+/// source's files stay untouched.
+async fn fork_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<Json<SessionMeta>, Response> {
+    let Json(source) = get_session(State(state.clone()), AxumPath(session_id)).await?;
+
+    let fork_id = Uuid::new_v4().to_string();
+    let dir = session_dir(&state, &fork_id);
+    evict_sessions_over_cap(&state).await;
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+
+    let now = now_ms();
+    let meta = SessionMeta {
+        id: fork_id,
+        title: format!("{} (fork)", source.title),
+        created_at_ms: now,
+        last_used_at_ms: now,
+        status: SessionStatus::Done,
+        events_path: dir.join("events.jsonl").to_string_lossy().to_string(),
+        stderr_path: dir.join("stderr.log").to_string_lossy().to_string(),
+        conclusion_path: dir.join("conclusion.md").to_string_lossy().to_string(),
+        archived: false,
+        ..source
+    };
+    write_meta(&dir.join("meta.json"), &meta)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Ok(Json(meta))
+}
+
+/// Bumped whenever the bundle layout changes; imports of any other version are rejected.
+const SESSION_BUNDLE_VERSION: u32 = 1;
+
+/// Request bodies for `/api/sessions/import` may carry a long session's whole event log.
+const SESSION_IMPORT_MAX_BYTES: usize = 256 * 1024 * 1024;
+
+/// A warp session packed into one document for sharing or backup.
+#[derive(Serialize, Deserialize)]
+struct SessionBundle {
+    version: u32,
+    meta: SessionMeta,
+    events: Vec<serde_json::Value>,
+    stderr: Vec<String>,
+    conclusion: String,
+    usage: Vec<UsageRecord>,
+}
+
+async fn export_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<Json<SessionBundle>, Response> {
+    let dir = session_dir(&state, &session_id);
+    let Some(meta) = read_meta(&dir.join("meta.json")).await else {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    };
+
+    let events = tokio::fs::read_to_string(dir.join("events.jsonl"))
+        .await
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let stderr = tokio::fs::read_to_string(dir.join("stderr.log"))
+        .await
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.to_string())
+        .collect();
+    let conclusion = tokio::fs::read_to_string(dir.join("conclusion.md"))
+        .await
+        .unwrap_or_default();
+
+    let mut usage = Vec::new();
+    if let Ok(file) = tokio::fs::File::open(state.data_dir.join("usage.jsonl")).await {
+        let mut lines = BufReader::new(file).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Ok(record) = serde_json::from_str::<UsageRecord>(&line) {
+                if record.session_id == session_id {
+                    usage.push(record);
+                }
+            }
+        }
+    }
+
+    Ok(Json(SessionBundle {
+        version: SESSION_BUNDLE_VERSION,
+        meta,
+        events,
+        stderr,
+        conclusion,
+        usage,
+    }))
+}
+
+/// Recreates an exported session under a fresh id. Usage records are informational only and are
+/// not merged into usage.jsonl, so importing a bundle never double-counts tokens.
+async fn import_session(
+    State(state): State<AppState>,
+    Json(bundle): Json<SessionBundle>,
+) -> Result<Json<SessionMeta>, Response> {
+    if bundle.version != SESSION_BUNDLE_VERSION {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "unsupported bundle version {} (expected {SESSION_BUNDLE_VERSION})",
+                bundle.version
+            ),
+        )
+            .into_response());
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let dir = session_dir(&state, &id);
+    evict_sessions_over_cap(&state).await;
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+
+    let events: String = bundle.events.iter().map(|evt| format!("{evt}\n")).collect();
+    let stderr: String = bundle
+        .stderr
+        .iter()
+        .map(|line| format!("{line}\n"))
+        .collect();
+    let files = [
+        ("events.jsonl", events),
+        ("stderr.log", stderr),
+        ("conclusion.md", bundle.conclusion),
+    ];
+    for (name, contents) in files {
+        tokio::fs::write(dir.join(name), contents)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    }
+
+    let source = bundle.meta;
+    let meta = SessionMeta {
+        id,
+        // Nothing is running for the copy; a bundle taken mid-turn imports as interrupted.
+        status: match source.status {
+            SessionStatus::Running => SessionStatus::Error,
+            status => status,
+        },
+        events_path: dir.join("events.jsonl").to_string_lossy().to_string(),
+        stderr_path: dir.join("stderr.log").to_string_lossy().to_string(),
+        conclusion_path: dir.join("conclusion.md").to_string_lossy().to_string(),
+        archived: false,
+        ..source
+    };
+    write_meta(&dir.join("meta.json"), &meta)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Ok(Json(meta))
+}
+
+#[derive(Serialize)]
+struct CompactResult {
+    before_lines: usize,
+    after_lines: usize,
+}
+
+/// High-frequency events that `/compact` drops: reasoning deltas plus usage and rate-limit
+/// snapshots that later lines supersede.
+fn is_compactable_event(line: &str) -> bool {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else {
+        return false;
+    };
+    let Some(method) = json.get("method").and_then(|v| v.as_str()) else {
+        return false;
+    };
+    method == "thread/tokenUsage/updated"
+        || method == "account/rateLimits/updated"
+        || (method.starts_with("item/reasoning/") && method != "item/reasoning/completed")
+}
+
+/// Returns the kept lines of `events` and the line counts before and after compaction.
+fn compact_event_lines(events: &str) -> (String, usize, usize) {
+    let mut kept = String::new();
+    let (mut before, mut after) = (0, 0);
+    for line in events.lines().filter(|l| !l.trim().is_empty()) {
+        before += 1;
+        if is_compactable_event(line) {
+            continue;
+        }
+        after += 1;
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    (kept, before, after)
+}
+
+/// Rewrites a finished session's events.jsonl without its noisy lines, keeping the original as
+/// events.jsonl.bak (or events.jsonl.<ms>.bak when an earlier backup is already there).
+async fn compact_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<Json<CompactResult>, Response> {
+    // Held so no turn can start appending while the file is swapped.
+    let _session_lock = lock_session(&state, &session_id).await;
+    if state.runs.lock().await.contains_key(&session_id) {
+        return Err((StatusCode::CONFLICT, "session is running").into_response());
+    }
+
+    let dir = session_dir(&state, &session_id);
+    let events_path = dir.join("events.jsonl");
+    let Ok(events) = tokio::fs::read_to_string(&events_path).await else {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    };
+    let (kept, before_lines, after_lines) = compact_event_lines(&events);
+
+    let internal =
+        |e: std::io::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    let mut backup = dir.join("events.jsonl.bak");
+    if tokio::fs::try_exists(&backup).await.unwrap_or(true) {
+        backup = dir.join(format!("events.jsonl.{}.bak", now_ms()));
+    }
+    let tmp = dir.join("events.jsonl.tmp");
+    tokio::fs::write(&tmp, kept).await.map_err(internal)?;
+    tokio::fs::write(&backup, &events).await.map_err(internal)?;
+    tokio::fs::rename(&tmp, &events_path)
+        .await
+        .map_err(internal)?;
+
+    Ok(Json(CompactResult {
+        before_lines,
+        after_lines,
+    }))
+}
+
+async fn archive_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<StatusCode, Response> {
+    let archived_dir = archived_session_dir(&state, &session_id);
+    if tokio::fs::metadata(&archived_dir).await.is_ok() {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    let _ = stop_session(State(state.clone()), AxumPath(session_id.clone())).await;
+    state.stop_on_disconnect.lock().await.remove(&session_id);
+    tokio::fs::create_dir_all(archived_root(&state))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+
+    let dir = session_dir(&state, &session_id);
+    if tokio::fs::metadata(&dir)
+        .await
+        .ok()
+        .is_some_and(|m| m.is_dir())
+    {
+        if let Some(mut meta) = read_meta(&dir.join("meta.json")).await {
+            meta.archived = true;
+            write_meta(&dir.join("meta.json"), &meta)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+        }
+        tokio::fs::rename(&dir, &archived_dir)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    // Native-only sessions keep their rollout files; an archived meta just hides them.
+    let Some(mut meta) = native_session_meta(&state, &session_id).await else {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
     };
-    let mut out: VecDeque<String> = VecDeque::new();
-    let mut lines = BufReader::new(file).lines();
-    while let Ok(Some(line)) = lines.next_line().await {
-        while out.len() >= max_lines {
-            out.pop_front();
-        }
-        out.push_back(line);
+    meta.archived = true;
+    tokio::fs::create_dir_all(&archived_dir)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    write_meta(&archived_dir.join("meta.json"), &meta)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn unarchive_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<StatusCode, Response> {
+    let archived_dir = archived_session_dir(&state, &session_id);
+    if tokio::fs::metadata(&archived_dir).await.is_err() {
+        return Err((StatusCode::NOT_FOUND, "session is not archived").into_response());
     }
-    out.into_iter().collect()
+
+    // A native-only session was archived as a bare meta; dropping it makes the rollout visible again.
+    let is_native_stub = tokio::fs::metadata(archived_dir.join("events.jsonl"))
+        .await
+        .is_err()
+        && {
+            ensure_native_cache(&state).await;
+            state
+                .native_cache
+                .lock()
+                .await
+                .rollouts_by_session
+                .contains_key(&session_id)
+        };
+    if is_native_stub {
+        tokio::fs::remove_dir_all(&archived_dir)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    let dir = session_dir(&state, &session_id);
+    if tokio::fs::metadata(&dir).await.is_ok() {
+        return Err((StatusCode::CONFLICT, "session already exists").into_response());
+    }
+    tokio::fs::create_dir_all(sessions_root(&state))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    tokio::fs::rename(&archived_dir, &dir)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    if let Some(mut meta) = read_meta(&dir.join("meta.json")).await {
+        meta.archived = false;
+        write_meta(&dir.join("meta.json"), &meta)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    }
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[derive(Deserialize)]
-struct CodexHistoryLine {
-    session_id: String,
-    ts: i64,
-    text: String,
+struct InstructionsRequest {
+    #[serde(default)]
+    instructions: Option<String>,
 }
 
-#[derive(Clone)]
-struct CodexHistoryAgg {
-    first_ts_ms: u64,
-    last_ts_ms: u64,
-    last_text: String,
+/// Pins instructions sent ahead of every later turn's prompt; empty or `null` clears them, falling
+/// back to `--instructions`.
+async fn set_session_instructions(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+    Json(req): Json<InstructionsRequest>,
+) -> Result<Json<SessionMeta>, Response> {
+    let _session_lock = lock_session(&state, &session_id).await;
+    let mp = meta_path(&state, &session_id);
+    let Some(mut meta) = read_meta(&mp).await else {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    };
+    meta.instructions = req
+        .instructions
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty());
+    write_meta(&mp, &meta)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Ok(Json(meta))
 }
 
-fn parse_rollout_session_id(file_name: &str) -> Option<String> {
-    if !file_name.starts_with("rollout-") || !file_name.ends_with(".jsonl") {
-        return None;
-    }
-    let base = &file_name["rollout-".len()..file_name.len() - ".jsonl".len()];
-    if base.len() <= 20 {
-        return None;
-    }
-    // "YYYY-MM-DDTHH-MM-SS-<session_id>"
-    if base.as_bytes().get(19) != Some(&b'-') {
-        return None;
-    }
-    let id = base[20..].trim();
-    if id.is_empty() {
-        return None;
-    }
-    Some(id.to_string())
+fn notes_dir(state: &AppState) -> PathBuf {
+    state.data_dir.join("notes")
 }
 
-fn scan_codex_rollouts(root: &Path) -> HashMap<String, Vec<PathBuf>> {
-    let mut out: HashMap<String, Vec<PathBuf>> = HashMap::new();
+fn native_note_path(state: &AppState, session_id: &str) -> PathBuf {
+    notes_dir(state).join(format!("{session_id}.txt"))
+}
 
-    let mut stack: Vec<PathBuf> = vec![root.to_path_buf()];
-    while let Some(dir) = stack.pop() {
-        let rd = match std::fs::read_dir(&dir) {
-            Ok(r) => r,
-            Err(_) => continue,
-        };
-        for entry in rd.flatten() {
-            let ty = match entry.file_type() {
-                Ok(t) => t,
-                Err(_) => continue,
-            };
-            let path = entry.path();
-            if ty.is_dir() {
-                stack.push(path);
-                continue;
-            }
-            if !ty.is_file() {
-                continue;
+async fn read_native_note(state: &AppState, session_id: &str) -> Option<String> {
+    let note = tokio::fs::read_to_string(native_note_path(state, session_id))
+        .await
+        .ok()?;
+    Some(note).filter(|n| !n.trim().is_empty())
+}
+
+#[derive(Deserialize)]
+struct NoteRequest {
+    #[serde(default)]
+    note: Option<String>,
+}
+
+/// Sets a session's note; empty or `null` clears it. Warp sessions keep it in meta.json, native-only
+/// ones in a sidecar file since their rollout isn't ours to write.
+async fn set_session_note(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+    Json(req): Json<NoteRequest>,
+) -> Result<Json<SessionMeta>, Response> {
+    let note = req
+        .note
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty());
+    let _session_lock = lock_session(&state, &session_id).await;
+    let sidecar = native_note_path(&state, &session_id);
+    let mp = meta_path(&state, &session_id);
+    if let Some(mut meta) = read_meta(&mp).await {
+        meta.note = note;
+        write_meta(&mp, &meta)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+        // A sidecar from before the session had a warp dir would otherwise fill a cleared note back in.
+        let _ = tokio::fs::remove_file(&sidecar).await;
+        return Ok(Json(meta));
+    }
+
+    if native_session_meta(&state, &session_id).await.is_none() {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    }
+    let written = match &note {
+        Some(note) => {
+            async {
+                tokio::fs::create_dir_all(notes_dir(&state)).await?;
+                tokio::fs::write(&sidecar, note).await
             }
-            let name = entry.file_name().to_string_lossy().to_string();
-            let Some(session_id) = parse_rollout_session_id(&name) else {
-                continue;
-            };
-            out.entry(session_id).or_default().push(path);
+            .await
         }
+        None => match tokio::fs::remove_file(&sidecar).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        },
+    };
+    written.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    match native_session_meta(&state, &session_id).await {
+        Some(meta) => Ok(Json(meta)),
+        None => Err((StatusCode::NOT_FOUND, "session not found").into_response()),
     }
+}
 
-    for paths in out.values_mut() {
-        paths.sort_by_key(|p| p.file_name().map(|s| s.to_string_lossy().to_string()));
+async fn rename_session(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+    Json(payload): Json<HashMap<String, String>>,
+) -> Result<StatusCode, Response> {
+    let Some(title) = payload.get("title") else {
+        return Err((StatusCode::BAD_REQUEST, "title required").into_response());
+    };
+    let mp = meta_path(&state, &session_id);
+    if let Some(mut meta) = read_meta(&mp).await {
+        meta.title = title.clone();
+        write_meta(&mp, &meta)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+        // Keep the official Codex app's title for the underlying thread in sync.
+        if let (Some(codex_home), Some(thread_id)) =
+            (state.codex_home.clone(), meta.codex_session_id)
+        {
+            write_codex_thread_titles(codex_home, vec![(thread_id, title.clone())])
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+        }
+        return Ok(StatusCode::NO_CONTENT);
     }
 
-    out
-}
+    ensure_native_cache(&state).await;
+    let has_native = {
+        let locked = state.native_cache.lock().await;
+        locked.rollouts_by_session.contains_key(&session_id)
+    };
+    if !has_native {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    }
 
-async fn ensure_native_cache(state: &AppState) {
     let Some(codex_home) = state.codex_home.clone() else {
-        return;
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "codex_home not configured").into_response());
     };
+    write_codex_thread_titles(codex_home, vec![(session_id, title.clone())])
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    {
-        let locked = state.native_cache.lock().await;
-        if locked.built_at_ms > 0 && locked.built_at_ms.saturating_add(3_000) > now_ms() {
-            return;
-        }
-    }
+const TITLE_SUGGEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Transcript characters sent to the title turn; the oldest prompts are dropped first.
+const TITLE_TRANSCRIPT_MAX_CHARS: usize = 8000;
+const TITLE_SUGGEST_PROMPT: &str =
+    "Summarize this conversation into a 5-word title. Reply with the title only, no quotes or punctuation.";
 
-    let codex_home_for_scan = codex_home.clone();
-    let scanned = tokio::task::spawn_blocking(move || {
-        let mut merged: HashMap<String, Vec<PathBuf>> = HashMap::new();
-        let sessions_dir = codex_home_for_scan.join("sessions");
-        if sessions_dir.is_dir() {
-            merged.extend(scan_codex_rollouts(&sessions_dir));
-        }
-        let archived_dir = codex_home_for_scan.join("archived_sessions");
-        if archived_dir.is_dir() {
-            for (k, v) in scan_codex_rollouts(&archived_dir) {
-                merged.entry(k).or_default().extend(v);
-            }
-        }
-        for paths in merged.values_mut() {
-            paths.sort_by_key(|p| p.file_name().map(|s| s.to_string_lossy().to_string()));
-        }
-        merged
-    })
-    .await
-    .unwrap_or_default();
+#[derive(Serialize)]
+struct TitleSuggestion {
+    title: String,
+}
 
-    {
-        let mut locked = state.native_cache.lock().await;
-        locked.built_at_ms = now_ms();
-        locked
-            .derived_by_session
-            .retain(|k, _| scanned.contains_key(k));
-        locked.rollouts_by_session = scanned;
+/// Builds the prompt for the title turn, keeping the latest prompts and the conclusion within
+/// `TITLE_TRANSCRIPT_MAX_CHARS`.
+fn title_suggest_prompt(prompts: &[PromptEntry], conclusion: &str) -> String {
+    let mut transcript = String::new();
+    if !conclusion.trim().is_empty() {
+        transcript = format!("Assistant: {}\n", conclusion.trim());
     }
+    for entry in prompts.iter().rev() {
+        let line = format!("User: {}\n", entry.prompt.trim());
+        if transcript.chars().count() + line.chars().count() > TITLE_TRANSCRIPT_MAX_CHARS {
+            break;
+        }
+        transcript.insert_str(0, &line);
+    }
+    let transcript: String = transcript
+        .chars()
+        .take(TITLE_TRANSCRIPT_MAX_CHARS)
+        .collect();
+    format!("{TITLE_SUGGEST_PROMPT}\n\n{transcript}")
 }
 
-async fn read_prefix(path: &Path, max_bytes: usize) -> anyhow::Result<Vec<u8>> {
-    use tokio::io::AsyncReadExt;
-    let mut file = tokio::fs::File::open(path).await?;
-    let mut buf = vec![0u8; max_bytes];
-    let n = file.read(&mut buf).await?;
-    buf.truncate(n);
-    Ok(buf)
+/// Runs a throwaway turn on a fresh thread and returns its last agent message. Nothing is written
+/// to the session directory or broadcast.
+/// Collects the agent's reply during a title turn; nothing is persisted or broadcast.
+#[derive(Default)]
+struct TitleSink {
+    agent_item_id: Option<String>,
+    agent_text: String,
+    completed: bool,
 }
 
-fn extract_json_string_field(prefix: &str, key: &str) -> Option<String> {
-    let needle = format!("\"{}\":\"", key);
-    let start = prefix.find(&needle)? + needle.len();
-    let mut out = String::new();
-    let mut chars = prefix[start..].chars();
-    while let Some(ch) = chars.next() {
-        if ch == '"' {
-            return Some(out);
-        }
-        if ch != '\\' {
-            out.push(ch);
-            continue;
+impl NotificationSink for TitleSink {
+    async fn notification(&mut self, _raw: &str, json: &serde_json::Value) {
+        capture_agent_message_text(json, &mut self.agent_item_id, &mut self.agent_text);
+        if json.get("method").and_then(|v| v.as_str()) == Some("turn/completed") {
+            self.completed = true;
         }
-        let Some(next) = chars.next() else {
-            break;
+    }
+}
+
+async fn run_title_turn(
+    codex: &Path,
+    codex_env: &[(String, String)],
+    codex_home: Option<&Path>,
+    prompt: String,
+) -> anyhow::Result<String> {
+    let mut cmd = Command::new(codex);
+    cmd.arg("app-server")
+        .envs(codex_env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(home) = codex_home {
+        cmd.env("CODEX_HOME", home);
+    }
+    let mut child = cmd.spawn().context("failed to start codex app-server")?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .context("failed to capture app-server stdin")?;
+    let stdout = child
+        .stdout
+        .take()
+        .context("failed to capture app-server stdout")?;
+    let mut lines = BufReader::new(stdout).lines();
+    // The request timeout is the only way out; the sender just has to outlive the reads.
+    let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+    let mut sink = TitleSink::default();
+
+    let client_info =
+        serde_json::json!({ "name": "codex-warp-server", "version": env!("CARGO_PKG_VERSION") });
+    write_jsonrpc_request(
+        &mut stdin,
+        1,
+        "initialize",
+        serde_json::json!({ "clientInfo": client_info }),
+    )
+    .await?;
+    codex_protocol::wait_for_app_server_response(&mut lines, &mut cancel_rx, &mut sink, 1).await?;
+    // Ephemeral: the side thread must not show up as a native session afterwards.
+    let params = serde_json::json!({ "config": thread_config(None, None), "ephemeral": true });
+    write_jsonrpc_request(&mut stdin, 2, "thread/start", params).await?;
+    let result =
+        codex_protocol::wait_for_app_server_response(&mut lines, &mut cancel_rx, &mut sink, 2)
+            .await?;
+    let thread_id = result
+        .get("thread")
+        .and_then(|v| v.get("id"))
+        .and_then(|v| v.as_str())
+        .context("thread start did not return a thread id")?
+        .to_string();
+
+    let params = serde_json::json!({
+        "threadId": thread_id,
+        "input": [{ "type": "text", "text": prompt }],
+    });
+    write_jsonrpc_request(&mut stdin, 3, "turn/start", params).await?;
+    codex_protocol::wait_for_app_server_response(&mut lines, &mut cancel_rx, &mut sink, 3).await?;
+    while !sink.completed {
+        let Some((raw, json)) = read_next_json_line(&mut lines, &mut cancel_rx).await? else {
+            anyhow::bail!("codex app-server stdout closed");
         };
-        match next {
-            'n' => out.push('\n'),
-            'r' => out.push('\r'),
-            't' => out.push('\t'),
-            '\\' => out.push('\\'),
-            '"' => out.push('"'),
-            'u' => {
-                let mut hex = String::new();
-                for _ in 0..4 {
-                    let Some(h) = chars.next() else {
-                        break;
-                    };
-                    hex.push(h);
-                }
-                if let Ok(code) = u32::from_str_radix(&hex, 16) {
-                    if let Some(c) = char::from_u32(code) {
-                        out.push(c);
-                    }
-                }
-            }
-            other => out.push(other),
-        }
+        sink.notification(&raw, &json).await;
     }
-    None
+    let _ = child.kill().await;
+    Ok(sink.agent_text)
 }
 
-async fn extract_cwd_from_rollout(path: &Path) -> Option<String> {
-    extract_session_meta_field_from_rollout(path, "cwd").await
-}
+/// Asks codex for a short title for the conversation without touching the session itself.
+async fn suggest_title(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<Json<TitleSuggestion>, Response> {
+    if state.runs.lock().await.contains_key(&session_id) {
+        return Err((StatusCode::CONFLICT, "session is running").into_response());
+    }
+    let Json(prompts) = session_prompts(State(state.clone()), AxumPath(session_id.clone())).await?;
+    if prompts.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "session has no prompts").into_response());
+    }
+    let conclusion = read_conclusion(State(state.clone()), AxumPath(session_id.clone()))
+        .await
+        .unwrap_or_default();
+    let codex_home = read_meta(&meta_path(&state, &session_id))
+        .await
+        .and_then(|m| m.codex_home)
+        .map(PathBuf::from);
 
-async fn extract_session_meta_field_from_rollout(path: &Path, key: &str) -> Option<String> {
-    let prefix = read_prefix(path, 16_384).await.ok()?;
-    let text = String::from_utf8_lossy(&prefix);
-    let clipped = match text.find("\"base_instructions\"") {
-        Some(idx) => &text[..idx],
-        None => &text,
+    let codex = resolve_codex_executable(&state)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    let prompt = title_suggest_prompt(&prompts, &conclusion);
+    let turn = run_title_turn(&codex, &state.codex_env, codex_home.as_deref(), prompt);
+    let text = match timeout(TITLE_SUGGEST_TIMEOUT, turn).await {
+        Ok(Ok(text)) => text,
+        Ok(Err(e)) => return Err((StatusCode::BAD_GATEWAY, e.to_string()).into_response()),
+        Err(_) => {
+            return Err((StatusCode::GATEWAY_TIMEOUT, "title suggestion timed out").into_response())
+        }
     };
-    extract_json_string_field(clipped, key)
-}
-
-async fn extract_session_meta_triplet_from_rollout(
-    path: &Path,
-) -> (Option<String>, Option<String>, Option<String>) {
-    let cwd = extract_session_meta_field_from_rollout(path, "cwd").await;
-    let originator = extract_session_meta_field_from_rollout(path, "originator").await;
-    let source = extract_session_meta_field_from_rollout(path, "source").await;
-
-    // Fast path: most rollouts have originator at the beginning of the meta line.
-    if originator.is_some() {
-        return (cwd, originator, source);
+    let text = text.trim().trim_matches(|c| c == '"' || c == '\'').trim();
+    if text.is_empty() {
+        return Err((StatusCode::BAD_GATEWAY, "codex returned an empty title").into_response());
     }
+    Ok(Json(TitleSuggestion {
+        title: safe_title(text),
+    }))
+}
 
-    // Slow path: handle cases where the session_meta line puts huge fields (like base_instructions)
-    // before originator/source, making prefix-scanning unreliable.
-    #[derive(Deserialize)]
-    struct RolloutMetaLine {
-        #[serde(rename = "type")]
-        ty: Option<String>,
-        payload: Option<RolloutMetaPayload>,
-    }
-    #[derive(Deserialize)]
-    struct RolloutMetaPayload {
-        cwd: Option<String>,
-        originator: Option<String>,
-        source: Option<String>,
-    }
+/// Stores native thread titles in codex's global state, the same place the official app reads.
+async fn write_codex_thread_titles(
+    codex_home: PathBuf,
+    titles: Vec<(String, String)>,
+) -> anyhow::Result<()> {
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        let path = codex_home.join(".codex-global-state.json");
+        let mut root: serde_json::Value = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|_| serde_json::json!({})),
+            Err(_) => serde_json::json!({}),
+        };
+        if !root.is_object() {
+            root = serde_json::json!({});
+        }
+        if !root.get("thread-titles").is_some_and(|v| v.is_object()) {
+            root["thread-titles"] = serde_json::json!({});
+        }
+        if !root["thread-titles"]
+            .get("titles")
+            .is_some_and(|v| v.is_object())
+        {
+            root["thread-titles"]["titles"] = serde_json::json!({});
+        }
+        if !root["thread-titles"]
+            .get("order")
+            .is_some_and(|v| v.is_array())
+        {
+            root["thread-titles"]["order"] = serde_json::json!([]);
+        }
 
-    let path_for_parse = path.to_path_buf();
-    let parsed = tokio::task::spawn_blocking(
-        move || -> Option<(Option<String>, Option<String>, Option<String>)> {
-            let file = std::fs::File::open(path_for_parse).ok()?;
-            let mut de = serde_json::Deserializer::from_reader(file);
-            let meta = RolloutMetaLine::deserialize(&mut de).ok()?;
-            if meta.ty.as_deref() != Some("session_meta") {
-                return None;
+        for (session_id, title) in titles {
+            root["thread-titles"]["titles"][&session_id] = serde_json::Value::String(title);
+            if let Some(arr) = root["thread-titles"]["order"].as_array_mut() {
+                let exists = arr.iter().any(|v| v.as_str() == Some(&session_id));
+                if !exists {
+                    arr.insert(0, serde_json::Value::String(session_id));
+                }
             }
-            let payload = meta.payload?;
-            Some((payload.cwd, payload.originator, payload.source))
-        },
-    )
-    .await
-    .ok()
-    .flatten();
+        }
 
-    if let Some((cwd2, originator2, source2)) = parsed {
-        return (cwd.or(cwd2), originator.or(originator2), source.or(source2));
-    }
+        std::fs::write(&path, serde_json::to_vec_pretty(&root)?)?;
+        Ok(())
+    })
+    .await?
+}
 
-    (cwd, originator, source)
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum RetitleStrategy {
+    FirstPrompt,
+    LastPrompt,
 }
 
-async fn file_mtime_ms(path: &Path) -> Option<u64> {
-    let meta = tokio::fs::metadata(path).await.ok()?;
-    let m = meta.modified().ok()?;
-    let dur = m.duration_since(UNIX_EPOCH).ok()?;
-    Some(dur.as_millis() as u64)
+#[derive(Deserialize)]
+struct RetitleRequest {
+    ids: Vec<String>,
+    strategy: RetitleStrategy,
 }
 
-fn load_codex_history(codex_home: &Path) -> HashMap<String, CodexHistoryAgg> {
-    use std::io::BufRead;
+#[derive(Serialize)]
+struct RetitleResult {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
 
-    let mut out: HashMap<String, CodexHistoryAgg> = HashMap::new();
-    let path = codex_home.join("history.jsonl");
-    let file = match std::fs::File::open(path) {
-        Ok(f) => f,
-        Err(_) => return out,
-    };
-    let reader = std::io::BufReader::new(file);
-    for line in reader.lines().flatten() {
-        let rec: CodexHistoryLine = match serde_json::from_str(&line) {
-            Ok(r) => r,
-            Err(_) => continue,
+async fn find_prompt_in_warp_events(path: &Path, strategy: RetitleStrategy) -> Option<String> {
+    let file = tokio::fs::File::open(path).await.ok()?;
+    let mut lines = BufReader::new(file).lines();
+    let mut found = None;
+    while let Ok(Some(raw)) = lines.next_line().await {
+        if !raw.contains("\"app.prompt\"") {
+            continue;
+        }
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(raw.trim()) else {
+            continue;
         };
-        if rec.session_id.trim().is_empty() {
+        if v.get("type").and_then(|x| x.as_str()) != Some("app.prompt") {
             continue;
         }
-        let ts_ms = rec.ts.max(0) as u64 * 1000;
-        out.entry(rec.session_id.clone())
-            .and_modify(|a| {
-                a.first_ts_ms = a.first_ts_ms.min(ts_ms);
-                if ts_ms >= a.last_ts_ms {
-                    a.last_ts_ms = ts_ms;
-                    a.last_text = rec.text.clone();
-                }
-            })
-            .or_insert_with(|| CodexHistoryAgg {
-                first_ts_ms: ts_ms,
-                last_ts_ms: ts_ms,
-                last_text: rec.text.clone(),
-            });
-    }
-    out
-}
-
-fn load_codex_thread_titles(codex_home: &Path) -> HashMap<String, String> {
-    let mut out: HashMap<String, String> = HashMap::new();
-    let path = codex_home.join(".codex-global-state.json");
-    let text = match std::fs::read_to_string(path) {
-        Ok(t) => t,
-        Err(_) => return out,
-    };
-    let value: serde_json::Value = match serde_json::from_str(&text) {
-        Ok(v) => v,
-        Err(_) => return out,
-    };
-    let Some(obj) = value
-        .get("thread-titles")
-        .and_then(|v| v.get("titles"))
-        .and_then(|v| v.as_object())
-    else {
-        return out;
-    };
-    for (k, v) in obj {
-        let Some(title) = v.as_str() else {
+        let Some(prompt) = v.get("prompt").and_then(|x| x.as_str()) else {
             continue;
         };
-        if !k.trim().is_empty() && !title.trim().is_empty() {
-            out.insert(k.clone(), title.trim().to_string());
+        if prompt.trim().is_empty() {
+            continue;
+        }
+        found = Some(prompt.trim().to_string());
+        if strategy == RetitleStrategy::FirstPrompt {
+            break;
         }
     }
-    out
-}
-
-fn should_show_rollout_user_text(text: &str) -> bool {
-    let t = text.trim();
-    if t.is_empty() {
-        return false;
-    }
-    if t.starts_with("# AGENTS.md") {
-        return false;
-    }
-    if t.starts_with("<environment_context") {
-        return false;
-    }
-    if t.contains("<INSTRUCTIONS>") {
-        return false;
-    }
-    true
+    found
 }
 
-fn extract_rollout_content_text(content: &serde_json::Value) -> String {
-    let Some(arr) = content.as_array() else {
-        return String::new();
+async fn retitle_sessions(
+    State(state): State<AppState>,
+    Json(req): Json<RetitleRequest>,
+) -> Result<Json<Vec<RetitleResult>>, Response> {
+    ensure_native_cache(&state).await;
+    let rollouts = {
+        let locked = state.native_cache.lock().await;
+        locked.rollouts_by_session.clone()
     };
-    let mut out = String::new();
-    for item in arr {
-        let Some(obj) = item.as_object() else {
-            continue;
-        };
-        let ty = obj.get("type").and_then(|v| v.as_str()).unwrap_or_default();
-        if ty != "input_text" && ty != "output_text" {
+
+    let mut results = Vec::with_capacity(req.ids.len());
+    let mut native_titles: Vec<(String, String)> = Vec::new();
+    let mut native_result_idx: Vec<usize> = Vec::new();
+    for id in req.ids {
+        let mp = meta_path(&state, &id);
+        if let Some(mut meta) = read_meta(&mp).await {
+            let prompt =
+                find_prompt_in_warp_events(Path::new(&meta.events_path), req.strategy).await;
+            let result = match prompt {
+                Some(prompt) => {
+                    meta.title = safe_title(&prompt);
+                    match write_meta(&mp, &meta).await {
+                        Ok(()) => RetitleResult {
+                            id,
+                            title: Some(meta.title),
+                            error: None,
+                        },
+                        Err(e) => RetitleResult {
+                            id,
+                            title: None,
+                            error: Some(e.to_string()),
+                        },
+                    }
+                }
+                None => RetitleResult {
+                    id,
+                    title: None,
+                    error: Some("no prompt found".to_string()),
+                },
+            };
+            results.push(result);
             continue;
         }
-        let Some(text) = obj.get("text").and_then(|v| v.as_str()) else {
+
+        let Some(paths) = rollouts.get(&id) else {
+            results.push(RetitleResult {
+                id,
+                title: None,
+                error: Some("session not found".to_string()),
+            });
             continue;
         };
-        out.push_str(text);
+        let prompt = match req.strategy {
+            RetitleStrategy::FirstPrompt => match paths.first() {
+                Some(path) => find_first_prompt_from_rollout(path).await,
+                None => None,
+            },
+            RetitleStrategy::LastPrompt => match paths.last() {
+                Some(path) => find_last_prompt_from_rollout(path).await,
+                None => None,
+            },
+        };
+        let Some(prompt) = prompt else {
+            results.push(RetitleResult {
+                id,
+                title: None,
+                error: Some("no prompt found".to_string()),
+            });
+            continue;
+        };
+        let title = safe_title(&prompt);
+        native_titles.push((id.clone(), title.clone()));
+        native_result_idx.push(results.len());
+        results.push(RetitleResult {
+            id,
+            title: Some(title),
+            error: None,
+        });
     }
-    out
-}
 
-async fn read_tail_bytes(path: &Path, max_bytes: u64) -> Vec<String> {
-    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+    // Native titles all live in one global-state file, so write them in a single pass.
+    if !native_titles.is_empty() {
+        let written = match state.codex_home.clone() {
+            Some(codex_home) => write_codex_thread_titles(codex_home, native_titles)
+                .await
+                .map_err(|e| e.to_string()),
+            None => Err("codex_home not configured".to_string()),
+        };
+        if let Err(e) = written {
+            for idx in native_result_idx {
+                results[idx].title = None;
+                results[idx].error = Some(e.clone());
+            }
+        }
+    }
 
-    let mut file = match tokio::fs::File::open(path).await {
-        Ok(f) => f,
-        Err(_) => return Vec::new(),
-    };
-    let len = match file.metadata().await {
-        Ok(m) => m.len(),
-        Err(_) => return Vec::new(),
-    };
-    let start = len.saturating_sub(max_bytes);
-    if start > 0 {
-        let _ = file.seek(std::io::SeekFrom::Start(start)).await;
+    Ok(Json(results))
+}
+
+async fn read_conclusion(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<String, Response> {
+    let dir = session_dir(&state, &session_id);
+    let path = dir.join("conclusion.md");
+    if let Ok(text) = tokio::fs::read_to_string(&path).await {
+        return Ok(text);
     }
-    let mut buf = Vec::new();
-    if file.read_to_end(&mut buf).await.is_err() {
-        return Vec::new();
+    // Mid-turn (or after a crash) only the last completed agent message may exist.
+    if let Ok(text) = tokio::fs::read_to_string(dir.join(PARTIAL_CONCLUSION_FILE)).await {
+        return Ok(text);
     }
-    let text = String::from_utf8_lossy(&buf);
-    let mut iter = text.lines();
-    if start > 0 {
-        // Drop potential partial line due to seeking into the middle.
-        let _ = iter.next();
+
+    ensure_native_cache(&state).await;
+    let has_native = {
+        let locked = state.native_cache.lock().await;
+        locked.rollouts_by_session.contains_key(&session_id)
+    };
+    if has_native {
+        return Ok(String::new());
     }
-    iter.map(|l| l.to_string()).collect()
+
+    Err((StatusCode::NOT_FOUND, "session not found").into_response())
 }
 
-async fn find_last_prompt_from_rollout(path: &Path) -> Option<String> {
-    const MAX_BYTES: u64 = 96 * 1024;
-    let lines = read_tail_bytes(path, MAX_BYTES).await;
-    for raw in lines.into_iter().rev() {
-        let t = raw.trim();
-        if t.is_empty() {
-            continue;
-        }
-        let v: serde_json::Value = serde_json::from_str(t).ok()?;
-        let Some(kind) = v.get("type").and_then(|x| x.as_str()) else {
-            continue;
-        };
+#[derive(Serialize, Debug, PartialEq)]
+struct LastMessage {
+    text: String,
+    ts_ms: u64,
+    /// Whether the turn that produced it has completed.
+    complete: bool,
+}
 
-        if kind == "event_msg" {
-            let Some(payload) = v.get("payload").and_then(|x| x.as_object()) else {
-                continue;
-            };
-            if payload.get("type").and_then(|x| x.as_str()) != Some("user_message") {
-                continue;
-            }
-            let Some(message) = payload.get("message").and_then(|x| x.as_str()) else {
-                continue;
-            };
-            if should_show_rollout_user_text(message) {
-                return Some(message.trim().to_string());
-            }
-            continue;
+/// The latest turn's last completed agent message, for clients polling for a new answer without
+/// re-reading the conclusion. Empty until that turn has completed a message.
+async fn last_message(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<Json<LastMessage>, Response> {
+    let events_path = session_dir(&state, &session_id).join("events.jsonl");
+    match tokio::task::spawn_blocking(move || find_last_message(&events_path)).await {
+        Ok(Ok(last)) => Ok(Json(last)),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err((StatusCode::NOT_FOUND, "session not found").into_response())
         }
+        Ok(Err(e)) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
 
-        if kind == "response_item" {
-            let Some(payload) = v.get("payload").and_then(|x| x.as_object()) else {
-                continue;
-            };
-            if payload.get("type").and_then(|x| x.as_str()) != Some("message") {
-                continue;
-            }
-            if payload.get("role").and_then(|x| x.as_str()) != Some("user") {
-                continue;
-            }
-            let content = payload.get("content")?;
-            let text = extract_rollout_content_text(content);
-            if should_show_rollout_user_text(&text) {
-                return Some(text.trim().to_string());
-            }
-            continue;
+/// Scans events.jsonl from the end back to the latest `app.prompt`, stopping at the first
+/// completed agent message.
+fn find_last_message(events_path: &Path) -> std::io::Result<LastMessage> {
+    let mut last = LastMessage {
+        text: String::new(),
+        ts_ms: 0,
+        complete: false,
+    };
+    let mut turn_completed = false;
+    for_each_line_backward(events_path, |line| {
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else {
+            return true;
+        };
+        if json.get("type").and_then(|v| v.as_str()) == Some("app.prompt") {
+            last.complete = turn_completed;
+            return false;
         }
-    }
-    None
+        if json.get("method").and_then(|v| v.as_str()) == Some("turn/completed") {
+            turn_completed = true;
+            return true;
+        }
+        if !is_agent_message_completed(&json) {
+            return true;
+        }
+        last = LastMessage {
+            text: strip_tool_citations(json["params"]["item"]["text"].as_str().unwrap_or_default()),
+            ts_ms: json.get("_ts_ms").and_then(|v| v.as_u64()).unwrap_or(0),
+            complete: turn_completed,
+        };
+        false
+    })?;
+    Ok(last)
 }
 
-async fn get_or_compute_native_derived(
-    state: &AppState,
-    session_id: &str,
-    latest_path: &Path,
-) -> NativeDerived {
-    let latest_mtime_ms = file_mtime_ms(latest_path).await.unwrap_or(0);
-    {
-        let locked = state.native_cache.lock().await;
-        if let Some(cached) = locked.derived_by_session.get(session_id) {
-            if cached.latest_path == latest_path && cached.latest_mtime_ms == latest_mtime_ms {
-                return cached.clone();
+/// Calls `visit` with each line of `path`, last line first, until it returns false. The file is
+/// read backwards in chunks, so stopping early only costs the tail.
+fn for_each_line_backward(path: &Path, mut visit: impl FnMut(&str) -> bool) -> std::io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+    const CHUNK: u64 = 64 * 1024;
+    let mut file = std::fs::File::open(path)?;
+    let mut pos = file.seek(SeekFrom::End(0))?;
+    // The start of the region already read, which may be the end of a line in earlier chunks.
+    let mut carry: Vec<u8> = Vec::new();
+    while pos > 0 {
+        let start = pos.saturating_sub(CHUNK);
+        let mut buf = vec![0u8; (pos - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buf)?;
+        pos = start;
+        buf.extend_from_slice(&carry);
+        let mut end = buf.len();
+        while let Some(newline) = buf[..end].iter().rposition(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(&buf[newline + 1..end]);
+            if !line.trim().is_empty() && !visit(line.trim_end()) {
+                return Ok(());
             }
+            end = newline;
         }
+        buf.truncate(end);
+        carry = buf;
     }
+    let line = String::from_utf8_lossy(&carry);
+    if !line.trim().is_empty() {
+        visit(line.trim_end());
+    }
+    Ok(())
+}
 
-    let (cwd, originator, source) = extract_session_meta_triplet_from_rollout(latest_path).await;
-    let last_prompt = find_last_prompt_from_rollout(latest_path).await;
+#[derive(Deserialize)]
+struct UsageQuery {
+    #[serde(default)]
+    max_records: Option<usize>,
+}
 
-    let derived = NativeDerived {
-        latest_path: latest_path.to_path_buf(),
-        latest_mtime_ms,
-        cwd,
-        originator,
-        source,
-        last_prompt,
+async fn list_usage_records(
+    State(state): State<AppState>,
+    Query(q): Query<UsageQuery>,
+) -> Result<Json<Vec<UsageRecord>>, StatusCode> {
+    let file = match tokio::fs::File::open(state.data_dir.join("usage.jsonl")).await {
+        Ok(f) => f,
+        Err(_) => return Ok(Json(Vec::new())),
     };
+    let max_records = q.max_records.unwrap_or(5000).clamp(1, 200_000);
 
-    let mut locked = state.native_cache.lock().await;
-    locked
-        .derived_by_session
-        .insert(session_id.to_string(), derived.clone());
-    derived
+    let mut out: VecDeque<UsageRecord> = VecDeque::new();
+    let mut lines = BufReader::new(file).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let record = match serde_json::from_str::<UsageRecord>(&line) {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        while out.len() >= max_records {
+            out.pop_front();
+        }
+        out.push_back(record);
+    }
+    Ok(Json(out.into_iter().collect()))
 }
 
-async fn native_session_meta(state: &AppState, session_id: &str) -> Option<SessionMeta> {
-    let codex_home = state.codex_home.clone()?;
-    ensure_native_cache(state).await;
-
-    let paths = {
-        let locked = state.native_cache.lock().await;
-        locked.rollouts_by_session.get(session_id).cloned()
-    };
-    let paths = paths?;
-    if paths.is_empty() {
-        return None;
+/// Empties usage.jsonl, resetting every usage report.
+async fn clear_usage(State(state): State<AppState>) -> Result<StatusCode, Response> {
+    let _guard = state.usage_lock.lock().await;
+    match tokio::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(state.data_dir.join("usage.jsonl"))
+        .await
+    {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
     }
-    let earliest_path = paths.first().cloned().unwrap_or_else(|| PathBuf::from(""));
-    let latest_path = paths.last().cloned().unwrap_or_else(|| PathBuf::from(""));
+}
 
-    let derived = get_or_compute_native_derived(state, session_id, &latest_path).await;
-    if derived.source.as_deref() == Some("exec") || derived.originator.as_deref() == Some("codex_exec") {
-        return None;
-    }
-    let cwd = derived.cwd.clone();
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum UsageGroupBy {
+    #[default]
+    Day,
+    Session,
+    Model,
+}
 
-    let session_id_owned = session_id.to_string();
-    let (titles, history) = tokio::task::spawn_blocking(move || {
-        (
-            load_codex_thread_titles(&codex_home),
-            load_codex_history(&codex_home),
-        )
-    })
-    .await
-    .unwrap_or_default();
+#[derive(Deserialize)]
+struct UsageSummaryQuery {
+    #[serde(default)]
+    group_by: UsageGroupBy,
+}
 
-    let mut created_at_ms = file_mtime_ms(&earliest_path).await.unwrap_or_else(now_ms);
-    let mut last_used_at_ms = file_mtime_ms(&latest_path).await.unwrap_or_else(now_ms);
-    if let Some(h) = history.get(&session_id_owned) {
-        created_at_ms = created_at_ms.min(h.first_ts_ms);
-        last_used_at_ms = last_used_at_ms.max(h.last_ts_ms);
-    }
+#[derive(Default, Serialize)]
+struct UsageBucket {
+    /// UTC date, session id, or model name depending on `group_by` (`unknown` when a record has
+    /// no model).
+    key: String,
+    records: u64,
+    total_tokens: u64,
+    input_tokens: u64,
+    output_tokens: u64,
+    cached_input_tokens: u64,
+}
 
-    let title = titles.get(&session_id_owned).cloned().or_else(|| {
-        derived
-            .last_prompt
-            .as_deref()
-            .filter(|t| should_show_rollout_user_text(t))
-            .map(safe_title)
-    }).or_else(|| {
-        history.get(&session_id_owned).and_then(|h| {
-            should_show_rollout_user_text(&h.last_text).then(|| safe_title(&h.last_text))
-        })
-    })?;
+fn summarize_usage(
+    records: impl IntoIterator<Item = UsageRecord>,
+    group_by: UsageGroupBy,
+) -> Vec<UsageBucket> {
+    let mut buckets: BTreeMap<String, UsageBucket> = BTreeMap::new();
+    for record in records {
+        let key = match group_by {
+            UsageGroupBy::Day => utc_day(record.ts_ms),
+            UsageGroupBy::Session => record.session_id,
+            UsageGroupBy::Model => record.model.unwrap_or_else(|| "unknown".to_string()),
+        };
+        let bucket = buckets.entry(key.clone()).or_insert_with(|| UsageBucket {
+            key,
+            ..UsageBucket::default()
+        });
+        bucket.records += 1;
+        bucket.total_tokens += record.total_tokens;
+        bucket.input_tokens += record.input_tokens;
+        bucket.output_tokens += record.output_tokens;
+        bucket.cached_input_tokens += record.cached_input_tokens;
+    }
+    buckets.into_values().collect()
+}
 
-    Some(SessionMeta {
-        id: session_id_owned.clone(),
-        title,
-        created_at_ms,
-        last_used_at_ms,
-        cwd,
-        status: SessionStatus::Done,
-        codex_session_id: Some(session_id_owned),
-        context_window: None,
-        context_used_tokens: None,
-        context_left_pct: None,
-        events_path: latest_path.to_string_lossy().to_string(),
-        stderr_path: String::new(),
-        conclusion_path: String::new(),
-    })
+async fn usage_summary(
+    State(state): State<AppState>,
+    Query(q): Query<UsageSummaryQuery>,
+) -> Result<Json<Vec<UsageBucket>>, StatusCode> {
+    let file = match tokio::fs::File::open(state.data_dir.join("usage.jsonl")).await {
+        Ok(f) => f,
+        Err(_) => return Ok(Json(Vec::new())),
+    };
+    let mut records = Vec::new();
+    let mut lines = BufReader::new(file).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Ok(record) = serde_json::from_str::<UsageRecord>(&line) {
+            records.push(record);
+        }
+    }
+    Ok(Json(summarize_usage(records, q.group_by)))
 }
 
-async fn list_sessions(State(state): State<AppState>) -> Result<Json<Vec<SessionMeta>>, StatusCode> {
-    let mut merged: HashMap<String, SessionMeta> = HashMap::new();
+async fn list_skills() -> Result<Json<Vec<SkillSummary>>, StatusCode> {
+    Ok(Json(scan_skills().await))
+}
 
-    let root = sessions_root(&state);
-    tokio::fs::create_dir_all(&root)
+async fn get_skill(AxumPath(name): AxumPath<String>) -> Result<Json<SkillPreview>, Response> {
+    let Some(skill) = scan_skills().await.into_iter().find(|s| s.name == name) else {
+        return Err((StatusCode::NOT_FOUND, "skill not found").into_response());
+    };
+    let text = tokio::fs::read_to_string(&skill.path)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Ok(Json(skill_preview(&skill.name, &text)))
+}
 
-    let mut rd = tokio::fs::read_dir(&root)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    while let Ok(Some(entry)) = rd.next_entry().await {
-        let ty = entry.file_type().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        if !ty.is_dir() {
-            continue;
-        }
-        let mp = entry.path().join("meta.json");
-        if let Some(meta) = read_meta(&mp).await {
-            merged.insert(meta.id.clone(), meta);
-        }
+/// Reads the instruction body of the named skill, or `None` when no such skill exists.
+async fn load_skill_instructions(name: &str) -> Option<String> {
+    let skill = scan_skills().await.into_iter().find(|s| s.name == name)?;
+    let text = tokio::fs::read_to_string(&skill.path).await.ok()?;
+    Some(split_skill_front_matter(&text).1.trim().to_string())
+}
+
+async fn scan_skills() -> Vec<SkillSummary> {
+    let Some(root) = codex_skills_root() else {
+        return Vec::new();
+    };
+    if tokio::fs::metadata(&root).await.is_err() {
+        return Vec::new();
     }
 
-    if let Some(codex_home) = state.codex_home.clone() {
-        ensure_native_cache(&state).await;
-        let rollouts = {
-            let locked = state.native_cache.lock().await;
-            locked.rollouts_by_session.clone()
-        };
-        let (titles, history) = tokio::task::spawn_blocking(move || {
-            (
-                load_codex_thread_titles(&codex_home),
-                load_codex_history(&codex_home),
-            )
-        })
-        .await
-        .unwrap_or_default();
+    let mut stack: Vec<PathBuf> = vec![root];
+    let mut out: Vec<SkillSummary> = Vec::new();
 
-        for (session_id, paths) in rollouts {
-            if paths.is_empty() {
+    while let Some(dir) = stack.pop() {
+        let mut rd = match tokio::fs::read_dir(&dir).await {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            let ty = match entry.file_type().await {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+            let path = entry.path();
+            if ty.is_dir() {
+                stack.push(path);
                 continue;
             }
-            let earliest_path = paths
-                .first()
-                .cloned()
-                .unwrap_or_else(|| PathBuf::from(""));
-            let latest_path = paths
-                .last()
-                .cloned()
-                .unwrap_or_else(|| PathBuf::from(""));
-
-            let derived = get_or_compute_native_derived(&state, &session_id, &latest_path).await;
-            if derived.source.as_deref() == Some("exec")
-                || derived.originator.as_deref() == Some("codex_exec")
-            {
+            if !ty.is_file() {
+                continue;
+            }
+            if entry.file_name().to_string_lossy() != "SKILL.md" {
                 continue;
             }
-            let cwd = derived.cwd.clone();
 
-            let mut created_at_ms = file_mtime_ms(&earliest_path).await.unwrap_or_else(now_ms);
-            let last_used_at_ms = if derived.latest_mtime_ms > 0 {
-                derived.latest_mtime_ms
-            } else {
-                file_mtime_ms(&latest_path).await.unwrap_or_else(now_ms)
+            let text = match tokio::fs::read_to_string(&path).await {
+                Ok(t) => t,
+                Err(_) => continue,
             };
-            let mut last_used_at_ms = last_used_at_ms;
-            if let Some(h) = history.get(&session_id) {
-                created_at_ms = created_at_ms.min(h.first_ts_ms);
-                last_used_at_ms = last_used_at_ms.max(h.last_ts_ms);
-            }
-
-            let title = titles.get(&session_id).cloned().or_else(|| {
-                derived
-                    .last_prompt
-                    .as_deref()
-                    .filter(|t| should_show_rollout_user_text(t))
-                    .map(safe_title)
-            }).or_else(|| {
-                history.get(&session_id).and_then(|h| {
-                    should_show_rollout_user_text(&h.last_text).then(|| safe_title(&h.last_text))
-                })
+            let (name, description) = parse_skill_front_matter(&text);
+            let name = name.or_else(|| {
+                path.parent()
+                    .and_then(|p| p.file_name())
+                    .and_then(|s| s.to_str())
+                    .map(|s| s.to_string())
             });
-            let Some(title) = title else {
-                // Hide sessions with no meaningful user prompt to keep the list close to the official app.
+            let Some(name) = name else {
                 continue;
             };
-
-            let native = SessionMeta {
-                id: session_id.clone(),
-                title,
-                created_at_ms,
-                last_used_at_ms,
-                cwd,
-                status: SessionStatus::Done,
-                codex_session_id: Some(session_id.clone()),
-                context_window: None,
-                context_used_tokens: None,
-                context_left_pct: None,
-                events_path: latest_path.to_string_lossy().to_string(),
-                stderr_path: String::new(),
-                conclusion_path: String::new(),
-            };
-
-            merged
-                .entry(session_id.clone())
-                .and_modify(|s| {
-                    if s.cwd.is_none() {
-                        s.cwd = native.cwd.clone();
-                    }
-                    if native.created_at_ms < s.created_at_ms {
-                        s.created_at_ms = native.created_at_ms;
-                    }
-                    s.last_used_at_ms = s.last_used_at_ms.max(native.last_used_at_ms);
-                })
-                .or_insert(native);
+            out.push(SkillSummary {
+                name,
+                description: description.unwrap_or_default(),
+                path: path.to_string_lossy().to_string(),
+            });
         }
     }
 
-    let mut sessions: Vec<SessionMeta> = merged.into_values().collect();
-    sessions.sort_by_key(|s| std::cmp::Reverse(s.last_used_at_ms.max(s.created_at_ms)));
-    Ok(Json(sessions))
-}
-
-#[derive(Deserialize)]
-struct StartRequest {
-    prompt: String,
-    cwd: Option<String>,
-    #[serde(default)]
-    session_id: Option<String>,
+    // Directory order is filesystem-dependent; sort so the same SKILL.md wins a name collision
+    // every time.
+    out.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut dedup: HashMap<String, SkillSummary> = HashMap::new();
+    for s in out {
+        dedup.entry(s.name.clone()).or_insert(s);
+    }
+    let mut skills = dedup.into_values().collect::<Vec<_>>();
+    skills.sort_by_key(|s| s.name.to_ascii_lowercase());
+    skills
 }
 
-async fn start_session(
+async fn touch_session(
     State(state): State<AppState>,
-    Json(req): Json<StartRequest>,
+    AxumPath(session_id): AxumPath<String>,
 ) -> Result<Json<SessionMeta>, Response> {
-    let prompt = req.prompt.trim().to_string();
-    if prompt.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "prompt is required").into_response());
+    let mp = meta_path(&state, &session_id);
+    if let Some(mut meta) = read_meta(&mp).await {
+        meta.last_used_at_ms = now_ms();
+        write_meta(&mp, &meta)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+        return Ok(Json(meta));
     }
 
-    let session_id = match req.session_id {
-        Some(raw) => Uuid::parse_str(raw.trim())
-            .map_err(|_| (StatusCode::BAD_REQUEST, "invalid session_id").into_response())?
-            .to_string(),
-        None => Uuid::new_v4().to_string(),
+    let Some(mut meta) = native_session_meta(&state, &session_id).await else {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
     };
+    meta.last_used_at_ms = now_ms();
+    Ok(Json(meta))
+}
 
-    let dir = session_dir(&state, &session_id);
-    if tokio::fs::metadata(&dir).await.is_ok() {
-        return Err((StatusCode::CONFLICT, "session already exists").into_response());
-    }
-    tokio::fs::create_dir_all(&dir)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-
-    let created_at_ms = now_ms();
-    let last_used_at_ms = created_at_ms;
+#[derive(Deserialize)]
+struct StreamQuery {
+    #[serde(default)]
+    tail: Option<usize>,
+    #[serde(default)]
+    stop_on_disconnect: Option<bool>,
+}
 
-    let events_path = dir.join("events.jsonl");
-    let stderr_path = dir.join("stderr.log");
-    let conclusion_path = dir.join("conclusion.md");
+const STOP_ON_DISCONNECT_GRACE: Duration = Duration::from_secs(10);
 
-    let cwd = req.cwd.and_then(|s| {
-        let t = s.trim().to_string();
-        if t.is_empty() {
-            None
-        } else {
-            Some(t)
-        }
-    });
+/// Held by each SSE stream; when the last subscriber of a session goes away and the session opted
+/// into stop-on-disconnect, the active run is stopped after a grace period (so reloads survive).
+struct SubscriberGuard {
+    state: AppState,
+    session_id: String,
+}
 
-    let meta = SessionMeta {
-        id: session_id.clone(),
-        title: safe_title(&prompt),
-        created_at_ms,
-        last_used_at_ms,
-        cwd: cwd.clone(),
-        status: SessionStatus::Running,
-        codex_session_id: None,
-        context_window: None,
-        context_used_tokens: None,
-        context_left_pct: None,
-        events_path: events_path.to_string_lossy().to_string(),
-        stderr_path: stderr_path.to_string_lossy().to_string(),
-        conclusion_path: conclusion_path.to_string_lossy().to_string(),
-    };
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let state = self.state.clone();
+        let session_id = std::mem::take(&mut self.session_id);
+        handle.spawn(async move {
+            tokio::time::sleep(STOP_ON_DISCONNECT_GRACE).await;
+            if !state.stop_on_disconnect.lock().await.contains(&session_id) {
+                return;
+            }
+            let subscribers = {
+                let locked = state.streams.lock().await;
+                locked
+                    .get(&session_id)
+                    .map(|tx| tx.receiver_count())
+                    .unwrap_or(0)
+            };
+            if subscribers > 0 || !state.runs.lock().await.contains_key(&session_id) {
+                return;
+            }
+            info!("stopping session {session_id}: last subscriber disconnected");
+            let _ = stop_session(State(state.clone()), AxumPath(session_id)).await;
+        });
+    }
+}
 
-    write_meta(&dir.join("meta.json"), &meta)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+#[derive(Deserialize)]
+struct StopOnDisconnectRequest {
+    #[serde(default = "default_true")]
+    enabled: bool,
+}
 
-    {
-        use tokio::io::AsyncWriteExt;
-        let ts = now_ms();
-        let prompt_event = serde_json::json!({
-            "type": "app.prompt",
-            "prompt": prompt.clone(),
-            "_ts_ms": ts,
-        });
-        let mut file = tokio::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&events_path)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-        file.write_all(prompt_event.to_string().as_bytes())
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-        file.write_all(b"\n")
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+fn default_true() -> bool {
+    true
+}
 
-        broadcast_ui_event(
-            &state,
-            UiEvent {
-                session_id: session_id.clone(),
-                ts_ms: ts,
-                stream: "stdout".to_string(),
-                raw: prompt_event.to_string(),
-                json: Some(prompt_event),
-            },
-        )
-        .await;
+async fn set_stop_on_disconnect(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+    Json(req): Json<StopOnDisconnectRequest>,
+) -> StatusCode {
+    let mut locked = state.stop_on_disconnect.lock().await;
+    if req.enabled {
+        locked.insert(session_id);
+    } else {
+        locked.remove(&session_id);
     }
+    StatusCode::NO_CONTENT
+}
 
-    let codex = resolve_codex_executable(&state)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = y - if m <= 2 { 1 } else { 0 };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = m + if m > 2 { -3 } else { 9 };
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
 
-    let (cancel_tx, cancel_rx) = oneshot::channel();
-    {
-        let mut runs = state.runs.lock().await;
-        runs.insert(
-            session_id.clone(),
-            RunHandle {
-                cancel: Some(cancel_tx),
-                pid: None,
-            },
-        );
+fn parse_rfc3339_ms(ts: &str) -> Option<u64> {
+    // Handles examples like: "2026-01-31T09:11:23.415Z"
+    let s = ts.trim();
+    if !s.ends_with('Z') {
+        return None;
     }
+    let s = &s[..s.len() - 1];
+    let (date, time) = s.split_once('T')?;
+    let mut date_it = date.split('-');
+    let y: i64 = date_it.next()?.parse().ok()?;
+    let m: i64 = date_it.next()?.parse().ok()?;
+    let d: i64 = date_it.next()?.parse().ok()?;
 
-    let state_for_run = state.clone();
-    let session_id_for_run = session_id.clone();
-    let events_path_for_run = events_path.clone();
-    let stderr_path_for_run = stderr_path.clone();
-    let conclusion_path_for_run = conclusion_path.clone();
-    let meta_path_for_run = dir.join("meta.json");
-    tokio::spawn(async move {
-        run_turn_via_app_server(
-            state_for_run,
-            session_id_for_run,
-            codex,
-            cwd,
-            None,
-            prompt,
-            events_path_for_run,
-            stderr_path_for_run,
-            conclusion_path_for_run,
-            meta_path_for_run,
-            cancel_rx,
-        )
-        .await;
-    });
+    let mut time_it = time.split(':');
+    let hh: i64 = time_it.next()?.parse().ok()?;
+    let mm: i64 = time_it.next()?.parse().ok()?;
+    let sec_part = time_it.next()?;
+    let (ss_str, frac_str) = sec_part.split_once('.').unwrap_or((sec_part, ""));
+    let ss: i64 = ss_str.parse().ok()?;
+    let mut ms: i64 = 0;
+    if !frac_str.is_empty() {
+        let mut digits = frac_str.chars().take(3).collect::<String>();
+        while digits.len() < 3 {
+            digits.push('0');
+        }
+        ms = digits.parse::<i64>().ok()?;
+    }
 
-    Ok(Json(meta))
+    let days = days_from_civil(y, m, d);
+    let total_ms = days
+        .checked_mul(86_400_000)?
+        .checked_add(hh.checked_mul(3_600_000)?)?
+        .checked_add(mm.checked_mul(60_000)?)?
+        .checked_add(ss.checked_mul(1_000)?)?
+        .checked_add(ms)?;
+    if total_ms < 0 {
+        return None;
+    }
+    Some(total_ms as u64)
 }
 
-#[derive(Deserialize)]
-struct ContinueRequest {
-    prompt: String,
-    cwd: Option<String>,
+/// A native rollout line as a `codex_event`, stamped with the record's own timestamp when it has
+/// one.
+fn rollout_line_event(session_id: &str, raw: String) -> UiEvent {
+    let json = serde_json::from_str::<serde_json::Value>(&raw).ok();
+    let ts_ms = json
+        .as_ref()
+        .and_then(|v| v.get("timestamp"))
+        .and_then(|x| x.as_str())
+        .and_then(parse_rfc3339_ms)
+        .unwrap_or_else(now_ms);
+    UiEvent {
+        session_id: session_id.to_string(),
+        ts_ms,
+        stream: "stdout".to_string(),
+        raw,
+        json,
+    }
 }
 
-async fn continue_session(
-    State(state): State<AppState>,
-    AxumPath(session_id): AxumPath<String>,
-    Json(req): Json<ContinueRequest>,
-) -> Result<Json<SessionMeta>, Response> {
-    let prompt = req.prompt.trim().to_string();
-    if prompt.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "prompt is required").into_response());
-    }
+/// How often a watched native rollout is checked for appended lines.
+const ROLLOUT_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
+/// Native sessions driven by another codex (e.g. the official app) never reach our broadcast
+/// channel, so while someone is subscribed, tail the latest rollout and broadcast what it appends.
+async fn ensure_rollout_watcher(state: &AppState, session_id: &str) {
+    if session_dir(state, session_id).is_dir() {
+        return;
+    }
+    let path = {
+        let locked = state.native_cache.lock().await;
+        locked
+            .rollouts_by_session
+            .get(session_id)
+            .and_then(|paths| paths.last().cloned())
+    };
+    let Some(path) = path else {
+        return;
+    };
+    if !state
+        .rollout_watchers
+        .lock()
+        .await
+        .insert(session_id.to_string())
     {
-        let runs = state.runs.lock().await;
-        if runs.contains_key(&session_id) {
-            return Err((StatusCode::CONFLICT, "session is already running").into_response());
-        }
+        return;
     }
-
-    let cwd = req.cwd.and_then(|s| {
-        let t = s.trim().to_string();
-        if t.is_empty() {
-            None
-        } else {
-            Some(t)
+    // The backlog already covers what's on disk now.
+    let mut offset = tokio::fs::metadata(&path)
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let state = state.clone();
+    let session_id = session_id.to_string();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(state.rollout_poll_interval).await;
+            {
+                // Checked under the watcher lock so a subscriber arriving now either sees this
+                // watcher still registered or starts a new one.
+                let mut watchers = state.rollout_watchers.lock().await;
+                let subscribers = {
+                    let locked = state.streams.lock().await;
+                    locked
+                        .get(&session_id)
+                        .map(|tx| tx.receiver_count())
+                        .unwrap_or(0)
+                };
+                if subscribers == 0 {
+                    watchers.remove(&session_id);
+                    return;
+                }
+            }
+            for raw in read_appended_lines(&path, &mut offset).await {
+                broadcast_persisted_event(&state, rollout_line_event(&session_id, raw)).await;
+            }
         }
     });
+}
 
-    let dir = session_dir(&state, &session_id);
-    tokio::fs::create_dir_all(&dir)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-    let meta_path = dir.join("meta.json");
-    let mut meta = if let Some(meta) = read_meta(&meta_path).await {
-        meta
-    } else {
-        let Some(native) = native_session_meta(&state, &session_id).await else {
-            return Err((StatusCode::NOT_FOUND, "session not found").into_response());
-        };
-        let now = now_ms();
-        let events_path = dir.join("events.jsonl");
-        let stderr_path = dir.join("stderr.log");
-        let conclusion_path = dir.join("conclusion.md");
-        let created_at_ms = native.created_at_ms;
-        let last_used_at_ms = now;
+/// Complete lines appended to `path` since `offset`, advancing it past them. A trailing partial
+/// line is left for the next call; a truncated file restarts from its new end.
+async fn read_appended_lines(path: &Path, offset: &mut u64) -> Vec<String> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let Ok(len) = tokio::fs::metadata(path).await.map(|m| m.len()) else {
+        return Vec::new();
+    };
+    if len < *offset {
+        *offset = len;
+    }
+    if len == *offset {
+        return Vec::new();
+    }
+    let Ok(mut file) = tokio::fs::File::open(path).await else {
+        return Vec::new();
+    };
+    if file.seek(std::io::SeekFrom::Start(*offset)).await.is_err() {
+        return Vec::new();
+    }
+    let mut buf = Vec::new();
+    if file
+        .take(len - *offset)
+        .read_to_end(&mut buf)
+        .await
+        .is_err()
+    {
+        return Vec::new();
+    }
+    let Some(end) = buf.iter().rposition(|b| *b == b'\n') else {
+        return Vec::new();
+    };
+    *offset += end as u64 + 1;
+    String::from_utf8_lossy(&buf[..end])
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.to_string())
+        .collect()
+}
 
-        let meta = SessionMeta {
-            id: session_id.clone(),
-            title: native.title,
-            created_at_ms,
-            last_used_at_ms,
-            cwd: cwd.clone().or(native.cwd),
-            status: SessionStatus::Done,
-            codex_session_id: Some(session_id.clone()),
-            context_window: None,
-            context_used_tokens: None,
-            context_left_pct: None,
-            events_path: events_path.to_string_lossy().to_string(),
-            stderr_path: stderr_path.to_string_lossy().to_string(),
-            conclusion_path: conclusion_path.to_string_lossy().to_string(),
-        };
-        write_meta(&meta_path, &meta)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-        meta
+async fn load_session_backlog(
+    state: &AppState,
+    session_id: &str,
+    tail: Option<usize>,
+) -> Result<Vec<UiEvent>, Response> {
+    let dir = session_dir(state, session_id);
+    let warp_exists = tokio::fs::metadata(&dir).await.ok().is_some_and(|m| m.is_dir());
+    let native_paths = {
+        ensure_native_cache(state).await;
+        let locked = state.native_cache.lock().await;
+        locked.rollouts_by_session.get(session_id).cloned()
+    };
+    if !warp_exists && native_paths.is_none() {
+        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    }
+
+    let tail = match tail {
+        Some(0) => 0,
+        Some(n) => n.clamp(50, 50_000),
+        None => 4000,
     };
 
     let events_path = dir.join("events.jsonl");
     let stderr_path = dir.join("stderr.log");
-    let conclusion_path = dir.join("conclusion.md");
-
-    meta.status = SessionStatus::Running;
-    meta.cwd = cwd.clone().or(meta.cwd);
-    meta.last_used_at_ms = now_ms();
-    meta.events_path = events_path.to_string_lossy().to_string();
-    meta.stderr_path = stderr_path.to_string_lossy().to_string();
-    meta.conclusion_path = conclusion_path.to_string_lossy().to_string();
 
-    write_meta(&meta_path, &meta)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    let mut backlog: Vec<(u64, usize, UiEvent)> = Vec::new();
+    let mut seq: usize = 0;
 
-    {
-        use tokio::io::AsyncWriteExt;
-        let ts = now_ms();
-        let prompt_event = serde_json::json!({
-            "type": "app.prompt",
-            "prompt": prompt.clone(),
-            "_ts_ms": ts,
-        });
-        let mut file = tokio::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&events_path)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-        file.write_all(prompt_event.to_string().as_bytes())
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-        file.write_all(b"\n")
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-        broadcast_ui_event(
-            &state,
-            UiEvent {
-                session_id: session_id.clone(),
-                ts_ms: ts,
-                stream: "stdout".to_string(),
-                raw: prompt_event.to_string(),
-                json: Some(prompt_event),
-            },
-        )
-        .await;
-    }
+    if tail > 0 {
+        if let Some(paths) = native_paths {
+            for path in paths {
+                for raw in read_tail_lines(&path, tail).await {
+                    let event = rollout_line_event(session_id, raw);
+                    backlog.push((event.ts_ms, seq, event));
+                    seq = seq.saturating_add(1);
+                }
+            }
+        }
 
-    let codex = resolve_codex_executable(&state)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+        if warp_exists {
+            // Replay stdout events
+            for raw in read_tail_lines(&events_path, tail).await {
+                let mut json: Option<serde_json::Value> = None;
+                let mut ts_ms = now_ms();
+                if let Ok(v) = serde_json::from_str::<serde_json::Value>(&raw) {
+                    if let Some(t) = v.get("_ts_ms").and_then(|x| x.as_u64()) {
+                        ts_ms = t;
+                    }
+                    json = Some(v);
+                }
+                backlog.push((
+                    ts_ms,
+                    seq,
+                    UiEvent {
+                        session_id: session_id.to_string(),
+                        ts_ms,
+                        stream: "stdout".to_string(),
+                        raw,
+                        json,
+                    },
+                ));
+                seq = seq.saturating_add(1);
+            }
 
-    let (cancel_tx, cancel_rx) = oneshot::channel();
-    {
-        let mut runs = state.runs.lock().await;
-        runs.insert(
-            session_id.clone(),
-            RunHandle {
-                cancel: Some(cancel_tx),
-                pid: None,
-            },
-        );
+            // Replay stderr as raw lines (timestamps may be embedded in text).
+            for raw in read_tail_lines(&stderr_path, tail).await {
+                let cleaned = strip_ansi_csi(&raw);
+                if should_ignore_codex_app_server_stderr_line(&cleaned) {
+                    continue;
+                }
+                let ts = now_ms();
+                backlog.push((
+                    ts,
+                    seq,
+                    UiEvent {
+                        session_id: session_id.to_string(),
+                        ts_ms: ts,
+                        stream: "stderr".to_string(),
+                        raw: cleaned,
+                        json: None,
+                    },
+                ));
+                seq = seq.saturating_add(1);
+            }
+        }
     }
 
-    let state_for_run = state.clone();
-    let session_id_for_run = session_id.clone();
-    let cwd_for_run = cwd.clone().or(meta.cwd.clone());
-    let thread_id_for_run = meta.codex_session_id.clone();
-    let events_path_for_run = events_path.clone();
-    let stderr_path_for_run = stderr_path.clone();
-    let conclusion_path_for_run = conclusion_path.clone();
-    tokio::spawn(async move {
-        run_turn_via_app_server(
-            state_for_run,
-            session_id_for_run,
-            codex,
-            cwd_for_run,
-            thread_id_for_run,
-            prompt,
-            events_path_for_run,
-            stderr_path_for_run,
-            conclusion_path_for_run,
-            meta_path,
-            cancel_rx,
-        )
-        .await;
-    });
+    backlog.sort_by_key(|(ts, seq, _)| (*ts, *seq));
+    let mut backlog: Vec<UiEvent> = backlog.into_iter().map(|(_, _, e)| e).collect();
+    if tail > 0 && backlog.len() > tail {
+        backlog = backlog.split_off(backlog.len() - tail);
+    }
+    Ok(backlog)
+}
 
-    Ok(Json(meta))
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum NormalizedKind {
+    Prompt,
+    AgentMessage,
+    Reasoning,
+    Command,
+    FileChange,
+    TokenUsage,
+    TurnBoundary,
+    Error,
 }
 
-async fn stop_session(
-    State(state): State<AppState>,
-    AxumPath(session_id): AxumPath<String>,
-) -> Result<StatusCode, Response> {
-    let (cancel, pid) = {
-        let mut runs = state.runs.lock().await;
-        let Some(handle) = runs.get_mut(&session_id) else {
-            return Ok(StatusCode::NO_CONTENT);
-        };
-        (handle.cancel.take(), handle.pid)
-    };
+/// Protocol-independent view of a persisted event. Only the fields relevant to `kind` are set.
+#[derive(Serialize)]
+struct NormalizedEvent {
+    kind: NormalizedKind,
+    ts_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    item_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context_window: Option<u64>,
+}
 
-    let mut receiver_dropped = false;
-    if let Some(cancel) = cancel {
-        if cancel.send(()).is_err() {
-            receiver_dropped = true;
+impl NormalizedEvent {
+    fn new(kind: NormalizedKind, ts_ms: u64) -> Self {
+        Self {
+            kind,
+            ts_ms,
+            item_id: None,
+            text: None,
+            command: None,
+            exit_code: None,
+            status: None,
+            paths: Vec::new(),
+            total_tokens: None,
+            context_window: None,
         }
     }
 
-    if let Some(pid) = pid {
-        #[cfg(unix)]
-        unsafe {
-            libc::kill(pid as i32, libc::SIGINT);
-        }
-        #[cfg(unix)]
-        {
-            tokio::spawn(async move {
-                tokio::time::sleep(Duration::from_millis(800)).await;
-                unsafe {
-                    // If the PID is still alive, force-kill it.
-                    if libc::kill(pid as i32, 0) == 0 {
-                        libc::kill(pid as i32, libc::SIGKILL);
+    fn with_text(mut self, text: &str) -> Self {
+        self.text = Some(strip_tool_citations(text));
+        self
+    }
+}
+
+fn json_str<'a>(v: &'a serde_json::Value, key: &str) -> Option<&'a str> {
+    v.get(key).and_then(|x| x.as_str())
+}
+
+/// Maps a codex app-server notification (warp sessions) onto the normalized schema.
+fn normalize_app_server_event(json: &serde_json::Value, ts_ms: u64) -> Option<NormalizedEvent> {
+    let method = json_str(json, "method")?;
+    let params = json.get("params");
+    match method {
+        "item/completed" => {
+            let item = params?.get("item")?;
+            let item_id = json_str(item, "id").map(|s| s.to_string());
+            let mut ev = match json_str(item, "type")? {
+                "agentMessage" => NormalizedEvent::new(NormalizedKind::AgentMessage, ts_ms)
+                    .with_text(json_str(item, "text")?),
+                "reasoning" => {
+                    let summary = item
+                        .get("summary")
+                        .and_then(|v| v.as_array())
+                        .map(|parts| {
+                            parts
+                                .iter()
+                                .filter_map(|p| p.as_str())
+                                .collect::<Vec<_>>()
+                                .join("\n\n")
+                        })
+                        .unwrap_or_default();
+                    if summary.trim().is_empty() {
+                        return None;
                     }
+                    NormalizedEvent::new(NormalizedKind::Reasoning, ts_ms).with_text(&summary)
                 }
-            });
+                "commandExecution" => {
+                    let mut ev = NormalizedEvent::new(NormalizedKind::Command, ts_ms)
+                        .with_text(json_str(item, "aggregatedOutput").unwrap_or_default());
+                    ev.command = json_str(item, "command").map(|s| s.to_string());
+                    ev.exit_code = item.get("exitCode").and_then(|v| v.as_i64());
+                    ev.status = json_str(item, "status").map(|s| s.to_string());
+                    ev
+                }
+                "fileChange" => {
+                    let mut ev = NormalizedEvent::new(NormalizedKind::FileChange, ts_ms);
+                    ev.paths = item
+                        .get("changes")
+                        .and_then(|v| v.as_array())
+                        .map(|changes| {
+                            changes
+                                .iter()
+                                .filter_map(|c| json_str(c, "path").map(|s| s.to_string()))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    ev.status = json_str(item, "status").map(|s| s.to_string());
+                    ev
+                }
+                _ => return None,
+            };
+            ev.item_id = item_id;
+            Some(ev)
+        }
+        "thread/tokenUsage/updated" => {
+            let snapshot = extract_token_usage_snapshot(json)?;
+            let mut ev = NormalizedEvent::new(NormalizedKind::TokenUsage, ts_ms);
+            ev.total_tokens = Some(snapshot.total_tokens);
+            ev.context_window = Some(snapshot.window);
+            Some(ev)
+        }
+        "turn/started" | "turn/completed" => {
+            let mut ev = NormalizedEvent::new(NormalizedKind::TurnBoundary, ts_ms);
+            ev.status = params
+                .and_then(|p| p.get("turn"))
+                .and_then(|t| json_str(t, "status"))
+                .map(|s| s.to_string())
+                .or_else(|| {
+                    Some(
+                        if method == "turn/started" {
+                            "started"
+                        } else {
+                            "completed"
+                        }
+                        .to_string(),
+                    )
+                });
+            Some(ev)
+        }
+        "error" => {
+            let message = params
+                .and_then(|p| p.get("error"))
+                .and_then(|e| json_str(e, "message"))
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| params.map(|p| p.to_string()).unwrap_or_default());
+            Some(NormalizedEvent::new(NormalizedKind::Error, ts_ms).with_text(&message))
         }
+        _ => None,
     }
+}
 
-    if receiver_dropped {
-        {
-            let mut runs = state.runs.lock().await;
-            runs.remove(&session_id);
+/// Maps a native rollout record onto the normalized schema.
+fn normalize_rollout_event(json: &serde_json::Value, ts_ms: u64) -> Option<NormalizedEvent> {
+    let payload = json.get("payload")?;
+    let payload_type = json_str(payload, "type")?;
+    match (json_str(json, "type")?, payload_type) {
+        ("event_msg", "user_message") => {
+            let message = json_str(payload, "message")?;
+            should_show_rollout_user_text(message).then(|| {
+                NormalizedEvent::new(NormalizedKind::Prompt, ts_ms).with_text(message.trim())
+            })
         }
-        if let Some(mut meta) = read_meta(&meta_path(&state, &session_id)).await {
-            meta.status = SessionStatus::Error;
-            let _ = write_meta(&meta_path(&state, &session_id), &meta).await;
+        ("event_msg", "agent_message") => Some(
+            NormalizedEvent::new(NormalizedKind::AgentMessage, ts_ms)
+                .with_text(json_str(payload, "message")?),
+        ),
+        ("event_msg", "agent_reasoning") => Some(
+            NormalizedEvent::new(NormalizedKind::Reasoning, ts_ms)
+                .with_text(json_str(payload, "text")?),
+        ),
+        ("event_msg", "token_count") => {
+            let info = payload.get("info")?;
+            let mut ev = NormalizedEvent::new(NormalizedKind::TokenUsage, ts_ms);
+            ev.total_tokens = info
+                .get("total_token_usage")
+                .and_then(|u| u.get("total_tokens"))
+                .and_then(json_u64);
+            ev.context_window = info.get("model_context_window").and_then(json_u64);
+            Some(ev)
         }
-        broadcast_run_finished(
-            &state,
-            RunFinished {
-                session_id,
-                ts_ms: now_ms(),
-                exit_code: None,
-                success: false,
-            },
-        )
-        .await;
+        ("event_msg", "task_started") | ("event_msg", "task_complete") => {
+            let mut ev = NormalizedEvent::new(NormalizedKind::TurnBoundary, ts_ms);
+            ev.status = Some(
+                if payload_type == "task_started" {
+                    "started"
+                } else {
+                    "completed"
+                }
+                .to_string(),
+            );
+            Some(ev)
+        }
+        ("event_msg", "error") => Some(
+            NormalizedEvent::new(NormalizedKind::Error, ts_ms)
+                .with_text(json_str(payload, "message")?),
+        ),
+        ("response_item", "function_call") => {
+            let mut ev = NormalizedEvent::new(NormalizedKind::Command, ts_ms);
+            ev.item_id = json_str(payload, "call_id").map(|s| s.to_string());
+            ev.command = json_str(payload, "arguments")
+                .and_then(|raw| serde_json::from_str::<serde_json::Value>(raw).ok())
+                .and_then(|args| match args.get("command") {
+                    Some(serde_json::Value::String(cmd)) => Some(cmd.clone()),
+                    Some(serde_json::Value::Array(parts)) => Some(
+                        parts
+                            .iter()
+                            .filter_map(|p| p.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    ),
+                    _ => None,
+                });
+            ev.status = Some("in_progress".to_string());
+            Some(ev)
+        }
+        ("response_item", "function_call_output") => {
+            let mut ev = NormalizedEvent::new(NormalizedKind::Command, ts_ms)
+                .with_text(json_str(payload, "output").unwrap_or_default());
+            ev.item_id = json_str(payload, "call_id").map(|s| s.to_string());
+            ev.status = Some("completed".to_string());
+            Some(ev)
+        }
+        ("response_item", "custom_tool_call")
+            if json_str(payload, "name") == Some("apply_patch") =>
+        {
+            let mut ev = NormalizedEvent::new(NormalizedKind::FileChange, ts_ms);
+            ev.item_id = json_str(payload, "call_id").map(|s| s.to_string());
+            ev.paths = json_str(payload, "input")
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|l| {
+                    ["*** Add File: ", "*** Update File: ", "*** Delete File: "]
+                        .iter()
+                        .find_map(|prefix| l.strip_prefix(prefix))
+                })
+                .map(|p| p.trim().to_string())
+                .collect();
+            Some(ev)
+        }
+        _ => None,
+    }
+}
+
+/// Builds the `codex_tool` payload for a completed `commandExecution` or `fileChange` item. The
+/// command output is left out; it stays available through the persisted event.
+fn tool_update(session_id: &str, json: &serde_json::Value) -> Option<ToolUpdate> {
+    let mut event = normalize_app_server_event(json, now_ms())?;
+    if !matches!(
+        event.kind,
+        NormalizedKind::Command | NormalizedKind::FileChange
+    ) {
+        return None;
+    }
+    event.text = None;
+    Some(ToolUpdate {
+        session_id: session_id.to_string(),
+        event,
+    })
+}
+
+fn normalize_event(event: &UiEvent) -> Option<NormalizedEvent> {
+    let json = event.json.as_ref()?;
+    if json_str(json, "type") == Some("app.prompt") {
+        return Some(
+            NormalizedEvent::new(NormalizedKind::Prompt, event.ts_ms)
+                .with_text(json_str(json, "prompt")?),
+        );
+    }
+    if json.get("method").is_some() {
+        return normalize_app_server_event(json, event.ts_ms);
     }
+    normalize_rollout_event(json, event.ts_ms)
+}
+
+#[derive(Deserialize)]
+struct EventsPageQuery {
+    #[serde(default)]
+    offset: Option<usize>,
+    #[serde(default)]
+    limit: Option<usize>,
+}
 
-    Ok(StatusCode::NO_CONTENT)
+#[derive(Serialize)]
+struct EventsPage {
+    lines: Vec<String>,
+    total: usize,
+    next_offset: Option<usize>,
 }
 
-async fn delete_session(
+const EVENTS_PAGE_MAX_LIMIT: usize = 5000;
+
+async fn read_events_page(
     State(state): State<AppState>,
     AxumPath(session_id): AxumPath<String>,
-) -> Result<StatusCode, Response> {
-    let _ = stop_session(State(state.clone()), AxumPath(session_id.clone())).await;
-    let dir = session_dir(&state, &session_id);
-    let warp_exists = tokio::fs::metadata(&dir).await.ok().is_some_and(|m| m.is_dir());
-    if warp_exists {
-        tokio::fs::remove_dir_all(&dir)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Query(q): Query<EventsPageQuery>,
+) -> Result<Json<EventsPage>, Response> {
+    let offset = q.offset.unwrap_or(0);
+    let limit = q.limit.unwrap_or(500).clamp(1, EVENTS_PAGE_MAX_LIMIT);
+
+    let mut path = session_dir(&state, &session_id).join("events.jsonl");
+    if tokio::fs::metadata(&path).await.is_err() {
+        ensure_native_cache(&state).await;
+        let latest = {
+            let locked = state.native_cache.lock().await;
+            locked
+                .rollouts_by_session
+                .get(&session_id)
+                .and_then(|paths| paths.last().cloned())
+        };
+        let Some(latest) = latest else {
+            return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+        };
+        path = latest;
     }
 
-    ensure_native_cache(&state).await;
-    let native_paths = {
-        let locked = state.native_cache.lock().await;
-        locked.rollouts_by_session.get(&session_id).cloned()
-    };
-    let has_native = native_paths.is_some();
-    if let Some(paths) = native_paths {
-        for p in paths {
-            let _ = tokio::fs::remove_file(p).await;
+    let file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Ok(Json(read_lines_page(file, offset, limit).await))
+}
+
+/// Reads `limit` lines starting at line `offset`, counting every line so callers can page on.
+async fn read_lines_page(file: tokio::fs::File, offset: usize, limit: usize) -> EventsPage {
+    let mut reader = BufReader::new(file).lines();
+    let mut lines = Vec::new();
+    let mut total = 0usize;
+    while let Ok(Some(line)) = reader.next_line().await {
+        if total >= offset && lines.len() < limit {
+            lines.push(line);
         }
+        total += 1;
     }
 
-    if !warp_exists && !has_native {
-        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    let end = offset.saturating_add(lines.len());
+    let next_offset = (offset < total && end < total).then_some(end);
+    EventsPage {
+        lines,
+        total,
+        next_offset,
     }
-    Ok(StatusCode::NO_CONTENT)
 }
 
-async fn rename_session(
+/// Pages through a session's stderr.log with the same `offset`/`limit` semantics as
+/// `/events`. A session without stderr output yields an empty page.
+async fn read_stderr_page(
     State(state): State<AppState>,
     AxumPath(session_id): AxumPath<String>,
-    Json(payload): Json<HashMap<String, String>>,
-) -> Result<StatusCode, Response> {
-    let Some(title) = payload.get("title") else {
-        return Err((StatusCode::BAD_REQUEST, "title required").into_response());
-    };
-    let mp = meta_path(&state, &session_id);
-    if let Some(mut meta) = read_meta(&mp).await {
-        meta.title = title.clone();
-        write_meta(&mp, &meta)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-        return Ok(StatusCode::NO_CONTENT);
+    Query(q): Query<EventsPageQuery>,
+) -> Json<EventsPage> {
+    let offset = q.offset.unwrap_or(0);
+    let limit = q.limit.unwrap_or(500).clamp(1, EVENTS_PAGE_MAX_LIMIT);
+
+    let path = session_dir(&state, &session_id).join("stderr.log");
+    match tokio::fs::File::open(&path).await {
+        Ok(file) => Json(read_lines_page(file, offset, limit).await),
+        Err(_) => Json(EventsPage {
+            lines: Vec::new(),
+            total: 0,
+            next_offset: None,
+        }),
     }
+}
 
-    ensure_native_cache(&state).await;
-    let has_native = {
-        let locked = state.native_cache.lock().await;
-        locked.rollouts_by_session.contains_key(&session_id)
-    };
-    if !has_native {
-        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
-    }
+#[derive(Deserialize)]
+struct NormalizedEventsQuery {
+    #[serde(default)]
+    tail: Option<usize>,
+}
 
-    let Some(codex_home) = state.codex_home.clone() else {
-        return Err((StatusCode::INTERNAL_SERVER_ERROR, "codex_home not configured").into_response());
-    };
-    let session_id_for_write = session_id.clone();
-    let title_for_write = title.clone();
-    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
-        let path = codex_home.join(".codex-global-state.json");
-        let mut root: serde_json::Value = match std::fs::read_to_string(&path) {
-            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|_| serde_json::json!({})),
-            Err(_) => serde_json::json!({}),
+async fn normalized_events(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+    Query(q): Query<NormalizedEventsQuery>,
+) -> Result<Json<Vec<NormalizedEvent>>, Response> {
+    let backlog = load_session_backlog(&state, &session_id, q.tail).await?;
+    Ok(Json(backlog.iter().filter_map(normalize_event).collect()))
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+enum MessageRole {
+    User,
+    Assistant,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct TranscriptMessage {
+    role: MessageRole,
+    text: String,
+    ts_ms: u64,
+}
+
+/// Folds a session's events into its conversation: prompts become user messages, and streamed
+/// `item/agentMessage/delta` fragments are merged into the agent message they belong to. A message
+/// still streaming keeps the text received so far; its `item/completed` text replaces it.
+fn transcript_messages(events: &[UiEvent]) -> Vec<TranscriptMessage> {
+    let mut messages: Vec<TranscriptMessage> = Vec::new();
+    // Parallel to `messages`: whether each came from a native rollout rather than events.jsonl.
+    let mut from_rollout: Vec<bool> = Vec::new();
+    // Position in `messages` of each app-server agent message, by item id.
+    let mut by_item: HashMap<String, usize> = HashMap::new();
+    for event in events {
+        let Some(json) = event.json.as_ref() else {
+            continue;
         };
-        if !root.is_object() {
-            root = serde_json::json!({});
-        }
-        if !root.get("thread-titles").is_some_and(|v| v.is_object()) {
-            root["thread-titles"] = serde_json::json!({});
+        if json_str(json, "method") == Some("item/agentMessage/delta") {
+            let params = &json["params"];
+            let (Some(item_id), Some(delta)) =
+                (json_str(params, "itemId"), json_str(params, "delta"))
+            else {
+                continue;
+            };
+            match by_item.get(item_id) {
+                Some(&i) => messages[i].text.push_str(delta),
+                None => {
+                    by_item.insert(item_id.to_string(), messages.len());
+                    messages.push(TranscriptMessage {
+                        role: MessageRole::Assistant,
+                        text: delta.to_string(),
+                        ts_ms: event.ts_ms,
+                    });
+                    from_rollout.push(false);
+                }
+            }
+            continue;
         }
-        if !root["thread-titles"]
-            .get("titles")
-            .is_some_and(|v| v.is_object())
-        {
-            root["thread-titles"]["titles"] = serde_json::json!({});
+        let Some(normalized) = normalize_event(event) else {
+            continue;
+        };
+        let role = match normalized.kind {
+            NormalizedKind::Prompt => MessageRole::User,
+            NormalizedKind::AgentMessage => MessageRole::Assistant,
+            _ => continue,
+        };
+        let text = normalized.text.unwrap_or_default();
+        if let Some(&i) = normalized.item_id.as_ref().and_then(|id| by_item.get(id)) {
+            messages[i].text = text;
+            continue;
         }
-        root["thread-titles"]["titles"][&session_id_for_write] =
-            serde_json::Value::String(title_for_write.clone());
-
-        if !root["thread-titles"]
-            .get("order")
-            .is_some_and(|v| v.is_array())
-        {
-            root["thread-titles"]["order"] = serde_json::json!([]);
+        if let Some(item_id) = normalized.item_id {
+            by_item.insert(item_id, messages.len());
         }
-        if let Some(arr) = root["thread-titles"]["order"].as_array_mut() {
-            let exists = arr.iter().any(|v| v.as_str() == Some(&session_id_for_write));
-            if !exists {
-                arr.insert(0, serde_json::Value::String(session_id_for_write.clone()));
+        messages.push(TranscriptMessage {
+            role,
+            text,
+            ts_ms: event.ts_ms,
+        });
+        from_rollout
+            .push(json.get("method").is_none() && json_str(json, "type") != Some("app.prompt"));
+    }
+    for message in &mut messages {
+        message.text = strip_tool_citations(&message.text);
+    }
+    // A warp turn on a native thread is in both the rollout and events.jsonl; drop the rollout's
+    // copy of each such message, but keep repeats within one source (e.g. a re-sent prompt).
+    let mut warp_copies: HashMap<(MessageRole, &str), usize> = HashMap::new();
+    for (message, _) in messages
+        .iter()
+        .zip(&from_rollout)
+        .filter(|(_, rollout)| !**rollout)
+    {
+        *warp_copies
+            .entry((message.role, message.text.as_str()))
+            .or_default() += 1;
+    }
+    let duplicated: Vec<bool> = messages
+        .iter()
+        .zip(&from_rollout)
+        .map(|(message, rollout)| {
+            let copies = warp_copies.get_mut(&(message.role, message.text.as_str()));
+            match copies {
+                Some(n) if *rollout && *n > 0 => {
+                    *n -= 1;
+                    true
+                }
+                _ => false,
             }
-        }
-
-        std::fs::write(&path, serde_json::to_vec_pretty(&root)?)?;
-        Ok(())
-    })
-    .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-    Ok(StatusCode::NO_CONTENT)
+        })
+        .collect();
+    let mut duplicated = duplicated.into_iter();
+    messages.retain(|_| !duplicated.next().unwrap_or(false));
+    messages
 }
 
-async fn read_conclusion(
+/// The session as a user/assistant transcript, so clients needn't coalesce deltas themselves.
+/// Works for warp sessions and native rollouts alike; `tail` bounds the events replayed.
+async fn session_messages(
     State(state): State<AppState>,
     AxumPath(session_id): AxumPath<String>,
-) -> Result<String, Response> {
-    let dir = session_dir(&state, &session_id);
-    let path = dir.join("conclusion.md");
-    if let Ok(text) = tokio::fs::read_to_string(&path).await {
-        return Ok(text);
-    }
-
-    ensure_native_cache(&state).await;
-    let has_native = {
-        let locked = state.native_cache.lock().await;
-        locked.rollouts_by_session.contains_key(&session_id)
-    };
-    if has_native {
-        return Ok(String::new());
-    }
-
-    Err((StatusCode::NOT_FOUND, "session not found").into_response())
+    Query(q): Query<NormalizedEventsQuery>,
+) -> Result<Json<Vec<TranscriptMessage>>, Response> {
+    let backlog = load_session_backlog(&state, &session_id, q.tail).await?;
+    Ok(Json(transcript_messages(&backlog)))
 }
 
-#[derive(Deserialize)]
-struct UsageQuery {
-    #[serde(default)]
-    max_records: Option<usize>,
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum FileChangeStatus {
+    Added,
+    Modified,
+    Deleted,
 }
 
-async fn list_usage_records(
-    State(state): State<AppState>,
-    Query(q): Query<UsageQuery>,
-) -> Result<Json<Vec<UsageRecord>>, StatusCode> {
-    let file = match tokio::fs::File::open(state.data_dir.join("usage.jsonl")).await {
-        Ok(f) => f,
-        Err(_) => return Ok(Json(Vec::new())),
-    };
-    let max_records = q.max_records.unwrap_or(5000).clamp(1, 200_000);
-
-    let mut out: VecDeque<UsageRecord> = VecDeque::new();
-    let mut lines = BufReader::new(file).lines();
-    while let Ok(Some(line)) = lines.next_line().await {
-        let record = match serde_json::from_str::<UsageRecord>(&line) {
-            Ok(r) => r,
-            Err(_) => continue,
-        };
-        while out.len() >= max_records {
-            out.pop_front();
+impl FileChangeStatus {
+    /// Folds a later change to the same path into the status seen so far.
+    fn then(self, next: FileChangeStatus) -> FileChangeStatus {
+        match (self, next) {
+            (_, FileChangeStatus::Deleted) => FileChangeStatus::Deleted,
+            (FileChangeStatus::Deleted, _) => FileChangeStatus::Modified,
+            (FileChangeStatus::Added, _) => FileChangeStatus::Added,
+            (_, next) => next,
         }
-        out.push_back(record);
     }
-    Ok(Json(out.into_iter().collect()))
 }
 
-async fn list_skills() -> Result<Json<Vec<SkillSummary>>, StatusCode> {
-    let Some(root) = codex_skills_root() else {
-        return Ok(Json(Vec::new()));
-    };
-    if tokio::fs::metadata(&root).await.is_err() {
-        return Ok(Json(Vec::new()));
-    }
+#[derive(Serialize, Debug)]
+struct FileDiffEntry {
+    path: String,
+    additions: u32,
+    deletions: u32,
+    status: FileChangeStatus,
+}
 
-    let mut stack: Vec<PathBuf> = vec![root];
-    let mut out: Vec<SkillSummary> = Vec::new();
+/// Per-path totals of every file change in a session, in first-touched order.
+#[derive(Default)]
+struct FileDiffSummary {
+    entries: Vec<FileDiffEntry>,
+}
 
-    while let Some(dir) = stack.pop() {
-        let mut rd = match tokio::fs::read_dir(&dir).await {
-            Ok(r) => r,
-            Err(_) => continue,
-        };
-        while let Ok(Some(entry)) = rd.next_entry().await {
-            let ty = match entry.file_type().await {
-                Ok(t) => t,
-                Err(_) => continue,
+impl FileDiffSummary {
+    fn record(&mut self, path: &str, status: FileChangeStatus, additions: u32, deletions: u32) {
+        let path = path.trim();
+        if path.is_empty() {
+            return;
+        }
+        match self.entries.iter_mut().find(|e| e.path == path) {
+            Some(entry) => {
+                entry.additions = entry.additions.saturating_add(additions);
+                entry.deletions = entry.deletions.saturating_add(deletions);
+                entry.status = entry.status.then(status);
+            }
+            None => self.entries.push(FileDiffEntry {
+                path: path.to_string(),
+                additions,
+                deletions,
+                status,
+            }),
+        }
+    }
+
+    /// Accepts one persisted line: an app-server `item/completed` fileChange (warp sessions) or a
+    /// native rollout `apply_patch` call. Anything else is ignored.
+    fn add_event(&mut self, json: &serde_json::Value) {
+        if json_str(json, "method") == Some("item/completed") {
+            let Some(item) = json.get("params").and_then(|p| p.get("item")) else {
+                return;
             };
-            let path = entry.path();
-            if ty.is_dir() {
-                stack.push(path);
-                continue;
+            if json_str(item, "type") != Some("fileChange") {
+                return;
             }
-            if !ty.is_file() {
-                continue;
+            for change in item
+                .get("changes")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+            {
+                let Some(path) = json_str(change, "path") else {
+                    continue;
+                };
+                let kind = change
+                    .get("kind")
+                    .and_then(|k| k.as_str().or_else(|| json_str(k, "type")))
+                    .unwrap_or("update");
+                let status = match kind {
+                    "add" => FileChangeStatus::Added,
+                    "delete" => FileChangeStatus::Deleted,
+                    _ => FileChangeStatus::Modified,
+                };
+                let diff = json_str(change, "diff").unwrap_or_default();
+                let (mut additions, mut deletions) = count_diff_lines(diff);
+                // Added and deleted files may carry the whole file body instead of a unified diff.
+                if additions == 0 && deletions == 0 {
+                    let body = u32::try_from(diff.lines().count()).unwrap_or(u32::MAX);
+                    match status {
+                        FileChangeStatus::Added => additions = body,
+                        FileChangeStatus::Deleted => deletions = body,
+                        FileChangeStatus::Modified => {}
+                    }
+                }
+                self.record(path, status, additions, deletions);
             }
-            if entry.file_name().to_string_lossy() != "SKILL.md" {
+            return;
+        }
+
+        let Some(payload) = json.get("payload") else {
+            return;
+        };
+        if json_str(json, "type") == Some("response_item")
+            && json_str(payload, "type") == Some("custom_tool_call")
+            && json_str(payload, "name") == Some("apply_patch")
+        {
+            self.add_apply_patch(json_str(payload, "input").unwrap_or_default());
+        }
+    }
+
+    /// Parses an `apply_patch` body: `*** Add/Update/Delete File:` headers followed by `+`/`-` lines.
+    fn add_apply_patch(&mut self, patch: &str) {
+        let mut current: Option<(String, FileChangeStatus, u32, u32)> = None;
+        for line in patch.lines() {
+            let header = [
+                ("*** Add File: ", FileChangeStatus::Added),
+                ("*** Update File: ", FileChangeStatus::Modified),
+                ("*** Delete File: ", FileChangeStatus::Deleted),
+            ]
+            .into_iter()
+            .find_map(|(prefix, status)| line.strip_prefix(prefix).map(|p| (p, status)));
+            if let Some((path, status)) = header {
+                if let Some((path, status, additions, deletions)) = current.take() {
+                    self.record(&path, status, additions, deletions);
+                }
+                current = Some((path.to_string(), status, 0, 0));
+            } else if line.starts_with("*** ") {
                 continue;
+            } else if let Some((_, _, additions, deletions)) = current.as_mut() {
+                if line.starts_with('+') {
+                    *additions = additions.saturating_add(1);
+                } else if line.starts_with('-') {
+                    *deletions = deletions.saturating_add(1);
+                }
             }
+        }
+        if let Some((path, status, additions, deletions)) = current {
+            self.record(&path, status, additions, deletions);
+        }
+    }
+}
 
-            let text = match tokio::fs::read_to_string(&path).await {
-                Ok(t) => t,
-                Err(_) => continue,
-            };
-            let (name, description) = parse_skill_front_matter(&text);
-            let name = name.or_else(|| {
-                path.parent()
-                    .and_then(|p| p.file_name())
-                    .and_then(|s| s.to_str())
-                    .map(|s| s.to_string())
-            });
-            let Some(name) = name else {
-                continue;
-            };
-            out.push(SkillSummary {
-                name,
-                description: description.unwrap_or_default(),
-                path: path.to_string_lossy().to_string(),
-            });
+/// Counts added/removed lines in a unified diff, ignoring the `+++`/`---` file headers.
+fn count_diff_lines(diff: &str) -> (u32, u32) {
+    let mut additions = 0u32;
+    let mut deletions = 0u32;
+    for line in diff.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            continue;
+        }
+        if line.starts_with('+') {
+            additions = additions.saturating_add(1);
+        } else if line.starts_with('-') {
+            deletions = deletions.saturating_add(1);
         }
     }
+    (additions, deletions)
+}
 
-    let mut dedup: HashMap<String, SkillSummary> = HashMap::new();
-    for s in out {
-        dedup.entry(s.name.clone()).or_insert(s);
+/// Summarizes the files the agent touched, from events.jsonl or the native rollouts. Lines that
+/// aren't valid JSON are skipped.
+async fn session_diff(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+) -> Result<Json<Vec<FileDiffEntry>>, Response> {
+    let events_path = session_dir(&state, &session_id).join("events.jsonl");
+    let paths = if tokio::fs::metadata(&events_path).await.is_ok() {
+        vec![events_path]
+    } else {
+        ensure_native_cache(&state).await;
+        let locked = state.native_cache.lock().await;
+        locked
+            .rollouts_by_session
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| (StatusCode::NOT_FOUND, "session not found").into_response())?
+    };
+
+    let mut summary = FileDiffSummary::default();
+    for path in paths {
+        let Ok(file) = tokio::fs::File::open(&path).await else {
+            continue;
+        };
+        let mut lines = BufReader::new(file).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) {
+                summary.add_event(&json);
+            }
+        }
     }
-    let mut skills = dedup.into_values().collect::<Vec<_>>();
-    skills.sort_by_key(|s| s.name.to_ascii_lowercase());
-    Ok(Json(skills))
+    Ok(Json(summary.entries))
 }
 
-async fn touch_session(
+/// Serialized `app.lagged` notice telling a subscriber it missed `dropped` live events and should
+/// re-read the events endpoint.
+fn lagged_event_data(session_id: &str, dropped: u64) -> Option<String> {
+    let json = serde_json::json!({ "type": "app.lagged", "dropped": dropped });
+    serde_json::to_string(&UiEvent {
+        session_id: session_id.to_string(),
+        ts_ms: now_ms(),
+        stream: "stdout".to_string(),
+        raw: json.to_string(),
+        json: Some(json),
+    })
+    .ok()
+}
+
+async fn stream_session(
     State(state): State<AppState>,
     AxumPath(session_id): AxumPath<String>,
-) -> Result<Json<SessionMeta>, Response> {
-    let mp = meta_path(&state, &session_id);
-    if let Some(mut meta) = read_meta(&mp).await {
-        meta.last_used_at_ms = now_ms();
-        write_meta(&mp, &meta)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
-        return Ok(Json(meta));
-    }
+    Query(q): Query<StreamQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl tokio_stream::Stream<Item = Result<Event, std::convert::Infallible>>>, Response>
+{
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let (backlog, rx, guard) = subscribe_session(&state, &session_id, &q).await?;
+    let mut seq = EventSeq::default();
+    let backlog = resume_backlog(backlog, &mut seq, last_event_id);
 
-    let Some(mut meta) = native_session_meta(&state, &session_id).await else {
-        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    let last_metrics = persisted_metrics(&state, &session_id).await;
+
+    let stream = stream! {
+        let _guard = guard;
+        for (id, evt) in backlog {
+            if let Ok(data) = serde_json::to_string(&evt) {
+                yield Ok(Event::default().id(id.to_string()).event("codex_event").data(data));
+            }
+        }
+        if let Some(data) = last_metrics {
+            yield Ok(Event::default().event("codex_metrics").data(data));
+        }
+
+        let mut live = BroadcastStream::new(rx);
+        while let Some(item) = live.next().await {
+            let msg = match item {
+                Ok(msg) => msg,
+                Err(BroadcastStreamRecvError::Lagged(dropped)) => {
+                    // No id: the dropped events are older than now, and a client resuming from
+                    // this marker would otherwise skip them in the backlog.
+                    if let Some(data) = lagged_event_data(&session_id, dropped) {
+                        yield Ok(Event::default().event("codex_event").data(data));
+                    }
+                    continue;
+                }
+            };
+            if msg.persisted {
+                let id = seq.next(msg.ts_ms);
+                yield Ok(Event::default().id(id.to_string()).event(msg.event).data(msg.data));
+            } else {
+                yield Ok(Event::default().event(msg.event).data(msg.data));
+            }
+        }
     };
-    meta.last_used_at_ms = now_ms();
-    Ok(Json(meta))
+
+    Ok(Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("keepalive"),
+    ))
 }
 
 #[derive(Deserialize)]
-struct StreamQuery {
+struct MultiStreamQuery {
+    /// Comma-separated session ids.
     #[serde(default)]
-    tail: Option<usize>,
+    ids: String,
 }
 
-fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
-    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
-    let y = y - if m <= 2 { 1 } else { 0 };
-    let era = if y >= 0 { y } else { y - 399 } / 400;
-    let yoe = y - era * 400;
-    let mp = m + if m > 2 { -3 } else { 9 };
-    let doy = (153 * mp + 2) / 5 + d - 1;
-    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
-    era * 146097 + doe - 719468
-}
+/// Live events of several sessions over one SSE connection, for dashboards. There is no backlog;
+/// every payload carries its `session_id`. Unknown ids are dropped.
+async fn stream_sessions(
+    State(state): State<AppState>,
+    Query(q): Query<MultiStreamQuery>,
+) -> Result<Sse<impl tokio_stream::Stream<Item = Result<Event, std::convert::Infallible>>>, Response>
+{
+    ensure_native_cache(&state).await;
+    let mut ids: Vec<String> = Vec::new();
+    for id in q.ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let known = session_dir(&state, id).is_dir()
+            || state
+                .native_cache
+                .lock()
+                .await
+                .rollouts_by_session
+                .contains_key(id);
+        if known && !ids.iter().any(|seen| seen == id) {
+            ids.push(id.to_string());
+        }
+    }
+    if ids.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "no known session ids").into_response());
+    }
 
-fn parse_rfc3339_ms(ts: &str) -> Option<u64> {
-    // Handles examples like: "2026-01-31T09:11:23.415Z"
-    let s = ts.trim();
-    if !s.ends_with('Z') {
-        return None;
+    let mut streams = Vec::new();
+    let mut guards = Vec::new();
+    for id in ids {
+        let rx = ensure_stream(&state, &id).await.subscribe();
+        ensure_rollout_watcher(&state, &id).await;
+        guards.push(SubscriberGuard {
+            state: state.clone(),
+            session_id: id.clone(),
+        });
+        streams.push(BroadcastStream::new(rx).map(move |item| (id.clone(), item)));
     }
-    let s = &s[..s.len() - 1];
-    let (date, time) = s.split_once('T')?;
-    let mut date_it = date.split('-');
-    let y: i64 = date_it.next()?.parse().ok()?;
-    let m: i64 = date_it.next()?.parse().ok()?;
-    let d: i64 = date_it.next()?.parse().ok()?;
 
-    let mut time_it = time.split(':');
-    let hh: i64 = time_it.next()?.parse().ok()?;
-    let mm: i64 = time_it.next()?.parse().ok()?;
-    let sec_part = time_it.next()?;
-    let (ss_str, frac_str) = sec_part.split_once('.').unwrap_or((sec_part, ""));
-    let ss: i64 = ss_str.parse().ok()?;
-    let mut ms: i64 = 0;
-    if !frac_str.is_empty() {
-        let mut digits = frac_str.chars().take(3).collect::<String>();
-        while digits.len() < 3 {
-            digits.push('0');
+    let stream = stream! {
+        let _guards = guards;
+        let mut live = futures::stream::select_all(streams);
+        while let Some((session_id, item)) = live.next().await {
+            match item {
+                Ok(msg) => yield Ok(Event::default().event(msg.event).data(msg.data)),
+                Err(BroadcastStreamRecvError::Lagged(dropped)) => {
+                    if let Some(data) = lagged_event_data(&session_id, dropped) {
+                        yield Ok(Event::default().event("codex_event").data(data));
+                    }
+                }
+            }
         }
-        ms = digits.parse::<i64>().ok()?;
-    }
+    };
 
-    let days = days_from_civil(y, m, d);
-    let total_ms = days
-        .checked_mul(86_400_000)?
-        .checked_add(hh.checked_mul(3_600_000)?)?
-        .checked_add(mm.checked_mul(60_000)?)?
-        .checked_add(ss.checked_mul(1_000)?)?
-        .checked_add(ms)?;
-    if total_ms < 0 {
-        return None;
-    }
-    Some(total_ms as u64)
+    Ok(Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("keepalive"),
+    ))
 }
 
-async fn load_session_backlog(
+/// Seeds the context gauge from the last persisted snapshot; native-only sessions have no meta
+/// and therefore no metrics to replay.
+async fn persisted_metrics(state: &AppState, session_id: &str) -> Option<String> {
+    let meta = read_meta(&meta_path(state, session_id)).await?;
+    let metrics = ContextMetrics {
+        session_id: session_id.to_string(),
+        ts_ms: meta.last_used_at_ms,
+        context_left_pct: meta.context_left_pct?,
+        context_used_tokens: meta.context_used_tokens?,
+        context_window: meta.context_window.filter(|w| *w > 0)?,
+    };
+    serde_json::to_string(&metrics).ok()
+}
+
+/// Loads the replay backlog and subscribes to live events, shared by the SSE and NDJSON streams.
+/// The returned guard must live as long as the client connection.
+async fn subscribe_session(
     state: &AppState,
     session_id: &str,
-    tail: Option<usize>,
-) -> Result<Vec<UiEvent>, Response> {
-    let dir = session_dir(state, session_id);
-    let warp_exists = tokio::fs::metadata(&dir).await.ok().is_some_and(|m| m.is_dir());
-    let native_paths = {
-        ensure_native_cache(state).await;
-        let locked = state.native_cache.lock().await;
-        locked.rollouts_by_session.get(session_id).cloned()
-    };
-    if !warp_exists && native_paths.is_none() {
-        return Err((StatusCode::NOT_FOUND, "session not found").into_response());
+    q: &StreamQuery,
+) -> Result<
+    (
+        Vec<UiEvent>,
+        broadcast::Receiver<SseMessage>,
+        SubscriberGuard,
+    ),
+    Response,
+> {
+    let backlog = load_session_backlog(state, session_id, q.tail).await?;
+
+    if q.stop_on_disconnect == Some(true) {
+        state
+            .stop_on_disconnect
+            .lock()
+            .await
+            .insert(session_id.to_string());
     }
 
-    let tail = match tail {
-        Some(0) => 0,
-        Some(n) => n.clamp(50, 50_000),
-        None => 4000,
+    let tx = ensure_stream(state, session_id).await;
+    let rx = tx.subscribe();
+    ensure_rollout_watcher(state, session_id).await;
+    let guard = SubscriberGuard {
+        state: state.clone(),
+        session_id: session_id.to_string(),
     };
+    Ok((backlog, rx, guard))
+}
 
-    let events_path = dir.join("events.jsonl");
-    let stderr_path = dir.join("stderr.log");
+/// Client → server messages on `/ws`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WsCommand {
+    Stop,
+    Turn(Box<ContinueRequest>),
+}
 
-    let mut backlog: Vec<(u64, usize, UiEvent)> = Vec::new();
-    let mut seq: usize = 0;
+/// Wraps an already-serialized payload as `{"event": ..., "data": ...}`, the websocket framing
+/// of an SSE `event:`/`data:` pair.
+fn ws_frame(event: &str, data: &str) -> Message {
+    Message::Text(format!(
+        "{{\"event\":{},\"data\":{data}}}",
+        serde_json::Value::from(event)
+    ))
+}
 
-    if tail > 0 {
-        if let Some(paths) = native_paths {
-            for path in paths {
-                for raw in read_tail_lines(&path, tail).await {
-                    let mut json: Option<serde_json::Value> = None;
-                    let mut ts_ms = now_ms();
-                    if let Ok(v) = serde_json::from_str::<serde_json::Value>(&raw) {
-                        if let Some(t) = v
-                            .get("timestamp")
-                            .and_then(|x| x.as_str())
-                            .and_then(parse_rfc3339_ms)
-                        {
-                            ts_ms = t;
-                        }
-                        json = Some(v);
-                    }
-                    backlog.push((
-                        ts_ms,
-                        seq,
-                        UiEvent {
-                            session_id: session_id.to_string(),
-                            ts_ms,
-                            stream: "stdout".to_string(),
-                            raw,
-                            json,
-                        },
-                    ));
-                    seq = seq.saturating_add(1);
-                }
+/// Bidirectional alternative to `/stream`: pushes the same events, and accepts `stop` and `turn`
+/// commands so interactive clients need only one connection.
+async fn session_ws(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+    Query(q): Query<StreamQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, Response> {
+    let (backlog, rx, guard) = subscribe_session(&state, &session_id, &q).await?;
+    Ok(ws.on_upgrade(move |socket| async move {
+        let _guard = guard;
+        run_session_ws(state, session_id, socket, backlog, rx).await;
+    }))
+}
+
+async fn run_session_ws(
+    state: AppState,
+    session_id: String,
+    mut socket: WebSocket,
+    backlog: Vec<UiEvent>,
+    rx: broadcast::Receiver<SseMessage>,
+) {
+    for evt in backlog {
+        if let Ok(data) = serde_json::to_string(&evt) {
+            if socket.send(ws_frame("codex_event", &data)).await.is_err() {
+                return;
             }
         }
+    }
+    if let Some(data) = persisted_metrics(&state, &session_id).await {
+        if socket.send(ws_frame("codex_metrics", &data)).await.is_err() {
+            return;
+        }
+    }
 
-        if warp_exists {
-            // Replay stdout events
-            for raw in read_tail_lines(&events_path, tail).await {
-                let mut json: Option<serde_json::Value> = None;
-                let mut ts_ms = now_ms();
-                if let Ok(v) = serde_json::from_str::<serde_json::Value>(&raw) {
-                    if let Some(t) = v.get("_ts_ms").and_then(|x| x.as_u64()) {
-                        ts_ms = t;
+    let mut live = BroadcastStream::new(rx);
+    loop {
+        let frame = tokio::select! {
+            item = live.next() => match item {
+                Some(Ok(msg)) => ws_frame(msg.event, &msg.data),
+                Some(Err(BroadcastStreamRecvError::Lagged(dropped))) => {
+                    match lagged_event_data(&session_id, dropped) {
+                        Some(data) => ws_frame("codex_event", &data),
+                        None => continue,
                     }
-                    json = Some(v);
                 }
-                backlog.push((
-                    ts_ms,
-                    seq,
-                    UiEvent {
-                        session_id: session_id.to_string(),
-                        ts_ms,
-                        stream: "stdout".to_string(),
-                        raw,
-                        json,
-                    },
-                ));
-                seq = seq.saturating_add(1);
+                None => return,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => match handle_ws_command(&state, &session_id, &text).await {
+                    Some(frame) => frame,
+                    None => continue,
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket.send(frame).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Runs one client command, returning the frame to answer with: `turn_started` with the session
+/// meta, or `ws_error` when the command is malformed or rejected.
+async fn handle_ws_command(state: &AppState, session_id: &str, text: &str) -> Option<Message> {
+    let error = |status: StatusCode, message: String| {
+        let data = serde_json::json!({ "status": status.as_u16(), "message": message });
+        Some(ws_frame("ws_error", &data.to_string()))
+    };
+    let command = match serde_json::from_str::<WsCommand>(text) {
+        Ok(command) => command,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    let result = match command {
+        WsCommand::Stop => stop_session(State(state.clone()), AxumPath(session_id.to_string()))
+            .await
+            .map(|_| None),
+        WsCommand::Turn(req) => continue_session(
+            State(state.clone()),
+            AxumPath(session_id.to_string()),
+            Json(*req),
+        )
+        .await
+        .map(|Json(meta)| {
+            serde_json::to_string(&meta)
+                .ok()
+                .map(|data| ws_frame("turn_started", &data))
+        }),
+    };
+    match result {
+        Ok(frame) => frame,
+        Err(resp) => {
+            let status = resp.status();
+            let body = axum::body::to_bytes(resp.into_body(), 64 * 1024)
+                .await
+                .unwrap_or_default();
+            error(status, String::from_utf8_lossy(&body).to_string())
+        }
+    }
+}
+
+/// Same events as `stream_session`, framed as one serialized `UiEvent` per line for clients that
+/// can't read SSE. Only `codex_event`s are written, and the response ends once the run finishes.
+async fn stream_session_ndjson(
+    State(state): State<AppState>,
+    AxumPath(session_id): AxumPath<String>,
+    Query(q): Query<StreamQuery>,
+) -> Result<Response, Response> {
+    let (backlog, rx, guard) = subscribe_session(&state, &session_id, &q).await?;
+
+    let stream = stream! {
+        let _guard = guard;
+        for evt in backlog {
+            if let Ok(data) = serde_json::to_string(&evt) {
+                yield Ok::<_, std::convert::Infallible>(format!("{data}\n"));
             }
+        }
 
-            // Replay stderr as raw lines (timestamps may be embedded in text).
-            for raw in read_tail_lines(&stderr_path, tail).await {
-                let cleaned = strip_ansi_csi(&raw);
-                if should_ignore_codex_app_server_stderr_line(&cleaned) {
-                    continue;
+        let mut live = BroadcastStream::new(rx);
+        while let Some(item) = live.next().await {
+            match item {
+                Ok(msg) if msg.event == "codex_event" => yield Ok(format!("{}\n", msg.data)),
+                Ok(msg) if msg.event == "codex_run_finished" => break,
+                Ok(_) => {}
+                Err(BroadcastStreamRecvError::Lagged(dropped)) => {
+                    if let Some(data) = lagged_event_data(&session_id, dropped) {
+                        yield Ok(format!("{data}\n"));
+                    }
                 }
-                let ts = now_ms();
-                backlog.push((
-                    ts,
-                    seq,
-                    UiEvent {
-                        session_id: session_id.to_string(),
-                        ts_ms: ts,
-                        stream: "stderr".to_string(),
-                        raw: cleaned,
-                        json: None,
-                    },
-                ));
-                seq = seq.saturating_add(1);
             }
         }
+    };
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        axum::body::Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+fn tokens_match(expected: &str, given: &str) -> bool {
+    // Compare in constant time so the token can't be guessed byte by byte from response timing.
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Rejects /api requests without the configured bearer token. The SSE stream and websocket routes
+/// also accept `?token=` because `EventSource` and browser `WebSocket` can't set request headers.
+async fn require_auth_token(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let Some(expected) = state.auth_token.as_deref() else {
+        return next.run(req).await;
+    };
+    let path = req.uri().path();
+    if !path.starts_with("/api/") {
+        return next.run(req).await;
     }
 
-    backlog.sort_by_key(|(ts, seq, _)| (*ts, *seq));
-    let mut backlog: Vec<UiEvent> = backlog.into_iter().map(|(_, _, e)| e).collect();
-    if tail > 0 && backlog.len() > tail {
-        backlog = backlog.split_off(backlog.len() - tail);
+    let header_token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string());
+    let query_token = if path.ends_with("/stream") || path.ends_with("/ws") {
+        req.uri().query().and_then(|q| {
+            q.split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(k, _)| *k == "token")
+                .map(|(_, v)| percent_decode(v))
+        })
+    } else {
+        None
+    };
+
+    let authorized = [header_token, query_token]
+        .into_iter()
+        .flatten()
+        .any(|token| tokens_match(expected, &token));
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({ "error": "unauthorized" })),
+        )
+            .into_response();
     }
-    Ok(backlog)
+    next.run(req).await
 }
 
-async fn stream_session(
-    State(state): State<AppState>,
-    AxumPath(session_id): AxumPath<String>,
-    Query(q): Query<StreamQuery>,
-    _headers: HeaderMap,
-) -> Result<Sse<impl tokio_stream::Stream<Item = Result<Event, std::convert::Infallible>>>, Response>
-{
-    let backlog = load_session_backlog(&state, &session_id, q.tail).await?;
+fn percent_decode(raw: &str) -> String {
+    let bytes = raw.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => match raw
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(b) => {
+                    out.push(b);
+                    i += 3;
+                    continue;
+                }
+                None => out.push(b'%'),
+            },
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
 
-    let tx = ensure_stream(&state, &session_id).await;
-    let rx = tx.subscribe();
+/// Room in a turn request body for everything besides the prompt (JSON escaping, instructions,
+/// attachments, ...).
+const PROMPT_BODY_HEADROOM: usize = 1024 * 1024;
 
-    let stream = stream! {
-        for evt in backlog {
-            if let Ok(data) = serde_json::to_string(&evt) {
-                yield Ok(Event::default().event("codex_event").data(data));
-            }
-        }
+/// Body limit for the turn-starting routes, so `--max-prompt-bytes` (rather than axum's 2 MB
+/// default) decides how big a prompt can be.
+fn prompt_body_limit(state: &AppState) -> DefaultBodyLimit {
+    match state.max_prompt_bytes {
+        Some(max) => DefaultBodyLimit::max(max.saturating_add(PROMPT_BODY_HEADROOM)),
+        None => DefaultBodyLimit::disable(),
+    }
+}
 
-        let mut live = BroadcastStream::new(rx);
-        while let Some(item) = live.next().await {
-            let Ok(msg) = item else { continue };
-            yield Ok(Event::default().event(msg.event).data(msg.data));
-        }
-    };
+fn api_router(state: AppState) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/api/status", get(server_status))
+        .route("/api/skills", get(list_skills))
+        .route("/api/skills/:name", get(get_skill))
+        .route("/api/codex/version", get(codex_version))
+        .route("/api/codex/preflight", get(codex_preflight))
+        .route("/api/codex/auth", get(codex_auth_status))
+        .route("/api/usage", get(list_usage_records).delete(clear_usage))
+        .route("/api/usage/summary", get(usage_summary))
+        .route("/api/maintenance/prune", post(prune_sessions))
+        .route("/api/search", get(search_sessions))
+        .route(
+            "/api/sessions",
+            get(list_sessions)
+                .post(start_session)
+                .layer(prompt_body_limit(&state)),
+        )
+        .route("/api/sessions/count", get(count_sessions))
+        .route("/api/sessions/export_all.ndjson", get(export_all_sessions))
+        .route("/api/cwds", get(list_cwds))
+        .route("/api/runs", get(list_runs))
+        .route("/api/stream", get(stream_sessions))
+        .route("/api/sessions/recent", get(recent_sessions))
+        .route("/api/sessions/retitle", post(retitle_sessions))
+        .route(
+            "/api/sessions/import",
+            post(import_session).layer(DefaultBodyLimit::max(SESSION_IMPORT_MAX_BYTES)),
+        )
+        .route(
+            "/api/sessions/turn_sync",
+            post(start_session_sync).layer(prompt_body_limit(&state)),
+        )
+        .route(
+            "/api/sessions/latest/turn",
+            post(continue_latest_session).layer(prompt_body_limit(&state)),
+        )
+        .route("/api/sessions/:id/touch", post(touch_session))
+        .route(
+            "/api/sessions/:id/turn",
+            post(continue_session).layer(prompt_body_limit(&state)),
+        )
+        .route(
+            "/api/sessions/:id/turn_sync",
+            post(continue_session_sync).layer(prompt_body_limit(&state)),
+        )
+        .route("/api/sessions/:id/stop", post(stop_session))
+        .route(
+            "/api/sessions/:id/stop-on-disconnect",
+            post(set_stop_on_disconnect),
+        )
+        .route("/api/sessions/:id/rename", post(rename_session))
+        .route(
+            "/api/sessions/:id/instructions",
+            post(set_session_instructions),
+        )
+        .route("/api/sessions/:id/note", post(set_session_note))
+        .route("/api/sessions/:id/title/suggest", post(suggest_title))
+        .route("/api/sessions/:id/fork", post(fork_session))
+        .route("/api/sessions/:id/export", get(export_session))
+        .route("/api/sessions/:id/compact", post(compact_session))
+        .route("/api/sessions/:id/archive", post(archive_session))
+        .route("/api/sessions/:id/unarchive", post(unarchive_session))
+        .route("/api/sessions/:id/conclusion", get(read_conclusion))
+        .route("/api/sessions/:id/last_message", get(last_message))
+        .route("/api/sessions/:id/prompts", get(session_prompts))
+        .route("/api/sessions/:id/stream", get(stream_session))
+        .route(
+            "/api/sessions/:id/events.ndjson",
+            get(stream_session_ndjson),
+        )
+        .route("/api/sessions/:id/ws", get(session_ws))
+        .route("/api/sessions/:id/events", get(read_events_page))
+        .route("/api/sessions/:id/stderr", get(read_stderr_page))
+        .route(
+            "/api/sessions/:id/events/normalized",
+            get(normalized_events),
+        )
+        .route("/api/sessions/:id/messages", get(session_messages))
+        .route("/api/sessions/:id/diff", get(session_diff))
+        .route("/api/sessions/:id/paths", get(session_paths))
+        .route("/api/sessions/:id", get(get_session).delete(delete_session))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_auth_token,
+        ))
+        // The default predicate already skips SSE; NDJSON streams must not be buffered either.
+        .layer(CompressionLayer::new().compress_when(
+            DefaultPredicate::new().and(NotForContentType::const_new("application/x-ndjson")),
+        ))
+        .layer(cors_layer(&state.cors_origins))
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
+}
 
-    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)).text("keepalive")))
+/// Restricts CORS to the configured origins (with credentials), or allows any origin when none
+/// are configured, as in development.
+fn cors_layer(origins: &[String]) -> CorsLayer {
+    if origins.is_empty() {
+        return CorsLayer::very_permissive();
+    }
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|o| HeaderValue::from_str(o).ok())
+        .collect();
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods(AllowMethods::mirror_request())
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(true)
 }
 
 async fn healthz() -> &'static str {
     "ok"
 }
 
-// --- Codex app-server runner (adapted from the desktop app) ---
-
-async fn write_jsonrpc_request(
-    stdin: &mut ChildStdin,
-    id: i64,
-    method: &str,
-    params: serde_json::Value,
-) -> anyhow::Result<()> {
-    use tokio::io::AsyncWriteExt;
-    let msg = serde_json::json!({ "id": id, "method": method, "params": params });
-    let line = msg.to_string();
-    stdin.write_all(line.as_bytes()).await?;
-    stdin.write_all(b"\n").await?;
-    Ok(())
+#[derive(Serialize)]
+struct ServerStatus {
+    active_runs: usize,
+    data_dir: String,
+    codex_home: Option<String>,
+    /// The codex binary turns would use right now, if one can be found.
+    codex_path: Option<String>,
+    /// Time since the native rollout index was last rebuilt (time since epoch if never built).
+    native_cache_age_ms: u64,
+    /// `initialize` result of the last app-server this server started; `None` until a run starts.
+    capabilities: Option<serde_json::Value>,
+    /// Origins allowed by CORS; empty means any origin.
+    cors_origins: Vec<String>,
 }
 
-async fn read_next_json_line(
-    lines: &mut tokio::io::Lines<BufReader<ChildStdout>>,
-    cancel_rx: &mut oneshot::Receiver<()>,
-) -> anyhow::Result<Option<(String, serde_json::Value)>> {
-    let next = tokio::select! {
-        _ = &mut *cancel_rx => return Err(anyhow::anyhow!("cancelled")),
-        next = lines.next_line() => next,
-    };
-
-    let Some(line) = next? else {
-        return Ok(None);
-    };
-    let json = serde_json::from_str::<serde_json::Value>(&line)?;
-    Ok(Some((line, json)))
+async fn server_status(State(state): State<AppState>) -> Json<ServerStatus> {
+    let active_runs = state.runs.lock().await.len();
+    let built_at_ms = state.native_cache.lock().await.built_at_ms;
+    Json(ServerStatus {
+        active_runs,
+        data_dir: state.data_dir.to_string_lossy().to_string(),
+        codex_home: state
+            .codex_home
+            .as_ref()
+            .map(|p| p.to_string_lossy().to_string()),
+        codex_path: resolve_codex_executable(&state)
+            .ok()
+            .map(|p| p.to_string_lossy().to_string()),
+        native_cache_age_ms: now_ms().saturating_sub(built_at_ms),
+        capabilities: state.last_capabilities.lock().await.clone(),
+        cors_origins: state.cors_origins.clone(),
+    })
 }
 
-fn jsonrpc_id_matches(value: &serde_json::Value, expected: i64) -> bool {
-    let Some(id) = value.get("id") else {
-        return false;
-    };
-    match id {
-        serde_json::Value::Number(n) => n.as_i64() == Some(expected),
-        serde_json::Value::String(s) => s == expected.to_string().as_str(),
-        _ => false,
-    }
-}
+// --- Codex app-server runner (adapted from the desktop app) ---
 
 async fn persist_and_emit_stdout(
     state: &AppState,
@@ -2126,13 +10590,16 @@ async fn persist_and_emit_stdout(
 ) -> anyhow::Result<()> {
     use tokio::io::AsyncWriteExt;
 
-    if let Some(method) = json.get("method").and_then(|v| v.as_str()) {
-        if method == "thread/tokenUsage/updated"
-            || method == "account/rateLimits/updated"
-            || method == "item/reasoning/summaryPartAdded"
-        {
-            return Ok(());
-        }
+    if let Some(status) = extract_rate_limit_status(&json) {
+        record_rate_limit(state, session_id, status).await;
+        return Ok(());
+    }
+    if json
+        .get("method")
+        .and_then(|v| v.as_str())
+        .is_some_and(is_unpersisted_notification)
+    {
+        return Ok(());
     }
 
     let ts_ms = now_ms();
@@ -2143,7 +10610,7 @@ async fn persist_and_emit_stdout(
     events_file.write_all(persisted.to_string().as_bytes()).await?;
     events_file.write_all(b"\n").await?;
 
-    broadcast_ui_event(
+    broadcast_persisted_event(
         state,
         UiEvent {
             session_id: session_id.to_string(),
@@ -2157,171 +10624,678 @@ async fn persist_and_emit_stdout(
     Ok(())
 }
 
-fn capture_agent_message_text(
+/// Rebuilds `conclusion.md` from the last completed agent message in `events.jsonl`, for turns
+/// that ended without any streamed agent text.
+async fn update_conclusion_from_events(dir: &Path) -> anyhow::Result<()> {
+    let file = match tokio::fs::File::open(dir.join("events.jsonl")).await {
+        Ok(f) => f,
+        Err(_) => return Ok(()),
+    };
+
+    let mut last_message: Option<String> = None;
+    let mut lines = BufReader::new(file).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) else {
+            continue;
+        };
+        if !is_agent_message_completed(&json) {
+            continue;
+        }
+        if let Some(text) = json
+            .get("params")
+            .and_then(|v| v.get("item"))
+            .and_then(|v| v.get("text"))
+            .and_then(|v| v.as_str())
+        {
+            last_message = Some(text.to_string());
+        }
+    }
+
+    if let Some(text) = last_message {
+        tokio::fs::write(dir.join("conclusion.md"), strip_tool_citations(&text)).await?;
+    }
+    Ok(())
+}
+
+/// Updates the accumulated agent message and, when it changed, broadcasts an `app.agent_message`
+/// event carrying the full text so far so clients don't have to replay deltas themselves.
+async fn track_agent_message(
+    state: &AppState,
+    session_id: &str,
     msg: &serde_json::Value,
     agent_item_id: &mut Option<String>,
     agent_text: &mut String,
 ) {
-    let Some(method) = msg.get("method").and_then(|v| v.as_str()) else {
+    capture_agent_message_text(msg, agent_item_id, agent_text);
+    let method = msg.get("method").and_then(|v| v.as_str());
+    if method != Some("item/agentMessage/delta") && !is_agent_message_completed(msg) {
         return;
+    }
+    let ts = now_ms();
+    let payload = serde_json::json!({
+        "type": "app.agent_message",
+        "item_id": agent_item_id.clone(),
+        "text": agent_text.clone(),
+        "completed": method == Some("item/completed"),
+        "_ts_ms": ts,
+    });
+    broadcast_ui_event(
+        state,
+        UiEvent {
+            session_id: session_id.to_string(),
+            ts_ms: ts,
+            stream: "stdout".to_string(),
+            raw: payload.to_string(),
+            json: Some(payload),
+        },
+    )
+    .await;
+}
+
+/// Pulls the item id and summary text out of an `item/reasoning/summaryPartAdded` notification.
+/// The text may be empty when the app-server only announces the part.
+fn extract_reasoning_summary(msg: &serde_json::Value) -> Option<(Option<String>, String)> {
+    if msg.get("method").and_then(|v| v.as_str()) != Some("item/reasoning/summaryPartAdded") {
+        return None;
+    }
+    let params = msg.get("params")?;
+    let item_id = params
+        .get("itemId")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let text = params
+        .get("part")
+        .and_then(|p| p.get("text"))
+        .or_else(|| params.get("text"))
+        .or_else(|| params.get("summary"))
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    Some((item_id, text))
+}
+
+/// Parses `account/rateLimits/updated`, reporting whichever window (primary/secondary) is
+/// closest to its limit.
+fn extract_rate_limit_status(msg: &serde_json::Value) -> Option<RateLimitStatus> {
+    if msg.get("method").and_then(|v| v.as_str()) != Some("account/rateLimits/updated") {
+        return None;
+    }
+    let params = msg.get("params")?;
+    let limits = params.get("rateLimits").unwrap_or(params);
+    ["primary", "secondary"]
+        .iter()
+        .filter_map(|key| {
+            let window = limits.get(*key)?;
+            let used_pct = window.get("usedPercent").and_then(|v| v.as_f64())?;
+            let reset_at_ms = window
+                .get("resetsAt")
+                .and_then(json_u64)
+                .map(|secs| secs.saturating_mul(1000))
+                .or_else(|| {
+                    let secs = window.get("resetsInSeconds").and_then(json_u64)?;
+                    Some(now_ms().saturating_add(secs.saturating_mul(1000)))
+                });
+            Some(RateLimitStatus {
+                used_pct,
+                reset_at_ms,
+                window: window.get("windowDurationMins").and_then(json_u64),
+            })
+        })
+        .max_by(|a, b| a.used_pct.total_cmp(&b.used_pct))
+}
+
+async fn record_rate_limit(state: &AppState, session_id: &str, status: RateLimitStatus) {
+    let path = meta_path(state, session_id);
+    if let Some(mut meta) = read_meta(&path).await {
+        meta.rate_limit = Some(status.clone());
+        let _ = write_meta(&path, &meta).await;
+    }
+    broadcast_rate_limit(
+        state,
+        RateLimitUpdate {
+            session_id: session_id.to_string(),
+            ts_ms: now_ms(),
+            status,
+        },
+    )
+    .await;
+}
+
+fn is_rate_limit_error(message: &str) -> bool {
+    let lower = message.to_ascii_lowercase();
+    lower.contains("rate limit")
+        || lower.contains("ratelimit")
+        || lower.contains("usage limit")
+        || lower.contains("429")
+}
+
+/// Appends the last known reset time to a rate-limit failure so the user knows when to retry.
+fn with_rate_limit_hint(error: String, last: Option<&RateLimitStatus>) -> String {
+    let Some(reset_at_ms) = last.and_then(|s| s.reset_at_ms) else {
+        return error;
     };
-    if method == "item/agentMessage/delta" {
-        let Some(params) = msg.get("params") else {
-            return;
-        };
-        let Some(item_id) = params.get("itemId").and_then(|v| v.as_str()) else {
-            return;
-        };
-        let delta = params.get("delta").and_then(|v| v.as_str()).unwrap_or_default();
-        if agent_item_id.as_deref() != Some(item_id) {
-            agent_text.clear();
-            *agent_item_id = Some(item_id.to_string());
+    if !is_rate_limit_error(&error) {
+        return error;
+    }
+    let mins = reset_at_ms.saturating_sub(now_ms()).div_ceil(60_000);
+    format!("{error} (rate limit resets in ~{mins} min)")
+}
+
+fn is_context_compaction_notification(msg: &serde_json::Value) -> bool {
+    msg.get("method").and_then(|v| v.as_str()) == Some("thread/compacted")
+}
+
+fn context_usage_dropped(prev: TokenUsageSnapshot, next: TokenUsageSnapshot) -> bool {
+    // Per-request usage only grows within a thread, so a large drop means earlier context was
+    // summarized away even when the app-server didn't announce it.
+    let dropped = prev.total_tokens.saturating_sub(next.total_tokens);
+    dropped > 0
+        && dropped.saturating_mul(2) >= prev.total_tokens
+        && dropped.saturating_mul(10) >= prev.window
+}
+
+async fn emit_context_compacted(
+    state: &AppState,
+    session_id: &str,
+    events_file: &mut tokio::fs::File,
+    before: Option<TokenUsageSnapshot>,
+    after: Option<TokenUsageSnapshot>,
+    inferred: bool,
+) {
+    let payload = ContextCompacted {
+        session_id: session_id.to_string(),
+        ts_ms: now_ms(),
+        before_tokens: before.map(|s| s.total_tokens),
+        after_tokens: after.map(|s| s.total_tokens),
+        context_window: after.or(before).map(|s| s.window),
+        inferred,
+    };
+    let marker = serde_json::json!({
+        "type": "app.context_compacted",
+        "before_tokens": payload.before_tokens,
+        "after_tokens": payload.after_tokens,
+        "context_window": payload.context_window,
+        "inferred": inferred,
+    });
+    let _ =
+        persist_and_emit_stdout(state, session_id, events_file, &marker.to_string(), marker).await;
+    broadcast_context_compacted(state, payload).await;
+}
+
+async fn persist_context_metrics(meta_path: &Path, snapshot: TokenUsageSnapshot) {
+    let Some(mut meta) = read_meta(meta_path).await else {
+        return;
+    };
+    meta.context_window = Some(snapshot.window);
+    meta.context_used_tokens = Some(snapshot.total_tokens);
+    meta.context_left_pct = Some(snapshot.pct_left);
+    let _ = write_meta(meta_path, &meta).await;
+}
+
+/// Adds one of our own diagnostics to a session's stderr.log, after whatever codex wrote.
+async fn append_stderr_note(path: &Path, note: &str) {
+    use tokio::io::AsyncWriteExt;
+    if let Ok(mut file) = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+    {
+        let _ = file.write_all(format!("{note}\n").as_bytes()).await;
+    }
+}
+
+async fn append_usage_record(state: &AppState, record: &UsageRecord) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+    let _guard = state.usage_lock.lock().await;
+    let path = state.data_dir.join("usage.jsonl");
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+    let line = serde_json::to_string(record)?;
+    file.write_all(line.as_bytes()).await?;
+    file.write_all(b"\n").await?;
+    drop(file);
+    if let Some(retention) = state.usage_retention {
+        let cutoff = now_ms().saturating_sub(retention.as_millis() as u64);
+        prune_usage_records(&path, cutoff).await?;
+    }
+    Ok(())
+}
+
+/// Rewrites usage.jsonl without records from before `cutoff_ms`, via a temp file and rename so
+/// readers never see a half-written file. Lines that don't parse are kept as they are.
+async fn prune_usage_records(path: &Path, cutoff_ms: u64) -> anyhow::Result<()> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    let mut kept = String::with_capacity(contents.len());
+    let mut dropped = 0usize;
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        if serde_json::from_str::<UsageRecord>(line).is_ok_and(|r| r.ts_ms < cutoff_ms) {
+            dropped += 1;
+            continue;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    if dropped == 0 {
+        return Ok(());
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    tokio::fs::write(&tmp, kept)
+        .await
+        .context("write usage.jsonl.tmp")?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .context("replace usage.jsonl")?;
+    info!(dropped, "pruned usage records past retention");
+    Ok(())
+}
+
+/// Delay before the first `initialize` retry; each further retry doubles it.
+const INIT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// A spawned app-server that has answered `initialize`.
+struct AppServer {
+    child: tokio::process::Child,
+    stdin: tokio::process::ChildStdin,
+    lines: tokio::io::Lines<BufReader<ChildStdout>>,
+}
+
+struct InitFailure {
+    error: String,
+    exit_code: Option<i32>,
+    /// Only a failed `initialize` is worth a fresh child; spawn errors and cancels are final.
+    retryable: bool,
+}
+
+impl InitFailure {
+    fn fatal(error: String) -> Self {
+        Self {
+            error,
+            exit_code: Some(1),
+            retryable: false,
+        }
+    }
+}
+
+/// Spawns `codex app-server` and performs the `initialize` handshake (request id 1), returning
+/// the child with its `initialize` result. Stderr is streamed into `stderr_path` right away so
+/// failed attempts still leave a trace.
+async fn spawn_and_initialize(
+    sink: &mut RunSink<'_>,
+    codex: &Path,
+    cwd: Option<&str>,
+    codex_home: Option<&str>,
+    stderr_path: &Path,
+    cancel_rx: &mut oneshot::Receiver<()>,
+) -> Result<(AppServer, serde_json::Value), InitFailure> {
+    let (state, session_id) = (sink.state, sink.session_id);
+    let mut cmd = Command::new(codex);
+    cmd.arg("app-server")
+        .envs(state.codex_env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = cwd {
+        cmd.current_dir(dir);
+    }
+    if let Some(home) = codex_home {
+        cmd.env("CODEX_HOME", home);
+    }
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| InitFailure::fatal(format!("Failed to start codex app-server: {e}")))?;
+
+    if let Some(pid) = child.id() {
+        let mut locked = state.runs.lock().await;
+        if let Some(handle) = locked.get_mut(session_id) {
+            handle.pid = Some(pid);
+        }
+    }
+
+    let (Some(mut stdin), Some(stdout), Some(stderr)) =
+        (child.stdin.take(), child.stdout.take(), child.stderr.take())
+    else {
+        let _ = child.kill().await;
+        return Err(InitFailure::fatal(
+            "Failed to capture app-server stdio".to_string(),
+        ));
+    };
+
+    let state_for_stderr = state.clone();
+    let session_id_for_stderr = session_id.to_string();
+    let stderr_path_for_stderr = stderr_path.to_path_buf();
+    tokio::spawn(async move {
+        stream_stderr(state_for_stderr, session_id_for_stderr, stderr, stderr_path_for_stderr).await;
+    });
+
+    let mut lines = BufReader::new(stdout).lines();
+    let sent = write_jsonrpc_request(
+        &mut stdin,
+        1,
+        "initialize",
+        serde_json::json!({
+            "clientInfo": {
+                "name": "codex-warp-server",
+                "version": env!("CARGO_PKG_VERSION"),
+            }
+        }),
+    )
+    .await;
+    let result = match sent {
+        Err(e) => Err(InitFailure {
+            error: format!("Failed to send initialize request: {e}"),
+            exit_code: Some(1),
+            retryable: true,
+        }),
+        Ok(()) => codex_protocol::wait_for_app_server_response(&mut lines, cancel_rx, sink, 1)
+            .await
+            .map_err(|e| {
+                if e.is_cancelled() {
+                    InitFailure {
+                        error: "Cancelled.".to_string(),
+                        exit_code: None,
+                        retryable: false,
+                    }
+                } else {
+                    InitFailure {
+                        error: format!("Initialize failed: {e}"),
+                        exit_code: Some(1),
+                        retryable: true,
+                    }
+                }
+            }),
+    };
+    match result {
+        Ok(capabilities) => Ok((
+            AppServer {
+                child,
+                stdin,
+                lines,
+            },
+            capabilities,
+        )),
+        Err(failure) => {
+            let _ = child.kill().await;
+            let _ = child.wait().await;
+            Err(failure)
+        }
+    }
+}
+
+/// Keeps the `initialize` result: as `capabilities.json` next to the session's meta, as the
+/// server-wide last-seen value, and as a `codex_capabilities` event for connected clients.
+async fn record_capabilities(
+    state: &AppState,
+    session_id: &str,
+    meta_path: &Path,
+    capabilities: serde_json::Value,
+) {
+    if let (Some(dir), Ok(bytes)) = (meta_path.parent(), serde_json::to_vec_pretty(&capabilities)) {
+        let _ = tokio::fs::write(dir.join("capabilities.json"), bytes).await;
+    }
+    *state.last_capabilities.lock().await = Some(capabilities.clone());
+    broadcast_capabilities(
+        state,
+        CodexCapabilities {
+            session_id: session_id.to_string(),
+            ts_ms: now_ms(),
+            capabilities,
+        },
+    )
+    .await;
+}
+
+/// A turn's side of the app-server stream: persists and broadcasts what arrives, and keeps the
+/// agent text and token usage the turn's conclusion and metrics are built from.
+struct RunSink<'a> {
+    state: &'a AppState,
+    session_id: &'a str,
+    meta_path: &'a Path,
+    events_path: &'a Path,
+    conclusion_path: &'a Path,
+    events_file: tokio::fs::File,
+    agent_item_id: Option<String>,
+    agent_text: String,
+    last_usage_snapshot: Option<TokenUsageSnapshot>,
+    // Set when the app-server announces a compaction; the "after" figure arrives with the next
+    // token-usage snapshot.
+    pending_compaction: Option<Option<TokenUsageSnapshot>>,
+    last_metrics_emit_ms: u64,
+    last_metrics_emitted_pct: Option<u8>,
+    last_reasoning_emit: Option<std::time::Instant>,
+    last_conclusion_flush: std::time::Instant,
+    last_flushed_len: usize,
+    last_events_check: std::time::Instant,
+    files_removed: bool,
+}
+
+impl<'a> RunSink<'a> {
+    fn new(
+        state: &'a AppState,
+        session_id: &'a str,
+        meta_path: &'a Path,
+        events_path: &'a Path,
+        conclusion_path: &'a Path,
+        events_file: tokio::fs::File,
+    ) -> Self {
+        let now = std::time::Instant::now();
+        Self {
+            state,
+            session_id,
+            meta_path,
+            events_path,
+            conclusion_path,
+            events_file,
+            agent_item_id: None,
+            agent_text: String::new(),
+            last_usage_snapshot: None,
+            pending_compaction: None,
+            last_metrics_emit_ms: 0,
+            last_metrics_emitted_pct: None,
+            last_reasoning_emit: None,
+            last_conclusion_flush: now,
+            last_flushed_len: 0,
+            last_events_check: now,
+            files_removed: false,
         }
-        agent_text.push_str(delta);
-        return;
     }
 
-    if method == "item/completed" {
-        let Some(params) = msg.get("params") else {
-            return;
-        };
-        let Some(item) = params.get("item") else {
-            return;
-        };
-        if item.get("type").and_then(|v| v.as_str()) != Some("agentMessage") {
-            return;
+    async fn record_token_usage(&mut self, snapshot: TokenUsageSnapshot) {
+        let (state, session_id) = (self.state, self.session_id);
+        if let Some(before) = self.pending_compaction.take() {
+            emit_context_compacted(
+                state,
+                session_id,
+                &mut self.events_file,
+                before,
+                Some(snapshot),
+                false,
+            )
+            .await;
+        } else if let Some(prev) = self.last_usage_snapshot {
+            if context_usage_dropped(prev, snapshot) {
+                emit_context_compacted(
+                    state,
+                    session_id,
+                    &mut self.events_file,
+                    Some(prev),
+                    Some(snapshot),
+                    true,
+                )
+                .await;
+            }
         }
-        let item_id = item.get("id").and_then(|v| v.as_str());
-        let text = item.get("text").and_then(|v| v.as_str());
-        let Some(text) = text else {
+        self.last_usage_snapshot = Some(snapshot);
+        if self.last_metrics_emitted_pct == Some(snapshot.pct_left) {
             return;
-        };
-        agent_text.clear();
-        agent_text.push_str(text);
-        *agent_item_id = item_id.map(|s| s.to_string());
+        }
+        let now = now_ms();
+        if self.last_metrics_emit_ms == 0 || now.saturating_sub(self.last_metrics_emit_ms) >= 5_000
+        {
+            persist_context_metrics(self.meta_path, snapshot).await;
+            broadcast_metrics(
+                state,
+                ContextMetrics {
+                    session_id: session_id.to_string(),
+                    ts_ms: now,
+                    context_left_pct: snapshot.pct_left,
+                    context_used_tokens: snapshot.total_tokens,
+                    context_window: snapshot.window,
+                },
+            )
+            .await;
+            self.last_metrics_emit_ms = now;
+            self.last_metrics_emitted_pct = Some(snapshot.pct_left);
+        }
     }
 }
 
-#[derive(Clone, Copy)]
-struct TokenUsageSnapshot {
-    window: u64,
-    total_tokens: u64,
-    input_tokens: u64,
-    output_tokens: u64,
-    reasoning_output_tokens: u64,
-    cached_input_tokens: u64,
-    pct_left: u8,
-}
-
-fn json_u64(v: &serde_json::Value) -> Option<u64> {
-    match v {
-        serde_json::Value::Number(n) => n.as_u64(),
-        serde_json::Value::String(s) => s.parse::<u64>().ok(),
-        _ => None,
+impl NotificationSink for RunSink<'_> {
+    async fn notification(&mut self, raw: &str, json: &serde_json::Value) {
+        let _ = persist_and_emit_stdout(
+            self.state,
+            self.session_id,
+            &mut self.events_file,
+            raw,
+            json.clone(),
+        )
+        .await;
+        track_agent_message(
+            self.state,
+            self.session_id,
+            json,
+            &mut self.agent_item_id,
+            &mut self.agent_text,
+        )
+        .await;
     }
 }
 
-fn extract_token_usage_snapshot(msg: &serde_json::Value) -> Option<TokenUsageSnapshot> {
-    if msg.get("method").and_then(|v| v.as_str()) != Some("thread/tokenUsage/updated") {
-        return None;
-    }
-    let params = msg.get("params")?;
-    let usage = params.get("tokenUsage")?;
-    let window = params
-        .get("modelContextWindow")
-        .and_then(json_u64)
-        .or_else(|| usage.get("modelContextWindow").and_then(json_u64))
-        .unwrap_or(0);
-    if window == 0 {
-        return None;
+impl TurnSink for RunSink<'_> {
+    async fn thread_ready(&mut self, thread_id: &str) {
+        tracing::Span::current().record("thread_id", thread_id);
+        if let Some(mut meta) = read_meta(self.meta_path).await {
+            if meta.codex_session_id.as_deref() != Some(thread_id) {
+                meta.codex_session_id = Some(thread_id.to_string());
+                let _ = write_meta(self.meta_path, &meta).await;
+            }
+        }
     }
-    let last = usage.get("last").or_else(|| usage.get("total"))?;
-    let input_tokens = last.get("inputTokens").and_then(json_u64).unwrap_or(0);
-    let cached_input_tokens = last.get("cachedInputTokens").and_then(json_u64).unwrap_or(0);
-    let output_tokens = last.get("outputTokens").and_then(json_u64).unwrap_or(0);
-    let reasoning_output_tokens = last
-        .get("reasoningOutputTokens")
-        .and_then(json_u64)
-        .unwrap_or(0);
-    let total_tokens = last
-        .get("totalTokens")
-        .and_then(json_u64)
-        .or_else(|| {
-            let sum = input_tokens + output_tokens + reasoning_output_tokens;
-            (sum > 0).then_some(sum)
-        })
-        .unwrap_or(0);
 
-    let remaining = window.saturating_sub(total_tokens);
-    let pct_left = ((remaining.saturating_mul(100) + (window / 2)) / window).min(100) as u8;
-
-    Some(TokenUsageSnapshot {
-        window,
-        total_tokens,
-        input_tokens,
-        output_tokens,
-        reasoning_output_tokens,
-        cached_input_tokens,
-        pct_left,
-    })
-}
+    async fn turn_notification(&mut self, raw: &str, json: &serde_json::Value) -> ControlFlow<()> {
+        let (state, session_id) = (self.state, self.session_id);
+        if is_context_compaction_notification(json) {
+            self.pending_compaction = Some(self.last_usage_snapshot);
+        }
 
-async fn persist_context_metrics(meta_path: &Path, snapshot: TokenUsageSnapshot) {
-    let Some(mut meta) = read_meta(meta_path).await else {
-        return;
-    };
-    meta.context_window = Some(snapshot.window);
-    meta.context_used_tokens = Some(snapshot.total_tokens);
-    meta.context_left_pct = Some(snapshot.pct_left);
-    let _ = write_meta(meta_path, &meta).await;
-}
+        // Reasoning parts are too chatty to persist; collapse them into a live-only event so
+        // clients can still show that the agent is thinking.
+        if let Some((item_id, summary)) = extract_reasoning_summary(json) {
+            if self
+                .last_reasoning_emit
+                .is_none_or(|t| t.elapsed() >= Duration::from_secs(1))
+            {
+                broadcast_reasoning(
+                    state,
+                    ReasoningUpdate {
+                        session_id: session_id.to_string(),
+                        ts_ms: now_ms(),
+                        item_id,
+                        summary,
+                    },
+                )
+                .await;
+                self.last_reasoning_emit = Some(std::time::Instant::now());
+            }
+            return ControlFlow::Continue(());
+        }
 
-async fn append_usage_record(state: &AppState, record: &UsageRecord) -> anyhow::Result<()> {
-    use tokio::io::AsyncWriteExt;
-    let path = state.data_dir.join("usage.jsonl");
-    if let Some(dir) = path.parent() {
-        tokio::fs::create_dir_all(dir).await?;
-    }
-    let mut file = tokio::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(path)
-        .await?;
-    let line = serde_json::to_string(record)?;
-    file.write_all(line.as_bytes()).await?;
-    file.write_all(b"\n").await?;
-    Ok(())
-}
+        if let Some(snapshot) = extract_token_usage_snapshot(json) {
+            self.record_token_usage(snapshot).await;
+        }
 
-async fn wait_for_app_server_response(
-    state: &AppState,
-    lines: &mut tokio::io::Lines<BufReader<ChildStdout>>,
-    cancel_rx: &mut oneshot::Receiver<()>,
-    session_id: &str,
-    events_file: &mut tokio::fs::File,
-    expected_id: i64,
-    agent_item_id: &mut Option<String>,
-    agent_text: &mut String,
-) -> anyhow::Result<serde_json::Value> {
-    loop {
-        let Some((raw, json)) = read_next_json_line(lines, cancel_rx).await? else {
-            anyhow::bail!("codex app-server stdout closed");
-        };
-        if json.get("method").and_then(|v| v.as_str()).is_some() {
-            let _ = persist_and_emit_stdout(state, session_id, events_file, &raw, json.clone()).await;
-            capture_agent_message_text(&json, agent_item_id, agent_text);
-            continue;
+        let persisted =
+            persist_and_emit_stdout(state, session_id, &mut self.events_file, raw, json.clone())
+                .await;
+        // Writes to a deleted file still succeed on unix, so the path is also looked at now and then.
+        let events_check_interval = state.heartbeat_interval.unwrap_or(EVENTS_CHECK_INTERVAL);
+        if persisted.is_err() || self.last_events_check.elapsed() >= events_check_interval {
+            self.last_events_check = std::time::Instant::now();
+            if tokio::fs::metadata(self.events_path).await.is_err() {
+                // Deleted on disk mid-turn: nothing more can be recorded, so stop instead of spinning.
+                self.files_removed = true;
+                return ControlFlow::Break(());
+            }
         }
-        if !jsonrpc_id_matches(&json, expected_id) {
-            continue;
+        if let Some(tool) = tool_update(session_id, json) {
+            broadcast_tool(state, tool).await;
+        }
+        track_agent_message(
+            state,
+            session_id,
+            json,
+            &mut self.agent_item_id,
+            &mut self.agent_text,
+        )
+        .await;
+        let agent_text = &self.agent_text;
+        if is_agent_message_completed(json) && !agent_text.trim().is_empty() {
+            let partial_conclusion_path =
+                self.conclusion_path.with_file_name(PARTIAL_CONCLUSION_FILE);
+            let _ =
+                tokio::fs::write(partial_conclusion_path, strip_tool_citations(agent_text)).await;
         }
-        if let Some(err) = json.get("error") {
-            anyhow::bail!(err.to_string());
+
+        if let Some(interval) = state.conclusion_flush_interval {
+            let due = is_agent_message_completed(json)
+                || self.last_conclusion_flush.elapsed() >= interval;
+            if due && agent_text.len() != self.last_flushed_len && !agent_text.trim().is_empty() {
+                let _ =
+                    tokio::fs::write(self.conclusion_path, strip_tool_citations(agent_text)).await;
+                self.last_flushed_len = agent_text.len();
+                self.last_conclusion_flush = std::time::Instant::now();
+            }
         }
-        let Some(result) = json.get("result") else {
-            anyhow::bail!("missing result");
-        };
-        return Ok(result.clone());
+        ControlFlow::Continue(())
+    }
+
+    async fn approval_request(
+        &mut self,
+        raw: &str,
+        json: &serde_json::Value,
+        _request_id: &str,
+    ) -> Option<String> {
+        let _ = persist_and_emit_stdout(
+            self.state,
+            self.session_id,
+            &mut self.events_file,
+            raw,
+            json.clone(),
+        )
+        .await;
+        let method = json["method"].as_str().unwrap_or_default();
+        let decision = self.state.approval_decision.clone();
+        info!(method, decision, "answering approval request");
+        broadcast_approval_request(
+            self.state,
+            ApprovalRequest {
+                session_id: self.session_id.to_string(),
+                ts_ms: now_ms(),
+                request_id: json["id"].clone(),
+                method: method.to_string(),
+                params: json.get("params").cloned().unwrap_or_default(),
+                decision: decision.clone(),
+            },
+        )
+        .await;
+        Some(decision)
     }
 }
 
@@ -2364,143 +11338,249 @@ async fn stream_stderr(
     }
 }
 
+/// Rejects a requested cwd that isn't an existing directory, so the spawn doesn't fail later with
+/// a bare OS error. With `create`, a missing directory is made instead.
+async fn ensure_cwd(cwd: Option<&str>, create: bool) -> Result<(), String> {
+    let Some(cwd) = cwd else {
+        return Ok(());
+    };
+    match tokio::fs::metadata(cwd).await {
+        Ok(m) if m.is_dir() => Ok(()),
+        Ok(_) => Err(format!("cwd is not a directory: {cwd}")),
+        Err(_) if create => tokio::fs::create_dir_all(cwd)
+            .await
+            .map_err(|e| format!("failed to create cwd {cwd}: {e}")),
+        Err(_) => Err(format!("cwd does not exist: {cwd}")),
+    }
+}
+
+/// Validates a per-session `CODEX_HOME`; blank means "use the server's".
+fn parse_codex_home(raw: Option<String>) -> Result<Option<String>, String> {
+    let Some(home) = raw.map(|s| s.trim().to_string()).filter(|s| !s.is_empty()) else {
+        return Ok(None);
+    };
+    if !Path::new(&home).is_dir() {
+        return Err(format!("codex_home is not a directory: {home}"));
+    }
+    Ok(Some(home))
+}
+
+/// Parses a webhook URL, which must be http(s) with a host.
+fn parse_webhook_url(raw: &str) -> Result<reqwest::Url, String> {
+    let url =
+        reqwest::Url::parse(raw.trim()).map_err(|e| format!("invalid webhook URL {raw:?}: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(format!("webhook must be an http(s) URL: {raw}"));
+    }
+    Ok(url)
+}
+
+fn parse_webhook_arg(raw: &str) -> Result<String, String> {
+    parse_webhook_url(raw).map(String::from)
+}
+
+/// Validates a per-request webhook against `--webhook-host`; blank means "use `--webhook-url`".
+fn request_webhook(state: &AppState, raw: Option<String>) -> Result<Option<String>, String> {
+    let Some(raw) = raw.filter(|s| !s.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let url = parse_webhook_url(&raw)?;
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    if state.webhook_hosts.is_empty() {
+        return Err(
+            "per-request webhooks are disabled; allow hosts with --webhook-host".to_string(),
+        );
+    }
+    if !state.webhook_hosts.contains(&host) {
+        return Err(format!("webhook host {host} is not allowed"));
+    }
+    Ok(Some(url.into()))
+}
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client for webhook deliveries. Redirects aren't followed: `--webhook-host` only vets the first
+/// URL, so an allowed host could otherwise bounce the conclusion to any address.
+fn webhook_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("webhook client needs no TLS configuration")
+}
+
+/// Body POSTed to a turn's webhook once it finishes.
+#[derive(Serialize)]
+struct RunWebhook {
+    session_id: String,
+    success: bool,
+    exit_code: Option<i32>,
+    conclusion: String,
+}
+
+/// Hex HMAC-SHA256 of a webhook body, sent as `X-Codex-Warp-Signature: sha256=<hex>`.
+fn webhook_signature(secret: &str, body: &[u8]) -> String {
+    use hmac::Mac;
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts any key length");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Fire-and-forget POST of the finished turn to `url`; failures are only logged.
+fn spawn_run_webhook(
+    state: &AppState,
+    url: String,
+    finished: &RunFinished,
+    conclusion_path: &Path,
+) {
+    let secret = state.webhook_secret.clone();
+    let client = state.http_client.clone();
+    let session_id = finished.session_id.clone();
+    let (success, exit_code) = (finished.success, finished.exit_code);
+    let conclusion_path = conclusion_path.to_path_buf();
+    tokio::spawn(async move {
+        let conclusion = tokio::fs::read_to_string(&conclusion_path)
+            .await
+            .unwrap_or_default();
+        let payload = RunWebhook {
+            session_id: session_id.clone(),
+            success,
+            exit_code,
+            conclusion,
+        };
+        let Ok(body) = serde_json::to_vec(&payload) else {
+            return;
+        };
+        let mut req = client
+            .post(&url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = secret.as_deref() {
+            req = req.header(
+                "X-Codex-Warp-Signature",
+                format!("sha256={}", webhook_signature(secret, &body)),
+            );
+        }
+        match req.body(body).send().await {
+            Ok(res) if res.status().is_success() => {}
+            Ok(res) => warn!(session_id = %session_id, status = %res.status(), "webhook rejected"),
+            Err(e) => warn!(session_id = %session_id, "webhook failed: {e}"),
+        }
+    });
+}
+
+/// Sandbox and approval policy of a regular turn. Sent explicitly on every `thread/start` and
+/// `thread/resume`, or a thread resumed after a `read_only` turn would stay read-only.
+const DEFAULT_SANDBOX: &str = "workspace-write";
+const DEFAULT_APPROVAL_POLICY: &str = "on-request";
+
+/// Sets the thread's sandbox and approval policy for the turn. A `read_only` turn can't write,
+/// though files the thread already changed stay changed.
+fn apply_run_policy(params: &mut serde_json::Value, read_only: bool) {
+    let (sandbox, approval_policy) = if read_only {
+        ("read-only", "never")
+    } else {
+        (DEFAULT_SANDBOX, DEFAULT_APPROVAL_POLICY)
+    };
+    params["sandbox"] = serde_json::Value::String(sandbox.to_string());
+    params["approvalPolicy"] = serde_json::Value::String(approval_policy.to_string());
+}
+
+fn thread_config(model: Option<&str>, reasoning_effort: Option<&str>) -> serde_json::Value {
+    let mut config = serde_json::json!({ "skip_git_repo_check": true });
+    if let Some(model) = model {
+        config["model"] = serde_json::Value::String(model.to_string());
+    }
+    if let Some(effort) = reasoning_effort {
+        config["model_reasoning_effort"] = serde_json::Value::String(effort.to_string());
+    }
+    config
+}
+
+#[tracing::instrument(
+    name = "turn",
+    skip_all,
+    fields(
+        session_id = %session_id,
+        thread_id = options.thread_id.as_deref(),
+        codex = %codex.display(),
+        cwd = options.cwd.as_deref(),
+    )
+)]
 async fn run_turn_via_app_server(
     state: AppState,
     session_id: String,
     codex: PathBuf,
-    cwd: Option<String>,
-    thread_id: Option<String>,
-    prompt_text: String,
-    events_path: PathBuf,
-    stderr_path: PathBuf,
-    conclusion_path: PathBuf,
-    meta_path: PathBuf,
+    options: TurnOptions,
+    input: TurnInput,
+    paths: TurnPaths,
     mut cancel_rx: oneshot::Receiver<()>,
 ) {
     async fn fail_and_finish(
         state: &AppState,
-        session_id: String,
+        session_id: &str,
         meta_path: &Path,
         stderr_path: &Path,
         conclusion_path: &Path,
         error: String,
         exit_code: Option<i32>,
     ) {
-        let _ = tokio::fs::write(stderr_path, format!("{error}\n")).await;
-        let _ = tokio::fs::write(conclusion_path, format!("# Error\n\n{error}\n")).await;
-        if let Some(mut meta) = read_meta(meta_path).await {
-            meta.status = SessionStatus::Error;
-            let _ = write_meta(meta_path, &meta).await;
-        }
-        {
-            let mut locked = state.runs.lock().await;
-            locked.remove(&session_id);
-        }
-        broadcast_run_finished(
-            state,
-            RunFinished {
-                session_id,
-                ts_ms: now_ms(),
-                exit_code,
-                success: false,
-            },
-        )
-        .await;
-    }
-
-    let mut cmd = Command::new(codex);
-    cmd.arg("app-server")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
-    if let Some(ref dir) = cwd {
-        cmd.current_dir(dir);
-    }
-
-    let mut child = match cmd.spawn() {
-        Ok(c) => c,
-        Err(e) => {
-            fail_and_finish(
-                &state,
-                session_id,
-                &meta_path,
-                &stderr_path,
-                &conclusion_path,
-                format!("Failed to start codex app-server: {e}"),
-                Some(1),
-            )
-            .await;
-            return;
-        }
-    };
-
-    if let Some(pid) = child.id() {
-        let mut locked = state.runs.lock().await;
-        if let Some(handle) = locked.get_mut(&session_id) {
-            handle.pid = Some(pid);
-        }
-    }
-
-    let mut stdin = match child.stdin.take() {
-        Some(s) => s,
-        None => {
-            let _ = child.kill().await;
-            fail_and_finish(
-                &state,
-                session_id,
-                &meta_path,
-                &stderr_path,
-                &conclusion_path,
-                "Failed to capture app-server stdin".to_string(),
-                Some(1),
-            )
-            .await;
-            return;
-        }
-    };
-    let stdout = match child.stdout.take() {
-        Some(s) => s,
-        None => {
-            let _ = child.kill().await;
-            fail_and_finish(
-                &state,
-                session_id,
-                &meta_path,
-                &stderr_path,
-                &conclusion_path,
-                "Failed to capture app-server stdout".to_string(),
-                Some(1),
-            )
-            .await;
-            return;
-        }
-    };
-    let stderr = match child.stderr.take() {
-        Some(s) => s,
-        None => {
-            let _ = child.kill().await;
-            fail_and_finish(
-                &state,
-                session_id,
-                &meta_path,
-                &stderr_path,
-                &conclusion_path,
-                "Failed to capture app-server stderr".to_string(),
-                Some(1),
-            )
-            .await;
-            return;
+        // A `None` exit code means the user cancelled, which isn't worth an error line.
+        if exit_code.is_some() {
+            error!(exit_code, "{error}");
+        } else {
+            info!("{error}");
         }
-    };
-
-    let state_for_stderr = state.clone();
-    let session_id_for_stderr = session_id.clone();
-    let stderr_path_for_stderr = stderr_path.clone();
-    tokio::spawn(async move {
-        stream_stderr(state_for_stderr, session_id_for_stderr, stderr, stderr_path_for_stderr).await;
-    });
+        let _ = tokio::fs::write(stderr_path, format!("{error}\n")).await;
+        let _ = tokio::fs::write(conclusion_path, format!("# Error\n\n{error}\n")).await;
+        let mut codex_session_id = None;
+        if let Some(mut meta) = read_meta(meta_path).await {
+            meta.status = SessionStatus::Error;
+            let _ = write_meta(meta_path, &meta).await;
+            codex_session_id = meta.codex_session_id;
+        }
+        let run = state.runs.lock().await.remove(session_id);
+        let finished = RunFinished {
+            session_id: session_id.to_string(),
+            ts_ms: now_ms(),
+            exit_code,
+            success: false,
+            codex_session_id,
+            status: Some(
+                if exit_code.is_some() {
+                    "error"
+                } else {
+                    "cancelled"
+                }
+                .to_string(),
+            ),
+            reason: Some(error),
+        };
+        broadcast_run_finished(state, finished.clone()).await;
+        if let Some(url) = run.and_then(|r| r.webhook) {
+            spawn_run_webhook(state, url, &finished, conclusion_path);
+        }
+    }
 
-    let mut events_file = match tokio::fs::OpenOptions::new()
+    let TurnOptions {
+        cwd,
+        thread_id,
+        model,
+        reasoning_effort,
+        codex_home,
+    } = options;
+    let TurnPaths {
+        events: events_path,
+        stderr: stderr_path,
+        conclusion: conclusion_path,
+        meta: meta_path,
+    } = paths;
+
+    let events_file = match tokio::fs::OpenOptions::new()
         .create(true)
         .append(true)
         .open(&events_path)
@@ -2508,10 +11588,9 @@ async fn run_turn_via_app_server(
     {
         Ok(f) => f,
         Err(e) => {
-            let _ = child.kill().await;
             fail_and_finish(
                 &state,
-                session_id,
+                &session_id,
                 &meta_path,
                 &stderr_path,
                 &conclusion_path,
@@ -2523,252 +11602,125 @@ async fn run_turn_via_app_server(
         }
     };
 
-    let mut lines = BufReader::new(stdout).lines();
-
-    let mut next_id: i64 = 1;
-    let mut agent_item_id: Option<String> = None;
-    let mut agent_text = String::new();
-    let mut effective_thread_id = thread_id.clone();
-
-    let init_id = next_id;
-    next_id += 1;
-    if let Err(e) = write_jsonrpc_request(
-        &mut stdin,
-        init_id,
-        "initialize",
-        serde_json::json!({
-            "clientInfo": {
-                "name": "codex-warp-server",
-                "version": env!("CARGO_PKG_VERSION"),
-            }
-        }),
-    )
-    .await
-    {
-        let _ = child.kill().await;
-        fail_and_finish(
-            &state,
-            session_id,
-            &meta_path,
-            &stderr_path,
-            &conclusion_path,
-            format!("Failed to send initialize request: {e}"),
-            Some(1),
-        )
-        .await;
-        return;
-    }
-
-    if let Err(e) = wait_for_app_server_response(
+    let mut sink = RunSink::new(
         &state,
-        &mut lines,
-        &mut cancel_rx,
         &session_id,
-        &mut events_file,
-        init_id,
-        &mut agent_item_id,
-        &mut agent_text,
-    )
-    .await
-    {
-        let _ = child.kill().await;
-        let (exit_code, error) = if e.to_string() == "cancelled" {
-            (None, "Cancelled.".to_string())
-        } else {
-            (Some(1), format!("Initialize failed: {e}"))
-        };
-        fail_and_finish(
-            &state,
-            session_id,
-            &meta_path,
+        &meta_path,
+        &events_path,
+        &conclusion_path,
+        events_file,
+    );
+    let turn_started = std::time::Instant::now();
+
+    // A fresh app-server occasionally fails `initialize` while it refreshes credentials, so that
+    // step alone gets a few attempts, each with a new child.
+    let mut retries = 0u32;
+    let AppServer {
+        mut child,
+        mut stdin,
+        mut lines,
+    } = loop {
+        let attempt = spawn_and_initialize(
+            &mut sink,
+            &codex,
+            cwd.as_deref(),
+            codex_home.as_deref(),
             &stderr_path,
-            &conclusion_path,
-            error,
-            exit_code,
-        )
-        .await;
-        return;
-    }
-
-    // Resume existing Codex thread if available; otherwise start a new one.
-    if let Some(existing) = thread_id.clone() {
-        let resume_id = next_id;
-        next_id += 1;
-        let _ = write_jsonrpc_request(
-            &mut stdin,
-            resume_id,
-            "thread/resume",
-            serde_json::json!({
-                "threadId": existing,
-                "cwd": cwd.clone(),
-                "config": { "skip_git_repo_check": true },
-            }),
-        )
-        .await;
-
-        match wait_for_app_server_response(
-            &state,
-            &mut lines,
             &mut cancel_rx,
-            &session_id,
-            &mut events_file,
-            resume_id,
-            &mut agent_item_id,
-            &mut agent_text,
-        )
-        .await
-        {
-            Ok(result) => {
-                if let Some(id) = result
-                    .get("thread")
-                    .and_then(|v| v.get("id"))
-                    .and_then(|v| v.as_str())
-                {
-                    effective_thread_id = Some(id.to_string());
-                }
-            }
-            Err(e) if e.to_string() == "cancelled" => {
-                let _ = child.kill().await;
-                fail_and_finish(
-                    &state,
-                    session_id,
-                    &meta_path,
-                    &stderr_path,
-                    &conclusion_path,
-                    "Cancelled.".to_string(),
-                    None,
-                )
-                .await;
-                return;
-            }
-            Err(_) => {
-                effective_thread_id = None;
-            }
-        }
-    }
-
-    if effective_thread_id.is_none() {
-        let start_id = next_id;
-        next_id += 1;
-        let _ = write_jsonrpc_request(
-            &mut stdin,
-            start_id,
-            "thread/start",
-            serde_json::json!({
-                "cwd": cwd.clone(),
-                "config": { "skip_git_repo_check": true },
-            }),
         )
         .await;
-
-        match wait_for_app_server_response(
-            &state,
-            &mut lines,
-            &mut cancel_rx,
-            &session_id,
-            &mut events_file,
-            start_id,
-            &mut agent_item_id,
-            &mut agent_text,
-        )
-        .await
-        {
-            Ok(result) => {
-                if let Some(id) = result
-                    .get("thread")
-                    .and_then(|v| v.get("id"))
-                    .and_then(|v| v.as_str())
-                {
-                    effective_thread_id = Some(id.to_string());
+        match attempt {
+            Ok((app_server, capabilities)) => {
+                info!(
+                    elapsed_ms = turn_started.elapsed().as_millis() as u64,
+                    retries, "app-server initialized"
+                );
+                record_capabilities(&state, &session_id, &meta_path, capabilities).await;
+                break app_server;
+            }
+            Err(failure) if failure.retryable && retries < state.init_retries => {
+                retries += 1;
+                let backoff = INIT_RETRY_BACKOFF * 2u32.pow(retries - 1);
+                warn!(
+                    retry = retries,
+                    backoff_ms = backoff.as_millis() as u64,
+                    "{}; respawning app-server",
+                    failure.error
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = &mut cancel_rx => {
+                        fail_and_finish(
+                            &state,
+                            &session_id,
+                            &meta_path,
+                            &stderr_path,
+                            &conclusion_path,
+                            "Cancelled.".to_string(),
+                            None,
+                        )
+                        .await;
+                        return;
+                    }
                 }
             }
-            Err(e) => {
-                let _ = child.kill().await;
-                let (exit_code, error) = if e.to_string() == "cancelled" {
-                    (None, "Cancelled.".to_string())
-                } else {
-                    (Some(1), format!("Thread start failed: {e}"))
-                };
+            Err(failure) => {
                 fail_and_finish(
                     &state,
-                    session_id,
+                    &session_id,
                     &meta_path,
                     &stderr_path,
                     &conclusion_path,
-                    error,
-                    exit_code,
+                    failure.error,
+                    failure.exit_code,
                 )
                 .await;
                 return;
             }
         }
-    }
-
-    let Some(thread_id) = effective_thread_id.clone() else {
-        let _ = child.kill().await;
-        fail_and_finish(
-            &state,
-            session_id,
-            &meta_path,
-            &stderr_path,
-            &conclusion_path,
-            "Thread start did not return a thread id".to_string(),
-            Some(1),
-        )
-        .await;
-        return;
     };
 
-    if let Some(mut meta) = read_meta(&meta_path).await {
-        if meta.codex_session_id.as_deref() != Some(thread_id.as_str()) {
-            meta.codex_session_id = Some(thread_id.clone());
-            let _ = write_meta(&meta_path, &meta).await;
-        }
-    }
-
-    let turn_start_id = next_id;
-    next_id += 1;
-    let _ = write_jsonrpc_request(
+    let mut thread_params = serde_json::json!({
+        "cwd": cwd.clone(),
+        "config": thread_config(model.as_deref(), reasoning_effort.as_deref()),
+    });
+    apply_run_policy(&mut thread_params, input.read_only);
+    let request = TurnRequest {
+        thread_id,
+        thread_params,
+        input: input.to_jsonrpc_input(),
+        idle_timeout: state.turn_timeout,
+        // Request id 1 was `initialize`.
+        first_request_id: 2,
+    };
+    let heartbeat = state
+        .heartbeat_interval
+        .map(|interval| spawn_heartbeat(&state, &session_id, turn_started, interval));
+    let turn = codex_protocol::run_turn(
         &mut stdin,
-        turn_start_id,
-        "turn/start",
-        serde_json::json!({
-            "threadId": thread_id,
-            "input": [ { "type": "text", "text": prompt_text } ],
-        }),
-    )
-    .await;
-
-    let turn_id_for_interrupt = match wait_for_app_server_response(
-        &state,
         &mut lines,
         &mut cancel_rx,
-        &session_id,
-        &mut events_file,
-        turn_start_id,
-        &mut agent_item_id,
-        &mut agent_text,
+        None,
+        &mut sink,
+        request,
     )
-    .await
-    {
-        Ok(result) => result
-            .get("turn")
-            .and_then(|v| v.get("id"))
-            .and_then(|v| match v {
-                serde_json::Value::String(s) => Some(s.to_string()),
-                serde_json::Value::Number(n) => n.as_i64().map(|i| i.to_string()),
-                _ => None,
-            }),
+    .await;
+    if let Some(heartbeat) = heartbeat {
+        heartbeat.abort();
+    }
+    let report = match turn {
+        Ok(report) => report,
         Err(e) => {
             let _ = child.kill().await;
-            let (exit_code, error) = if e.to_string() == "cancelled" {
-                (None, "Cancelled.".to_string())
-            } else {
-                (Some(1), format!("Turn start failed: {e}"))
-            };
+            let exit_code = (!e.is_cancelled()).then_some(1);
+            let mut error = e.to_string();
+            if matches!(e, TurnStartError::TurnStart(_)) {
+                let last = read_meta(&meta_path).await.and_then(|m| m.rate_limit);
+                error = with_rate_limit_hint(error, last.as_ref());
+            }
             fail_and_finish(
                 &state,
-                session_id,
+                &session_id,
                 &meta_path,
                 &stderr_path,
                 &conclusion_path,
@@ -2780,83 +11732,76 @@ async fn run_turn_via_app_server(
         }
     };
 
-    let mut cancelled = false;
-    let mut success = false;
-    let mut exit_code: Option<i32> = Some(1);
-    let mut last_metrics_emit_ms: u64 = 0;
-    let mut last_metrics_emitted_pct: Option<u8> = None;
-    let mut last_usage_snapshot: Option<TokenUsageSnapshot> = None;
-
-    loop {
-        let next = read_next_json_line(&mut lines, &mut cancel_rx).await;
-        let (raw, json) = match next {
-            Ok(Some(v)) => v,
-            Ok(None) => break,
-            Err(e) => {
-                cancelled = e.to_string() == "cancelled";
-                break;
-            }
-        };
-
-        let Some(method) = json.get("method").and_then(|v| v.as_str()) else {
-            continue;
-        };
-
-        if method == "thread/tokenUsage/updated" {
-            if let Some(snapshot) = extract_token_usage_snapshot(&json) {
-                last_usage_snapshot = Some(snapshot);
-                if last_metrics_emitted_pct != Some(snapshot.pct_left) {
-                    let now = now_ms();
-                    if last_metrics_emit_ms == 0 || now.saturating_sub(last_metrics_emit_ms) >= 5_000 {
-                        persist_context_metrics(&meta_path, snapshot).await;
-                        broadcast_metrics(
-                            &state,
-                            ContextMetrics {
-                                session_id: session_id.clone(),
-                                ts_ms: now,
-                                context_left_pct: snapshot.pct_left,
-                                context_used_tokens: snapshot.total_tokens,
-                                context_window: snapshot.window,
-                            },
-                        )
-                        .await;
-                        last_metrics_emit_ms = now;
-                        last_metrics_emitted_pct = Some(snapshot.pct_left);
-                    }
-                }
-            }
-        }
-
-        let _ = persist_and_emit_stdout(&state, &session_id, &mut events_file, &raw, json.clone()).await;
-        capture_agent_message_text(&json, &mut agent_item_id, &mut agent_text);
-
-        if method == "turn/completed" {
-            let status = json
-                .get("params")
-                .and_then(|v| v.get("turn"))
-                .and_then(|v| v.get("status"))
-                .and_then(|v| v.as_str())
-                .unwrap_or_default();
-            success = status == "completed";
-            exit_code = if success { None } else if status == "interrupted" { None } else { Some(1) };
-            break;
-        }
+    if let Some(before) = sink.pending_compaction.take() {
+        emit_context_compacted(
+            &state,
+            &session_id,
+            &mut sink.events_file,
+            before,
+            None,
+            false,
+        )
+        .await;
     }
 
-    if cancelled {
-        exit_code = None;
-        if let (Some(thread_id), Some(turn_id)) = (effective_thread_id.as_deref(), turn_id_for_interrupt.as_deref()) {
-            let interrupt_id = next_id;
-            let _ = write_jsonrpc_request(
-                &mut stdin,
-                interrupt_id,
-                "turn/interrupt",
-                serde_json::json!({ "threadId": thread_id, "turnId": turn_id }),
-            )
-            .await;
-        }
+    let cancelled = report.end == TurnEnd::Cancelled;
+    let timed_out = report.end == TurnEnd::TimedOut;
+    let files_removed = sink.files_removed;
+    let turn_completed = matches!(report.end, TurnEnd::Completed(_));
+    let (mut success, mut exit_code, mut turn_status, mut failure_reason) = match report.end {
+        TurnEnd::Completed(outcome) => (
+            outcome.success(),
+            outcome.exit_code(),
+            Some(outcome.status),
+            outcome.reason,
+        ),
+        _ => (false, Some(1), None, None),
+    };
+    let effective_thread_id = Some(report.thread_id);
+    let effective_model = report.model.or(model);
+    let last_usage_snapshot = sink.last_usage_snapshot;
+    let agent_text = std::mem::take(&mut sink.agent_text);
+    let partial_conclusion_path = conclusion_path.with_file_name(PARTIAL_CONCLUSION_FILE);
+    drop(sink);
+
+    if files_removed {
+        warn!(session_id = %session_id, "{SESSION_FILES_REMOVED}; stopping the turn");
+        let ts_ms = now_ms();
+        let json = serde_json::json!({ "type": "app.error", "message": SESSION_FILES_REMOVED, "_ts_ms": ts_ms });
+        broadcast_ui_event(
+            &state,
+            UiEvent {
+                session_id: session_id.clone(),
+                ts_ms,
+                stream: "stdout".to_string(),
+                raw: json.to_string(),
+                json: Some(json),
+            },
+        )
+        .await;
+        failure_reason = Some(SESSION_FILES_REMOVED.to_string());
+    }
+    if cancelled || timed_out || files_removed {
+        exit_code = if cancelled { None } else { Some(1) };
+        let status = if files_removed {
+            "error"
+        } else if timed_out {
+            "timed_out"
+        } else {
+            "cancelled"
+        };
+        turn_status = Some(status.to_string());
         success = false;
     }
+    info!(
+        success,
+        exit_code,
+        cancelled,
+        timed_out,
+        files_removed,
+        elapsed_ms = turn_started.elapsed().as_millis() as u64,
+        "turn finished"
+    );
 
     if let Some(snapshot) = last_usage_snapshot {
         let now = now_ms();
@@ -2884,44 +11829,181 @@ async fn run_turn_via_app_server(
                 reasoning_output_tokens: snapshot.reasoning_output_tokens,
                 cached_input_tokens: snapshot.cached_input_tokens,
                 context_window: snapshot.window,
+                model: effective_model.clone(),
             },
         )
         .await;
     }
 
-    if !agent_text.trim().is_empty() {
-        let _ = tokio::fs::write(&conclusion_path, agent_text).await;
+    if timed_out {
+        let idle = state.turn_timeout.map(|d| d.as_secs()).unwrap_or_default();
+        let error = format!("Turn timed out after {idle}s without output from codex app-server.");
+        warn!(session_id = %session_id, "{error}");
+        let _ = tokio::fs::write(&stderr_path, format!("{error}\n")).await;
+        let _ = tokio::fs::write(&conclusion_path, format!("# Error\n\n{error}\n")).await;
+        failure_reason = Some(error);
+    } else if files_removed {
+        // Don't recreate files the user deleted.
+    } else if !agent_text.trim().is_empty() {
+        let conclusion = render_conclusion(
+            state.conclusion_template.as_deref(),
+            &ConclusionFields {
+                prompt: &input.prompt,
+                answer: &agent_text,
+                model: effective_model.as_deref(),
+                tokens: last_usage_snapshot.map(|s| s.total_tokens),
+                ts_ms: now_ms(),
+            },
+        );
+        let _ = tokio::fs::write(&conclusion_path, conclusion).await;
+    } else if let Some(dir) = meta_path.parent() {
+        let _ = update_conclusion_from_events(dir).await;
     }
+    // conclusion.md now holds the final answer.
+    let _ = tokio::fs::remove_file(&partial_conclusion_path).await;
 
     drop(stdin);
-    match timeout(Duration::from_secs(2), child.wait()).await {
-        Ok(_) => {}
-        Err(_) => {
-            let _ = child.kill().await;
-            let _ = child.wait().await;
-        }
-    }
-
+    if timeout(state.child_exit_timeout, child.wait())
+        .await
+        .is_err()
     {
-        let mut locked = state.runs.lock().await;
-        locked.remove(&session_id);
+        let _ = child.kill().await;
+        let _ = child.wait().await;
+        let note = format!(
+            "codex app-server did not exit within {}s and was force-killed; its rollout may be incomplete.",
+            state.child_exit_timeout.as_secs()
+        );
+        warn!(session_id = %session_id, "{note}");
+        append_stderr_note(&stderr_path, &note).await;
     }
 
+    let run = state.runs.lock().await.remove(&session_id);
+
     if let Some(mut meta) = read_meta(&meta_path).await {
         meta.status = if success { SessionStatus::Done } else { SessionStatus::Error };
+        // A cancelled or timed-out turn still counts once it has cost tokens.
+        if turn_completed || last_usage_snapshot.is_some() {
+            meta.turn_count = meta.turn_count.saturating_add(1);
+            meta.cumulative_tokens += last_usage_snapshot.map(|s| s.total_tokens).unwrap_or(0);
+        }
         let _ = write_meta(&meta_path, &meta).await;
     }
 
-    broadcast_run_finished(
-        &state,
-        RunFinished {
-            session_id,
-            ts_ms: now_ms(),
-            exit_code,
-            success,
-        },
-    )
-    .await;
+    let finished = RunFinished {
+        session_id,
+        ts_ms: now_ms(),
+        exit_code,
+        success,
+        codex_session_id: effective_thread_id,
+        status: turn_status,
+        reason: failure_reason,
+    };
+    broadcast_run_finished(&state, finished.clone()).await;
+    if let Some(url) = run.and_then(|r| r.webhook) {
+        spawn_run_webhook(&state, url, &finished, &conclusion_path);
+    }
+}
+
+const SESSION_FILES_REMOVED: &str = "session files were removed";
+
+/// How often a turn checks that its events.jsonl still exists when heartbeats are off.
+const EVENTS_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+const INTERRUPTED_NOTE: &str = "Interrupted: the server stopped before this turn finished.";
+
+/// The turn's last completed agent message, rewritten as each one lands so the answer survives a
+/// crash; replaced by conclusion.md when the turn ends.
+const PARTIAL_CONCLUSION_FILE: &str = "conclusion.partial.md";
+
+/// Flips sessions left `Running` by a crashed server to `Error`, keeping any partial conclusion
+/// and appending a note. Sessions in `running` are left alone. Returns how many were fixed.
+async fn reconcile_stale_runs(root: &Path, running: &HashSet<String>) -> usize {
+    let Ok(mut rd) = tokio::fs::read_dir(root).await else {
+        return 0;
+    };
+    let mut fixed = 0;
+    while let Ok(Some(entry)) = rd.next_entry().await {
+        let path = entry.path().join("meta.json");
+        let Some(mut meta) = read_meta(&path).await else {
+            continue;
+        };
+        if !matches!(meta.status, SessionStatus::Running) || running.contains(&meta.id) {
+            continue;
+        }
+        meta.status = SessionStatus::Error;
+        if write_meta(&path, &meta).await.is_err() {
+            continue;
+        }
+        let partial_path = Path::new(&meta.conclusion_path).with_file_name(PARTIAL_CONCLUSION_FILE);
+        let partial = match tokio::fs::read_to_string(&partial_path).await {
+            Ok(text) if !text.trim().is_empty() => text,
+            _ => tokio::fs::read_to_string(&meta.conclusion_path)
+                .await
+                .unwrap_or_default(),
+        };
+        let _ = tokio::fs::remove_file(&partial_path).await;
+        let conclusion = if partial.trim().is_empty() {
+            format!("# Error\n\n{INTERRUPTED_NOTE}\n")
+        } else {
+            format!("{}\n\n{INTERRUPTED_NOTE}\n", partial.trim_end())
+        };
+        let _ = tokio::fs::write(&meta.conclusion_path, conclusion).await;
+        fixed += 1;
+    }
+    fixed
+}
+
+/// How long shutdown waits for cancelled runs to wind down before exiting anyway.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Stops every in-flight run so no `codex app-server` outlives the server, then marks those
+/// sessions as failed so they don't come back looking `Running`. Dropping the SSE senders ends
+/// the open streams, letting graceful shutdown finish.
+async fn cancel_runs_for_shutdown(state: &AppState) {
+    let session_ids: Vec<String> = state.runs.lock().await.keys().cloned().collect();
+    if !session_ids.is_empty() {
+        info!("shutting down; stopping {} run(s)", session_ids.len());
+    }
+    for session_id in &session_ids {
+        let _ = stop_session(State(state.clone()), AxumPath(session_id.clone())).await;
+    }
+
+    let deadline = tokio::time::Instant::now() + SHUTDOWN_GRACE;
+    while !state.runs.lock().await.is_empty() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    for session_id in &session_ids {
+        let path = meta_path(state, session_id);
+        let Some(mut meta) = read_meta(&path).await else {
+            continue;
+        };
+        meta.status = SessionStatus::Error;
+        let _ = write_meta(&path, &meta).await;
+        let _ = tokio::fs::write(&meta.conclusion_path, "# Error\n\nServer shutdown.\n").await;
+    }
+    state.streams.lock().await.clear();
 }
 
 #[tokio::main]
@@ -2946,6 +12028,7 @@ async fn main() -> anyhow::Result<()> {
     tokio::fs::create_dir_all(&data_dir)
         .await
         .context("create data_dir")?;
+    let data_dir_lock = lock_data_dir(&data_dir)?;
 
     let codex_path = args
         .codex_path
@@ -2971,29 +12054,69 @@ async fn main() -> anyhow::Result<()> {
         codex_path,
         codex_home,
         runs: Arc::new(Mutex::new(HashMap::new())),
+        session_locks: Arc::new(Mutex::new(HashMap::new())),
+        idempotency_keys: Arc::new(Mutex::new(HashMap::new())),
         streams: Arc::new(Mutex::new(HashMap::new())),
         native_cache: Arc::new(Mutex::new(NativeCache {
             built_at_ms: 0,
             rollouts_by_session: HashMap::new(),
+            latest_mtime_by_session: HashMap::new(),
             derived_by_session: HashMap::new(),
+            derived_clock: 0,
+            scanned_dirs: HashMap::new(),
         })),
+        stop_on_disconnect: Arc::new(Mutex::new(HashSet::new())),
+        rollout_watchers: Arc::new(Mutex::new(HashSet::new())),
+        rollout_poll_interval: ROLLOUT_POLL_INTERVAL,
+        conclusion_flush_interval: args
+            .conclusion_flush_secs
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs),
+        max_sessions: args.max_sessions.filter(|n| *n > 0),
+        turn_timeout: args
+            .turn_timeout_secs
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs),
+        stream_buffer: args.stream_buffer.max(1),
+        child_exit_timeout: Duration::from_secs(args.child_exit_timeout_secs),
+        heartbeat_interval: Some(Duration::from_secs(args.heartbeat_secs)).filter(|d| !d.is_zero()),
+        init_retries: args.init_retries,
+        approval_decision: args.approval_decision,
+        codex_env: args.codex_env,
+        conclusion_template: args.conclusion_template.filter(|t| !t.trim().is_empty()),
+        auth_token: args
+            .auth_token
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty()),
+        cors_origins: args.cors_origins,
+        webhook_url: args.webhook_url,
+        webhook_hosts: args
+            .webhook_hosts
+            .iter()
+            .map(|h| h.trim().to_ascii_lowercase())
+            .collect(),
+        webhook_secret: args.webhook_secret.filter(|s| !s.is_empty()),
+        http_client: webhook_client(),
+        last_capabilities: Arc::new(Mutex::new(None)),
+        usage_retention: args
+            .usage_retention_days
+            .filter(|days| *days > 0)
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+        default_instructions: args
+            .instructions
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty()),
+        usage_lock: Arc::new(Mutex::new(())),
+        max_prompt_bytes: Some(args.max_prompt_bytes).filter(|n| *n > 0),
     };
 
-    let mut app = Router::new()
-        .route("/healthz", get(healthz))
-        .route("/api/skills", get(list_skills))
-        .route("/api/usage", get(list_usage_records))
-        .route("/api/sessions", get(list_sessions).post(start_session))
-        .route("/api/sessions/:id/touch", post(touch_session))
-        .route("/api/sessions/:id/turn", post(continue_session))
-        .route("/api/sessions/:id/stop", post(stop_session))
-        .route("/api/sessions/:id/rename", post(rename_session))
-        .route("/api/sessions/:id/conclusion", get(read_conclusion))
-        .route("/api/sessions/:id/stream", get(stream_session))
-        .route("/api/sessions/:id", delete(delete_session))
-        .layer(CorsLayer::very_permissive())
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+    let running: HashSet<String> = state.runs.lock().await.keys().cloned().collect();
+    let reconciled = reconcile_stale_runs(&sessions_root(&state), &running).await;
+    if reconciled > 0 {
+        warn!("marked {reconciled} interrupted session(s) as failed");
+    }
+
+    let mut app = api_router(state.clone());
 
     let web_dist = args.web_dist.map(PathBuf::from).unwrap_or_else(|| {
         PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../dist")
@@ -3012,6 +12135,12 @@ async fn main() -> anyhow::Result<()> {
     }
 
     info!("listening on http://{bind}");
-    axum::serve(tokio::net::TcpListener::bind(bind).await?, app).await?;
+    axum::serve(tokio::net::TcpListener::bind(bind).await?, app)
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            cancel_runs_for_shutdown(&state).await;
+        })
+        .await?;
+    drop(data_dir_lock);
     Ok(())
 }
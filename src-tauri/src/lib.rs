@@ -1,19 +1,29 @@
+use codex_protocol::{
+    capture_agent_message_text, codex_auth_from_json, extract_token_usage_snapshot,
+    is_unpersisted_notification, jsonrpc_id_matches, jsonrpc_result, parse_approval_decision,
+    parse_reasoning_effort, read_next_json_line, render_conclusion, strip_tool_citations,
+    write_jsonrpc_request, ConclusionFields, NotificationSink, TokenUsageSnapshot, TurnEnd,
+    TurnRequest, TurnSink,
+};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::{Read, Write},
+    ops::ControlFlow,
     path::{Path, PathBuf},
     process::Stdio,
     sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_opener::OpenerExt;
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
-    process::{ChildStdin, ChildStdout, Command},
-    sync::{oneshot, Mutex},
+    process::Command,
+    sync::{mpsc, oneshot, Mutex},
     time::{timeout, Duration},
 };
+use unicode_segmentation::UnicodeSegmentation;
 use uuid::Uuid;
 
 const SHELL_CWD_MARKER: &[u8] = b"__CODEX_CWD__=";
@@ -34,6 +44,23 @@ struct RunFinished {
     ts_ms: u64,
     exit_code: Option<i32>,
     success: bool,
+    /// Codex thread id to pass when continuing this session, so clients needn't re-fetch meta.
+    codex_session_id: Option<String>,
+    /// The turn's raw status (`completed`, `interrupted`, `failed`, ...), or `cancelled`, `timed_out`
+    /// or `error` when the turn didn't complete on its own.
+    status: Option<String>,
+    /// Why the turn failed: codex's error message or kind, or the app's own error.
+    reason: Option<String>,
+}
+
+/// A command/patch approval the app-server is waiting on; answered via `respond_approval`.
+#[derive(Clone, Serialize)]
+struct ApprovalRequest {
+    session_id: String,
+    ts_ms: u64,
+    request_id: String,
+    method: String,
+    params: serde_json::Value,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -93,6 +120,16 @@ struct SessionMeta {
     events_path: String,
     stderr_path: String,
     conclusion_path: String,
+    /// Effective approval policy / sandbox of the last run, reused when the session is resumed.
+    #[serde(default)]
+    approval_policy: Option<String>,
+    #[serde(default)]
+    sandbox: Option<String>,
+    #[serde(default)]
+    reasoning_effort: Option<String>,
+    /// Whether the latest turn ran read-only; that turn's policy is not kept above.
+    #[serde(default)]
+    last_turn_read_only: bool,
 }
 
 #[derive(Clone, Serialize)]
@@ -109,11 +146,177 @@ struct Settings {
     codex_path: Option<String>,
     default_cwd: Option<String>,
     last_cwd: Option<String>,
+    /// Default `approvalPolicy` for new threads (`never`, `on-request`, `on-failure`, `untrusted`).
+    #[serde(default)]
+    approval_policy: Option<String>,
+    /// Default sandbox for new threads (`read-only`, `workspace-write`, `danger-full-access`).
+    #[serde(default)]
+    sandbox: Option<String>,
+    /// Default `model_reasoning_effort` (`minimal`, `low`, `medium`, `high`).
+    #[serde(default)]
+    reasoning_effort: Option<String>,
+    /// Interrupt a turn after this many seconds without output from the app-server. Unset or 0
+    /// disables the timeout.
+    #[serde(default)]
+    turn_timeout_secs: Option<u64>,
+    /// Seconds to wait for the app-server to exit after a turn before killing it (default 5). It
+    /// flushes its rollout file on shutdown, so a short wait can truncate it.
+    #[serde(default)]
+    child_exit_timeout_secs: Option<u64>,
+    /// Extra directories searched for a `codex` binary before the built-in locations.
+    #[serde(default)]
+    extra_codex_dirs: Vec<String>,
+    /// Extra environment for the codex child process. Overrides variables the app inherited.
+    #[serde(default)]
+    env: HashMap<String, String>,
+    /// Template for conclusion.md with `{prompt}`, `{answer}`, `{model}`, `{tokens}` and `{date}`
+    /// placeholders. Unset writes just the final answer.
+    #[serde(default)]
+    conclusion_template: Option<String>,
+    /// Reject turn prompts larger than this many bytes before a turn starts (default 1 MiB). 0
+    /// disables the limit.
+    #[serde(default)]
+    max_prompt_bytes: Option<usize>,
+}
+
+/// Machine-readable reason attached to every command error so the UI can branch without
+/// matching on message text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ErrorCode {
+    CodexNotFound,
+    SessionExists,
+    SessionNotFound,
+    SessionRunning,
+    ShellNotStarted,
+    InvalidInput,
+    PromptTooLarge,
+    Timeout,
+    Io,
+    Internal,
+}
+
+#[derive(Debug, Serialize)]
+struct CommandError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl CommandError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Internal helpers still report `String` errors; anything not classified at the call site is
+/// surfaced as `internal`.
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(ErrorCode::Io, e.to_string())
+    }
+}
+
+const APPROVAL_POLICIES: &[&str] = &["never", "on-request", "on-failure", "untrusted"];
+const SANDBOX_MODES: &[&str] = &["read-only", "workspace-write", "danger-full-access"];
+
+/// Approval policy, sandbox and reasoning effort passed to `thread/start` / `thread/resume`.
+/// `None` leaves the choice to codex's own configuration.
+#[derive(Clone, Default)]
+struct RunPolicy {
+    approval_policy: Option<String>,
+    sandbox: Option<String>,
+    reasoning_effort: Option<String>,
+}
+
+impl RunPolicy {
+    fn parse(
+        approval_policy: Option<String>,
+        sandbox: Option<String>,
+        reasoning_effort: Option<String>,
+    ) -> Result<Self, String> {
+        let clean = |v: Option<String>| v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        let approval_policy = clean(approval_policy);
+        let sandbox = clean(sandbox);
+        if let Some(v) = approval_policy.as_deref() {
+            if !APPROVAL_POLICIES.contains(&v) {
+                return Err(format!(
+                    "invalid approval policy {v:?} (expected one of: {})",
+                    APPROVAL_POLICIES.join(", ")
+                ));
+            }
+        }
+        if let Some(v) = sandbox.as_deref() {
+            if !SANDBOX_MODES.contains(&v) {
+                return Err(format!(
+                    "invalid sandbox {v:?} (expected one of: {})",
+                    SANDBOX_MODES.join(", ")
+                ));
+            }
+        }
+        Ok(Self {
+            approval_policy,
+            sandbox,
+            reasoning_effort: parse_reasoning_effort(reasoning_effort)?,
+        })
+    }
+
+    /// The policy for a read-only turn: codex may read and run commands but never write, and is
+    /// never asked for approval. Files changed by earlier turns are left as they are.
+    fn read_only(self) -> RunPolicy {
+        RunPolicy {
+            approval_policy: Some("never".to_string()),
+            sandbox: Some("read-only".to_string()),
+            reasoning_effort: self.reasoning_effort,
+        }
+    }
+
+    /// Fills unset fields from `fallback`.
+    fn or(self, fallback: RunPolicy) -> RunPolicy {
+        RunPolicy {
+            approval_policy: self.approval_policy.or(fallback.approval_policy),
+            sandbox: self.sandbox.or(fallback.sandbox),
+            reasoning_effort: self.reasoning_effort.or(fallback.reasoning_effort),
+        }
+    }
+
+    fn apply(&self, params: &mut serde_json::Value) {
+        if let Some(v) = self.approval_policy.as_deref() {
+            params["approvalPolicy"] = serde_json::Value::String(v.to_string());
+        }
+        if let Some(v) = self.sandbox.as_deref() {
+            params["sandbox"] = serde_json::Value::String(v.to_string());
+        }
+        if let Some(v) = self.reasoning_effort.as_deref() {
+            params["config"]["model_reasoning_effort"] = serde_json::Value::String(v.to_string());
+        }
+    }
+}
+
+fn settings_run_policy(settings: &Settings) -> RunPolicy {
+    // Settings are validated on save; ignore anything invalid written by hand.
+    RunPolicy::parse(
+        settings.approval_policy.clone(),
+        settings.sandbox.clone(),
+        settings.reasoning_effort.clone(),
+    )
+    .unwrap_or_default()
 }
 
 struct RunHandle {
     cancel: Option<oneshot::Sender<()>>,
     pid: Option<u32>,
+    started_at_ms: u64,
+    /// (request id, decision) answers for the run's pending approval requests.
+    approvals: mpsc::UnboundedSender<(String, String)>,
 }
 
 struct ShellHandle {
@@ -136,6 +339,46 @@ fn now_ms() -> u64 {
         .as_millis() as u64
 }
 
+/// An exclusive lock on `app_data_dir/.lock`, held for the app's lifetime so a second instance
+/// can't race this one on meta.json and usage.jsonl. The OS releases it when the file is closed.
+struct DataDirLock {
+    _file: std::fs::File,
+}
+
+fn lock_data_dir(data_dir: &Path) -> Result<DataDirLock, String> {
+    let path = data_dir.join(".lock");
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&path)
+        .map_err(|e| format!("open {}: {e}", path.display()))?;
+    // Windows has no `flock`; there the lock file only records the pid.
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::WouldBlock {
+                return Err(format!("lock {}: {err}", path.display()));
+            }
+            let holder = std::fs::read_to_string(&path).unwrap_or_default();
+            let holder = match holder.trim() {
+                "" => String::new(),
+                pid => format!(" (pid {pid})"),
+            };
+            return Err(format!(
+                "{} is already in use by another instance of the app{holder}",
+                data_dir.display()
+            ));
+        }
+    }
+    file.set_len(0).map_err(|e| e.to_string())?;
+    writeln!(file, "{}", std::process::id()).map_err(|e| e.to_string())?;
+    Ok(DataDirLock { _file: file })
+}
+
 fn sessions_root(app: &AppHandle) -> Result<PathBuf, String> {
     let base = app.path().app_data_dir().map_err(|e| e.to_string())?;
     Ok(base.join("sessions"))
@@ -160,22 +403,27 @@ fn shell_root(app: &AppHandle) -> Result<PathBuf, String> {
     Ok(base.join("shell"))
 }
 
-fn codex_skills_root() -> Option<PathBuf> {
+/// `$CODEX_HOME`, falling back to `~/.codex`.
+fn default_codex_home() -> Option<PathBuf> {
     if let Ok(raw) = std::env::var("CODEX_HOME") {
         let t = raw.trim();
         if !t.is_empty() {
-            return Some(PathBuf::from(t).join("skills"));
+            return Some(PathBuf::from(t));
         }
     }
     if let Ok(home) = std::env::var("HOME") {
         let t = home.trim();
         if !t.is_empty() {
-            return Some(PathBuf::from(t).join(".codex").join("skills"));
+            return Some(PathBuf::from(t).join(".codex"));
         }
     }
     None
 }
 
+fn codex_skills_root() -> Option<PathBuf> {
+    default_codex_home().map(|home| home.join("skills"))
+}
+
 fn escape_single_quotes(s: &str) -> String {
     // Wrap in single quotes and escape internal single quotes in a POSIX-compatible way.
     // Example: abc'd -> 'abc'"'"'d'
@@ -244,6 +492,22 @@ fn strip_ansi_csi(text: &str) -> String {
     out
 }
 
+/// Rejects a cwd that isn't an existing directory, so the spawn doesn't fail later with a bare OS
+/// error. With `create`, a missing directory is made instead.
+async fn ensure_cwd(cwd: Option<&str>, create: bool) -> Result<(), String> {
+    let Some(cwd) = cwd else {
+        return Ok(());
+    };
+    match tokio::fs::metadata(cwd).await {
+        Ok(m) if m.is_dir() => Ok(()),
+        Ok(_) => Err(format!("cwd is not a directory: {cwd}")),
+        Err(_) if create => tokio::fs::create_dir_all(cwd)
+            .await
+            .map_err(|e| format!("failed to create cwd {cwd}: {e}")),
+        Err(_) => Err(format!("cwd does not exist: {cwd}")),
+    }
+}
+
 fn choose_initial_cwd(settings: &Settings, requested: Option<String>) -> Option<String> {
     let mut cwd = requested.and_then(|s| {
         let t = s.trim().to_string();
@@ -511,6 +775,7 @@ async fn write_settings(app: &AppHandle, settings: &Settings) -> Result<(), Stri
     .map_err(|e| e.to_string())
 }
 
+/// Windows has no executable bit, so any existing file counts there.
 fn is_executable(path: &Path) -> bool {
     if !path.is_file() {
         return false;
@@ -532,61 +797,86 @@ fn push_unique(out: &mut Vec<PathBuf>, path: PathBuf) {
     out.push(path);
 }
 
-fn detect_codex_paths() -> Vec<PathBuf> {
-    let mut out = Vec::new();
+/// File names probed for the codex binary in each search directory, in preference order.
+#[cfg(windows)]
+const CODEX_BINARY_NAMES: &[&str] = &["codex.exe", "codex.cmd"];
+#[cfg(not(windows))]
+const CODEX_BINARY_NAMES: &[&str] = &["codex"];
 
-    // PATH lookup first.
-    if let Some(path_env) = std::env::var_os("PATH") {
-        for dir in std::env::split_paths(&path_env) {
-            let cand = dir.join("codex");
-            if is_executable(&cand) {
-                push_unique(&mut out, cand);
-            }
-        }
-    }
-
-    // Common global install locations.
-    for cand in [
-        PathBuf::from("/opt/homebrew/bin/codex"),
-        PathBuf::from("/usr/local/bin/codex"),
-        PathBuf::from("/usr/bin/codex"),
-    ] {
+fn push_codex_in(out: &mut Vec<PathBuf>, dir: &Path) {
+    for name in CODEX_BINARY_NAMES {
+        let cand = dir.join(name);
         if is_executable(&cand) {
-            push_unique(&mut out, cand);
+            push_unique(out, cand);
         }
     }
+}
 
-    // User-level locations (nvm/asdf/pnpm).
-    if let Ok(home) = std::env::var("HOME") {
-        let home = PathBuf::from(home);
+/// Probes `<root>/<version>/<bin_dir>` for every installed node version, newest name first.
+fn push_node_version_bins(out: &mut Vec<PathBuf>, root: &Path, bin_dir: &str) {
+    let Ok(rd) = std::fs::read_dir(root) else {
+        return;
+    };
+    let mut entries = rd.flatten().collect::<Vec<_>>();
+    entries.sort_by_key(|e| e.file_name());
+    entries.reverse();
+
+    for entry in entries {
+        push_codex_in(out, &entry.path().join(bin_dir));
+    }
+}
 
-        let asdf = home.join(".asdf/shims/codex");
-        if is_executable(&asdf) {
-            push_unique(&mut out, asdf);
+fn detect_codex_paths(extra_dirs: &[String]) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+
+    // User-configured directories first; missing ones are simply skipped.
+    for dir in extra_dirs {
+        let dir = dir.trim();
+        if dir.is_empty() {
+            continue;
         }
+        let dir = match (dir.strip_prefix("~/"), home.as_ref()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => PathBuf::from(dir),
+        };
+        push_codex_in(&mut out, &dir);
+    }
 
-        let local_bin = home.join(".local/bin/codex");
-        if is_executable(&local_bin) {
-            push_unique(&mut out, local_bin);
+    // PATH lookup next.
+    if let Some(path_env) = std::env::var_os("PATH") {
+        for dir in std::env::split_paths(&path_env) {
+            push_codex_in(&mut out, &dir);
         }
+    }
 
-        let pnpm = home.join("Library/pnpm/codex");
-        if is_executable(&pnpm) {
-            push_unique(&mut out, pnpm);
+    // Common global install locations.
+    for dir in ["/opt/homebrew/bin", "/usr/local/bin", "/usr/bin"] {
+        push_codex_in(&mut out, Path::new(dir));
+    }
+    #[cfg(windows)]
+    if let Some(appdata) = std::env::var_os("APPDATA") {
+        // `npm install -g` shims on Windows.
+        push_codex_in(&mut out, &PathBuf::from(appdata).join("npm"));
+    }
+
+    // User-level locations (nvm/fnm/volta/asdf/pnpm).
+    if let Some(home) = home {
+        for dir in [".asdf/shims", ".local/bin", "Library/pnpm", ".volta/bin"] {
+            push_codex_in(&mut out, &home.join(dir));
         }
 
-        let nvm_root = home.join(".nvm/versions/node");
-        if let Ok(rd) = std::fs::read_dir(&nvm_root) {
-            let mut entries = rd.flatten().collect::<Vec<_>>();
-            entries.sort_by_key(|e| e.file_name());
-            entries.reverse();
+        push_node_version_bins(&mut out, &home.join(".nvm/versions/node"), "bin");
 
-            for entry in entries {
-                let cand = entry.path().join("bin/codex");
-                if is_executable(&cand) {
-                    push_unique(&mut out, cand);
-                }
-            }
+        // fnm keeps versions under ~/.fnm on older installs and the XDG data dir on newer ones.
+        for fnm_root in [home.join(".fnm"), home.join(".local/share/fnm")] {
+            push_node_version_bins(
+                &mut out,
+                &fnm_root.join("node-versions"),
+                "installation/bin",
+            );
         }
     }
 
@@ -606,7 +896,7 @@ async fn resolve_codex_executable(app: &AppHandle) -> Result<PathBuf, String> {
         ));
     }
 
-    let candidates = detect_codex_paths();
+    let candidates = detect_codex_paths(&settings.extra_codex_dirs);
     if let Some(path) = candidates.into_iter().next() {
         return Ok(path);
     }
@@ -643,6 +933,39 @@ async fn write_meta(path: &Path, meta: &SessionMeta) -> Result<(), String> {
         .map_err(|e| e.to_string())
 }
 
+const NEW_THREAD_WARNING: &str = "starting new thread; prior context lost";
+
+/// Persists one of our own `app.*` events to events.jsonl and emits it as a `codex_event`.
+async fn append_app_event(
+    app: &AppHandle,
+    session_id: &str,
+    events_path: &Path,
+    ts_ms: u64,
+    event: serde_json::Value,
+) -> Result<(), String> {
+    use tokio::io::AsyncWriteExt;
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(events_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    file.write_all(format!("{event}\n").as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit(
+        "codex_event",
+        UiEvent {
+            session_id: session_id.to_string(),
+            ts_ms,
+            stream: "stdout".to_string(),
+            raw: event.to_string(),
+            json: Some(event),
+        },
+    );
+    Ok(())
+}
+
 async fn try_find_codex_session_id(events_path: &Path) -> Option<String> {
     let file = tokio::fs::File::open(events_path).await.ok()?;
     let mut lines = BufReader::new(file).lines();
@@ -663,31 +986,6 @@ async fn try_find_codex_session_id(events_path: &Path) -> Option<String> {
     None
 }
 
-fn strip_tool_citations(text: &str) -> String {
-    // Some OpenAI tool annotations are encoded using private-use Unicode characters
-    // like `\u{E200}` ... `\u{E201}` (e.g. citations). In a plain-text GUI these can
-    // show up as "garbled" glyph boxes, so we remove them for the conclusion file.
-    const START: &str = "\u{E200}";
-    const END: &str = "\u{E201}";
-
-    let mut out = String::with_capacity(text.len());
-    let mut rest = text;
-    while let Some(start) = rest.find(START) {
-        out.push_str(&rest[..start]);
-        while out.ends_with(' ') || out.ends_with('\t') {
-            out.pop();
-        }
-        let after_start = &rest[start + START.len()..];
-        if let Some(end_rel) = after_start.find(END) {
-            rest = &after_start[end_rel + END.len()..];
-        } else {
-            return out;
-        }
-    }
-    out.push_str(rest);
-    out
-}
-
 async fn update_conclusion_from_events(dir: &Path) -> Result<(), String> {
     let events_path = dir.join("events.jsonl");
     let file = match tokio::fs::File::open(&events_path).await {
@@ -751,53 +1049,16 @@ fn safe_title(prompt: &str) -> String {
         return "New session".to_string();
     }
     let s = trimmed.replace('\n', " ");
-    const MAX_CHARS: usize = 60;
-    if s.chars().count() <= MAX_CHARS {
+    // Counted in grapheme clusters so a CJK character or an emoji with modifiers is never split.
+    const MAX_GRAPHEMES: usize = 60;
+    if s.graphemes(true).count() <= MAX_GRAPHEMES {
         return s;
     }
-    let mut out: String = s.chars().take(MAX_CHARS).collect();
+    let mut out: String = s.graphemes(true).take(MAX_GRAPHEMES).collect();
     out.push('…');
     out
 }
 
-async fn write_jsonrpc_request(
-    stdin: &mut ChildStdin,
-    id: i64,
-    method: &str,
-    params: serde_json::Value,
-) -> Result<(), String> {
-    use tokio::io::AsyncWriteExt;
-
-    let msg = serde_json::json!({
-        "id": id,
-        "method": method,
-        "params": params,
-    });
-    let line = msg.to_string();
-    stdin
-        .write_all(line.as_bytes())
-        .await
-        .map_err(|e| e.to_string())?;
-    stdin.write_all(b"\n").await.map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-async fn read_next_json_line(
-    lines: &mut tokio::io::Lines<BufReader<ChildStdout>>,
-    cancel_rx: &mut oneshot::Receiver<()>,
-) -> Result<Option<(String, serde_json::Value)>, String> {
-    let next = tokio::select! {
-        _ = &mut *cancel_rx => return Err("cancelled".to_string()),
-        next = lines.next_line() => next,
-    };
-
-    let Some(line) = next.map_err(|e| e.to_string())? else {
-        return Ok(None);
-    };
-    let json = serde_json::from_str::<serde_json::Value>(&line).map_err(|e| e.to_string())?;
-    Ok(Some((line, json)))
-}
-
 async fn persist_and_emit_stdout(
     app: &AppHandle,
     session_id: &str,
@@ -807,13 +1068,12 @@ async fn persist_and_emit_stdout(
 ) -> Result<(), String> {
     use tokio::io::AsyncWriteExt;
 
-    if let Some(method) = json.get("method").and_then(|v| v.as_str()) {
-        if method == "thread/tokenUsage/updated"
-            || method == "account/rateLimits/updated"
-            || method == "item/reasoning/summaryPartAdded"
-        {
-            return Ok(());
-        }
+    if json
+        .get("method")
+        .and_then(|v| v.as_str())
+        .is_some_and(is_unpersisted_notification)
+    {
+        return Ok(());
     }
 
     let ts_ms = now_ms();
@@ -845,179 +1105,134 @@ async fn persist_and_emit_stdout(
     Ok(())
 }
 
-fn jsonrpc_id_matches(value: &serde_json::Value, expected: i64) -> bool {
-    let Some(id) = value.get("id") else {
-        return false;
+async fn persist_context_metrics(meta_path: &Path, snapshot: TokenUsageSnapshot) {
+    let Some(mut meta) = read_meta(meta_path).await else {
+        return;
     };
-    match id {
-        serde_json::Value::Number(n) => n.as_i64() == Some(expected),
-        serde_json::Value::String(s) => s == expected.to_string().as_str(),
-        _ => false,
-    }
+    meta.context_window = Some(snapshot.window);
+    meta.context_used_tokens = Some(snapshot.total_tokens);
+    meta.context_left_pct = Some(snapshot.pct_left);
+    let _ = write_meta(meta_path, &meta).await;
 }
 
-fn capture_agent_message_text(
-    msg: &serde_json::Value,
-    agent_item_id: &mut Option<String>,
-    agent_text: &mut String,
-) {
-    let Some(method) = msg.get("method").and_then(|v| v.as_str()) else {
-        return;
-    };
-    if method == "item/agentMessage/delta" {
-        let Some(params) = msg.get("params") else {
-            return;
-        };
-        let Some(item_id) = params.get("itemId").and_then(|v| v.as_str()) else {
-            return;
-        };
-        let delta = params
-            .get("delta")
-            .and_then(|v| v.as_str())
-            .unwrap_or_default();
-        if agent_item_id.as_deref() != Some(item_id) {
-            agent_text.clear();
-            *agent_item_id = Some(item_id.to_string());
+/// A turn's side of the app-server stream: persists and emits what arrives, and keeps the agent
+/// text and token usage the turn's conclusion and metrics are built from.
+struct RunSink<'a> {
+    app: &'a AppHandle,
+    session_id: &'a str,
+    meta_path: &'a Path,
+    events_file: tokio::fs::File,
+    agent_item_id: Option<String>,
+    agent_text: String,
+    last_usage_snapshot: Option<TokenUsageSnapshot>,
+    last_metrics_emit_ms: u64,
+    last_metrics_emitted_pct: Option<u8>,
+}
+
+impl<'a> RunSink<'a> {
+    fn new(
+        app: &'a AppHandle,
+        session_id: &'a str,
+        meta_path: &'a Path,
+        events_file: tokio::fs::File,
+    ) -> Self {
+        Self {
+            app,
+            session_id,
+            meta_path,
+            events_file,
+            agent_item_id: None,
+            agent_text: String::new(),
+            last_usage_snapshot: None,
+            last_metrics_emit_ms: 0,
+            last_metrics_emitted_pct: None,
         }
-        agent_text.push_str(delta);
-        return;
     }
 
-    if method == "item/completed" {
-        let Some(params) = msg.get("params") else {
-            return;
-        };
-        let Some(item) = params.get("item") else {
-            return;
-        };
-        if item.get("type").and_then(|v| v.as_str()) != Some("agentMessage") {
+    async fn record_token_usage(&mut self, snapshot: TokenUsageSnapshot) {
+        self.last_usage_snapshot = Some(snapshot);
+        if self.last_metrics_emitted_pct == Some(snapshot.pct_left) {
             return;
         }
-        let item_id = item.get("id").and_then(|v| v.as_str());
-        let text = item.get("text").and_then(|v| v.as_str());
-        let Some(text) = text else {
-            return;
-        };
-        agent_text.clear();
-        agent_text.push_str(text);
-        *agent_item_id = item_id.map(|s| s.to_string());
+        let now = now_ms();
+        if self.last_metrics_emit_ms == 0
+            || now.saturating_sub(self.last_metrics_emit_ms) >= CONTEXT_METRICS_EMIT_MIN_INTERVAL_MS
+        {
+            persist_context_metrics(self.meta_path, snapshot).await;
+            let _ = self.app.emit(
+                "codex_metrics",
+                ContextMetrics {
+                    session_id: self.session_id.to_string(),
+                    ts_ms: now,
+                    context_left_pct: snapshot.pct_left,
+                    context_used_tokens: snapshot.total_tokens,
+                    context_window: snapshot.window,
+                },
+            );
+            self.last_metrics_emit_ms = now;
+            self.last_metrics_emitted_pct = Some(snapshot.pct_left);
+        }
     }
 }
 
-#[derive(Clone, Copy)]
-struct TokenUsageSnapshot {
-    window: u64,
-    total_tokens: u64,
-    input_tokens: u64,
-    output_tokens: u64,
-    reasoning_output_tokens: u64,
-    cached_input_tokens: u64,
-    pct_left: u8,
-}
-
-fn json_u64(v: &serde_json::Value) -> Option<u64> {
-    match v {
-        serde_json::Value::Number(n) => n.as_u64(),
-        serde_json::Value::String(s) => s.parse::<u64>().ok(),
-        _ => None,
-    }
-}
-
-fn extract_token_usage_snapshot(msg: &serde_json::Value) -> Option<TokenUsageSnapshot> {
-    if msg.get("method").and_then(|v| v.as_str()) != Some("thread/tokenUsage/updated") {
-        return None;
-    }
-    let params = msg.get("params")?;
-    let usage = params.get("tokenUsage")?;
-    let window = params
-        .get("modelContextWindow")
-        .and_then(json_u64)
-        .or_else(|| usage.get("modelContextWindow").and_then(json_u64))
-        .unwrap_or(0);
-    if window == 0 {
-        return None;
-    }
-
-    // For context-remaining, we want the token usage of the *current request* (prompt+generated),
-    // not the cumulative, lifetime usage of the thread.
-    let last = usage.get("last").or_else(|| usage.get("total"))?;
-    let input_tokens = last.get("inputTokens").and_then(json_u64).unwrap_or(0);
-    let cached_input_tokens = last
-        .get("cachedInputTokens")
-        .and_then(json_u64)
-        .unwrap_or(0);
-    let output_tokens = last.get("outputTokens").and_then(json_u64).unwrap_or(0);
-    let reasoning_output_tokens = last
-        .get("reasoningOutputTokens")
-        .and_then(json_u64)
-        .unwrap_or(0);
-    let total_tokens = last
-        .get("totalTokens")
-        .and_then(json_u64)
-        .or_else(|| {
-            let sum = input_tokens + output_tokens + reasoning_output_tokens;
-            if sum == 0 {
-                None
-            } else {
-                Some(sum)
-            }
-        })
-        .unwrap_or(0);
-
-    let remaining = window.saturating_sub(total_tokens);
-    // Round to nearest integer to avoid jitter.
-    let pct_left = ((remaining.saturating_mul(100) + (window / 2)) / window).min(100) as u8;
-
-    Some(TokenUsageSnapshot {
-        window,
-        total_tokens,
-        input_tokens,
-        output_tokens,
-        reasoning_output_tokens,
-        cached_input_tokens,
-        pct_left,
-    })
-}
-
-async fn persist_context_metrics(meta_path: &Path, snapshot: TokenUsageSnapshot) {
-    let Some(mut meta) = read_meta(meta_path).await else {
-        return;
-    };
-    meta.context_window = Some(snapshot.window);
-    meta.context_used_tokens = Some(snapshot.total_tokens);
-    meta.context_left_pct = Some(snapshot.pct_left);
-    let _ = write_meta(meta_path, &meta).await;
+impl NotificationSink for RunSink<'_> {
+    async fn notification(&mut self, raw: &str, json: &serde_json::Value) {
+        let _ = persist_and_emit_stdout(
+            self.app,
+            self.session_id,
+            &mut self.events_file,
+            raw,
+            json.clone(),
+        )
+        .await;
+        capture_agent_message_text(json, &mut self.agent_item_id, &mut self.agent_text);
+    }
 }
 
-async fn wait_for_app_server_response(
-    lines: &mut tokio::io::Lines<BufReader<ChildStdout>>,
-    cancel_rx: &mut oneshot::Receiver<()>,
-    app: &AppHandle,
-    session_id: &str,
-    events_file: &mut tokio::fs::File,
-    expected_id: i64,
-    agent_item_id: &mut Option<String>,
-    agent_text: &mut String,
-) -> Result<serde_json::Value, String> {
-    loop {
-        let Some((raw, json)) = read_next_json_line(lines, cancel_rx).await? else {
-            return Err("codex app-server stdout closed".to_string());
-        };
-        if json.get("method").and_then(|v| v.as_str()).is_some() {
-            let _ = persist_and_emit_stdout(app, session_id, events_file, &raw, json.clone()).await;
-            capture_agent_message_text(&json, agent_item_id, agent_text);
-            continue;
-        }
-        if !jsonrpc_id_matches(&json, expected_id) {
-            continue;
+impl TurnSink for RunSink<'_> {
+    async fn thread_ready(&mut self, thread_id: &str) {
+        if let Some(mut meta) = read_meta(self.meta_path).await {
+            if meta.codex_session_id.as_deref() != Some(thread_id) {
+                meta.codex_session_id = Some(thread_id.to_string());
+                let _ = write_meta(self.meta_path, &meta).await;
+            }
         }
-        if let Some(err) = json.get("error") {
-            return Err(err.to_string());
+    }
+
+    async fn turn_notification(&mut self, raw: &str, json: &serde_json::Value) -> ControlFlow<()> {
+        if let Some(snapshot) = extract_token_usage_snapshot(json) {
+            self.record_token_usage(snapshot).await;
         }
-        let Some(result) = json.get("result") else {
-            return Err("missing result".to_string());
-        };
-        return Ok(result.clone());
+        self.notification(raw, json).await;
+        ControlFlow::Continue(())
+    }
+
+    /// Leaves the request for the user, whose answer comes back through `approvals_rx`.
+    async fn approval_request(
+        &mut self,
+        raw: &str,
+        json: &serde_json::Value,
+        request_id: &str,
+    ) -> Option<String> {
+        let _ = persist_and_emit_stdout(
+            self.app,
+            self.session_id,
+            &mut self.events_file,
+            raw,
+            json.clone(),
+        )
+        .await;
+        let _ = self.app.emit(
+            "codex_approval_request",
+            ApprovalRequest {
+                session_id: self.session_id.to_string(),
+                ts_ms: now_ms(),
+                request_id: request_id.to_string(),
+                method: json["method"].as_str().unwrap_or_default().to_string(),
+                params: json.get("params").cloned().unwrap_or_default(),
+            },
+        );
+        None
     }
 }
 
@@ -1104,6 +1319,39 @@ async fn stream_lines<R: tokio::io::AsyncRead + Unpin>(
     }
 }
 
+const DEFAULT_CHILD_EXIT_TIMEOUT_SECS: u64 = 5;
+
+const DEFAULT_MAX_PROMPT_BYTES: usize = 1024 * 1024;
+
+/// Fails fast on prompts over the `max_prompt_bytes` setting, before anything is persisted or
+/// spawned.
+fn check_prompt_size(settings: &Settings, prompt: &str) -> Result<(), CommandError> {
+    let max = settings
+        .max_prompt_bytes
+        .unwrap_or(DEFAULT_MAX_PROMPT_BYTES);
+    let len = prompt.trim().len();
+    if max > 0 && len > max {
+        return Err(CommandError::new(
+            ErrorCode::PromptTooLarge,
+            format!("prompt is {len} bytes; the limit is {max} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+/// Adds one of our own diagnostics to a session's stderr.log, after whatever codex wrote.
+async fn append_stderr_note(path: &Path, note: &str) {
+    use tokio::io::AsyncWriteExt;
+    if let Ok(mut file) = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+    {
+        let _ = file.write_all(format!("{note}\n").as_bytes()).await;
+    }
+}
+
 async fn run_turn_via_app_server(
     app: AppHandle,
     runs: Arc<Mutex<HashMap<String, RunHandle>>>,
@@ -1111,17 +1359,19 @@ async fn run_turn_via_app_server(
     codex: PathBuf,
     cwd: Option<String>,
     thread_id: Option<String>,
+    policy: RunPolicy,
     prompt_text: String,
     events_path: PathBuf,
     stderr_path: PathBuf,
     conclusion_path: PathBuf,
     meta_path: PathBuf,
     mut cancel_rx: oneshot::Receiver<()>,
+    mut approvals_rx: mpsc::UnboundedReceiver<(String, String)>,
 ) {
     async fn fail_and_finish(
         app: &AppHandle,
         runs: &Arc<Mutex<HashMap<String, RunHandle>>>,
-        session_id: String,
+        session_id: &str,
         meta_path: &Path,
         stderr_path: &Path,
         conclusion_path: &Path,
@@ -1130,27 +1380,39 @@ async fn run_turn_via_app_server(
     ) {
         let _ = tokio::fs::write(stderr_path, format!("{error}\n")).await;
         let _ = tokio::fs::write(conclusion_path, format!("# Error\n\n{error}\n")).await;
+        let mut codex_session_id = None;
         if let Some(mut meta) = read_meta(meta_path).await {
             meta.status = SessionStatus::Error;
             let _ = write_meta(meta_path, &meta).await;
+            codex_session_id = meta.codex_session_id;
         }
         {
             let mut locked = runs.lock().await;
-            locked.remove(&session_id);
+            locked.remove(session_id);
         }
+        // A `None` exit code means the user cancelled.
+        let status = if exit_code.is_some() {
+            "error"
+        } else {
+            "cancelled"
+        };
         let _ = app.emit(
             "codex_run_finished",
             RunFinished {
-                session_id,
+                session_id: session_id.to_string(),
                 ts_ms: now_ms(),
                 exit_code,
                 success: false,
+                codex_session_id,
+                status: Some(status.to_string()),
+                reason: Some(error),
             },
         );
     }
 
     let mut cmd = Command::new(codex);
     cmd.arg("app-server")
+        .envs(read_settings(&app).await.env)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
@@ -1164,7 +1426,7 @@ async fn run_turn_via_app_server(
             fail_and_finish(
                 &app,
                 &runs,
-                session_id,
+                &session_id,
                 &meta_path,
                 &stderr_path,
                 &conclusion_path,
@@ -1190,7 +1452,7 @@ async fn run_turn_via_app_server(
             fail_and_finish(
                 &app,
                 &runs,
-                session_id,
+                &session_id,
                 &meta_path,
                 &stderr_path,
                 &conclusion_path,
@@ -1208,7 +1470,7 @@ async fn run_turn_via_app_server(
             fail_and_finish(
                 &app,
                 &runs,
-                session_id,
+                &session_id,
                 &meta_path,
                 &stderr_path,
                 &conclusion_path,
@@ -1226,7 +1488,7 @@ async fn run_turn_via_app_server(
             fail_and_finish(
                 &app,
                 &runs,
-                session_id,
+                &session_id,
                 &meta_path,
                 &stderr_path,
                 &conclusion_path,
@@ -1253,7 +1515,7 @@ async fn run_turn_via_app_server(
         .await;
     });
 
-    let mut events_file = match tokio::fs::OpenOptions::new()
+    let events_file = match tokio::fs::OpenOptions::new()
         .create(true)
         .append(true)
         .open(&events_path)
@@ -1265,7 +1527,7 @@ async fn run_turn_via_app_server(
             fail_and_finish(
                 &app,
                 &runs,
-                session_id,
+                &session_id,
                 &meta_path,
                 &stderr_path,
                 &conclusion_path,
@@ -1278,17 +1540,11 @@ async fn run_turn_via_app_server(
     };
 
     let mut lines = BufReader::new(stdout).lines();
+    let mut sink = RunSink::new(&app, &session_id, &meta_path, events_file);
 
-    let mut next_id: i64 = 1;
-    let mut agent_item_id: Option<String> = None;
-    let mut agent_text = String::new();
-    let mut effective_thread_id = thread_id.clone();
-
-    let init_id = next_id;
-    next_id += 1;
     if let Err(e) = write_jsonrpc_request(
         &mut stdin,
-        init_id,
+        1,
         "initialize",
         serde_json::json!({
             "clientInfo": {
@@ -1303,7 +1559,7 @@ async fn run_turn_via_app_server(
         fail_and_finish(
             &app,
             &runs,
-            session_id,
+            &session_id,
             &meta_path,
             &stderr_path,
             &conclusion_path,
@@ -1314,20 +1570,11 @@ async fn run_turn_via_app_server(
         return;
     }
 
-    if let Err(e) = wait_for_app_server_response(
-        &mut lines,
-        &mut cancel_rx,
-        &app,
-        &session_id,
-        &mut events_file,
-        init_id,
-        &mut agent_item_id,
-        &mut agent_text,
-    )
-    .await
+    if let Err(e) =
+        codex_protocol::wait_for_app_server_response(&mut lines, &mut cancel_rx, &mut sink, 1).await
     {
         let _ = child.kill().await;
-        let (exit_code, error) = if e == "cancelled" {
+        let (exit_code, error) = if e.is_cancelled() {
             (None, "Cancelled.".to_string())
         } else {
             (Some(1), format!("Initialize failed: {e}"))
@@ -1335,7 +1582,7 @@ async fn run_turn_via_app_server(
         fail_and_finish(
             &app,
             &runs,
-            session_id,
+            &session_id,
             &meta_path,
             &stderr_path,
             &conclusion_path,
@@ -1346,290 +1593,70 @@ async fn run_turn_via_app_server(
         return;
     }
 
-    // Resume existing Codex thread if available; otherwise start a new one.
-    if let Some(existing) = thread_id.clone() {
-        let resume_id = next_id;
-        next_id += 1;
-        let _ = write_jsonrpc_request(
-            &mut stdin,
-            resume_id,
-            "thread/resume",
-            serde_json::json!({
-                "threadId": existing,
-                "cwd": cwd.clone(),
-                "config": { "skip_git_repo_check": true },
-            }),
-        )
-        .await;
-
-        match wait_for_app_server_response(
-            &mut lines,
-            &mut cancel_rx,
-            &app,
-            &session_id,
-            &mut events_file,
-            resume_id,
-            &mut agent_item_id,
-            &mut agent_text,
-        )
-        .await
-        {
-            Ok(result) => {
-                if let Some(id) = result
-                    .get("thread")
-                    .and_then(|v| v.get("id"))
-                    .and_then(|v| v.as_str())
-                {
-                    effective_thread_id = Some(id.to_string());
-                }
-            }
-            Err(e) if e == "cancelled" => {
-                let _ = child.kill().await;
-                fail_and_finish(
-                    &app,
-                    &runs,
-                    session_id,
-                    &meta_path,
-                    &stderr_path,
-                    &conclusion_path,
-                    "Cancelled.".to_string(),
-                    None,
-                )
-                .await;
-                return;
-            }
-            Err(_) => {
-                // Fall back to starting a fresh Codex thread.
-                effective_thread_id = None;
-            }
-        }
-    }
-
-    if effective_thread_id.is_none() {
-        let start_id = next_id;
-        next_id += 1;
-        let _ = write_jsonrpc_request(
-            &mut stdin,
-            start_id,
-            "thread/start",
-            serde_json::json!({
-                "cwd": cwd.clone(),
-                "config": { "skip_git_repo_check": true },
-            }),
-        )
-        .await;
-
-        match wait_for_app_server_response(
-            &mut lines,
-            &mut cancel_rx,
-            &app,
-            &session_id,
-            &mut events_file,
-            start_id,
-            &mut agent_item_id,
-            &mut agent_text,
-        )
-        .await
-        {
-            Ok(result) => {
-                if let Some(id) = result
-                    .get("thread")
-                    .and_then(|v| v.get("id"))
-                    .and_then(|v| v.as_str())
-                {
-                    effective_thread_id = Some(id.to_string());
-                }
-            }
-            Err(e) => {
-                let _ = child.kill().await;
-                let (exit_code, error) = if e == "cancelled" {
-                    (None, "Cancelled.".to_string())
-                } else {
-                    (Some(1), format!("Thread start failed: {e}"))
-                };
-                fail_and_finish(
-                    &app,
-                    &runs,
-                    session_id,
-                    &meta_path,
-                    &stderr_path,
-                    &conclusion_path,
-                    error,
-                    exit_code,
-                )
-                .await;
-                return;
-            }
-        }
-    }
-
-    let Some(thread_id) = effective_thread_id.clone() else {
-        let _ = child.kill().await;
-        fail_and_finish(
-            &app,
-            &runs,
-            session_id,
-            &meta_path,
-            &stderr_path,
-            &conclusion_path,
-            "Thread start did not return a thread id".to_string(),
-            Some(1),
-        )
-        .await;
-        return;
+    let settings = read_settings(&app).await;
+    let turn_timeout = settings
+        .turn_timeout_secs
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    let mut thread_params = serde_json::json!({
+        "cwd": cwd.clone(),
+        "config": { "skip_git_repo_check": true },
+    });
+    policy.apply(&mut thread_params);
+    let request = TurnRequest {
+        thread_id,
+        thread_params,
+        input: serde_json::json!([ { "type": "text", "text": prompt_text } ]),
+        idle_timeout: turn_timeout,
+        // Request id 1 was `initialize`.
+        first_request_id: 2,
     };
-
-    if let Some(mut meta) = read_meta(&meta_path).await {
-        if meta.codex_session_id.as_deref() != Some(thread_id.as_str()) {
-            meta.codex_session_id = Some(thread_id.clone());
-            let _ = write_meta(&meta_path, &meta).await;
-        }
-    }
-
-    let turn_start_id = next_id;
-    next_id += 1;
-    let _ = write_jsonrpc_request(
+    let turn = codex_protocol::run_turn(
         &mut stdin,
-        turn_start_id,
-        "turn/start",
-        serde_json::json!({
-            "threadId": thread_id,
-            "input": [ { "type": "text", "text": prompt_text } ],
-        }),
-    )
-    .await;
-
-    let turn_id_for_interrupt = match wait_for_app_server_response(
         &mut lines,
-        &mut cancel_rx,
-        &app,
-        &session_id,
-        &mut events_file,
-        turn_start_id,
-        &mut agent_item_id,
-        &mut agent_text,
-    )
-    .await
-    {
-        Ok(result) => result
-            .get("turn")
-            .and_then(|v| v.get("id"))
-            .and_then(|v| match v {
-                serde_json::Value::String(s) => Some(s.to_string()),
-                serde_json::Value::Number(n) => n.as_i64().map(|i| i.to_string()),
-                _ => None,
-            }),
-        Err(e) => {
-            let _ = child.kill().await;
-            let (exit_code, error) = if e == "cancelled" {
-                (None, "Cancelled.".to_string())
-            } else {
-                (Some(1), format!("Turn start failed: {e}"))
-            };
-            fail_and_finish(
-                &app,
-                &runs,
-                session_id,
-                &meta_path,
-                &stderr_path,
-                &conclusion_path,
-                error,
-                exit_code,
-            )
-            .await;
-            return;
-        }
-    };
-
-    let mut cancelled = false;
-    let mut success = false;
-    let mut exit_code: Option<i32> = Some(1);
-    let mut last_metrics_emit_ms: u64 = 0;
-    let mut last_metrics_emitted_pct: Option<u8> = None;
-    let mut last_usage_snapshot: Option<TokenUsageSnapshot> = None;
-
-    loop {
-        let next = read_next_json_line(&mut lines, &mut cancel_rx).await;
-        let (raw, json) = match next {
-            Ok(Some(v)) => v,
-            Ok(None) => break,
-            Err(msg) => {
-                cancelled = msg == "cancelled";
-                break;
-            }
-        };
-
-        let Some(method) = json.get("method").and_then(|v| v.as_str()) else {
-            continue;
-        };
-
-        if method == "thread/tokenUsage/updated" {
-            if let Some(snapshot) = extract_token_usage_snapshot(&json) {
-                last_usage_snapshot = Some(snapshot);
-                if last_metrics_emitted_pct != Some(snapshot.pct_left) {
-                    let now = now_ms();
-                    if last_metrics_emit_ms == 0
-                        || now.saturating_sub(last_metrics_emit_ms)
-                            >= CONTEXT_METRICS_EMIT_MIN_INTERVAL_MS
-                    {
-                        persist_context_metrics(&meta_path, snapshot).await;
-                        let _ = app.emit(
-                            "codex_metrics",
-                            ContextMetrics {
-                                session_id: session_id.clone(),
-                                ts_ms: now,
-                                context_left_pct: snapshot.pct_left,
-                                context_used_tokens: snapshot.total_tokens,
-                                context_window: snapshot.window,
-                            },
-                        );
-                        last_metrics_emit_ms = now;
-                        last_metrics_emitted_pct = Some(snapshot.pct_left);
-                    }
-                }
-            }
-        }
-
-        let _ =
-            persist_and_emit_stdout(&app, &session_id, &mut events_file, &raw, json.clone()).await;
-        capture_agent_message_text(&json, &mut agent_item_id, &mut agent_text);
-
-        if method == "turn/completed" {
-            let status = json
-                .get("params")
-                .and_then(|v| v.get("turn"))
-                .and_then(|v| v.get("status"))
-                .and_then(|v| v.as_str())
-                .unwrap_or_default();
-            success = status == "completed";
-            exit_code = if success {
-                None
-            } else if status == "interrupted" {
-                None
-            } else {
-                Some(1)
-            };
-            break;
-        }
-    }
-
-    if cancelled {
-        exit_code = None;
-        if let (Some(thread_id), Some(turn_id)) = (
-            effective_thread_id.as_deref(),
-            turn_id_for_interrupt.as_deref(),
-        ) {
-            let interrupt_id = next_id;
-            let _ = write_jsonrpc_request(
-                &mut stdin,
-                interrupt_id,
-                "turn/interrupt",
-                serde_json::json!({ "threadId": thread_id, "turnId": turn_id }),
+        &mut cancel_rx,
+        Some(&mut approvals_rx),
+        &mut sink,
+        request,
+    )
+    .await;
+    let report = match turn {
+        Ok(report) => report,
+        Err(e) => {
+            let _ = child.kill().await;
+            let exit_code = (!e.is_cancelled()).then_some(1);
+            fail_and_finish(
+                &app,
+                &runs,
+                &session_id,
+                &meta_path,
+                &stderr_path,
+                &conclusion_path,
+                e.to_string(),
+                exit_code,
             )
             .await;
+            return;
         }
-        success = false;
-    }
+    };
+
+    let timed_out = report.end == TurnEnd::TimedOut;
+    let (success, exit_code, turn_status, mut failure_reason) = match report.end {
+        TurnEnd::Completed(outcome) => (
+            outcome.success(),
+            outcome.exit_code(),
+            Some(outcome.status),
+            outcome.reason,
+        ),
+        TurnEnd::Cancelled => (false, None, Some("cancelled".to_string()), None),
+        TurnEnd::TimedOut => (false, Some(1), Some("timed_out".to_string()), None),
+        TurnEnd::Closed | TurnEnd::Stopped => (false, Some(1), None, None),
+    };
+    let effective_thread_id = Some(report.thread_id);
+    let effective_model = report.model;
+    let last_usage_snapshot = sink.last_usage_snapshot;
+    let agent_text = std::mem::take(&mut sink.agent_text);
+    drop(sink);
 
     if let Some(snapshot) = last_usage_snapshot {
         let now = now_ms();
@@ -1662,19 +1689,45 @@ async fn run_turn_via_app_server(
     }
 
     let cleaned_agent_text = strip_tool_citations(&agent_text);
-    if !cleaned_agent_text.trim().is_empty() {
-        let _ = tokio::fs::write(&conclusion_path, cleaned_agent_text).await;
+    if timed_out {
+        let idle = turn_timeout.map(|d| d.as_secs()).unwrap_or_default();
+        let error = format!("Turn timed out after {idle}s without output from codex app-server.");
+        let _ = tokio::fs::write(&stderr_path, format!("{error}\n")).await;
+        let _ = tokio::fs::write(&conclusion_path, format!("# Error\n\n{error}\n")).await;
+        failure_reason = Some(error);
+    } else if !cleaned_agent_text.trim().is_empty() {
+        let conclusion = render_conclusion(
+            settings.conclusion_template.as_deref(),
+            &ConclusionFields {
+                prompt: &prompt_text,
+                answer: &agent_text,
+                model: effective_model.as_deref(),
+                tokens: last_usage_snapshot.map(|s| s.total_tokens),
+                ts_ms: now_ms(),
+            },
+        );
+        let _ = tokio::fs::write(&conclusion_path, conclusion).await;
     } else if let Some(dir) = meta_path.parent() {
         let _ = update_conclusion_from_events(dir).await;
     }
 
     drop(stdin);
-    match timeout(Duration::from_secs(2), child.wait()).await {
-        Ok(_) => {}
-        Err(_) => {
-            let _ = child.kill().await;
-            let _ = child.wait().await;
-        }
+    let child_exit_timeout = Duration::from_secs(
+        settings
+            .child_exit_timeout_secs
+            .unwrap_or(DEFAULT_CHILD_EXIT_TIMEOUT_SECS),
+    );
+    if timeout(child_exit_timeout, child.wait()).await.is_err() {
+        let _ = child.kill().await;
+        let _ = child.wait().await;
+        append_stderr_note(
+            &stderr_path,
+            &format!(
+                "codex app-server did not exit within {}s and was force-killed; its rollout may be incomplete.",
+                child_exit_timeout.as_secs()
+            ),
+        )
+        .await;
     }
 
     {
@@ -1695,6 +1748,9 @@ async fn run_turn_via_app_server(
         ts_ms: now_ms(),
         exit_code,
         success,
+        codex_session_id: effective_thread_id,
+        status: turn_status,
+        reason: failure_reason,
     };
     let _ = app.emit("codex_run_finished", payload);
 }
@@ -1706,28 +1762,27 @@ async fn start_run(
     session_id: Option<String>,
     prompt: String,
     cwd: Option<String>,
-) -> Result<SessionMeta, String> {
+    approval_policy: Option<String>,
+    sandbox: Option<String>,
+    reasoning_effort: Option<String>,
+    create_cwd: Option<bool>,
+    read_only: Option<bool>,
+) -> Result<SessionMeta, CommandError> {
+    let read_only = read_only.unwrap_or(false);
+    let settings = read_settings(&app).await;
+    check_prompt_size(&settings, &prompt)?;
+    let policy = RunPolicy::parse(approval_policy, sandbox, reasoning_effort)
+        .map_err(|e| CommandError::new(ErrorCode::InvalidInput, e))?
+        .or(settings_run_policy(&settings));
     let session_id = match session_id {
         Some(s) => Uuid::parse_str(s.trim())
-            .map_err(|_| "invalid session id".to_string())?
+            .map_err(|_| CommandError::new(ErrorCode::InvalidInput, "invalid session id"))?
             .to_string(),
         None => Uuid::new_v4().to_string(),
     };
     let created_at_ms = now_ms();
     let last_used_at_ms = created_at_ms;
 
-    let dir = session_dir(&app, &session_id)?;
-    if tokio::fs::metadata(&dir).await.is_ok() {
-        return Err("session already exists".to_string());
-    }
-    tokio::fs::create_dir_all(&dir)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    let events_path = dir.join("events.jsonl");
-    let stderr_path = dir.join("stderr.log");
-    let conclusion_path = dir.join("conclusion.md");
-
     let mut cwd = cwd.and_then(|s| {
         let t = s.trim().to_string();
         if t.is_empty() {
@@ -1745,6 +1800,24 @@ async fn start_run(
             }
         }
     }
+    ensure_cwd(cwd.as_deref(), create_cwd.unwrap_or(false))
+        .await
+        .map_err(|e| CommandError::new(ErrorCode::InvalidInput, e))?;
+
+    let dir = session_dir(&app, &session_id)?;
+    if tokio::fs::metadata(&dir).await.is_ok() {
+        return Err(CommandError::new(
+            ErrorCode::SessionExists,
+            "session already exists",
+        ));
+    }
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let events_path = dir.join("events.jsonl");
+    let stderr_path = dir.join("stderr.log");
+    let conclusion_path = dir.join("conclusion.md");
 
     if let Some(dir) = cwd.clone() {
         let mut settings = read_settings(&app).await;
@@ -1758,7 +1831,7 @@ async fn start_run(
         Ok(p) => p,
         Err(msg) => {
             let details = msg;
-            let candidates = detect_codex_paths()
+            let candidates = detect_codex_paths(&read_settings(&app).await.extra_codex_dirs)
                 .iter()
                 .map(|p| p.display().to_string())
                 .collect::<Vec<_>>()
@@ -1798,6 +1871,10 @@ async fn start_run(
                 events_path: events_path.to_string_lossy().to_string(),
                 stderr_path: stderr_path.to_string_lossy().to_string(),
                 conclusion_path: conclusion_path.to_string_lossy().to_string(),
+                approval_policy: policy.approval_policy.clone(),
+                sandbox: policy.sandbox.clone(),
+                reasoning_effort: policy.reasoning_effort.clone(),
+                last_turn_read_only: read_only,
             };
 
             let meta_path = dir.join("meta.json");
@@ -1814,6 +1891,9 @@ async fn start_run(
                     ts_ms: now_ms(),
                     exit_code: None,
                     success: false,
+                    codex_session_id: None,
+                    status: Some("error".to_string()),
+                    reason: Some(error),
                 },
             );
 
@@ -1854,6 +1934,7 @@ async fn start_run(
     );
 
     let (cancel_tx, cancel_rx) = oneshot::channel();
+    let (approvals_tx, approvals_rx) = mpsc::unbounded_channel();
     {
         let mut runs = state.runs.lock().await;
         runs.insert(
@@ -1861,6 +1942,8 @@ async fn start_run(
             RunHandle {
                 cancel: Some(cancel_tx),
                 pid: None,
+                started_at_ms: now_ms(),
+                approvals: approvals_tx,
             },
         );
     }
@@ -1879,6 +1962,10 @@ async fn start_run(
         events_path: events_path.to_string_lossy().to_string(),
         stderr_path: stderr_path.to_string_lossy().to_string(),
         conclusion_path: conclusion_path.to_string_lossy().to_string(),
+        approval_policy: policy.approval_policy.clone(),
+        sandbox: policy.sandbox.clone(),
+        reasoning_effort: policy.reasoning_effort.clone(),
+        last_turn_read_only: read_only,
     };
 
     let meta_path = dir.join("meta.json");
@@ -1906,12 +1993,18 @@ async fn start_run(
             codex,
             cwd_for_run,
             None,
+            if read_only {
+                policy.read_only()
+            } else {
+                policy
+            },
             prompt_text,
             events_path_for_run,
             stderr_path_for_run,
             conclusion_path_for_run,
             meta_path_for_run,
             cancel_rx,
+            approvals_rx,
         )
         .await;
     });
@@ -1926,19 +2019,34 @@ async fn continue_run(
     session_id: String,
     prompt: String,
     cwd: Option<String>,
-) -> Result<SessionMeta, String> {
+    approval_policy: Option<String>,
+    sandbox: Option<String>,
+    reasoning_effort: Option<String>,
+    create_cwd: Option<bool>,
+    read_only: Option<bool>,
+) -> Result<SessionMeta, CommandError> {
+    let read_only = read_only.unwrap_or(false);
+    check_prompt_size(&read_settings(&app).await, &prompt)?;
+    let requested_policy = RunPolicy::parse(approval_policy, sandbox, reasoning_effort)
+        .map_err(|e| CommandError::new(ErrorCode::InvalidInput, e))?;
     // Avoid multiple concurrent runs per session.
     {
         let runs = state.runs.lock().await;
         if runs.contains_key(&session_id) {
-            return Err("session is already running".to_string());
+            return Err(CommandError::new(
+                ErrorCode::SessionRunning,
+                "session is already running",
+            ));
         }
     }
 
     let dir = session_dir(&app, &session_id)?;
     let meta_path = dir.join("meta.json");
     let Some(mut meta) = read_meta(&meta_path).await else {
-        return Err("meta.json not found".to_string());
+        return Err(CommandError::new(
+            ErrorCode::SessionNotFound,
+            "meta.json not found",
+        ));
     };
 
     let events_path = dir.join("events.jsonl");
@@ -1965,6 +2073,9 @@ async fn continue_run(
             }
         }
     }
+    ensure_cwd(cwd.as_deref(), create_cwd.unwrap_or(false))
+        .await
+        .map_err(|e| CommandError::new(ErrorCode::InvalidInput, e))?;
 
     if let Some(dir) = cwd.clone() {
         let mut settings = read_settings(&app).await;
@@ -1977,6 +2088,25 @@ async fn continue_run(
     if meta.codex_session_id.is_none() {
         meta.codex_session_id = try_find_codex_session_id(&events_path).await;
     }
+    let starts_new_thread = meta.codex_session_id.is_none();
+
+    // Keep the session's previous policy unless the caller explicitly changes it.
+    let policy = requested_policy
+        .or(RunPolicy {
+            approval_policy: meta.approval_policy.clone(),
+            sandbox: meta.sandbox.clone(),
+            reasoning_effort: meta.reasoning_effort.clone(),
+        })
+        .or(settings_run_policy(&read_settings(&app).await));
+    meta.approval_policy = policy.approval_policy.clone();
+    meta.sandbox = policy.sandbox.clone();
+    meta.reasoning_effort = policy.reasoning_effort.clone();
+    meta.last_turn_read_only = read_only;
+    let policy = if read_only {
+        policy.read_only()
+    } else {
+        policy
+    };
 
     meta.status = SessionStatus::Running;
     meta.cwd = cwd.clone();
@@ -1987,7 +2117,9 @@ async fn continue_run(
 
     write_meta(&meta_path, &meta).await?;
 
-    let codex = resolve_codex_executable(&app).await?;
+    let codex = resolve_codex_executable(&app)
+        .await
+        .map_err(|e| CommandError::new(ErrorCode::CodexNotFound, e))?;
 
     // Persist + emit the prompt marker.
     let prompt_text = prompt.trim().to_string();
@@ -2021,8 +2153,18 @@ async fn continue_run(
             json: Some(prompt_event),
         },
     );
+    if starts_new_thread {
+        let ts = now_ms();
+        let warning = serde_json::json!({
+            "type": "app.warning",
+            "message": NEW_THREAD_WARNING,
+            "_ts_ms": ts,
+        });
+        let _ = append_app_event(&app, &session_id, &events_path, ts, warning).await;
+    }
 
     let (cancel_tx, cancel_rx) = oneshot::channel();
+    let (approvals_tx, approvals_rx) = mpsc::unbounded_channel();
     {
         let mut runs = state.runs.lock().await;
         runs.insert(
@@ -2030,6 +2172,8 @@ async fn continue_run(
             RunHandle {
                 cancel: Some(cancel_tx),
                 pid: None,
+                started_at_ms: now_ms(),
+                approvals: approvals_tx,
             },
         );
     }
@@ -2051,12 +2195,14 @@ async fn continue_run(
             codex,
             cwd_for_run,
             thread_id_for_run,
+            policy,
             prompt_text,
             events_path_for_run,
             stderr_path_for_run,
             conclusion_path_for_run,
             meta_path_for_run,
             cancel_rx,
+            approvals_rx,
         )
         .await;
     });
@@ -2064,12 +2210,41 @@ async fn continue_run(
     Ok(meta)
 }
 
+#[derive(Serialize)]
+struct RunInfo {
+    session_id: String,
+    pid: Option<u32>,
+    started_at_ms: u64,
+}
+
+/// Turns in progress right now, oldest first.
+#[tauri::command]
+async fn list_running(state: tauri::State<'_, AppState>) -> Result<Vec<RunInfo>, CommandError> {
+    let mut runs: Vec<RunInfo> = state
+        .runs
+        .lock()
+        .await
+        .iter()
+        .map(|(session_id, run)| RunInfo {
+            session_id: session_id.clone(),
+            pid: run.pid,
+            started_at_ms: run.started_at_ms,
+        })
+        .collect();
+    runs.sort_by(|a, b| {
+        a.started_at_ms
+            .cmp(&b.started_at_ms)
+            .then_with(|| a.session_id.cmp(&b.session_id))
+    });
+    Ok(runs)
+}
+
 #[tauri::command]
 async fn stop_run(
     app: AppHandle,
     state: tauri::State<'_, AppState>,
     session_id: String,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     let (cancel, pid) = {
         let mut runs = state.runs.lock().await;
         let Some(handle) = runs.get_mut(&session_id) else {
@@ -2085,13 +2260,25 @@ async fn stop_run(
         }
     }
 
+    // Windows has no signals; there the run task kills the child once the cancel lands.
+    #[cfg(unix)]
     if let Some(pid) = pid {
-        #[cfg(unix)]
         unsafe {
             // Best-effort SIGINT to stop the codex child even if the task is wedged.
             libc::kill(pid as i32, libc::SIGINT);
         }
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(800)).await;
+            unsafe {
+                // If the PID is still alive (e.g. app-server not reading stdin), force-kill it.
+                if libc::kill(pid as i32, 0) == 0 {
+                    libc::kill(pid as i32, libc::SIGKILL);
+                }
+            }
+        });
     }
+    #[cfg(not(unix))]
+    let _ = pid;
 
     if receiver_dropped {
         // The run task likely panicked/died; clear the stale state so UI can recover.
@@ -2100,9 +2287,11 @@ async fn stop_run(
             runs.remove(&session_id);
         }
         let meta_path = session_dir(&app, &session_id)?.join("meta.json");
+        let mut codex_session_id = None;
         if let Some(mut meta) = read_meta(&meta_path).await {
             meta.status = SessionStatus::Error;
             let _ = write_meta(&meta_path, &meta).await;
+            codex_session_id = meta.codex_session_id;
         }
         let _ = app.emit(
             "codex_run_finished",
@@ -2111,6 +2300,9 @@ async fn stop_run(
                 ts_ms: now_ms(),
                 exit_code: None,
                 success: false,
+                codex_session_id,
+                status: Some("cancelled".to_string()),
+                reason: None,
             },
         );
     }
@@ -2118,8 +2310,31 @@ async fn stop_run(
     Ok(())
 }
 
+/// Answers an approval request from a running turn with `approved` or `denied`.
+#[tauri::command]
+async fn respond_approval(
+    state: tauri::State<'_, AppState>,
+    session_id: String,
+    request_id: String,
+    decision: String,
+) -> Result<(), CommandError> {
+    let decision = parse_approval_decision(&decision)
+        .map_err(|e| CommandError::new(ErrorCode::InvalidInput, e))?;
+    let runs = state.runs.lock().await;
+    let Some(handle) = runs.get(&session_id) else {
+        return Err(CommandError::new(
+            ErrorCode::SessionNotFound,
+            "session is not running",
+        ));
+    };
+    handle
+        .approvals
+        .send((request_id, decision))
+        .map_err(|_| CommandError::new(ErrorCode::SessionNotFound, "session is not running"))
+}
+
 #[tauri::command]
-async fn list_sessions(app: AppHandle) -> Result<Vec<SessionMeta>, String> {
+async fn list_sessions(app: AppHandle) -> Result<Vec<SessionMeta>, CommandError> {
     use std::cmp::Reverse;
 
     let root = sessions_root(&app)?;
@@ -2146,12 +2361,32 @@ async fn list_sessions(app: AppHandle) -> Result<Vec<SessionMeta>, String> {
     Ok(sessions)
 }
 
+/// Continues the most recently used session, or starts a new one when there are none.
+#[tauri::command]
+async fn continue_latest(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    prompt: String,
+    cwd: Option<String>,
+) -> Result<SessionMeta, CommandError> {
+    let latest = list_sessions(app.clone()).await?.into_iter().next();
+    match latest {
+        Some(meta) => {
+            continue_run(
+                app, state, meta.id, prompt, cwd, None, None, None, None, None,
+            )
+            .await
+        }
+        None => start_run(app, state, None, prompt, cwd, None, None, None, None, None).await,
+    }
+}
+
 #[tauri::command]
 async fn read_session_events(
     app: AppHandle,
     session_id: String,
     max_lines: Option<usize>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, CommandError> {
     use std::collections::VecDeque;
 
     let dir = session_dir(&app, &session_id)?;
@@ -2178,7 +2413,7 @@ async fn read_session_stderr(
     app: AppHandle,
     session_id: String,
     max_lines: Option<usize>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, CommandError> {
     use std::collections::VecDeque;
 
     let dir = session_dir(&app, &session_id)?;
@@ -2206,12 +2441,10 @@ async fn read_session_stderr(
 }
 
 #[tauri::command]
-async fn read_conclusion(app: AppHandle, session_id: String) -> Result<String, String> {
+async fn read_conclusion(app: AppHandle, session_id: String) -> Result<String, CommandError> {
     let dir = session_dir(&app, &session_id)?;
     let path = dir.join("conclusion.md");
-    tokio::fs::read_to_string(path)
-        .await
-        .map_err(|e| e.to_string())
+    Ok(tokio::fs::read_to_string(path).await?)
 }
 
 async fn append_usage_record(app: &AppHandle, record: &UsageRecord) -> Result<(), String> {
@@ -2236,7 +2469,7 @@ async fn append_usage_record(app: &AppHandle, record: &UsageRecord) -> Result<()
 async fn list_usage_records(
     app: AppHandle,
     max_records: Option<usize>,
-) -> Result<Vec<UsageRecord>, String> {
+) -> Result<Vec<UsageRecord>, CommandError> {
     use std::collections::VecDeque;
 
     let path = usage_log_path(&app)?;
@@ -2263,7 +2496,7 @@ async fn list_usage_records(
 }
 
 #[tauri::command]
-async fn list_skills() -> Result<Vec<SkillSummary>, String> {
+async fn list_skills() -> Result<Vec<SkillSummary>, CommandError> {
     let Some(root) = codex_skills_root() else {
         return Ok(Vec::new());
     };
@@ -2328,22 +2561,69 @@ async fn list_skills() -> Result<Vec<SkillSummary>, String> {
 }
 
 #[tauri::command]
-async fn rename_session(app: AppHandle, session_id: String, title: String) -> Result<(), String> {
+async fn rename_session(
+    app: AppHandle,
+    session_id: String,
+    title: String,
+) -> Result<(), CommandError> {
     let dir = session_dir(&app, &session_id)?;
     let meta_path = dir.join("meta.json");
     let Some(mut meta) = read_meta(&meta_path).await else {
-        return Err("meta.json not found".to_string());
+        return Err(CommandError::new(
+            ErrorCode::SessionNotFound,
+            "meta.json not found",
+        ));
     };
     meta.title = title;
-    write_meta(&meta_path, &meta).await
+    Ok(write_meta(&meta_path, &meta).await?)
+}
+
+/// Opens the session's directory in the OS file manager.
+#[tauri::command]
+async fn reveal_session(app: AppHandle, session_id: String) -> Result<(), CommandError> {
+    let dir = existing_session_dir(&app, &session_id).await?;
+    app.opener()
+        .open_path(dir.to_string_lossy(), None::<&str>)
+        .map_err(|e| CommandError::new(ErrorCode::Internal, e.to_string()))
+}
+
+/// Shows the session's conclusion.md selected in the OS file manager.
+#[tauri::command]
+async fn reveal_conclusion(app: AppHandle, session_id: String) -> Result<(), CommandError> {
+    let path = existing_session_dir(&app, &session_id)
+        .await?
+        .join("conclusion.md");
+    if tokio::fs::metadata(&path).await.is_err() {
+        return Err(CommandError::new(
+            ErrorCode::SessionNotFound,
+            "conclusion.md not found",
+        ));
+    }
+    app.opener()
+        .reveal_item_in_dir(&path)
+        .map_err(|e| CommandError::new(ErrorCode::Internal, e.to_string()))
+}
+
+async fn existing_session_dir(app: &AppHandle, session_id: &str) -> Result<PathBuf, CommandError> {
+    let dir = session_dir(app, session_id)?;
+    if read_meta(&dir.join("meta.json")).await.is_none() {
+        return Err(CommandError::new(
+            ErrorCode::SessionNotFound,
+            "meta.json not found",
+        ));
+    }
+    Ok(dir)
 }
 
 #[tauri::command]
-async fn touch_session(app: AppHandle, session_id: String) -> Result<SessionMeta, String> {
+async fn touch_session(app: AppHandle, session_id: String) -> Result<SessionMeta, CommandError> {
     let dir = session_dir(&app, &session_id)?;
     let meta_path = dir.join("meta.json");
     let Some(mut meta) = read_meta(&meta_path).await else {
-        return Err("meta.json not found".to_string());
+        return Err(CommandError::new(
+            ErrorCode::SessionNotFound,
+            "meta.json not found",
+        ));
     };
     meta.last_used_at_ms = now_ms();
     write_meta(&meta_path, &meta).await?;
@@ -2355,29 +2635,263 @@ async fn delete_session(
     app: AppHandle,
     state: tauri::State<'_, AppState>,
     session_id: String,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     // Best-effort stop if it's still running.
     let _ = stop_run(app.clone(), state, session_id.clone()).await;
 
     let dir = session_dir(&app, &session_id)?;
-    tokio::fs::remove_dir_all(dir)
-        .await
-        .map_err(|e| e.to_string())
+    Ok(tokio::fs::remove_dir_all(dir).await?)
 }
 
 #[tauri::command]
-async fn get_settings(app: AppHandle) -> Result<Settings, String> {
+async fn get_settings(app: AppHandle) -> Result<Settings, CommandError> {
     Ok(read_settings(&app).await)
 }
 
+/// Rejects variable names the OS can't set: empty, or containing `=` or NUL.
+fn validate_codex_env(env: &HashMap<String, String>) -> Result<(), String> {
+    for key in env.keys() {
+        if key.trim().is_empty() {
+            return Err("environment variable name must not be empty".to_string());
+        }
+        if key.contains('=') || key.contains('\0') {
+            return Err(format!("invalid environment variable name `{key}`"));
+        }
+    }
+    Ok(())
+}
+
 #[tauri::command]
-async fn save_settings(app: AppHandle, settings: Settings) -> Result<Settings, String> {
+async fn save_settings(app: AppHandle, mut settings: Settings) -> Result<Settings, CommandError> {
+    let policy = RunPolicy::parse(
+        settings.approval_policy.take(),
+        settings.sandbox.take(),
+        settings.reasoning_effort.take(),
+    )
+    .map_err(|e| CommandError::new(ErrorCode::InvalidInput, e))?;
+    settings.approval_policy = policy.approval_policy;
+    settings.sandbox = policy.sandbox;
+    settings.reasoning_effort = policy.reasoning_effort;
+    validate_codex_env(&settings.env).map_err(|e| CommandError::new(ErrorCode::InvalidInput, e))?;
     write_settings(&app, &settings).await?;
     Ok(settings)
 }
 
 #[tauri::command]
-async fn detect_codex_paths_cmd(app: AppHandle) -> Result<Vec<String>, String> {
+async fn export_settings(app: AppHandle) -> Result<String, CommandError> {
+    let settings = read_settings(&app).await;
+    Ok(serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?)
+}
+
+/// Parses an exported settings blob, rejecting keys `Settings` doesn't have so a typo isn't
+/// silently dropped. `last_cwd` is per-machine state, so the current one is kept when the blob
+/// omits it.
+fn parse_settings_import(json: &str, current_last_cwd: Option<String>) -> Result<Settings, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(json).map_err(|e| format!("invalid settings JSON: {e}"))?;
+    let Some(object) = value.as_object() else {
+        return Err("settings must be a JSON object".to_string());
+    };
+    let known = serde_json::to_value(Settings::default()).map_err(|e| e.to_string())?;
+    if let Some(unknown) = object.keys().find(|k| known.get(k.as_str()).is_none()) {
+        return Err(format!("unknown setting `{unknown}`"));
+    }
+    let keep_last_cwd = !object.contains_key("last_cwd");
+    let mut settings: Settings =
+        serde_json::from_value(value).map_err(|e| format!("invalid settings: {e}"))?;
+    if keep_last_cwd {
+        settings.last_cwd = current_last_cwd;
+    }
+    Ok(settings)
+}
+
+#[tauri::command]
+async fn import_settings(app: AppHandle, json: String) -> Result<Settings, CommandError> {
+    let current = read_settings(&app).await;
+    let settings = parse_settings_import(&json, current.last_cwd)
+        .map_err(|e| CommandError::new(ErrorCode::InvalidInput, e))?;
+    save_settings(app, settings).await
+}
+
+#[derive(Serialize)]
+struct Semver {
+    major: u64,
+    minor: u64,
+    patch: u64,
+}
+
+#[derive(Serialize)]
+struct CodexVersion {
+    path: String,
+    /// Raw `codex --version` output, e.g. `codex-cli 0.46.0`.
+    version: String,
+    semver: Option<Semver>,
+}
+
+const CODEX_VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pulls the first `X.Y.Z` (optionally `v`-prefixed, pre-release suffix ignored) out of a
+/// version string.
+fn parse_semver(text: &str) -> Option<Semver> {
+    text.split_whitespace().find_map(|token| {
+        let token = token.trim_start_matches('v');
+        let core = token.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+        let (major, minor, patch) = (parts.next()??, parts.next()??, parts.next()??);
+        Some(Semver {
+            major,
+            minor,
+            patch,
+        })
+    })
+}
+
+/// Login state from `$CODEX_HOME/auth.json`, so the UI can ask for `codex login` up front.
+#[derive(Serialize)]
+struct CodexAuthStatus {
+    logged_in: bool,
+    method: Option<String>,
+    expires_at_ms: Option<u64>,
+}
+
+#[tauri::command]
+async fn codex_auth_status() -> Result<CodexAuthStatus, CommandError> {
+    let auth = match default_codex_home() {
+        Some(home) => tokio::fs::read(home.join("auth.json"))
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok()),
+        None => None,
+    };
+    let auth = codex_auth_from_json(auth.as_ref(), now_ms());
+    Ok(CodexAuthStatus {
+        logged_in: auth.logged_in,
+        method: auth.method.map(str::to_string),
+        expires_at_ms: auth.expires_at_ms,
+    })
+}
+
+#[tauri::command]
+async fn codex_version(app: AppHandle) -> Result<CodexVersion, CommandError> {
+    let codex = resolve_codex_executable(&app)
+        .await
+        .map_err(|e| CommandError::new(ErrorCode::CodexNotFound, e))?;
+    let output = Command::new(&codex)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = match timeout(CODEX_VERSION_TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("failed to run codex --version: {e}").into()),
+        Err(_) => {
+            return Err(CommandError::new(
+                ErrorCode::Timeout,
+                "codex --version timed out after 5s",
+            ))
+        }
+    };
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(CodexVersion {
+        path: codex.to_string_lossy().to_string(),
+        semver: parse_semver(&version),
+        version,
+    })
+}
+
+#[derive(Serialize)]
+struct CodexPreflight {
+    ok: bool,
+    /// `userAgent` reported by the app-server, e.g. `codex_cli_rs/0.46.0 (...)`.
+    version: Option<String>,
+    /// The raw `initialize` result.
+    capabilities: serde_json::Value,
+    error: Option<String>,
+}
+
+impl CodexPreflight {
+    fn failed(error: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            version: None,
+            capabilities: serde_json::Value::Null,
+            error: Some(error.into()),
+        }
+    }
+}
+
+const CODEX_PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Spawns `codex app-server`, performs only the `initialize` handshake and returns its result.
+async fn initialize_app_server(codex: &Path) -> Result<serde_json::Value, String> {
+    let mut child = Command::new(codex)
+        .arg("app-server")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to start codex app-server: {e}"))?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or("failed to capture app-server stdin")?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or("failed to capture app-server stdout")?;
+    let mut lines = BufReader::new(stdout).lines();
+    // Nothing cancels a preflight; the sender just has to outlive the read loop.
+    let (_cancel_tx, mut cancel_rx) = oneshot::channel();
+
+    write_jsonrpc_request(
+        &mut stdin,
+        1,
+        "initialize",
+        serde_json::json!({
+            "clientInfo": {
+                "name": "codex-warp-gui",
+                "version": env!("CARGO_PKG_VERSION"),
+            }
+        }),
+    )
+    .await?;
+    let result = loop {
+        let Some((_, json)) = read_next_json_line(&mut lines, &mut cancel_rx).await? else {
+            return Err("codex app-server stdout closed".to_string());
+        };
+        if json.get("method").is_none() && jsonrpc_id_matches(&json, 1) {
+            break jsonrpc_result(&json).map_err(String::from);
+        }
+    };
+    let _ = child.kill().await;
+    result
+}
+
+#[tauri::command]
+async fn preflight_codex(app: AppHandle) -> Result<CodexPreflight, CommandError> {
+    let codex = match resolve_codex_executable(&app).await {
+        Ok(c) => c,
+        Err(e) => return Ok(CodexPreflight::failed(e)),
+    };
+    let preflight = match timeout(CODEX_PREFLIGHT_TIMEOUT, initialize_app_server(&codex)).await {
+        Ok(Ok(result)) => CodexPreflight {
+            ok: true,
+            version: result
+                .get("userAgent")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+            capabilities: result,
+            error: None,
+        },
+        Ok(Err(e)) => CodexPreflight::failed(format!("initialize failed: {e}")),
+        Err(_) => CodexPreflight::failed("codex app-server did not answer initialize within 10s"),
+    };
+    Ok(preflight)
+}
+
+#[tauri::command]
+async fn detect_codex_paths_cmd(app: AppHandle) -> Result<Vec<String>, CommandError> {
     let settings = read_settings(&app).await;
     let mut out = Vec::new();
 
@@ -2388,7 +2902,7 @@ async fn detect_codex_paths_cmd(app: AppHandle) -> Result<Vec<String>, String> {
         }
     }
 
-    for p in detect_codex_paths() {
+    for p in detect_codex_paths(&settings.extra_codex_dirs) {
         out.push(p.display().to_string());
     }
 
@@ -2402,7 +2916,7 @@ async fn start_shell(
     app: AppHandle,
     state: tauri::State<'_, AppState>,
     cwd: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     {
         let shell = state.shell.lock().await;
         if shell.is_some() {
@@ -2499,11 +3013,14 @@ RPROMPT=''
 }
 
 #[tauri::command]
-async fn shell_write(state: tauri::State<'_, AppState>, data: String) -> Result<(), String> {
+async fn shell_write(state: tauri::State<'_, AppState>, data: String) -> Result<(), CommandError> {
     let writer = {
         let shell = state.shell.lock().await;
         let Some(handle) = shell.as_ref() else {
-            return Err("shell not started".to_string());
+            return Err(CommandError::new(
+                ErrorCode::ShellNotStarted,
+                "shell not started",
+            ));
         };
         handle.writer.clone()
     };
@@ -2524,11 +3041,14 @@ async fn shell_resize(
     state: tauri::State<'_, AppState>,
     cols: u16,
     rows: u16,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     let master = {
         let shell = state.shell.lock().await;
         let Some(handle) = shell.as_ref() else {
-            return Err("shell not started".to_string());
+            return Err(CommandError::new(
+                ErrorCode::ShellNotStarted,
+                "shell not started",
+            ));
         };
         handle.master.clone()
     };
@@ -2549,7 +3069,7 @@ async fn shell_resize(
 }
 
 #[tauri::command]
-async fn shell_cd(state: tauri::State<'_, AppState>, cwd: String) -> Result<(), String> {
+async fn shell_cd(state: tauri::State<'_, AppState>, cwd: String) -> Result<(), CommandError> {
     let dir = cwd.trim();
     if dir.is_empty() {
         return Ok(());
@@ -2559,7 +3079,7 @@ async fn shell_cd(state: tauri::State<'_, AppState>, cwd: String) -> Result<(),
 }
 
 #[tauri::command]
-async fn stop_shell(state: tauri::State<'_, AppState>) -> Result<(), String> {
+async fn stop_shell(state: tauri::State<'_, AppState>) -> Result<(), CommandError> {
     let handle = {
         let mut shell = state.shell.lock().await;
         shell.take()
@@ -2585,7 +3105,7 @@ async fn save_pasted_image(
     session_id: Option<String>,
     ext: Option<String>,
     data_base64: String,
-) -> Result<String, String> {
+) -> Result<String, CommandError> {
     use base64::Engine;
 
     let ext = sanitize_file_ext(ext.as_deref().unwrap_or("png"));
@@ -2617,7 +3137,7 @@ async fn import_dropped_files(
     app: AppHandle,
     session_id: Option<String>,
     paths: Vec<String>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, CommandError> {
     let base = match session_id.as_deref() {
         Some(sid) if !sid.trim().is_empty() => session_dir(&app, sid)?,
         _ => app.path().app_data_dir().map_err(|e| e.to_string())?,
@@ -2670,16 +3190,66 @@ async fn import_dropped_files(
     Ok(out)
 }
 
+const INTERRUPTED_NOTE: &str = "Interrupted: the app closed before this turn finished.";
+
+/// Flips sessions left `Running` by a crashed app to `Error`, keeping any partial conclusion and
+/// appending a note. Sessions in `running` are left alone. Returns how many were fixed.
+fn reconcile_stale_runs(root: &Path, running: &HashSet<String>) -> usize {
+    let Ok(rd) = std::fs::read_dir(root) else {
+        return 0;
+    };
+    let mut fixed = 0;
+    for entry in rd.flatten() {
+        let path = entry.path().join("meta.json");
+        let Some(mut meta) = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<SessionMeta>(&bytes).ok())
+        else {
+            continue;
+        };
+        if !matches!(meta.status, SessionStatus::Running) || running.contains(&meta.id) {
+            continue;
+        }
+        meta.status = SessionStatus::Error;
+        let bytes = serde_json::to_vec_pretty(&meta).unwrap_or_default();
+        if std::fs::write(&path, bytes).is_err() {
+            continue;
+        }
+        let partial = std::fs::read_to_string(&meta.conclusion_path).unwrap_or_default();
+        let conclusion = if partial.trim().is_empty() {
+            format!("# Error\n\n{INTERRUPTED_NOTE}\n")
+        } else {
+            format!("{}\n\n{INTERRUPTED_NOTE}\n", partial.trim_end())
+        };
+        let _ = std::fs::write(&meta.conclusion_path, conclusion);
+        fixed += 1;
+    }
+    fixed
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .manage(AppState::default())
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            std::fs::create_dir_all(&data_dir)?;
+            app.manage(lock_data_dir(&data_dir)?);
+            // Nothing is running yet, so every `Running` session is left over from a crash.
+            if let Ok(root) = sessions_root(app.handle()) {
+                reconcile_stale_runs(&root, &HashSet::new());
+            }
+            Ok(())
+        })
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             start_run,
             continue_run,
+            continue_latest,
             stop_run,
+            list_running,
+            respond_approval,
             list_sessions,
             read_session_events,
             read_session_stderr,
@@ -2688,10 +3258,17 @@ pub fn run() {
             list_skills,
             rename_session,
             touch_session,
+            reveal_session,
+            reveal_conclusion,
             delete_session,
             get_settings,
             save_settings,
+            export_settings,
+            import_settings,
             detect_codex_paths_cmd,
+            codex_version,
+            codex_auth_status,
+            preflight_codex,
             start_shell,
             shell_write,
             shell_resize,
@@ -2703,3 +3280,222 @@ pub fn run() {
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes_serialize_as_snake_case() {
+        let all = [
+            (ErrorCode::CodexNotFound, "codex_not_found"),
+            (ErrorCode::SessionExists, "session_exists"),
+            (ErrorCode::SessionNotFound, "session_not_found"),
+            (ErrorCode::SessionRunning, "session_running"),
+            (ErrorCode::ShellNotStarted, "shell_not_started"),
+            (ErrorCode::InvalidInput, "invalid_input"),
+            (ErrorCode::PromptTooLarge, "prompt_too_large"),
+            (ErrorCode::Timeout, "timeout"),
+            (ErrorCode::Io, "io"),
+            (ErrorCode::Internal, "internal"),
+        ];
+        for (code, expected) in all {
+            // Adding a variant without listing it above fails to compile here.
+            match code {
+                ErrorCode::CodexNotFound
+                | ErrorCode::SessionExists
+                | ErrorCode::SessionNotFound
+                | ErrorCode::SessionRunning
+                | ErrorCode::ShellNotStarted
+                | ErrorCode::InvalidInput
+                | ErrorCode::PromptTooLarge
+                | ErrorCode::Timeout
+                | ErrorCode::Io
+                | ErrorCode::Internal => {}
+            }
+            let value = serde_json::to_value(CommandError::new(code, "boom")).unwrap();
+            assert_eq!(value["code"], expected);
+            assert_eq!(value["message"], "boom");
+        }
+    }
+
+    #[test]
+    fn string_and_io_errors_keep_their_message() {
+        let err: CommandError = "disk full".to_string().into();
+        assert_eq!(err.code, ErrorCode::Internal);
+        assert_eq!(err.message, "disk full");
+
+        let err: CommandError = std::io::Error::new(std::io::ErrorKind::NotFound, "gone").into();
+        assert_eq!(err.code, ErrorCode::Io);
+        assert_eq!(err.message, "gone");
+    }
+
+    #[test]
+    fn check_prompt_size_defaults_to_one_mib_and_zero_disables_it() {
+        let mut settings = Settings::default();
+        let big = "x".repeat(DEFAULT_MAX_PROMPT_BYTES + 1);
+        let err = check_prompt_size(&settings, &big).unwrap_err();
+        assert_eq!(err.code, ErrorCode::PromptTooLarge);
+        assert_eq!(
+            err.message,
+            format!("prompt is {} bytes; the limit is 1048576 bytes", big.len())
+        );
+        assert!(check_prompt_size(&settings, "hello").is_ok());
+
+        settings.max_prompt_bytes = Some(0);
+        assert!(check_prompt_size(&settings, &big).is_ok());
+        settings.max_prompt_bytes = Some(4);
+        assert!(check_prompt_size(&settings, "  abcd  ").is_ok());
+        assert!(check_prompt_size(&settings, "abcde").is_err());
+    }
+
+    #[test]
+    fn safe_title_truncates_on_grapheme_boundaries() {
+        let cjk = "修复登录页面在移动端偶发的白屏问题".repeat(5);
+        let title = safe_title(&cjk);
+        assert_eq!(title.chars().count(), 61);
+        assert!(title.ends_with('…'));
+
+        // Each family emoji is one grapheme made of several chars.
+        let family = "👨‍👩‍👧 ".repeat(40);
+        let title = safe_title(&family);
+        assert!(title.ends_with('…'));
+        assert_eq!(title.graphemes(true).count(), 61);
+        assert!(title.trim_end_matches('…').trim_end().ends_with("👨‍👩‍👧"));
+        assert_eq!(safe_title("  \n "), "New session");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn data_dir_lock_rejects_a_second_instance() {
+        let dir = std::env::temp_dir().join(format!("codex-warp-lock-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let lock = lock_data_dir(&dir).unwrap();
+        let err = lock_data_dir(&dir).err().unwrap();
+        assert!(
+            err.contains(&format!("pid {}", std::process::id())),
+            "{err}"
+        );
+        drop(lock);
+        assert!(lock_data_dir(&dir).is_ok());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn run_policy_rejects_unknown_values() {
+        assert!(RunPolicy::parse(Some("sometimes".to_string()), None, None).is_err());
+        assert!(RunPolicy::parse(None, Some("yolo".to_string()), None).is_err());
+        assert!(RunPolicy::parse(None, None, Some("hgih".to_string())).is_err());
+        let policy = RunPolicy::parse(
+            Some(" on-request ".to_string()),
+            Some(String::new()),
+            Some("high".to_string()),
+        )
+        .unwrap();
+        assert_eq!(policy.approval_policy.as_deref(), Some("on-request"));
+        assert_eq!(policy.sandbox, None);
+
+        let mut params = serde_json::json!({ "config": { "skip_git_repo_check": true } });
+        policy.apply(&mut params);
+        assert_eq!(params["approvalPolicy"], "on-request");
+        assert_eq!(params["config"]["model_reasoning_effort"], "high");
+
+        let mut params = serde_json::json!({});
+        policy.read_only().apply(&mut params);
+        assert_eq!(params["approvalPolicy"], "never");
+        assert_eq!(params["sandbox"], "read-only");
+        assert_eq!(params["config"]["model_reasoning_effort"], "high");
+    }
+
+    #[test]
+    fn settings_import_rejects_unknown_keys_and_keeps_last_cwd() {
+        let err = parse_settings_import(r#"{"codex_pth": "/bin/codex"}"#, None).unwrap_err();
+        assert!(err.contains("codex_pth"), "{err}");
+        assert!(parse_settings_import("[]", None).is_err());
+
+        let imported =
+            parse_settings_import(r#"{"default_cwd": "/work"}"#, Some("/here".to_string()))
+                .unwrap();
+        assert_eq!(imported.default_cwd.as_deref(), Some("/work"));
+        assert_eq!(imported.last_cwd.as_deref(), Some("/here"));
+
+        let exported = serde_json::to_string(&imported).unwrap();
+        let round_trip = parse_settings_import(&exported, Some("/elsewhere".to_string())).unwrap();
+        assert_eq!(round_trip.last_cwd.as_deref(), Some("/here"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn push_codex_in_probes_windows_binary_names() {
+        let dir = std::env::temp_dir().join(format!("codex-warp-win-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("codex.cmd"), "@echo off\r\n").unwrap();
+        std::fs::write(dir.join("codex.exe"), "").unwrap();
+
+        let mut out = Vec::new();
+        push_codex_in(&mut out, &dir);
+        assert_eq!(out, vec![dir.join("codex.exe"), dir.join("codex.cmd")]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detect_codex_paths_prefers_extra_dirs_and_skips_missing_ones() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("codex-warp-extra-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let codex = dir.join("codex");
+        std::fs::write(&codex, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&codex, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let extra = vec![
+            "/definitely/not/here".to_string(),
+            String::new(),
+            dir.to_string_lossy().to_string(),
+        ];
+        let found = detect_codex_paths(&extra);
+        assert_eq!(found.first(), Some(&codex));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reconcile_stale_runs_flips_running_sessions_to_error() {
+        let root = std::env::temp_dir().join(format!("codex-warp-sessions-{}", Uuid::new_v4()));
+        let dir = root.join("stale");
+        std::fs::create_dir_all(&dir).unwrap();
+        let meta = SessionMeta {
+            id: "stale".to_string(),
+            title: "Stale".to_string(),
+            created_at_ms: 1,
+            last_used_at_ms: 1,
+            cwd: None,
+            status: SessionStatus::Running,
+            codex_session_id: None,
+            context_window: None,
+            context_used_tokens: None,
+            context_left_pct: None,
+            events_path: dir.join("events.jsonl").to_string_lossy().to_string(),
+            stderr_path: dir.join("stderr.log").to_string_lossy().to_string(),
+            conclusion_path: dir.join("conclusion.md").to_string_lossy().to_string(),
+            approval_policy: None,
+            sandbox: None,
+            reasoning_effort: None,
+            last_turn_read_only: false,
+        };
+        std::fs::write(dir.join("meta.json"), serde_json::to_vec(&meta).unwrap()).unwrap();
+
+        assert_eq!(reconcile_stale_runs(&root, &HashSet::new()), 1);
+        let meta: SessionMeta =
+            serde_json::from_slice(&std::fs::read(dir.join("meta.json")).unwrap()).unwrap();
+        assert!(matches!(meta.status, SessionStatus::Error));
+        let conclusion = std::fs::read_to_string(dir.join("conclusion.md")).unwrap();
+        assert!(conclusion.contains(INTERRUPTED_NOTE));
+        assert_eq!(reconcile_stale_runs(&root, &HashSet::new()), 0);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
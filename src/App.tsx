@@ -24,6 +24,14 @@ type Settings = {
   codex_path?: string | null;
   default_cwd?: string | null;
   last_cwd?: string | null;
+  approval_policy?: string | null;
+  sandbox?: string | null;
+  reasoning_effort?: string | null;
+  turn_timeout_secs?: number | null;
+  child_exit_timeout_secs?: number | null;
+  extra_codex_dirs?: string[];
+  env?: Record<string, string>;
+  conclusion_template?: string | null;
 };
 
 type SessionStatus = "running" | "done" | "error";
@@ -69,6 +77,7 @@ type UsageRecord = {
   reasoning_output_tokens: number;
   cached_input_tokens: number;
   context_window: number;
+  model?: string | null;
 };
 
 type RunFinished = {
@@ -76,6 +85,15 @@ type RunFinished = {
   ts_ms: number;
   exit_code: number | null;
   success: boolean;
+  codex_session_id?: string | null;
+};
+
+type ApprovalRequest = {
+  session_id: string;
+  ts_ms: number;
+  request_id: string;
+  method: string;
+  params: Record<string, unknown> | null;
 };
 
 type SkillSummary = {
@@ -321,6 +339,12 @@ function stripToolCitations(text: string): string {
   return text.replace(TOOL_MARKUP_RE, "");
 }
 
+// Tauri commands reject with `{ code, message }`; the HTTP client throws plain Errors.
+function errorMessage(e: unknown): string {
+  if (isObject(e) && typeof (e as any).message === "string") return String((e as any).message);
+  return String(e);
+}
+
 const ANSI_ESCAPE_RE = /\u001b\[[0-9;]*m/g;
 const ANSI_ESCAPE_RE_ALT = /\u009b[0-9;]*m/g;
 const ANSI_ESCAPE_RE_FALLBACK = /\uFFFD\[[0-9;]*m/g;
@@ -1046,7 +1070,21 @@ function applyUiEventToBlocks(blocks: Block[], e: UiEvent): Block[] {
     ];
   }
 
-  if (type === "thread.started" || type === "turn.started") {
+  if (type === "app.warning") {
+    const message = typeof e.json.message === "string" ? e.json.message : "";
+    if (!message) return blocks;
+    const key = `app_warning:${e.ts_ms}`;
+    return upsertBlock(blocks, {
+      id: key,
+      key,
+      kind: "status",
+      title: "Warning",
+      body: message,
+      ts_ms: e.ts_ms,
+    });
+  }
+
+  if (type === "thread.started" || type === "turn.started" || type === "app.agent_message") {
     return blocks;
   }
 
@@ -1579,12 +1617,12 @@ function App() {
               }
               setErrorBanner(null);
               if (IS_TAURI) {
-                void openUrl(url).catch((err) => setErrorBanner(String(err)));
+                void openUrl(url).catch((err) => setErrorBanner(errorMessage(err)));
               } else {
                 try {
                   window.open(url, "_blank", "noopener,noreferrer");
                 } catch (err) {
-                  setErrorBanner(String(err));
+                  setErrorBanner(errorMessage(err));
                 }
               }
             }}
@@ -1633,7 +1671,7 @@ function App() {
             const next = imported.length > 0 ? imported : rawPaths;
             insertPromptText(next.map(quotePathIfNeeded).join("\n"));
           } catch (err) {
-            setErrorBanner(String(err));
+            setErrorBanner(errorMessage(err));
             insertPromptText(rawPaths.map(quotePathIfNeeded).join("\n"));
           }
         })();
@@ -1683,7 +1721,7 @@ function App() {
           insertPromptText(path + (i === images.length - 1 ? "" : "\n"));
         }
       } catch (err) {
-        setErrorBanner(String(err));
+        setErrorBanner(errorMessage(err));
       }
     },
     [activeSessionId, insertPromptText, isRemote],
@@ -1717,7 +1755,7 @@ function App() {
       if (!sid) return;
       e.preventDefault();
       setErrorBanner(null);
-      void stopRun(sid).catch((err) => setErrorBanner(String(err)));
+      void stopRun(sid).catch((err) => setErrorBanner(errorMessage(err)));
     }
 
     // Capture to ensure Escape works even if focused widgets stop propagation (xterm, inputs, etc).
@@ -2040,7 +2078,7 @@ function App() {
         return copy;
       });
     } catch (e) {
-      setErrorBanner(String(e));
+      setErrorBanner(errorMessage(e));
     } finally {
       setLoadingSessionId((cur) => (cur === loadId ? null : cur));
     }
@@ -2065,7 +2103,7 @@ function App() {
         if (s) await loadSession(s);
       }
     } catch (e) {
-      setErrorBanner(String(e));
+      setErrorBanner(errorMessage(e));
     }
   }
 
@@ -2235,7 +2273,13 @@ function App() {
 
         setSessions((prev) =>
           prev.map((s) =>
-            s.id === payload.session_id ? { ...s, status: payload.success ? "done" : "error" } : s,
+            s.id === payload.session_id
+              ? {
+                  ...s,
+                  status: payload.success ? "done" : "error",
+                  codex_session_id: payload.codex_session_id ?? s.codex_session_id,
+                }
+              : s,
           ),
         );
         setRunStartedAtBySession((prev) => {
@@ -2289,6 +2333,7 @@ function App() {
     let unlistenEvent: (() => void) | null = null;
     let unlistenFinished: (() => void) | null = null;
     let unlistenMetrics: (() => void) | null = null;
+    let unlistenApproval: (() => void) | null = null;
 
     void listen<UiEvent>("codex_event", ({ payload }) => {
       if (!payload?.session_id) return;
@@ -2352,7 +2397,13 @@ function App() {
       if (!payload?.session_id) return;
       setSessions((prev) =>
         prev.map((s) =>
-          s.id === payload.session_id ? { ...s, status: payload.success ? "done" : "error" } : s,
+          s.id === payload.session_id
+            ? {
+                ...s,
+                status: payload.success ? "done" : "error",
+                codex_session_id: payload.codex_session_id ?? s.codex_session_id,
+              }
+            : s,
         ),
       );
       setRunStartedAtBySession((prev) => {
@@ -2385,11 +2436,30 @@ function App() {
       })
       .catch(() => {});
 
+    void listen<ApprovalRequest>("codex_approval_request", ({ payload }) => {
+      if (!payload?.session_id) return;
+      const raw = payload.params?.command;
+      const command = Array.isArray(raw) ? raw.join(" ") : typeof raw === "string" ? raw : null;
+      const what = command ? `Run \`${command}\`?` : `Allow ${payload.method}?`;
+      const decision = window.confirm(`Codex is asking for approval.\n\n${what}`) ? "approved" : "denied";
+      void invoke("respond_approval", {
+        sessionId: payload.session_id,
+        requestId: payload.request_id,
+        decision,
+      }).catch(() => {});
+    })
+      .then((unlisten) => {
+        if (disposed) unlisten();
+        else unlistenApproval = unlisten;
+      })
+      .catch(() => {});
+
     return () => {
       disposed = true;
       unlistenEvent?.();
       unlistenFinished?.();
       unlistenMetrics?.();
+      unlistenApproval?.();
     };
   }, [isRemote, refreshUsageRecords]);
 
@@ -2413,7 +2483,7 @@ function App() {
         }
       } catch (e) {
         if (!alive) return;
-        setErrorBanner(String(e));
+        setErrorBanner(errorMessage(e));
       }
     })();
     return () => {
@@ -2439,7 +2509,7 @@ function App() {
       })
       .catch((err) => {
         if (!alive) return;
-        setErrorBanner(`Failed to load skills: ${String(err)}`);
+        setErrorBanner(`Failed to load skills: ${errorMessage(err)}`);
       })
       .finally(() => {
         if (!alive) return;
@@ -2544,7 +2614,7 @@ function App() {
         return next;
       });
       setActiveSessionId(prevActiveSessionId);
-      setErrorBanner(String(e));
+      setErrorBanner(errorMessage(e));
       return false;
     } finally {
       setStartingSessionId((cur) => (cur === sessionId ? null : cur));
@@ -2618,7 +2688,7 @@ function App() {
         delete next[activeSessionId];
         return next;
       });
-      setErrorBanner(String(e));
+      setErrorBanner(errorMessage(e));
       setPrompt(promptText);
     }
   }
@@ -2674,7 +2744,7 @@ function App() {
       });
       setSettings(saved);
     } catch (e) {
-      setErrorBanner(String(e));
+      setErrorBanner(errorMessage(e));
     }
   }
 
@@ -2728,7 +2798,7 @@ function App() {
       });
       await refreshSessions();
     } catch (e) {
-      setErrorBanner(String(e));
+      setErrorBanner(errorMessage(e));
     }
   }
 
@@ -2754,7 +2824,7 @@ function App() {
       const paths = await invoke<string[]>("detect_codex_paths_cmd");
       setDetectedCodexPaths(paths);
     } catch (e) {
-      setErrorBanner(String(e));
+      setErrorBanner(errorMessage(e));
     }
   }
 
@@ -2772,7 +2842,7 @@ function App() {
       setRemoteBaseUrlDraft(draft);
       setConnectionMode("remote");
     } catch (e) {
-      setErrorBanner(`Remote check failed: ${String(e)}`);
+      setErrorBanner(`Remote check failed: ${errorMessage(e)}`);
     } finally {
       setCheckingRemote(false);
     }
@@ -2800,7 +2870,7 @@ function App() {
       if (!cwd.trim() && initialCwd) setCwd(initialCwd);
       setShowSettings(false);
     } catch (e) {
-      setErrorBanner(String(e));
+      setErrorBanner(errorMessage(e));
     }
   }
 
@@ -3131,7 +3201,7 @@ function App() {
                       const next = imported.length > 0 ? imported : paths;
                       insertPromptText(next.map(quotePathIfNeeded).join("\n"));
                     } catch (e) {
-                      setErrorBanner(String(e));
+                      setErrorBanner(errorMessage(e));
                     }
                   }}
                 >
@@ -3216,7 +3286,7 @@ function App() {
                       e.stopPropagation();
                       setErrorBanner(null);
                       setSkillPicker(null);
-                      void stopRun(activeSessionId).catch((err) => setErrorBanner(String(err)));
+                      void stopRun(activeSessionId).catch((err) => setErrorBanner(errorMessage(err)));
                       return;
                     }
 
@@ -3287,7 +3357,7 @@ function App() {
                       e.preventDefault();
                       e.stopPropagation();
                       setErrorBanner(null);
-                      void stopRun(activeSessionId).catch((err) => setErrorBanner(String(err)));
+                      void stopRun(activeSessionId).catch((err) => setErrorBanner(errorMessage(err)));
                     }
                   }}
                   rows={2}
@@ -3680,7 +3750,7 @@ function App() {
                       setShowRename(false);
                       await refreshSessions(activeSessionId);
                     } catch (e) {
-                      setErrorBanner(String(e));
+                      setErrorBanner(errorMessage(e));
                     } finally {
                       setRenameSaving(false);
                     }